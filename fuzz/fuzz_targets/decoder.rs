@@ -0,0 +1,22 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use write_together::protocol::Decoder;
+
+// Feeds arbitrary bytes to the decoder, a byte at a time after the first
+// half, which must never panic however they are framed.
+fuzz_target!(|data: &[u8]| {
+    let (first, rest) = data.split_at(data.len() / 2);
+    let mut decoder = Decoder::default();
+    decoder.extend(first);
+    let mut chunks = rest.chunks(1);
+    loop {
+        match decoder.next_message() {
+            Ok(Some(_)) => continue,
+            Ok(None) => match chunks.next() {
+                Some(chunk) => decoder.extend(chunk),
+                None => break,
+            },
+            Err(_) => break,
+        }
+    }
+});
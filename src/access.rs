@@ -0,0 +1,248 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// A block of addresses, such as `203.0.113.0/24` or `2001:db8::/32`. A
+/// bare address is a block of just that one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `address` is in the block. IPv4 addresses match whether or
+    /// not they come mapped into IPv6, as they do on a dual-stack listener.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let (network, width) = bits(self.network);
+        match bits(address.to_canonical()) {
+            (address, address_width) if address_width == width => {
+                (network ^ address)
+                    .checked_shr(u32::from(width - self.prefix))
+                    .unwrap_or(0)
+                    == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(address: IpAddr) -> Self {
+        let address = address.to_canonical();
+        Self {
+            network: address,
+            prefix: bits(address).1,
+        }
+    }
+}
+
+/// An address as a number, and how many bits long it is.
+fn bits(address: IpAddr) -> (u128, u8) {
+    match address {
+        IpAddr::V4(address) => (u128::from(u32::from(address)), 32),
+        IpAddr::V6(address) => (u128::from(address), 128),
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "'{}' isn't an address or a block of them such as 10.0.0.0/8",
+                s
+            )
+        };
+        let (address, prefix) = match s.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let block = Cidr::from(address.parse::<IpAddr>().map_err(|_| invalid())?);
+        match prefix.map(str::parse::<u8>) {
+            None => Ok(block),
+            Some(Ok(prefix)) if prefix <= block.prefix => Ok(Self { prefix, ..block }),
+            Some(_) => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.prefix == bits(self.network).1 {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix)
+        }
+    }
+}
+
+/// Who may connect to us: anyone who isn't blocked, or only those allowed
+/// when any are.
+#[derive(Debug, Default)]
+pub struct AccessList {
+    allow: Vec<Cidr>,
+    block: Vec<Cidr>,
+    /// Where peers blocked while playing are saved, if anywhere.
+    path: Option<PathBuf>,
+}
+
+impl AccessList {
+    pub fn new(allow: Vec<Cidr>, block: Vec<Cidr>) -> Self {
+        Self {
+            allow,
+            block,
+            path: None,
+        }
+    }
+
+    /// Adds the blocklist saved at `path`, skipping lines that aren't
+    /// addresses, and saves peers blocked from now on there too. A missing
+    /// file blocks nobody.
+    pub fn load_blocklist(mut self, path: PathBuf) -> io::Result<Self> {
+        match fs::read_to_string(&path) {
+            Ok(contents) => self.block.extend(
+                contents
+                    .lines()
+                    .filter_map(|line| line.trim().parse::<Cidr>().ok()),
+            ),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    /// Whether `address` may connect to us.
+    pub fn permits(&self, address: IpAddr) -> bool {
+        let listed = |blocks: &[Cidr]| blocks.iter().any(|block| block.contains(address));
+        !listed(&self.block) && (self.allow.is_empty() || listed(&self.allow))
+    }
+
+    /// Blocks `address` from connecting again, adding it to the saved
+    /// blocklist.
+    pub fn block(&mut self, address: IpAddr) -> io::Result<()> {
+        let block = Cidr::from(address);
+        self.block.push(block);
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", block)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where peers blocked while playing are kept.
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("write_together").join("blocklist"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessList, Cidr};
+    use std::{fs, net::IpAddr};
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn cidr(block: &str) -> Cidr {
+        block.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_addresses_and_blocks() {
+        assert_eq!(cidr("203.0.113.7").to_string(), "203.0.113.7");
+        assert_eq!(cidr(" 203.0.113.0/24 ").to_string(), "203.0.113.0/24");
+        assert_eq!(cidr("2001:db8::/32").to_string(), "2001:db8::/32");
+        assert_eq!(cidr("::1/128").to_string(), "::1");
+        // Mapped IPv4 addresses are taken as what they are
+        assert_eq!(cidr("::ffff:203.0.113.7"), cidr("203.0.113.7"));
+        for invalid in [
+            "",
+            "203.0.113",
+            "203.0.113.0/33",
+            "2001:db8::/129",
+            "203.0.113.0/",
+            "203.0.113.0/-1",
+            "example.com",
+        ] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn blocks_hold_the_addresses_they_cover() {
+        let block = cidr("203.0.113.0/24");
+        assert!(block.contains(ip("203.0.113.0")));
+        assert!(block.contains(ip("203.0.113.255")));
+        assert!(block.contains(ip("::ffff:203.0.113.9")));
+        assert!(!block.contains(ip("203.0.114.0")));
+        assert!(!block.contains(ip("2001:db8::1")));
+
+        let block = cidr("2001:db8::/32");
+        assert!(block.contains(ip("2001:db8::1")));
+        assert!(block.contains(ip("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert!(!block.contains(ip("2001:db9::")));
+        assert!(!block.contains(ip("203.0.113.1")));
+
+        assert!(cidr("2001:db8::1").contains(ip("2001:db8::1")));
+        assert!(!cidr("2001:db8::1").contains(ip("2001:db8::2")));
+        assert!(cidr("2001:db8::/127").contains(ip("2001:db8::1")));
+
+        // Nothing is left out of the widest blocks
+        assert!(cidr("0.0.0.0/0").contains(ip("198.51.100.1")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("::1")));
+        assert!(cidr("::/0").contains(ip("::1")));
+    }
+
+    #[test]
+    fn blocked_and_unlisted_addresses_are_turned_away() {
+        let anyone = AccessList::new(vec![], vec![cidr("203.0.113.0/24")]);
+        assert!(anyone.permits(ip("198.51.100.1")));
+        assert!(!anyone.permits(ip("203.0.113.1")));
+
+        // Blocking wins over allowing
+        let listed = AccessList::new(
+            vec![cidr("203.0.113.0/24"), cidr("2001:db8::/32")],
+            vec![cidr("203.0.113.66")],
+        );
+        assert!(listed.permits(ip("203.0.113.1")));
+        assert!(listed.permits(ip("2001:db8::5")));
+        assert!(!listed.permits(ip("203.0.113.66")));
+        assert!(!listed.permits(ip("198.51.100.1")));
+    }
+
+    #[test]
+    fn blocked_peers_are_saved_for_next_time() {
+        let dir = std::env::temp_dir().join(format!("wt-blocklist-{}", std::process::id()));
+        let path = dir.join("blocklist");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut access = AccessList::default().load_blocklist(path.clone()).unwrap();
+        access.block(ip("203.0.113.66")).unwrap();
+        access.block(ip("::ffff:198.51.100.1")).unwrap();
+        assert!(!access.permits(ip("203.0.113.66")));
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + "not an address\n2001:db8::/32\n",
+        )
+        .unwrap();
+
+        let access = AccessList::default().load_blocklist(path.clone()).unwrap();
+        assert!(!access.permits(ip("203.0.113.66")));
+        assert!(!access.permits(ip("198.51.100.1")));
+        assert!(!access.permits(ip("2001:db8::7")));
+        assert!(access.permits(ip("203.0.113.67")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
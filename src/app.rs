@@ -1,179 +1,2869 @@
-use crate::{error::Error, ui_actor::UIHandle};
+use crate::{
+    access::AccessList,
+    constraints::Constraint,
+    error::{self, Error},
+    export::{self, Appendices, Format},
+    filter::{Incoming, WordFilter},
+    git_export::GitExport,
+    hook::{Author, TurnHook},
+    i18n::Message,
+    log_buffer::Level,
+    metrics::ActorMetrics,
+    peer::{Peer, PeerEvent},
+    protocol::{self, PeerMessage, TurnPreference},
+    sessions::{self, format_duration, Event, Milestones, SessionInstance, Turn},
+    throttle::{Throttle, Verdict},
+    ui_actor::{UIMessage, UISink},
+};
+use chrono::Utc;
 use futures::future::OptionFuture;
 use std::{
+    collections::VecDeque,
     fmt::{Display, Formatter},
+    io,
     net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::AsyncWriteExt,
     net::{TcpListener, TcpStream},
     sync::{
         mpsc,
         mpsc::{Receiver, Sender},
     },
+    task::JoinHandle,
+    time::{sleep_until, timeout, Duration, Instant},
 };
+use tracing::{debug, error, info, trace, warn};
+
+/// How long to wait for the app to stop when quitting, which leaves the
+/// peer time to close its side of the connection.
+const SHUTDOWN: Duration = Duration::from_secs(5);
+
+/// How long connecting out can take before it's given up on, well short of
+/// the minutes the OS can take with an address that never answers.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Everything the app starts with that the user can configure.
+#[derive(Debug)]
+pub struct AppConfig {
+    /// Port to listen on for a peer, or None to only connect out.
+    pub listen_port: Option<u16>,
+    /// Address to listen on, such as 0.0.0.0 for every interface.
+    pub bind: IpAddr,
+    /// Who may connect to us.
+    pub access: AccessList,
+    pub save_dir: PathBuf,
+    /// Session to continue when a peer connects.
+    pub resume: Option<SessionInstance>,
+    /// How long writing to the peer may take before it counts as gone.
+    pub write_timeout: Duration,
+    /// Which turn to ask for in a fresh story, or None to leave it to chance.
+    pub turn_preference: Option<TurnPreference>,
+    /// Words the peer's sentences are screened for.
+    pub word_filter: Option<WordFilter>,
+    /// Command run for every sentence added to the story.
+    pub turn_hook: Option<TurnHook>,
+    /// Git repository every sentence added to the story is committed to.
+    pub git_export: Option<PathBuf>,
+    /// How long both players have to agree on writing another story once
+    /// one ends, before the connection is closed.
+    pub rematch_timeout: Duration,
+    /// Word counts to celebrate, or None to let them pass quietly.
+    pub milestones: Option<Milestones>,
+    /// What exports end with besides the milestones.
+    pub appendices: Appendices,
+    /// The least time to leave between one sentence and the next, unless
+    /// the peer asks for longer.
+    pub cooldown: Duration,
+    /// How long a connection can go with nothing happening before it's
+    /// closed, or None to keep it open however long.
+    pub idle_timeout: Option<Duration>,
+    /// Whether time either player is away doesn't count towards the idle
+    /// timeout.
+    pub idle_pause_away: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            listen_port: None,
+            bind: IpAddr::from([127, 0, 0, 1]),
+            access: AccessList::default(),
+            save_dir: sessions::default_save_dir(),
+            resume: None,
+            write_timeout: Duration::from_secs(15),
+            turn_preference: None,
+            word_filter: None,
+            turn_hook: None,
+            git_export: None,
+            rematch_timeout: Duration::from_secs(60),
+            milestones: Some(Milestones::default()),
+            appendices: Appendices::default(),
+            cooldown: Duration::ZERO,
+            idle_timeout: None,
+            idle_pause_away: false,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) enum AppInput {
     Connect(SocketAddr),
-    Input(String),
+    /// How connecting out to the address went, from the task doing it.
+    Connected(SocketAddr, io::Result<TcpStream>),
+    Input(String, Option<Constraint>),
+    Export(Format),
+    /// End the story, asking both players whether to write another.
+    EndStory,
+    /// Our answer to writing another story.
+    Rematch(bool),
+    ListSaved,
+    Resume(SessionInstance),
+    /// Whether we've stepped away, for the peer to know.
+    Away(bool),
+    /// Ask the peer to scrap the story and start again.
+    ProposeClear,
+    /// Our answer to the peer asking to scrap the story.
+    AnswerClear(bool),
+    /// What's in our scratchpad, to save with the session.
+    Notes(String),
+    /// Ask the peer to check that their story is the same as ours.
+    Verify,
+    /// Tell the peer we're still writing our turn, this many minutes in.
+    StillThinking(u64),
+    /// Our answer to taking the peer's story in place of ours, once they
+    /// were found to differ.
+    AnswerResync(bool),
+    /// Disconnect, and never let the peer's address connect again.
+    BlockPeer,
+    Disconnect,
+    /// Close the connection and stop.
+    Shutdown,
+    /// Something happened on the connection with the given id.
+    Peer(u64, PeerEvent),
 }
 
 impl Display for AppInput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             AppInput::Connect(_) => write!(f, "Connect"),
-            AppInput::Input(_) => write!(f, "Input"),
+            AppInput::Connected(..) => write!(f, "Connected"),
+            AppInput::Input(..) => write!(f, "Input"),
+            AppInput::Export(_) => write!(f, "Export"),
+            AppInput::EndStory => write!(f, "EndStory"),
+            AppInput::Rematch(_) => write!(f, "Rematch"),
+            AppInput::ListSaved => write!(f, "ListSaved"),
+            AppInput::Resume(_) => write!(f, "Resume"),
+            AppInput::Away(_) => write!(f, "Away"),
+            AppInput::ProposeClear => write!(f, "ProposeClear"),
+            AppInput::AnswerClear(_) => write!(f, "AnswerClear"),
+            AppInput::Notes(_) => write!(f, "Notes"),
+            AppInput::Verify => write!(f, "Verify"),
+            AppInput::StillThinking(_) => write!(f, "StillThinking"),
+            AppInput::AnswerResync(_) => write!(f, "AnswerResync"),
+            AppInput::BlockPeer => write!(f, "BlockPeer"),
+            AppInput::Disconnect => write!(f, "Disconnect"),
+            AppInput::Shutdown => write!(f, "Shutdown"),
+            AppInput::Peer(..) => write!(f, "Peer"),
         }
     }
 }
 
+#[derive(Debug)]
+struct Connection {
+    peer: Peer,
+    address: SocketAddr,
+    session: SessionInstance,
+    initiator: bool,
+    resumed: bool,
+    /// Our half of the coin flip for who writes first.
+    nonce: u64,
+    /// Whether both sides have agreed on the session, which until then
+    /// can't be written to.
+    ready: bool,
+    /// The answers to writing another story, once this one has ended.
+    rematch: Option<Rematch>,
+    /// Which turn the peer asked for in a fresh story.
+    their_preference: Option<TurnPreference>,
+    /// How far a proposal to scrap the story has got.
+    clear: Option<Clear>,
+    /// The least time between one sentence and the next, agreed on hello.
+    cooldown: Duration,
+    /// When the latest sentence was added to the story.
+    last_sentence: Option<Instant>,
+    /// Sentences, ours or the peer's, waiting for the cooldown to pass, in
+    /// the order they came.
+    held: VecDeque<Held>,
+    /// Whether a player asked for the story to be checked, and the peer has
+    /// yet to answer.
+    verifying: bool,
+    /// Set once our story and the peer's were found to differ, to whether
+    /// ours is the one kept.
+    diverged: Option<bool>,
+    /// Whether the peer is away.
+    their_away: bool,
+    /// How long nothing has happened for.
+    idle: IdleClock,
+}
+
+/// A sentence waiting for the cooldown to pass, or the peer's check of the
+/// story that has to wait for the sentences before it.
+#[derive(Debug)]
+enum Held {
+    /// Ours, with the constraint it sets.
+    Ours(String, Option<Constraint>),
+    /// The peer's, from the connection with the given id.
+    Theirs(u64, PeerMessage),
+}
+
+#[derive(Debug)]
+struct Rematch {
+    /// When not having agreed counts as a no.
+    deadline: Instant,
+    ours: Option<bool>,
+    theirs: Option<bool>,
+}
+
+impl Rematch {
+    fn agreed(&self) -> bool {
+        self.ours == Some(true) && self.theirs == Some(true)
+    }
+}
+
+/// A proposal to scrap the story and start again, which only happens once
+/// the proposer has the peer's yes and has sent the fresh session.
+#[derive(Debug)]
+enum Clear {
+    /// We asked, with our half of the coin flip.
+    Proposed(u64),
+    /// The peer asked, and is waiting for our answer.
+    Asked,
+    /// We said yes, and are waiting for the fresh session.
+    Accepted,
+}
+
+/// Who closed a connection.
+#[derive(Clone, Copy, Debug)]
+enum Closer {
+    Us,
+    Peer,
+    /// It was lost.
+    Neither,
+}
+
+impl Connection {
+    /// Notes in the story's timeline that whoever connected has, once both
+    /// sides agree on the session.
+    fn record_connection(&mut self) {
+        let ours = self.session.our_player();
+        let by = if self.initiator { ours } else { 3 - ours };
+        let event = if self.resumed {
+            Event::Resumed
+        } else {
+            Event::Connected
+        };
+        self.session.record(Some(by), event);
+    }
+
+    /// Exports the story before an idle connection is closed, so that it's
+    /// to hand for whoever comes back to it.
+    async fn export_before_closing(
+        &self,
+        appendices: Appendices,
+        ui_handle: &impl UISink,
+    ) -> Result<(), Error> {
+        if self.session.turns().is_empty() {
+            return Ok(());
+        }
+        match export(&self.session, Format::Markdown, appendices) {
+            Ok(path) => {
+                ui_handle
+                    .toast(Message::new("exported-story").arg("path", path))
+                    .await
+            }
+            // Closing the connection matters more, and the session is saved
+            Err(err) => {
+                ui_handle
+                    .error(Message::new("error").arg("error", &err))
+                    .await
+            }
+        }
+    }
+
+    /// When the next sentence may be added to the story.
+    fn cooled_down_at(&self) -> Option<Instant> {
+        self.last_sentence
+            .map(|last| last + self.cooldown)
+            .filter(|_| !self.cooldown.is_zero())
+    }
+
+    /// Tells the player that our story and the peer's, which has
+    /// `their_turns` turns, differ, unless they've been told already. The
+    /// story with more turns is kept, or the connecting side's when they
+    /// have as many.
+    async fn diverge(&mut self, their_turns: usize, ui_handle: &impl UISink) -> Result<(), Error> {
+        if self.diverged.is_some() {
+            return Ok(());
+        }
+        let ours = self.session.turns().len();
+        let kept = ours > their_turns || (ours == their_turns && self.initiator);
+        warn!(ours, theirs = their_turns, kept, "the stories differ");
+        self.diverged = Some(kept);
+        ui_handle.diverged(kept).await
+    }
+
+    /// Ends the story, unless it already has been.
+    async fn end_story(&mut self, timeout: Duration, ui_handle: &impl UISink) -> Result<(), Error> {
+        if self.rematch.is_none() {
+            self.rematch = Some(Rematch {
+                deadline: Instant::now() + timeout,
+                ours: None,
+                theirs: None,
+            });
+            ui_handle.story_ended().await?;
+        }
+        Ok(())
+    }
+
+    /// Starts the next story once both players want one. The connecting side
+    /// starts it, so that both sides end up with the same session.
+    async fn settle_rematch(
+        &mut self,
+        save_dir: &Path,
+        ui_handle: &impl UISink,
+    ) -> Result<(), Error> {
+        if self.rematch.as_ref().is_some_and(Rematch::agreed) && self.initiator {
+            self.start_new_story(save_dir, ui_handle).await?;
+        }
+        Ok(())
+    }
+
+    /// Starts a fresh story with the same peer, letting whoever did not start
+    /// the previous one write first.
+    async fn start_new_story(
+        &mut self,
+        save_dir: &Path,
+        ui_handle: &impl UISink,
+    ) -> Result<(), Error> {
+        let session = SessionInstance::new(!self.session.we_started());
+        let message = PeerMessage::NewStory {
+            session: session.shared(),
+        };
+        self.peer.send(&message)?;
+        self.replace_session(session, save_dir, ui_handle).await
+    }
+
+    /// Scraps the story we proposed clearing once the peer agrees, settling
+    /// who writes first in the fresh one as when connecting.
+    async fn clear_story(
+        &mut self,
+        their_nonce: u64,
+        preference: Option<TurnPreference>,
+        appendices: Appendices,
+        save_dir: &Path,
+        ui_handle: &impl UISink,
+    ) -> Result<(), Error> {
+        let our_nonce = match self.clear {
+            Some(Clear::Proposed(nonce)) => nonce,
+            _ => return Ok(()),
+        };
+        let we_first = protocol::we_write_first(
+            preference,
+            self.their_preference,
+            our_nonce,
+            their_nonce,
+            self.initiator,
+        );
+        let session = SessionInstance::new(we_first);
+        self.peer.send(&PeerMessage::Cleared {
+            session: session.shared(),
+        })?;
+        self.export_and_replace(session, appendices, save_dir, ui_handle)
+            .await
+    }
+
+    /// Exports the story before it's cleared, so nothing is lost, and
+    /// continues with `session`.
+    async fn export_and_replace(
+        &mut self,
+        session: SessionInstance,
+        appendices: Appendices,
+        save_dir: &Path,
+        ui_handle: &impl UISink,
+    ) -> Result<(), Error> {
+        if !self.session.turns().is_empty() {
+            let path = export(&self.session, Format::Markdown, appendices)?;
+            ui_handle
+                .toast(Message::new("story-cleared").arg("path", path))
+                .await?;
+        }
+        self.replace_session(session, save_dir, ui_handle).await
+    }
+
+    /// Archives the current story and continues with `session`.
+    async fn replace_session(
+        &mut self,
+        session: SessionInstance,
+        save_dir: &Path,
+        ui_handle: &impl UISink,
+    ) -> Result<(), Error> {
+        let old = std::mem::replace(&mut self.session, session);
+        self.resumed = false;
+        self.rematch = None;
+        self.clear = None;
+        self.verifying = false;
+        self.diverged = None;
+        if !old.turns().is_empty() {
+            let path = save(&old, save_dir)?;
+            ui_handle
+                .log(
+                    Message::new("archived-story")
+                        .arg("id", &old.id)
+                        .arg("path", path.display()),
+                )
+                .await?;
+        }
+        ui_handle.new_story(self.session.clone()).await?;
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 enum State {
     Waiting,
-    Connected(TcpStream),
+    Connected(Box<Connection>),
 }
 
 #[derive(Debug)]
-struct App {
-    ui_handle: UIHandle,
+struct App<U> {
+    ui_handle: U,
     state: State,
-    listen_port: u16,
+    /// None when only connecting out.
+    listen_port: Option<u16>,
+    bind: IpAddr,
+    access: AccessList,
+    save_dir: PathBuf,
+    resume: Option<SessionInstance>,
+    write_timeout: Duration,
+    turn_preference: Option<TurnPreference>,
+    word_filter: Option<WordFilter>,
+    turn_hook: Option<TurnHook>,
+    git_export: Option<GitExport>,
+    rematch_timeout: Duration,
+    milestones: Option<Milestones>,
+    appendices: Appendices,
+    cooldown: Duration,
+    idle_timeout: Option<Duration>,
+    idle_pause_away: bool,
+    /// Whether we're away, told to every peer that connects.
+    away: bool,
+    /// Times our turns.
+    stopwatch: Stopwatch,
+    /// Keeps what comes of connections being made to us from flooding the Log.
+    throttle: Throttle,
+    /// Where each connection's tasks report to.
+    peer_events: Sender<(u64, PeerEvent)>,
+    /// Where tasks the app hands work to, such as connecting out, report to.
+    follow_ups: Sender<AppInput>,
+    /// Where we're connecting out to, until it's known how that went.
+    connecting: Option<SocketAddr>,
+    /// Ids handed out to connections, so events from old ones can be told apart.
+    connections: u64,
+    metrics: Arc<ActorMetrics>,
 }
 
-impl App {
-    fn new(ui_handle: UIHandle, listen_port: u16) -> Self {
+impl<U: UISink> App<U> {
+    fn new(
+        ui_handle: U,
+        config: AppConfig,
+        peer_events: Sender<(u64, PeerEvent)>,
+        follow_ups: Sender<AppInput>,
+    ) -> Self {
+        let git_export = config
+            .git_export
+            .map(|repo| GitExport::spawn(repo, ui_handle.clone()));
         Self {
             ui_handle,
             state: State::Waiting,
-            listen_port,
+            listen_port: config.listen_port,
+            bind: config.bind,
+            access: config.access,
+            save_dir: config.save_dir,
+            resume: config.resume,
+            write_timeout: config.write_timeout,
+            turn_preference: config.turn_preference,
+            word_filter: config.word_filter,
+            turn_hook: config.turn_hook,
+            git_export,
+            rematch_timeout: config.rematch_timeout,
+            milestones: config.milestones,
+            appendices: config.appendices,
+            cooldown: config.cooldown,
+            idle_timeout: config.idle_timeout,
+            idle_pause_away: config.idle_pause_away,
+            away: false,
+            stopwatch: Stopwatch::default(),
+            throttle: Throttle::new(NOISY_PER_MINUTE, Duration::from_secs(60)),
+            peer_events,
+            follow_ups,
+            connecting: None,
+            connections: 0,
+            metrics: Arc::default(),
         }
     }
 
+    /// Handles `msg`, counting it and how long it took for the metrics
+    /// overlay.
+    async fn handle_timed(&mut self, msg: AppInput) -> Result<(), Error> {
+        let started = Instant::now();
+        let result = self.handle_message(msg).await;
+        self.metrics.handled(started.elapsed());
+        result
+    }
+
+    /// Handles `msg`, unless it's a sentence that has to wait for the
+    /// cooldown, which is then handled once it's over.
     async fn handle_message(&mut self, msg: AppInput) -> Result<(), Error> {
+        let connection = match &mut self.state {
+            State::Connected(connection) => connection,
+            State::Waiting => return self.handle(msg).await,
+        };
+        let now = Instant::now();
+        let ready = connection.cooled_down_at().filter(|ready| *ready > now);
+        if ready.is_none() && connection.held.is_empty() {
+            return self.handle(msg).await;
+        }
+        let held = match msg {
+            AppInput::Input(text, next_constraint) => Held::Ours(text, next_constraint),
+            AppInput::Peer(id, PeerEvent::Message(message @ PeerMessage::Sentence { .. })) => {
+                Held::Theirs(id, message)
+            }
+            // Checked against the story once the sentences before it are in
+            AppInput::Peer(id, PeerEvent::Message(message @ PeerMessage::Digest { .. }))
+                if !connection.held.is_empty() =>
+            {
+                Held::Theirs(id, message)
+            }
+            msg => return self.handle(msg).await,
+        };
+        debug!(?held, "held for the cooldown");
+        let ours = matches!(held, Held::Ours(..));
+        connection.held.push_back(held);
+        if let (true, Some(ready)) = (ours, ready) {
+            self.ui_handle.cooldown(ready - now).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: AppInput) -> Result<(), Error> {
+        debug!(input = %msg, "handling app input");
         match msg {
             AppInput::Connect(address) => match self.state {
-                State::Waiting => {
+                State::Waiting if self.connecting.is_none() => {
                     self.ui_handle
-                        .log(format!("Attempting to connect to {:?}", address))
+                        .log(Message::new("attempting-connect").arg("address", address))
                         .await?;
-                    let socket = TcpStream::connect(address).await?;
-                    self.state = State::Connected(socket);
-                    self.ui_handle.connected(true).await?;
+                    info!(%address, "connecting");
+                    self.ui_handle.connecting(address).await?;
+                    self.connecting = Some(address);
+                    // On its own task, so that nothing else waits on an
+                    // address that doesn't answer
+                    let follow_ups = self.follow_ups.clone();
+                    tokio::spawn(async move {
+                        let connected = timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+                            .await
+                            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()));
+                        let _ = follow_ups
+                            .send(AppInput::Connected(address, connected))
+                            .await;
+                    });
+                }
+                _ => {}
+            },
+            AppInput::Connected(address, connected) => {
+                self.connecting = None;
+                match (&self.state, connected) {
+                    (State::Waiting, Ok(socket)) => {
+                        self.start_session(socket, true).await?;
+                        self.ui_handle
+                            .log(Message::new("connected-to").arg("address", address))
+                            .await?;
+                    }
+                    (State::Waiting, Err(err)) => {
+                        warn!(%address, %err, "connect failed");
+                        self.ui_handle
+                            .connect_failed(address, error::reason(&err))
+                            .await?;
+                    }
+                    // Someone connected to us meanwhile
+                    (State::Connected(_), _) => {
+                        debug!(%address, "connected out after a peer connected in, dropping it");
+                    }
+                }
+            }
+            AppInput::Input(input, next_constraint) => match &mut self.state {
+                State::Connected(connection)
+                    if connection.ready
+                        && connection.session.is_our_turn()
+                        && !matches!(connection.clear, Some(Clear::Accepted)) =>
+                {
+                    let turn = Turn {
+                        text: input,
+                        timestamp: Utc::now(),
+                        next_constraint,
+                        writing_secs: self
+                            .stopwatch
+                            .elapsed(Instant::now())
+                            .map(|writing| writing.as_secs()),
+                    };
+                    let message = PeerMessage::Sentence {
+                        index: connection.session.turns().len(),
+                        turn: turn.clone(),
+                    };
+                    connection.peer.send(&message)?;
+                    connection.session.push(turn.clone());
+                    connection.last_sentence = Some(Instant::now());
+                    connection.idle.restart(Instant::now());
+                    let milestone = self
+                        .milestones
+                        .and_then(|milestones| connection.session.reach_milestones(&milestones));
+                    // The peer has the sentence now, so the rest follows even if it isn't saved
+                    save_reporting(&connection.session, &self.save_dir, &self.ui_handle).await?;
+                    if let Some(hook) = &self.turn_hook {
+                        let number = connection.session.turns().len();
+                        hook.run(
+                            &turn,
+                            Author::Me,
+                            number,
+                            &connection.session.id,
+                            &self.ui_handle,
+                        );
+                    }
+                    if let Some(git_export) = &self.git_export {
+                        let index = connection.session.turns().len() - 1;
+                        git_export.commit(&turn, export::author(&connection.session, index));
+                    }
+                    self.ui_handle.sentence_accepted(turn).await?;
+                    if let Some(milestone) = milestone {
+                        self.ui_handle.milestone(milestone).await?;
+                    }
+                }
+                _ => {
                     self.ui_handle
-                        .log(format!("Connected to remote {:?}", address))
+                        .error(Message::new("unexpected-input"))
                         .await?;
                 }
-                State::Connected(_) => {}
             },
-            AppInput::Input(input) => match &mut self.state {
-                State::Waiting => {
+            AppInput::Export(format) => {
+                if let State::Connected(connection) = &self.state {
+                    let path = export(&connection.session, format, self.appendices)?;
                     self.ui_handle
-                        .log("ERROR: Unexpected input".to_string())
+                        .toast(Message::new("exported-story").arg("path", path))
                         .await?;
                 }
-                State::Connected(stream) => {
-                    stream.write_all(input.as_bytes()).await?;
+            }
+            AppInput::EndStory => match &mut self.state {
+                State::Connected(connection)
+                    if connection.ready && connection.rematch.is_none() =>
+                {
+                    connection.peer.send(&PeerMessage::StoryEnded)?;
+                    connection
+                        .end_story(self.rematch_timeout, &self.ui_handle)
+                        .await?;
+                }
+                _ => {}
+            },
+            AppInput::Rematch(accept) => {
+                if let State::Connected(connection) = &mut self.state {
+                    match &mut connection.rematch {
+                        Some(rematch) if rematch.ours.is_none() => rematch.ours = Some(accept),
+                        _ => return Ok(()),
+                    }
+                    connection.peer.send(&PeerMessage::Rematch { accept })?;
+                    if accept {
+                        connection
+                            .settle_rematch(&self.save_dir, &self.ui_handle)
+                            .await?;
+                    } else {
+                        self.ui_handle.log(Message::new("said-goodbye")).await?;
+                        self.disconnect(Closer::Us).await?;
+                    }
                 }
+            }
+            AppInput::ProposeClear => match &mut self.state {
+                State::Connected(connection)
+                    if connection.ready
+                        && connection.rematch.is_none()
+                        && connection.clear.is_none() =>
+                {
+                    let nonce = rand::random();
+                    connection.peer.send(&PeerMessage::ProposeClear { nonce })?;
+                    connection.clear = Some(Clear::Proposed(nonce));
+                    self.ui_handle.log(Message::new("proposed-clear")).await?;
+                }
+                _ => {}
+            },
+            AppInput::AnswerClear(accept) => {
+                if let State::Connected(connection) = &mut self.state {
+                    if matches!(connection.clear, Some(Clear::Asked)) {
+                        connection.peer.send(&PeerMessage::ClearAnswer {
+                            accept,
+                            nonce: rand::random(),
+                        })?;
+                        connection.clear = if accept { Some(Clear::Accepted) } else { None };
+                    }
+                }
+            }
+            AppInput::Notes(notes) => {
+                if let State::Connected(connection) = &mut self.state {
+                    connection.session.set_notes(notes);
+                    save(&connection.session, &self.save_dir)?;
+                }
+            }
+            AppInput::Verify => match &mut self.state {
+                State::Connected(connection) if connection.ready => {
+                    let turns = connection.session.turns().len();
+                    let digest = connection.session.digest(turns).expect("all the turns");
+                    connection.peer.send(&PeerMessage::Digest { digest })?;
+                    connection.verifying = true;
+                    self.ui_handle.log(Message::new("verify-sent")).await?;
+                }
+                _ => {}
+            },
+            AppInput::StillThinking(minutes) => match &self.state {
+                State::Connected(connection) if connection.ready => {
+                    connection
+                        .peer
+                        .send(&PeerMessage::StillThinking { minutes })?;
+                }
+                _ => {}
+            },
+            AppInput::AnswerResync(accept) => {
+                if let State::Connected(connection) = &mut self.state {
+                    if connection.diverged == Some(false) {
+                        if accept {
+                            connection.peer.send(&PeerMessage::RequestResync)?;
+                            self.ui_handle.log(Message::new("resync-requested")).await?;
+                        } else {
+                            // Checked again later, in case it was a fluke
+                            connection.diverged = None;
+                        }
+                    }
+                }
+            }
+            AppInput::ListSaved => {
+                let save_dir = self.save_dir.clone();
+                let ui_handle = self.ui_handle.clone();
+                tokio::spawn(async move {
+                    let scan = tokio::task::spawn_blocking(move || sessions::scan_saved(&save_dir));
+                    if let Ok((saved, unreadable)) = scan.await {
+                        for problem in unreadable {
+                            ui_handle
+                                .warn(Message::new("skipped-session").arg("problem", problem))
+                                .await?;
+                        }
+                        ui_handle.saved_sessions(saved).await?;
+                    }
+                    Ok::<(), Error>(())
+                });
+            }
+            AppInput::Resume(session) => {
+                self.ui_handle
+                    .log(Message::new("will-resume").arg("id", &session.id))
+                    .await?;
+                self.resume = Some(session);
+            }
+            AppInput::Away(away) => {
+                self.away = away;
+                self.stopwatch.set_away(away, Instant::now());
+                if let State::Connected(connection) = &mut self.state {
+                    connection.peer.send(&PeerMessage::Status { away })?;
+                    let paused = self.idle_pause_away && (away || connection.their_away);
+                    connection.idle.set_paused(paused, Instant::now());
+                }
+            }
+            AppInput::BlockPeer => {
+                if let State::Connected(connection) = &self.state {
+                    let address = connection.address.ip();
+                    info!(%address, "blocking peer");
+                    if let Err(err) = self.access.block(address) {
+                        warn!(%err, "couldn't save the blocklist");
+                        self.ui_handle
+                            .warn(
+                                Message::new("blocklist-unsaved")
+                                    .arg_message("reason", error::reason(&err)),
+                            )
+                            .await?;
+                    }
+                    self.ui_handle
+                        .log(Message::new("blocked-peer").arg("address", address))
+                        .await?;
+                    self.disconnect(Closer::Us).await?;
+                }
+            }
+            // Handled by the run loop, which stops
+            AppInput::Shutdown => {}
+            AppInput::Disconnect => self.disconnect(Closer::Us).await?,
+            AppInput::Peer(id, event) => match &self.state {
+                State::Connected(connection) if connection.peer.id == id => match event {
+                    PeerEvent::Message(message) => self.handle_peer_message(message).await?,
+                    PeerEvent::Closed => self.disconnect(Closer::Peer).await?,
+                    PeerEvent::Failed(err) => return Err(err),
+                },
+                // Left over from a connection that has already been closed
+                _ => trace!(id, "ignoring event from an old connection"),
             },
         }
         Ok(())
     }
 
-    async fn process_data(&mut self, result: usize, buf: Vec<u8>) -> Result<(), Error> {
-        if result > 0 {
+    async fn start_session(&mut self, stream: TcpStream, initiator: bool) -> Result<(), Error> {
+        let resumed = self.resume.is_some();
+        let session = self
+            .resume
+            .take()
+            .unwrap_or_else(|| SessionInstance::new(initiator));
+        info!(session = %session.id, initiator, resumed, "session started");
+        let address = stream.peer_addr().map_err(Error::PeerIo)?;
+        self.connections += 1;
+        let peer = Peer::spawn(
+            stream,
+            self.connections,
+            self.peer_events.clone(),
+            self.write_timeout,
+        );
+        // The session is announced once both sides know who writes first
+        let nonce = rand::random();
+        peer.send(&PeerMessage::Hello {
+            nonce,
+            preference: self.turn_preference,
+            cooldown_secs: self.cooldown.as_secs(),
+            compression: true,
+        })?;
+        if self.away {
+            peer.send(&PeerMessage::Status { away: true })?;
+        }
+        let mut idle = IdleClock::new(Instant::now());
+        idle.set_paused(self.idle_pause_away && self.away, Instant::now());
+        self.state = State::Connected(Box::new(Connection {
+            peer,
+            address,
+            session,
+            initiator,
+            resumed,
+            nonce,
+            ready: false,
+            rematch: None,
+            their_preference: None,
+            clear: None,
+            cooldown: self.cooldown,
+            last_sentence: None,
+            held: VecDeque::new(),
+            verifying: false,
+            diverged: None,
+            their_away: false,
+            idle,
+        }));
+        Ok(())
+    }
+
+    async fn handle_peer_message(&mut self, message: PeerMessage) -> Result<(), Error> {
+        debug!(?message, "peer message");
+        // Set when the peer said goodbye
+        let mut hang_up = false;
+        if let State::Connected(connection) = &mut self.state {
+            // Whatever the peer's app sends by itself doesn't count
+            if !matches!(
+                message,
+                PeerMessage::Status { .. }
+                    | PeerMessage::StillThinking { .. }
+                    | PeerMessage::IdleWarning { .. }
+                    | PeerMessage::IdleTimeout
+            ) {
+                connection.idle.restart(Instant::now());
+            }
+            match message {
+                PeerMessage::Sentence { index, turn }
+                    if index < connection.session.turns().len() =>
+                {
+                    // Both sides wrote this turn. Each side resolves the conflict the
+                    // same way: the sentence of whoever's turn it canonically was wins.
+                    let ours = &connection.session.turns()[index];
+                    if connection.session.is_ours(index) {
+                        self.ui_handle
+                            .warn(
+                                Message::new("conflict-kept-ours")
+                                    .arg("turn", index + 1)
+                                    .arg("ours", &ours.text)
+                                    .arg("theirs", &turn.text),
+                            )
+                            .await?;
+                    } else {
+                        // Too late to turn it down, as it's already displacing ours
+                        let mut turn = turn;
+                        if let Some(filter) = &self.word_filter {
+                            turn.text = filter.mask(&turn.text);
+                        }
+                        let bounced = connection.session.replace_from(index, turn.clone());
+                        for (undone, turn) in (index..).zip(&bounced) {
+                            let by = connection.session.player(undone);
+                            let text = turn.text.clone();
+                            connection.session.record(by, Event::Undone { text });
+                        }
+                        connection.last_sentence = Some(Instant::now());
+                        let milestone = self.milestones.and_then(|milestones| {
+                            connection.session.reach_milestones(&milestones)
+                        });
+                        save(&connection.session, &self.save_dir)?;
+                        self.ui_handle
+                            .sentence_bounced(index, turn, bounced)
+                            .await?;
+                        if let Some(milestone) = milestone {
+                            self.ui_handle.milestone(milestone).await?;
+                        }
+                    }
+                }
+                PeerMessage::Sentence { index, mut turn } => {
+                    if index > connection.session.turns().len() {
+                        self.ui_handle
+                            .warn(Message::new("peer-skipped").arg("turn", index + 1))
+                            .await?;
+                    }
+                    match &self.word_filter {
+                        Some(filter) if filter.find(&turn.text).is_empty() => {}
+                        Some(filter) if filter.incoming == Incoming::Mask => {
+                            turn.text = filter.mask(&turn.text);
+                        }
+                        Some(_) => {
+                            connection
+                                .peer
+                                .send(&PeerMessage::SentenceRefused { index })?;
+                            self.ui_handle.log(Message::new("turned-down-peer")).await?;
+                            return Ok(());
+                        }
+                        None => {}
+                    }
+                    if let Some(Err(reason)) = connection
+                        .session
+                        .current_constraint()
+                        .map(|constraint| constraint.check(&turn.text))
+                    {
+                        self.ui_handle
+                            .warn(
+                                Message::new("peer-ignored-constraint")
+                                    .arg_message("reason", reason),
+                            )
+                            .await?;
+                    }
+                    connection.session.push(turn.clone());
+                    connection.last_sentence = Some(Instant::now());
+                    let milestone = self
+                        .milestones
+                        .and_then(|milestones| connection.session.reach_milestones(&milestones));
+                    save_reporting(&connection.session, &self.save_dir, &self.ui_handle).await?;
+                    if let Some(hook) = &self.turn_hook {
+                        let number = connection.session.turns().len();
+                        hook.run(
+                            &turn,
+                            Author::Peer,
+                            number,
+                            &connection.session.id,
+                            &self.ui_handle,
+                        );
+                    }
+                    if let Some(git_export) = &self.git_export {
+                        let index = connection.session.turns().len() - 1;
+                        git_export.commit(&turn, export::author(&connection.session, index));
+                    }
+                    self.ui_handle.sentence_received(turn).await?;
+                    if let Some(milestone) = milestone {
+                        self.ui_handle.milestone(milestone).await?;
+                    }
+                    // The peer has every sentence before it, so both sides can
+                    // check they agree on the story so far
+                    let turns = connection.session.turns().len();
+                    if turns % VERIFY_EVERY == 0 {
+                        let digest = connection.session.digest(turns).expect("all the turns");
+                        connection.peer.send(&PeerMessage::Digest { digest })?;
+                    }
+                }
+                PeerMessage::SentenceRefused { index } => {
+                    let latest = connection.session.turns().len().checked_sub(1);
+                    if latest == Some(index) && connection.session.is_ours(index) {
+                        if let Some(turn) = connection.session.pop() {
+                            let by = connection.session.player(index);
+                            connection.session.record(
+                                by,
+                                Event::Undone {
+                                    text: turn.text.clone(),
+                                },
+                            );
+                            save_reporting(&connection.session, &self.save_dir, &self.ui_handle)
+                                .await?;
+                            self.ui_handle.sentence_refused(index, turn).await?;
+                        }
+                    }
+                }
+                PeerMessage::Status { away } => {
+                    connection.their_away = away;
+                    let paused = self.idle_pause_away && (self.away || away);
+                    connection.idle.set_paused(paused, Instant::now());
+                    self.ui_handle.peer_away(away).await?
+                }
+                PeerMessage::IdleWarning { secs_left } => {
+                    let left = format_duration(chrono::Duration::seconds(secs_left as i64));
+                    self.ui_handle
+                        .toast(Message::new("peer-idle-warning").arg("left", left))
+                        .await?
+                }
+                PeerMessage::IdleTimeout => {
+                    self.ui_handle
+                        .log(Message::new("peer-idle-timeout"))
+                        .await?;
+                    connection
+                        .export_before_closing(self.appendices, &self.ui_handle)
+                        .await?;
+                }
+                PeerMessage::StillThinking { minutes } => {
+                    self.ui_handle
+                        .toast(Message::new("peer-still-thinking").arg("minutes", minutes))
+                        .await?
+                }
+                PeerMessage::Hello {
+                    nonce,
+                    preference,
+                    cooldown_secs,
+                    compression,
+                } => {
+                    connection.peer.set_compression(compression);
+                    connection.their_preference = preference;
+                    connection.cooldown = self.cooldown.max(Duration::from_secs(cooldown_secs));
+                    let we_first = protocol::we_write_first(
+                        self.turn_preference,
+                        preference,
+                        connection.nonce,
+                        nonce,
+                        connection.initiator,
+                    );
+                    if preference.is_some() && preference == self.turn_preference {
+                        self.ui_handle.log(Message::new("coin-flip")).await?;
+                    }
+                    if !connection.resumed {
+                        // The connecting side's session is adopted by the other
+                        // side, which checks it agrees on who writes first
+                        connection.session = SessionInstance::new(we_first);
+                    }
+                    if connection.initiator {
+                        connection.peer.send(&PeerMessage::Session {
+                            session: connection.session.shared(),
+                            resumed: connection.resumed,
+                        })?;
+                        connection.ready = true;
+                        connection.record_connection();
+                        self.ui_handle
+                            .connected(connection.session.clone(), connection.address)
+                            .await?;
+                    }
+                }
+                PeerMessage::Session { session, resumed } => {
+                    let adopt = if connection.initiator {
+                        !connection.resumed
+                    } else {
+                        resumed || !connection.resumed
+                    };
+                    let agreed_first = connection.session.we_started();
+                    connection.ready = true;
+                    if adopt {
+                        let mut session = session.for_peer();
+                        // Both sides resuming the same story keep their own notes and timeline
+                        if session.id == connection.session.id {
+                            session.set_notes(connection.session.notes().to_string());
+                            session.keep_events(&connection.session);
+                        }
+                        connection.session = session;
+                        if !resumed && connection.session.we_started() != agreed_first {
+                            self.ui_handle
+                                .warn(Message::new("first-turn-disagreement"))
+                                .await?;
+                        }
+                        connection.resumed = resumed;
+                        connection.record_connection();
+                        self.ui_handle
+                            .connected(connection.session.clone(), connection.address)
+                            .await?;
+                        if resumed {
+                            self.ui_handle
+                                .log(
+                                    Message::new("resumed-session")
+                                        .arg("id", &connection.session.id),
+                                )
+                                .await?;
+                        }
+                    } else {
+                        // We are resuming and the connecting side is not, so our
+                        // session wins
+                        let message = PeerMessage::Session {
+                            session: connection.session.shared(),
+                            resumed: true,
+                        };
+                        connection.peer.send(&message)?;
+                        connection.record_connection();
+                        self.ui_handle
+                            .connected(connection.session.clone(), connection.address)
+                            .await?;
+                        self.ui_handle
+                            .log(Message::new("resumed-session").arg("id", &connection.session.id))
+                            .await?;
+                    }
+                }
+                PeerMessage::StoryEnded => {
+                    connection
+                        .end_story(self.rematch_timeout, &self.ui_handle)
+                        .await?;
+                }
+                PeerMessage::Rematch { accept } => {
+                    if let Some(rematch) = &mut connection.rematch {
+                        rematch.theirs = Some(accept);
+                    }
+                    if accept {
+                        connection
+                            .settle_rematch(&self.save_dir, &self.ui_handle)
+                            .await?;
+                    } else {
+                        self.ui_handle
+                            .log(Message::new("peer-declined-rematch"))
+                            .await?;
+                        hang_up = true;
+                    }
+                }
+                PeerMessage::NewStory { session } => {
+                    connection
+                        .replace_session(session.for_peer(), &self.save_dir, &self.ui_handle)
+                        .await?;
+                }
+                PeerMessage::ProposeClear { nonce } => match connection.clear {
+                    // Both asked at once, which the connecting side takes as a
+                    // yes to its own proposal, and the other side as its answer
+                    Some(Clear::Proposed(_)) if connection.initiator => {
+                        connection
+                            .clear_story(
+                                nonce,
+                                self.turn_preference,
+                                self.appendices,
+                                &self.save_dir,
+                                &self.ui_handle,
+                            )
+                            .await?;
+                    }
+                    Some(Clear::Proposed(_)) => connection.clear = Some(Clear::Accepted),
+                    None if connection.ready && connection.rematch.is_none() => {
+                        connection.clear = Some(Clear::Asked);
+                        self.ui_handle.clear_proposed().await?;
+                    }
+                    _ => connection.peer.send(&PeerMessage::ClearAnswer {
+                        accept: false,
+                        nonce: rand::random(),
+                    })?,
+                },
+                PeerMessage::ClearAnswer { accept, nonce } => {
+                    if matches!(connection.clear, Some(Clear::Proposed(_))) {
+                        if accept {
+                            connection
+                                .clear_story(
+                                    nonce,
+                                    self.turn_preference,
+                                    self.appendices,
+                                    &self.save_dir,
+                                    &self.ui_handle,
+                                )
+                                .await?;
+                        } else {
+                            connection.clear = None;
+                            self.ui_handle
+                                .toast(Message::new("peer-declined-clear"))
+                                .await?;
+                        }
+                    }
+                }
+                PeerMessage::Digest { digest } => {
+                    let matched = connection.session.agrees_with(digest);
+                    let turns = connection.session.turns().len();
+                    connection
+                        .peer
+                        .send(&PeerMessage::Checked { matched, turns })?;
+                    if !matched {
+                        connection.diverge(digest.turns, &self.ui_handle).await?;
+                    }
+                }
+                PeerMessage::Checked { matched, turns } => {
+                    let asked = std::mem::take(&mut connection.verifying);
+                    if !matched {
+                        connection.diverge(turns, &self.ui_handle).await?;
+                    } else if asked {
+                        self.ui_handle.log(Message::new("story-verified")).await?;
+                    }
+                }
+                PeerMessage::RequestResync => {
+                    if connection.diverged == Some(true) {
+                        connection.peer.send(&PeerMessage::Resync {
+                            session: connection.session.shared(),
+                        })?;
+                        connection.diverged = None;
+                        self.ui_handle.log(Message::new("resync-sent")).await?;
+                    } else {
+                        warn!("peer asked for our story without it differing");
+                    }
+                }
+                PeerMessage::Resync { session } => {
+                    if connection.diverged == Some(false) {
+                        let mut session = session.for_peer();
+                        if session.id == connection.session.id {
+                            session.set_notes(connection.session.notes().to_string());
+                            session.keep_events(&connection.session);
+                        }
+                        connection.session = session;
+                        connection.diverged = None;
+                        save(&connection.session, &self.save_dir)?;
+                        self.ui_handle.resynced(connection.session.clone()).await?;
+                    } else {
+                        warn!("peer sent its story without our asking");
+                    }
+                }
+                PeerMessage::Cleared { session } => {
+                    if matches!(connection.clear, Some(Clear::Accepted)) {
+                        connection
+                            .export_and_replace(
+                                session.for_peer(),
+                                self.appendices,
+                                &self.save_dir,
+                                &self.ui_handle,
+                            )
+                            .await?;
+                    } else {
+                        warn!("peer cleared the story without our agreement");
+                    }
+                }
+            }
+        }
+        if hang_up {
+            self.disconnect(Closer::Us).await?;
+        }
+        Ok(())
+    }
+
+    /// When the players stop having a chance to agree on another story.
+    fn rematch_deadline(&self) -> Option<Instant> {
+        match &self.state {
+            State::Connected(connection) => connection
+                .rematch
+                .as_ref()
+                .filter(|rematch| !rematch.agreed())
+                .map(|rematch| rematch.deadline),
+            State::Waiting => None,
+        }
+    }
+
+    /// When to warn about or close an idle connection, if there's a limit.
+    fn idle_deadline(&self) -> Option<Instant> {
+        match &self.state {
+            State::Connected(connection) if connection.ready => {
+                connection.idle.deadline(self.idle_timeout?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Warns both players once nothing has happened for most of the idle
+    /// timeout, then closes the connection if nothing does for the rest.
+    async fn idle_timed_out(&mut self) -> Result<(), Error> {
+        let (timeout, connection) = match (self.idle_timeout, &mut self.state) {
+            (Some(timeout), State::Connected(connection)) => (timeout, connection),
+            _ => return Ok(()),
+        };
+        if !connection.idle.warned {
+            connection.idle.warned = true;
+            let left = timeout - idle_warning(timeout);
+            connection.peer.send(&PeerMessage::IdleWarning {
+                secs_left: left.as_secs(),
+            })?;
+            let left = format_duration(chrono::Duration::seconds(left.as_secs() as i64));
+            return self
+                .ui_handle
+                .toast(Message::new("idle-warning").arg("left", left))
+                .await;
+        }
+        info!(?timeout, "closing idle connection");
+        connection.peer.send(&PeerMessage::IdleTimeout)?;
+        self.ui_handle.log(Message::new("idle-timeout")).await?;
+        connection
+            .export_before_closing(self.appendices, &self.ui_handle)
+            .await?;
+        self.disconnect(Closer::Us).await
+    }
+
+    /// When the first sentence held for the cooldown can be handled.
+    fn cooldown_deadline(&self) -> Option<Instant> {
+        match &self.state {
+            State::Connected(connection) if !connection.held.is_empty() => {
+                Some(connection.cooled_down_at().unwrap_or_else(Instant::now))
+            }
+            _ => None,
+        }
+    }
+
+    /// Handles the first sentence held for the cooldown.
+    async fn release_held(&mut self) -> Result<(), Error> {
+        let held = match &mut self.state {
+            State::Connected(connection) => connection.held.pop_front(),
+            State::Waiting => None,
+        };
+        match held {
+            Some(Held::Ours(text, next_constraint)) => {
+                self.handle(AppInput::Input(text, next_constraint)).await
+            }
+            Some(Held::Theirs(id, message)) => {
+                self.handle(AppInput::Peer(id, PeerEvent::Message(message)))
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes not agreeing in time on another story as a no.
+    async fn rematch_timed_out(&mut self) -> Result<(), Error> {
+        if let State::Connected(connection) = &self.state {
+            if connection
+                .rematch
+                .as_ref()
+                .is_some_and(|rematch| rematch.ours != Some(false))
+            {
+                connection
+                    .peer
+                    .send(&PeerMessage::Rematch { accept: false })?;
+            }
+        }
+        self.ui_handle
+            .log(Message::new("rematch-timed-out"))
+            .await?;
+        self.disconnect(Closer::Us).await
+    }
+
+    /// Reports an error that the app can carry on after, dropping the peer
+    /// if the connection can't be trusted any more. Fatal errors are passed on.
+    async fn recover(&mut self, err: Error) -> Result<(), Error> {
+        if err.is_fatal() {
+            return Err(err);
+        }
+        warn!(%err, "recovering from error");
+        if matches!(err, Error::PeerIo(_) | Error::Protocol(_)) {
             self.ui_handle
-                .sentence_received(String::from_utf8(buf).unwrap())
+                .error(Message::new("lost-peer").arg_message("reason", err.reason()))
                 .await?;
+            self.disconnect(Closer::Neither).await?;
         } else {
-            self.state = State::Waiting;
-            self.ui_handle.disconnected().await?;
             self.ui_handle
-                .log(String::from("Disconnected from remote"))
+                .error(Message::new("error").arg("error", &err))
                 .await?;
         }
+        Ok(())
+    }
 
+    async fn disconnect(&mut self, closer: Closer) -> Result<(), Error> {
+        let mut connection = match std::mem::replace(&mut self.state, State::Waiting) {
+            State::Connected(connection) => connection,
+            State::Waiting => return Ok(()),
+        };
+        info!(?closer, "disconnected");
+        // Whatever is queued is sent in the background
+        tokio::spawn(connection.peer.close());
+        if connection.ready {
+            let ours = connection.session.our_player();
+            let (by, event) = match closer {
+                Closer::Us => (Some(ours), Event::Disconnected),
+                Closer::Peer => (Some(3 - ours), Event::Disconnected),
+                Closer::Neither => (None, Event::Lost),
+            };
+            connection.session.record(by, event);
+        }
+        if !connection.session.turns().is_empty() {
+            match save(&connection.session, &self.save_dir) {
+                Ok(path) => {
+                    self.ui_handle
+                        .log(Message::new("saved-session").arg("path", path.display()))
+                        .await?
+                }
+                // Every sentence was saved as it came, so only the timeline is lost
+                Err(err) => {
+                    self.ui_handle
+                        .error(Message::new("error").arg("error", &err))
+                        .await?
+                }
+            }
+        }
+        self.ui_handle.disconnected().await?;
+        self.ui_handle.toast(Message::new("disconnected")).await?;
         Ok(())
     }
 
-    fn socket(&mut self) -> Option<&mut TcpStream> {
-        match &mut self.state {
-            State::Waiting => None,
-            State::Connected(tcp_stream) => Some(tcp_stream),
+    /// Sends our sentences held for the cooldown, as the peer holds them
+    /// itself, then closes the connection once everything queued for the
+    /// peer is written and the peer has closed its side too.
+    async fn close(&mut self) {
+        while matches!(&self.state, State::Connected(connection) if !connection.held.is_empty()) {
+            if let Err(err) = self.release_held().await {
+                debug!(%err, "couldn't send what was held for the cooldown");
+                break;
+            }
+        }
+        if let State::Connected(connection) = std::mem::replace(&mut self.state, State::Waiting) {
+            connection.peer.close().await;
         }
     }
 
+    /// Drops a connection from an address that isn't let in, before it can
+    /// say anything. The Log line is the same every time an address tries,
+    /// so that it shows once with a count.
+    fn turn_away(&mut self, stream: TcpStream, addr: SocketAddr) -> Result<(), Error> {
+        debug!(%addr, "turned away connection");
+        drop(stream);
+        self.ui_handle.send_or_drop(UIMessage::Log(
+            Level::Warn,
+            Message::new("turned-away").arg("address", addr.ip()),
+        ))
+    }
+
     async fn accept(&mut self, mut stream: TcpStream, addr: SocketAddr) -> Result<(), Error> {
         if matches!(self.state, State::Waiting) {
-            self.state = State::Connected(stream);
-            self.ui_handle.connected(false).await?;
-            self.ui_handle.log(format!("Connected to {}", addr)).await?;
-        } else {
-            stream.shutdown().await?;
+            info!(%addr, "accepted connection");
+            self.start_session(stream, false).await?;
             self.ui_handle
-                .log(String::from("Already connected, dropping new connection"))
+                .log(Message::new("connected-to").arg("address", addr))
                 .await?;
+        } else {
+            // Nothing to do with the peer we have, whom it mustn't cost
+            if let Err(err) = stream.shutdown().await {
+                debug!(%addr, %err, "surplus connection failed to shut down");
+            }
+            self.log_throttled(Level::Warn, Message::new("already-connected"))?;
         }
         Ok(())
     }
+
+    /// Starts timing our turn once it's ours to write, and stops once it
+    /// isn't.
+    fn time_turn(&mut self) {
+        let writing = matches!(
+            &self.state,
+            State::Connected(connection)
+                if connection.ready && connection.rematch.is_none() && connection.session.is_our_turn()
+        );
+        if writing {
+            self.stopwatch.start(Instant::now());
+        } else {
+            self.stopwatch.stop();
+        }
+    }
+
+    /// Logs `message`, unless as many like it were logged in the last minute
+    /// as a flapping peer or port scanner would cause, when one last line
+    /// says the rest are left out.
+    fn log_throttled(&mut self, level: Level, message: Message) -> Result<(), Error> {
+        let message = match self.throttle.check(message.key(), Instant::now()) {
+            Verdict::Pass => message,
+            Verdict::Quiet => Message::new("log-quieted").arg_message("message", message),
+            Verdict::Drop => return Ok(()),
+        };
+        self.ui_handle.send_or_drop(UIMessage::Log(level, message))
+    }
 }
 
-async fn run_app(mut app: App, mut receiver: Receiver<AppInput>) -> Result<(), Error> {
-    let listener = TcpListener::bind(SocketAddr::new(
-        IpAddr::from([127, 0, 0, 1]),
-        app.listen_port,
-    ))
-    .await?;
+/// Times how long we spend writing a turn, leaving out time away.
+#[derive(Debug, Default)]
+struct Stopwatch {
+    /// When the turn became ours, while it is.
+    started: Option<Instant>,
+    /// When we stepped away, while we are.
+    away_since: Option<Instant>,
+    /// How long we were away during the turn, before `away_since`.
+    away: Duration,
+}
+
+impl Stopwatch {
+    fn start(&mut self, now: Instant) {
+        if self.started.is_none() {
+            self.started = Some(now);
+            self.away = Duration::ZERO;
+        }
+    }
+
+    fn stop(&mut self) {
+        self.started = None;
+    }
 
-    app.ui_handle
-        .log(format!("Bound to localhost:{}", app.listen_port))
-        .await?;
+    fn set_away(&mut self, away: bool, now: Instant) {
+        if away {
+            self.away_since.get_or_insert(now);
+        } else if let (Some(since), Some(started)) = (self.away_since.take(), self.started) {
+            self.away += now.saturating_duration_since(since.max(started));
+        }
+    }
+
+    /// How long we've been writing the turn, if it's ours.
+    fn elapsed(&self, now: Instant) -> Option<Duration> {
+        let started = self.started?;
+        let away_now = self.away_since.map_or(Duration::ZERO, |since| {
+            now.saturating_duration_since(since.max(started))
+        });
+        Some(
+            now.saturating_duration_since(started)
+                .saturating_sub(self.away + away_now),
+        )
+    }
+}
+
+/// Times how long nothing has happened on a connection, leaving out time
+/// either player is away when that's asked for.
+#[derive(Debug)]
+struct IdleClock {
+    /// When something last happened, moved on by however long the clock
+    /// was paused since.
+    since: Instant,
+    /// When the clock was paused, while it is.
+    paused_at: Option<Instant>,
+    /// Whether the players were warned that the connection will be closed.
+    warned: bool,
+}
+
+impl IdleClock {
+    fn new(now: Instant) -> Self {
+        Self {
+            since: now,
+            paused_at: None,
+            warned: false,
+        }
+    }
+
+    /// Starts counting again from `now`.
+    fn restart(&mut self, now: Instant) {
+        self.since = now;
+        self.warned = false;
+    }
+
+    fn set_paused(&mut self, paused: bool, now: Instant) {
+        if paused {
+            self.paused_at.get_or_insert(now);
+        } else if let Some(paused_at) = self.paused_at.take() {
+            self.since += now.saturating_duration_since(paused_at);
+        }
+    }
+
+    /// When to warn the players, or once they have been, when to close the
+    /// connection after `timeout` with nothing happening. None while paused.
+    fn deadline(&self, timeout: Duration) -> Option<Instant> {
+        if self.paused_at.is_some() {
+            None
+        } else if self.warned {
+            Some(self.since + timeout)
+        } else {
+            Some(self.since + idle_warning(timeout))
+        }
+    }
+}
+
+/// How long a connection is idle for before the players are warned that
+/// it will be closed: four fifths of the `timeout`.
+fn idle_warning(timeout: Duration) -> Duration {
+    timeout * 4 / 5
+}
+
+/// How many sentences go by between checks that both sides have the same
+/// story.
+const VERIFY_EVERY: usize = 10;
+
+/// How many of each message about incoming connections are logged a minute.
+const NOISY_PER_MINUTE: usize = 5;
+
+/// Exports `session` in `format` to the working directory, returning where.
+/// Only the `appendices` asked for are kept.
+pub fn export(
+    session: &SessionInstance,
+    format: Format,
+    appendices: Appendices,
+) -> Result<String, Error> {
+    let path = export::file_name(session, format);
+    std::fs::write(&path, export::render(&appendices.apply(session), format)).map_err(
+        |source| Error::Save {
+            path: PathBuf::from(&path),
+            source,
+        },
+    )?;
+    Ok(path)
+}
+
+/// Saves `session` in `save_dir`, returning where.
+fn save(session: &SessionInstance, save_dir: &Path) -> Result<PathBuf, Error> {
+    session.save(save_dir).map_err(|source| Error::Save {
+        path: save_dir.join(session.file_name()),
+        source,
+    })
+}
+
+/// Saves `session` in `save_dir`, only reporting a failure, for where the
+/// story has already moved on with the peer and the UI has to follow.
+async fn save_reporting<U: UISink>(
+    session: &SessionInstance,
+    save_dir: &Path,
+    ui_handle: &U,
+) -> Result<(), Error> {
+    if let Err(err) = save(session, save_dir) {
+        warn!(%err, "saving the session failed");
+        ui_handle
+            .error(Message::new("error").arg("error", &err))
+            .await?;
+    }
+    Ok(())
+}
+
+async fn run_app<U: UISink>(
+    mut app: App<U>,
+    mut receiver: Receiver<AppInput>,
+    mut peer_events: Receiver<(u64, PeerEvent)>,
+    mut follow_ups: Receiver<AppInput>,
+) -> Result<(), Error> {
+    let listener = match app.listen_port {
+        Some(port) => {
+            let addr = SocketAddr::new(app.bind, port);
+            let bind_error = |source| Error::Bind { addr, source };
+            let listener = TcpListener::bind(addr).await.map_err(bind_error)?;
+            let bound = listener.local_addr().map_err(bind_error)?;
+            app.ui_handle
+                .log(Message::new("bound").arg("address", bound))
+                .await?;
+            info!(%bound, "listening");
+            app.ui_handle.listening(bound).await?;
+            Some(listener)
+        }
+        None => {
+            info!("not listening, only connecting out");
+            app.ui_handle
+                .log(Message::new("not-listening-hint"))
+                .await?;
+            None
+        }
+    };
 
     loop {
-        let mut buf = vec![0; 1024];
-        tokio::select! {
-            Ok((socket, addr)) = listener.accept() => {
-                app.ui_handle.log(String::from("Accepting connection")).await?;
-                app.accept(socket, addr).await?;
+        let result = tokio::select! {
+            Some(accepted) = OptionFuture::from(listener.as_ref().map(TcpListener::accept)) => {
+                match accepted {
+                    Ok((socket, addr)) if !app.access.permits(addr.ip()) => {
+                        app.turn_away(socket, addr)
+                    }
+                    Ok((socket, addr)) => {
+                        app.log_throttled(Level::Info, Message::new("accepting-connection"))?;
+                        app.accept(socket, addr).await
+                    }
+                    Err(err) => {
+                        warn!(%err, "accept failed");
+                        app.log_throttled(
+                            Level::Warn,
+                            Message::new("accept-failed").arg_message("reason", error::reason(&err)),
+                        )
+                    }
+                }
             }
             msg = receiver.recv() => {
-                if let Some(msg) = msg {
-                    app.handle_message(msg).await?;
+                if let Some(AppInput::Shutdown) = msg {
+                    info!("shutting down");
+                    receiver.close();
+                    // Let the peer see us go rather than just vanish. What it
+                    // sends meanwhile is read, so as not to reset the
+                    // connection, but then dropped.
+                    let closing = app.close();
+                    tokio::pin!(closing);
+                    loop {
+                        tokio::select! {
+                            _ = &mut closing => break,
+                            Some(_) = peer_events.recv() => {}
+                        }
+                    }
+                    break Ok(());
+                } else if let Some(msg) = msg {
+                    app.metrics.received();
+                    app.handle_timed(msg).await
                 } else {
                     // Lost connection to the ui actor so we should die
-                    app.ui_handle.log(String::from("Lost connection to UI")).await?;
+                    app.ui_handle.error(Message::new("lost-ui")).await?;
                     break Ok(());
                 }
             }
-            Some(result) = OptionFuture::from(app.socket().map(|stream| stream.read(&mut buf))) => {
-                app.process_data(result.unwrap(), buf).await?;
+            Some((id, event)) = peer_events.recv() => {
+                app.handle_timed(AppInput::Peer(id, event)).await
+            }
+            Some(msg) = follow_ups.recv() => {
+                app.handle_timed(msg).await
+            }
+            Some(()) = OptionFuture::from(app.rematch_deadline().map(sleep_until)) => {
+                app.rematch_timed_out().await
+            }
+            Some(()) = OptionFuture::from(app.cooldown_deadline().map(sleep_until)) => {
+                app.release_held().await
+            }
+            Some(()) = OptionFuture::from(app.idle_deadline().map(sleep_until)) => {
+                app.idle_timed_out().await
             }
             else => {
                 break Ok(())
             },
+        };
+        if let Err(err) = result {
+            app.recover(err).await?;
         }
+        app.time_turn();
     }
 }
 
 pub struct AppHandle {
     sender: Sender<AppInput>,
+    /// The app actor, which ends with its final error if it failed.
+    task: Option<JoinHandle<Result<(), Error>>>,
+    metrics: Arc<ActorMetrics>,
+}
+
+#[cfg(test)]
+impl AppHandle {
+    /// A handle that isn't connected to an app, for testing the UI alone.
+    pub fn detached() -> Self {
+        let (sender, _) = mpsc::channel(1);
+        Self {
+            sender,
+            task: None,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// A handle whose requests are only recorded, for testing what the UI asks for.
+    pub(crate) fn recording() -> (Self, Receiver<AppInput>) {
+        let (sender, receiver) = mpsc::channel(8);
+        let handle = Self {
+            sender,
+            task: None,
+            metrics: Arc::default(),
+        };
+        (handle, receiver)
+    }
 }
 
 impl AppHandle {
-    pub fn new(listen_port: u16, ui_handle: UIHandle) -> Self {
+    pub fn new(config: AppConfig, ui_handle: impl UISink) -> Self {
         let (sender, receiver) = mpsc::channel(8);
-        let app = App::new(ui_handle, listen_port);
-        tokio::spawn(run_app(app, receiver));
-        Self { sender }
+        let (peer_sender, peer_events) = mpsc::channel(8);
+        let (follow_up_sender, follow_ups) = mpsc::channel(8);
+        let app = App::new(ui_handle.clone(), config, peer_sender, follow_up_sender);
+        let metrics = app.metrics.clone();
+        let task = tokio::spawn(async move {
+            match tokio::spawn(run_app(app, receiver, peer_events, follow_ups)).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(err)) => {
+                    error!(%err, "app actor failed");
+                    // The UI may already be gone, in which case there is no one to tell
+                    let _ = ui_handle.app_stopped(err.to_string()).await;
+                    Err(err)
+                }
+                // The panic hook has already restored the terminal and
+                // reported the panic; whoever runs the app decides what
+                // becomes of the process
+                Err(err) if err.is_panic() => {
+                    error!(%err, "app actor panicked");
+                    let err = Error::Panicked(err.to_string());
+                    let _ = ui_handle.app_stopped(err.to_string()).await;
+                    Err(err)
+                }
+                // Cancelled as the runtime shuts down
+                Err(_) => Ok(()),
+            }
+        });
+        Self {
+            sender,
+            task: Some(task),
+            metrics,
+        }
     }
 
-    pub async fn send_sentence(&self, sentence: String) -> Result<(), Error> {
-        self.sender.send(AppInput::Input(sentence)).await?;
+    /// How the app actor is keeping up, for the metrics overlay.
+    pub(crate) fn metrics(&self) -> &ActorMetrics {
+        &self.metrics
+    }
+
+    /// Sends `input` to the app, counting it as queued until it's taken.
+    async fn send(&self, input: AppInput) -> Result<(), mpsc::error::SendError<AppInput>> {
+        self.metrics.sent();
+        let sent = self.sender.send(input).await;
+        if sent.is_err() {
+            self.metrics.received();
+        }
+        sent
+    }
+
+    /// Asks the app to close the connection and stop, and waits a moment for
+    /// it to, returning the error it failed with if it already had.
+    pub async fn shutdown(self) -> Result<(), Error> {
+        // The app is already gone if this fails, and the task says why
+        let _ = self.send(AppInput::Shutdown).await;
+        let task = match self.task {
+            Some(task) => task,
+            None => return Ok(()),
+        };
+        match timeout(SHUTDOWN, task).await {
+            Ok(Ok(result)) => result,
+            // Cancelled, which only happens as the runtime shuts down
+            Ok(Err(_)) => Ok(()),
+            Err(_) => {
+                warn!("app didn't stop in time");
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn send_sentence(
+        &self,
+        sentence: String,
+        next_constraint: Option<Constraint>,
+    ) -> Result<(), Error> {
+        self.send(AppInput::Input(sentence, next_constraint))
+            .await?;
         Ok(())
     }
 
     pub async fn connect(&self, address: SocketAddr) -> Result<(), Error> {
-        self.sender.send(AppInput::Connect(address)).await?;
+        self.send(AppInput::Connect(address)).await?;
+        Ok(())
+    }
+
+    pub async fn export(&self, format: Format) -> Result<(), Error> {
+        self.send(AppInput::Export(format)).await?;
+        Ok(())
+    }
+
+    /// Ends the story, asking both players whether to write another.
+    pub async fn end_story(&self) -> Result<(), Error> {
+        self.send(AppInput::EndStory).await?;
+        Ok(())
+    }
+
+    pub async fn disconnect(&self) -> Result<(), Error> {
+        self.send(AppInput::Disconnect).await?;
+        Ok(())
+    }
+
+    pub async fn list_saved(&self) -> Result<(), Error> {
+        self.send(AppInput::ListSaved).await?;
         Ok(())
     }
+
+    pub async fn resume(&self, session: SessionInstance) -> Result<(), Error> {
+        self.send(AppInput::Resume(session)).await?;
+        Ok(())
+    }
+
+    /// Answers whether to write another story once one has ended.
+    pub async fn rematch(&self, accept: bool) -> Result<(), Error> {
+        self.send(AppInput::Rematch(accept)).await?;
+        Ok(())
+    }
+
+    /// Asks the peer to scrap the story and start again.
+    pub async fn propose_clear(&self) -> Result<(), Error> {
+        self.send(AppInput::ProposeClear).await?;
+        Ok(())
+    }
+
+    /// Answers the peer asking to scrap the story.
+    pub async fn answer_clear(&self, accept: bool) -> Result<(), Error> {
+        self.send(AppInput::AnswerClear(accept)).await?;
+        Ok(())
+    }
+
+    /// Asks the peer to check that their story is the same as ours.
+    pub async fn verify_story(&self) -> Result<(), Error> {
+        self.send(AppInput::Verify).await?;
+        Ok(())
+    }
+
+    /// Tells the peer we're still writing our turn, `minutes` in.
+    pub async fn still_thinking(&self, minutes: u64) -> Result<(), Error> {
+        self.send(AppInput::StillThinking(minutes)).await?;
+        Ok(())
+    }
+
+    /// Answers whether to take the peer's story in place of ours, once they
+    /// were found to differ.
+    pub async fn answer_resync(&self, accept: bool) -> Result<(), Error> {
+        self.send(AppInput::AnswerResync(accept)).await?;
+        Ok(())
+    }
+
+    /// Disconnects from the peer, and keeps their address from connecting
+    /// again.
+    pub async fn block_peer(&self) -> Result<(), Error> {
+        self.send(AppInput::BlockPeer).await?;
+        Ok(())
+    }
+
+    /// Saves `notes` with the story, without telling the peer.
+    pub async fn save_notes(&self, notes: String) -> Result<(), Error> {
+        self.send(AppInput::Notes(notes)).await?;
+        Ok(())
+    }
+
+    /// Tells the peer, now and on connecting, whether we're away.
+    pub async fn set_away(&self, away: bool) -> Result<(), Error> {
+        self.send(AppInput::Away(away)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{App, AppConfig, AppHandle, AppInput, IdleClock, State, Stopwatch};
+    use crate::{
+        error::Error,
+        i18n::Message,
+        log_buffer::Level,
+        peer::PeerEvent,
+        protocol::{encode, Decoder, PeerMessage, TurnPreference},
+        sessions::{Event, SessionInstance, Turn},
+        ui_actor::{UIMessage, UISink},
+    };
+    use chrono::Utc;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc::{self, Receiver},
+        time::{sleep_until, timeout, Duration, Instant},
+    };
+
+    /// Keeps everything the app sends, to check afterwards.
+    #[derive(Clone, Debug, Default)]
+    struct RecordingSink {
+        messages: Arc<Mutex<Vec<UIMessage>>>,
+    }
+
+    impl RecordingSink {
+        fn take(&self) -> Vec<UIMessage> {
+            std::mem::take(&mut self.messages.lock().unwrap())
+        }
+    }
+
+    impl UISink for RecordingSink {
+        async fn send(&self, message: UIMessage) -> Result<(), Error> {
+            self.send_or_drop(message)
+        }
+
+        fn send_or_drop(&self, message: UIMessage) -> Result<(), Error> {
+            self.messages.lock().unwrap().push(message);
+            Ok(())
+        }
+    }
+
+    /// Records everything like [`RecordingSink`], but panics, and so
+    /// panics the app, on being told it's listening.
+    #[derive(Clone, Debug, Default)]
+    struct PanickingSink(RecordingSink);
+
+    impl UISink for PanickingSink {
+        async fn send(&self, message: UIMessage) -> Result<(), Error> {
+            self.send_or_drop(message)
+        }
+
+        fn send_or_drop(&self, message: UIMessage) -> Result<(), Error> {
+            if let UIMessage::Listening(_) = message {
+                panic!("told it's listening");
+            }
+            self.0.send_or_drop(message)
+        }
+    }
+
+    /// What an app's connections and its own tasks report, to feed back to
+    /// it by hand.
+    struct Events {
+        peer: Receiver<(u64, PeerEvent)>,
+        follow_ups: Receiver<AppInput>,
+    }
+
+    /// An app that isn't run, along with what it sends to the UI and the
+    /// events reported to it.
+    fn app() -> (App<RecordingSink>, RecordingSink, Events) {
+        let sink = RecordingSink::default();
+        let save_dir = std::env::temp_dir().join("write_together-app-tests");
+        let (peer_events, peer) = mpsc::channel(8);
+        let (follow_up_sender, follow_ups) = mpsc::channel(8);
+        (
+            App::new(
+                sink.clone(),
+                AppConfig {
+                    save_dir,
+                    ..AppConfig::default()
+                },
+                peer_events,
+                follow_up_sender,
+            ),
+            sink,
+            Events { peer, follow_ups },
+        )
+    }
+
+    /// Hands the app the next thing reported to it, such as how connecting
+    /// out went or what its connection read.
+    async fn next_event(app: &mut App<RecordingSink>, events: &mut Events) -> Result<(), Error> {
+        let msg = tokio::select! {
+            Some(msg) = events.follow_ups.recv() => msg,
+            Some((id, event)) = events.peer.recv() => AppInput::Peer(id, event),
+        };
+        app.handle_message(msg).await
+    }
+
+    /// Both ends of a fresh TCP connection.
+    async fn stream_pair() -> (TcpStream, TcpStream, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ours = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (theirs, address) = listener.accept().await.unwrap();
+        (ours, theirs, address)
+    }
+
+    #[tokio::test]
+    async fn failed_connect_leaves_the_app_waiting() {
+        let (mut app, sink, mut events) = app();
+        // Nothing listens on a port that was just freed
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        app.handle_message(AppInput::Connect(address))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+
+        let messages = sink.take();
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                UIMessage::Log(Level::Info, _),
+                UIMessage::Connecting(connecting),
+                UIMessage::ConnectFailed { address: failed, reason },
+            ] if *connecting == address && *failed == address && *reason == Message::new("reason-refused")
+        ));
+        assert!(matches!(app.state, State::Waiting));
+    }
+
+    #[tokio::test]
+    async fn other_input_is_handled_while_connecting_out() {
+        let (mut app, sink, _events) = app();
+        // Nothing answers on a private address that isn't in use
+        let address = "10.255.255.1:9".parse().unwrap();
+        timeout(
+            Duration::from_secs(1),
+            app.handle_message(AppInput::Connect(address)),
+        )
+        .await
+        .expect("the app waited on the connect")
+        .unwrap();
+        assert_eq!(app.connecting, Some(address));
+        sink.take();
+
+        app.handle_message(AppInput::Away(true)).await.unwrap();
+        assert!(app.away);
+        // Connecting again before it's known how the first went does nothing
+        app.handle_message(AppInput::Connect(address))
+            .await
+            .unwrap();
+        assert!(sink.take().is_empty());
+        assert!(matches!(app.state, State::Waiting));
+    }
+
+    #[tokio::test]
+    async fn input_while_waiting_is_an_error() {
+        let (mut app, sink, _events) = app();
+        app.handle_message(AppInput::Input(String::from("Hello."), None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Error, message)] if *message == Message::new("unexpected-input")
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_panicking_app_stops_with_an_error_rather_than_the_process() {
+        let sink = PanickingSink::default();
+        let app = AppHandle::new(
+            AppConfig {
+                listen_port: Some(0),
+                save_dir: std::env::temp_dir().join("write_together-app-tests"),
+                ..AppConfig::default()
+            },
+            sink.clone(),
+        );
+        let err = app.shutdown().await.unwrap_err();
+        assert!(matches!(err, Error::Panicked(_)), "{}", err);
+        assert!(sink
+            .0
+            .take()
+            .iter()
+            .any(|message| matches!(message, UIMessage::AppStopped(_))));
+    }
+
+    #[tokio::test]
+    async fn accepts_one_peer_at_a_time() {
+        let (mut app, sink, _events) = app();
+        let (_first, stream, address) = stream_pair().await;
+        app.accept(stream, address).await.unwrap();
+        // Connected only once the peer has sent the session
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Info, _)]
+        ));
+
+        let (_second, stream, address) = stream_pair().await;
+        app.accept(stream, address).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Warn, message)]
+                if *message == Message::new("already-connected")
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_surplus_connection_resetting_leaves_the_session_be() {
+        let (mut app, sink, _events) = app();
+        let (_first, stream, address) = stream_pair().await;
+        app.accept(stream, address).await.unwrap();
+        sink.take();
+
+        // Reset before we get round to shutting it down, as a port scan does
+        let (second, stream, address) = stream_pair().await;
+        second.set_linger(Some(Duration::from_secs(0))).unwrap();
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        app.accept(stream, address).await.unwrap();
+        assert!(matches!(app.state, State::Connected(_)));
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Warn, message)]
+                if *message == Message::new("already-connected")
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_blocked_peer_is_dropped_and_turned_away_from_then_on() {
+        let (mut app, sink, _events) = app();
+        let (_peer, stream, address) = stream_pair().await;
+        app.accept(stream, address).await.unwrap();
+        sink.take();
+
+        app.handle_message(AppInput::BlockPeer).await.unwrap();
+        assert!(matches!(app.state, State::Waiting));
+        let messages = sink.take();
+        assert!(matches!(
+            &messages[0],
+            UIMessage::Log(Level::Info, message)
+                if *message == Message::new("blocked-peer").arg("address", address.ip())
+        ));
+        assert!(messages
+            .iter()
+            .any(|message| matches!(message, UIMessage::Disconnected)));
+        assert!(!app.access.permits(address.ip()));
+
+        // Every try from the address logs the same line, to be counted
+        for _ in 0..2 {
+            let (_peer, stream, address) = stream_pair().await;
+            app.turn_away(stream, address).unwrap();
+        }
+        let turned_away = Message::new("turned-away").arg("address", address.ip());
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Warn, first), UIMessage::Log(Level::Warn, second)]
+                if *first == turned_away && *second == turned_away
+        ));
+        assert!(matches!(app.state, State::Waiting));
+    }
+
+    #[tokio::test]
+    async fn garbage_from_the_peer_drops_it_but_keeps_the_app_going() {
+        let (mut app, sink, mut events) = app();
+        let (mut peer, stream, address) = stream_pair().await;
+        app.accept(stream, address).await.unwrap();
+        sink.take();
+
+        let mut garbage = 4u32.to_be_bytes().to_vec();
+        garbage.extend(b"oops");
+        peer.write_all(&garbage).await.unwrap();
+        let err = next_event(&mut app, &mut events).await.unwrap_err();
+        app.recover(err).await.unwrap();
+        let messages = sink.take();
+        assert!(matches!(messages[0], UIMessage::Log(Level::Error, _)));
+        assert!(messages
+            .iter()
+            .any(|message| matches!(message, UIMessage::Disconnected)));
+        assert!(matches!(app.state, State::Waiting));
+
+        let fatal = Error::Send(Box::new(std::fmt::Error));
+        assert!(app.recover(fatal).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_huge_frame_drops_the_peer_without_reading_it() {
+        let (mut app, sink, mut events) = app();
+        let (mut peer, stream, address) = stream_pair().await;
+        app.accept(stream, address).await.unwrap();
+        sink.take();
+
+        peer.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+        let err = next_event(&mut app, &mut events).await.unwrap_err();
+        assert!(matches!(err, Error::Protocol(_)));
+        app.recover(err).await.unwrap();
+        assert!(matches!(app.state, State::Waiting));
+    }
+
+    #[tokio::test]
+    async fn writes_once_the_peer_agrees_who_goes_first() {
+        let (mut app, sink, mut events) = app();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        app.handle_message(AppInput::Connect(address))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let input = || AppInput::Input(String::from("Hello."), None);
+        app.handle_message(input()).await.unwrap();
+        assert!(sink.take().iter().any(
+            |message| matches!(message, UIMessage::Log(Level::Error, text) if *text == Message::new("unexpected-input"))
+        ));
+
+        // Neither side had a say, so the peer asking to go second settles it
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: Some(TurnPreference::Second),
+            cooldown_secs: 0,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Connected { session, .. }] if session.is_our_turn()
+        ));
+        app.handle_message(input()).await.unwrap();
+        app.handle_message(AppInput::Disconnect).await.unwrap();
+        assert!(sink
+            .take()
+            .iter()
+            .any(|message| matches!(message, UIMessage::Disconnected)));
+
+        // The writer closes the connection once the queue is written
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).await.unwrap();
+        let mut decoder = Decoder::default();
+        decoder.extend(&received);
+        assert!(matches!(
+            decoder.next_message().unwrap(),
+            Some(PeerMessage::Hello {
+                preference: None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            decoder.next_message().unwrap(),
+            Some(PeerMessage::Session { session, .. }) if session.we_started()
+        ));
+        assert!(matches!(
+            decoder.next_message().unwrap(),
+            Some(PeerMessage::Sentence { index: 0, turn }) if turn.text == "Hello."
+        ));
+        assert!(decoder.next_message().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn a_sentence_that_cant_be_saved_still_reaches_the_ui() {
+        let (mut app, sink, mut events) = app();
+        // A file where the directory should be
+        let blocked = std::env::temp_dir().join("write_together-app-tests-blocked");
+        std::fs::write(&blocked, "").unwrap();
+        app.save_dir = blocked;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        app.handle_message(AppInput::Connect(listener.local_addr().unwrap()))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: Some(TurnPreference::Second),
+            cooldown_secs: 0,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        sink.take();
+
+        app.handle_message(AppInput::Input(String::from("One."), None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Error, _), UIMessage::SentenceAccepted(turn)]
+                if turn.text == "One."
+        ));
+
+        let theirs = PeerMessage::Sentence {
+            index: 1,
+            turn: Turn {
+                text: String::from("Two."),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            },
+        };
+        peer.write_all(&encode(&theirs)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(Level::Error, _), UIMessage::SentenceReceived(turn)]
+                if turn.text == "Two."
+        ));
+        assert!(matches!(app.state, State::Connected(_)));
+    }
+
+    #[tokio::test]
+    async fn sentences_wait_for_the_longer_cooldown() {
+        let (mut app, sink, mut events) = app();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        app.handle_message(AppInput::Connect(address))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: Some(TurnPreference::Second),
+            cooldown_secs: 1,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        sink.take();
+
+        // Nothing to wait for before the first sentence
+        app.handle_message(AppInput::Input(String::from("One."), None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::SentenceAccepted(_)]
+        ));
+        let theirs = PeerMessage::Sentence {
+            index: 1,
+            turn: Turn {
+                text: String::from("Two."),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            },
+        };
+        peer.write_all(&encode(&theirs)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(sink.take().is_empty());
+
+        let deadline = app.cooldown_deadline().unwrap();
+        sleep_until(deadline).await;
+        app.release_held().await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::SentenceReceived(turn)] if turn.text == "Two."
+        ));
+
+        // Ours waits too, and the UI is told for how long
+        app.handle_message(AppInput::Input(String::from("Three."), None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Cooldown(wait)] if *wait <= Duration::from_secs(1)
+        ));
+        sleep_until(app.cooldown_deadline().unwrap()).await;
+        app.release_held().await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::SentenceAccepted(turn)] if turn.text == "Three."
+        ));
+        assert!(app.cooldown_deadline().is_none());
+    }
+
+    #[tokio::test]
+    async fn closing_sends_what_was_held_and_waits_for_the_peer_to_close() {
+        let (mut app, sink, mut events) = app();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        app.handle_message(AppInput::Connect(address))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: Some(TurnPreference::Second),
+            cooldown_secs: 60,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        app.handle_message(AppInput::Input(String::from("One."), None))
+            .await
+            .unwrap();
+        let theirs = PeerMessage::Sentence {
+            index: 1,
+            turn: Turn {
+                text: String::from("Two."),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            },
+        };
+        peer.write_all(&encode(&theirs)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        app.handle_message(AppInput::Input(String::from("Three."), None))
+            .await
+            .unwrap();
+        sink.take();
+
+        let read_all = async {
+            let mut bytes = vec![];
+            peer.read_to_end(&mut bytes).await.unwrap();
+            peer.shutdown().await.unwrap();
+            bytes
+        };
+        let ((), bytes) = timeout(Duration::from_secs(5), async {
+            tokio::join!(app.close(), read_all)
+        })
+        .await
+        .expect("closing never finished");
+        assert!(matches!(app.state, State::Waiting));
+        let mut decoder = Decoder::default();
+        decoder.extend(&bytes);
+        let mut sentences = vec![];
+        while let Some(message) = decoder.next_message().unwrap() {
+            if let PeerMessage::Sentence { turn, .. } = message {
+                sentences.push(turn.text);
+            }
+        }
+        assert_eq!(sentences, ["One.", "Three."]);
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::SentenceReceived(two), UIMessage::SentenceAccepted(three)]
+                if two.text == "Two." && three.text == "Three."
+        ));
+    }
+
+    #[tokio::test]
+    async fn diverged_stories_are_resynced_from_the_longer_one() {
+        let (mut app, sink, mut events) = app();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        app.handle_message(AppInput::Connect(address))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: Some(TurnPreference::Second),
+            cooldown_secs: 0,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        app.handle_message(AppInput::Input(String::from("One."), None))
+            .await
+            .unwrap();
+        sink.take();
+        let theirs = |texts: &[&str]| {
+            let mut session = SessionInstance::new(false);
+            for text in texts {
+                session.push(Turn {
+                    text: String::from(*text),
+                    timestamp: Utc::now(),
+                    next_constraint: None,
+                    writing_secs: None,
+                });
+            }
+            session
+        };
+
+        // As long as ours, and we connected, so ours is kept
+        let digest = theirs(&["Uno."]).digest(1).unwrap();
+        peer.write_all(&encode(&PeerMessage::Digest { digest }))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Diverged(true)]
+        ));
+        peer.write_all(&encode(&PeerMessage::RequestResync))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(_, message)] if *message == Message::new("resync-sent")
+        ));
+
+        // Shorter than theirs, so we're asked to take it
+        let sentence = PeerMessage::Sentence {
+            index: 1,
+            turn: theirs(&["Two."]).turns()[0].clone(),
+        };
+        peer.write_all(&encode(&sentence)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        app.handle_message(AppInput::Verify).await.unwrap();
+        let checked = PeerMessage::Checked {
+            matched: false,
+            turns: 3,
+        };
+        peer.write_all(&encode(&checked)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [
+                UIMessage::SentenceReceived(_),
+                UIMessage::Log(..),
+                UIMessage::Diverged(false)
+            ]
+        ));
+        app.handle_message(AppInput::AnswerResync(true))
+            .await
+            .unwrap();
+        let resync = PeerMessage::Resync {
+            session: theirs(&["One.", "Two.", "Three."]).for_peer(),
+        };
+        peer.write_all(&encode(&resync)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(
+            sink.take().as_slice(),
+            [UIMessage::Log(..), UIMessage::Resynced(session)]
+                if session.content() == "One. Two. Three." && !session.we_started()
+        ));
+        let session = match &app.state {
+            State::Connected(connection) => connection.session.clone(),
+            State::Waiting => unreachable!(),
+        };
+        assert!(session.agrees_with(theirs(&["One.", "Two.", "Three."]).digest(3).unwrap()));
+
+        app.handle_message(AppInput::Disconnect).await.unwrap();
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).await.unwrap();
+        let mut decoder = Decoder::default();
+        decoder.extend(&received);
+        let mut sent = vec![];
+        while let Some(message) = decoder.next_message().unwrap() {
+            sent.push(message);
+        }
+        assert!(matches!(
+            &sent[3..],
+            [
+                PeerMessage::Checked { matched: false, turns: 1 },
+                PeerMessage::Resync { session },
+                PeerMessage::Digest { digest },
+                PeerMessage::RequestResync,
+            ] if session.content() == "One." && digest.turns == 2
+        ));
+    }
+
+    #[test]
+    fn writing_time_leaves_out_time_away() {
+        let mut stopwatch = Stopwatch::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(stopwatch.elapsed(at(0)), None);
+        // Away from before the turn started only counts from the start
+        stopwatch.set_away(true, at(0));
+        stopwatch.start(at(10));
+        stopwatch.set_away(false, at(30));
+        stopwatch.set_away(true, at(50));
+        assert_eq!(stopwatch.elapsed(at(60)), Some(Duration::from_secs(20)));
+        stopwatch.set_away(false, at(70));
+        // Starting again while the turn is still ours changes nothing
+        stopwatch.start(at(80));
+        assert_eq!(stopwatch.elapsed(at(100)), Some(Duration::from_secs(50)));
+        stopwatch.stop();
+        assert_eq!(stopwatch.elapsed(at(100)), None);
+    }
+
+    #[test]
+    fn the_idle_clock_stops_while_paused() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let timeout = Duration::from_secs(100);
+        let mut clock = IdleClock::new(at(0));
+        assert_eq!(clock.deadline(timeout), Some(at(80)));
+        clock.set_paused(true, at(10));
+        assert_eq!(clock.deadline(timeout), None);
+        // Pausing again changes nothing
+        clock.set_paused(true, at(20));
+        clock.set_paused(false, at(40));
+        assert_eq!(clock.deadline(timeout), Some(at(110)));
+        clock.warned = true;
+        assert_eq!(clock.deadline(timeout), Some(at(130)));
+        clock.restart(at(50));
+        assert_eq!(clock.deadline(timeout), Some(at(130)));
+        assert!(!clock.warned);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_warned_then_closed() {
+        let (mut app, sink, mut events) = app();
+        app.idle_timeout = Some(Duration::from_secs(100));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        app.handle_message(AppInput::Connect(listener.local_addr().unwrap()))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: None,
+            cooldown_secs: 0,
+            compression: false,
+        };
+        let connected = Instant::now();
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let deadline = app.idle_deadline().unwrap();
+        assert!(deadline >= connected + Duration::from_secs(80));
+        assert!(deadline <= Instant::now() + Duration::from_secs(80));
+
+        app.idle_timed_out().await.unwrap();
+        assert!(sink.take().iter().any(|message| matches!(
+            message,
+            UIMessage::Toast(message) if *message == Message::new("idle-warning").arg("left", "20s")
+        )));
+        assert!(app.idle_deadline().unwrap() > deadline + Duration::from_secs(19));
+        // The peer stepping away doesn't count as anything happening
+        peer.write_all(&encode(&PeerMessage::Status { away: true }))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        app.idle_timed_out().await.unwrap();
+        assert!(matches!(app.state, State::Waiting));
+
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).await.unwrap();
+        let mut decoder = Decoder::default();
+        decoder.extend(&received);
+        let mut messages = vec![];
+        while let Some(message) = decoder.next_message().unwrap() {
+            messages.push(message);
+        }
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                PeerMessage::Hello {
+                    compression: true,
+                    ..
+                },
+                PeerMessage::Session { .. },
+                PeerMessage::IdleWarning { secs_left: 20 },
+                PeerMessage::IdleTimeout,
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn away_players_can_pause_the_idle_clock() {
+        let (mut app, _sink, mut events) = app();
+        app.idle_timeout = Some(Duration::from_secs(100));
+        app.idle_pause_away = true;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        app.handle_message(AppInput::Connect(listener.local_addr().unwrap()))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: None,
+            cooldown_secs: 0,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(app.idle_deadline().is_some());
+        peer.write_all(&encode(&PeerMessage::Status { away: true }))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert_eq!(app.idle_deadline(), None);
+        // Still paused while either of us is away
+        app.handle_message(AppInput::Away(true)).await.unwrap();
+        peer.write_all(&encode(&PeerMessage::Status { away: false }))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert_eq!(app.idle_deadline(), None);
+        app.handle_message(AppInput::Away(false)).await.unwrap();
+        assert!(app.idle_deadline().is_some());
+    }
+
+    #[tokio::test]
+    async fn notes_are_saved_but_never_sent() {
+        let (mut app, _sink, mut events) = app();
+        let mut session = SessionInstance::new(true);
+        session.set_notes(String::from("The keeper did it"));
+        app.resume = Some(session);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        app.handle_message(AppInput::Connect(listener.local_addr().unwrap()))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: None,
+            cooldown_secs: 0,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        app.handle_message(AppInput::Notes(String::from("The keeper's brother did it")))
+            .await
+            .unwrap();
+        let saved = match &app.state {
+            State::Connected(connection) => {
+                SessionInstance::load(&app.save_dir.join(connection.session.file_name())).unwrap()
+            }
+            State::Waiting => panic!("not connected"),
+        };
+        assert_eq!(saved.notes(), "The keeper's brother did it");
+        app.handle_message(AppInput::Disconnect).await.unwrap();
+
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).await.unwrap();
+        let mut decoder = Decoder::default();
+        decoder.extend(&received);
+        decoder.next_message().unwrap();
+        assert!(matches!(
+            decoder.next_message().unwrap(),
+            Some(PeerMessage::Session { session, resumed: true }) if session.notes().is_empty()
+        ));
+        assert!(!String::from_utf8_lossy(&received).contains("keeper"));
+    }
+
+    #[tokio::test]
+    async fn long_stories_are_compressed_only_for_peers_that_can_read_it() {
+        for compression in [false, true] {
+            let (mut app, _sink, mut events) = app();
+            let mut session = SessionInstance::new(true);
+            for _ in 0..200 {
+                session.push(Turn {
+                    text: String::from("The lighthouse was dark, and nobody had kept it."),
+                    timestamp: Utc::now(),
+                    next_constraint: None,
+                    writing_secs: None,
+                });
+            }
+            app.resume = Some(session.clone());
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            app.handle_message(AppInput::Connect(listener.local_addr().unwrap()))
+                .await
+                .unwrap();
+            next_event(&mut app, &mut events).await.unwrap();
+            let (mut peer, _) = listener.accept().await.unwrap();
+            let hello = PeerMessage::Hello {
+                nonce: 0,
+                preference: None,
+                cooldown_secs: 0,
+                compression,
+            };
+            peer.write_all(&encode(&hello)).await.unwrap();
+            next_event(&mut app, &mut events).await.unwrap();
+            app.handle_message(AppInput::Disconnect).await.unwrap();
+
+            let mut received = Vec::new();
+            peer.read_to_end(&mut received).await.unwrap();
+            // Hello, then the story
+            let mut flags = vec![];
+            let mut rest = &received[..];
+            while !rest.is_empty() {
+                let prefix = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+                flags.push(prefix >> 31 == 1);
+                rest = &rest[4 + (prefix & 0x7fff_ffff) as usize..];
+            }
+            assert_eq!(flags, [false, compression]);
+            let mut decoder = Decoder::default();
+            decoder.extend(&received);
+            decoder.next_message().unwrap();
+            assert!(matches!(
+                decoder.next_message().unwrap(),
+                Some(PeerMessage::Session { session: theirs, resumed: true })
+                    if theirs.content() == session.content()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn connecting_and_leaving_are_on_the_timeline() {
+        let (mut app, _sink, mut events) = app();
+        let mut session = SessionInstance::new(true);
+        session.push(Turn {
+            text: String::from("The lighthouse was dark."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        });
+        let path = app.save_dir.join(session.file_name());
+        app.resume = Some(session);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        app.handle_message(AppInput::Connect(listener.local_addr().unwrap()))
+            .await
+            .unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let hello = PeerMessage::Hello {
+            nonce: 0,
+            preference: None,
+            cooldown_secs: 0,
+            compression: false,
+        };
+        peer.write_all(&encode(&hello)).await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        // The peer hangs up
+        peer.shutdown().await.unwrap();
+        next_event(&mut app, &mut events).await.unwrap();
+        assert!(matches!(app.state, State::Waiting));
+
+        let timeline: Vec<_> = SessionInstance::load(&path)
+            .unwrap()
+            .timeline()
+            .into_iter()
+            .map(|moment| (moment.by, moment.turns, moment.event))
+            .collect();
+        assert_eq!(
+            timeline,
+            vec![
+                (Some(1), 1, Event::Resumed),
+                (Some(2), 1, Event::Disconnected)
+            ]
+        );
+    }
 }
@@ -0,0 +1,153 @@
+use crate::{app::AppHandle, error::Error, ui_actor::UIMessage};
+use std::{collections::VecDeque, fs, io, net::SocketAddr, path::Path};
+use tokio::{
+    sync::mpsc::Receiver,
+    time::{sleep, Duration},
+};
+use tracing::info;
+
+/// The sentences a bot writes, in order.
+#[derive(Debug)]
+pub struct Script {
+    lines: VecDeque<String>,
+}
+
+impl Script {
+    /// Loads a script of one sentence per line. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let script = Self::parse(&fs::read_to_string(path)?);
+        if script.lines.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: the script has no sentences", path.display()),
+            ));
+        }
+        Ok(script)
+    }
+
+    fn parse(text: &str) -> Self {
+        Self {
+            lines: text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// Plays a session without a terminal, writing the next line of `script`
+/// `delay` after each of the peer's sentences. Connects to `connect` if
+/// given, otherwise waits for the peer to connect. Returns once the script
+/// runs out or the peer disconnects.
+pub async fn run(
+    app: AppHandle,
+    ui: Receiver<UIMessage>,
+    script: Script,
+    delay: Duration,
+    connect: Option<SocketAddr>,
+) -> Result<(), Error> {
+    let played = play(&app, ui, script, delay, connect).await;
+    let stopped = app.shutdown().await;
+    played.and(stopped)
+}
+
+async fn play(
+    app: &AppHandle,
+    mut ui: Receiver<UIMessage>,
+    mut script: Script,
+    delay: Duration,
+    connect: Option<SocketAddr>,
+) -> Result<(), Error> {
+    if let Some(address) = connect {
+        app.connect(address).await?;
+    }
+    loop {
+        let our_turn = match ui.recv().await {
+            Some(UIMessage::Listening(address)) => {
+                println!("Listening on {}", address);
+                false
+            }
+            Some(UIMessage::Connected { session, peer }) => {
+                println!("Connected to {}", peer);
+                session.is_our_turn()
+            }
+            Some(UIMessage::ConnectFailed { address, reason }) => {
+                return Err(Error::Connect {
+                    addr: address,
+                    source: io::Error::other(reason.to_string()),
+                });
+            }
+            Some(UIMessage::SentenceReceived(turn)) => {
+                println!("peer: {}", turn.text);
+                true
+            }
+            Some(UIMessage::SentenceAccepted(turn)) => {
+                println!("bot:  {}", turn.text);
+                false
+            }
+            // On to the next line, rather than trying the same one again
+            Some(UIMessage::SentenceRefused { turn, .. }) => {
+                println!("peer turned down: {}", turn.text);
+                true
+            }
+            // Always up for another story
+            Some(UIMessage::StoryEnded) => {
+                app.rematch(true).await?;
+                false
+            }
+            Some(UIMessage::NewStory(session)) => {
+                println!("New story");
+                session.is_our_turn()
+            }
+            Some(UIMessage::Disconnected) => {
+                println!("Peer disconnected");
+                return Ok(());
+            }
+            Some(UIMessage::AppStopped(reason)) => {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, reason).into())
+            }
+            // Only the script gets written
+            Some(UIMessage::ClearProposed) => {
+                app.answer_clear(false).await?;
+                false
+            }
+            // Going along with the peer's story, as the script follows on from any
+            Some(UIMessage::Diverged(false)) => {
+                app.answer_resync(true).await?;
+                false
+            }
+            Some(UIMessage::Resynced(session)) => session.is_our_turn(),
+            Some(UIMessage::Log(level, message)) => {
+                info!(%level, "{}", message);
+                false
+            }
+            Some(_) => false,
+            None => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the app stopped").into()),
+        };
+        if our_turn {
+            let line = match script.lines.pop_front() {
+                Some(line) => line,
+                None => {
+                    println!("Script finished");
+                    return Ok(());
+                }
+            };
+            sleep(delay).await;
+            app.send_sentence(line, None).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Script;
+
+    #[test]
+    fn skips_blank_lines_and_comments() {
+        let script = Script::parse("# opening\nIt was a dark night.\n\n  The end.  \n");
+        assert_eq!(script.lines, vec!["It was a dark night.", "The end."]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::screen;
+use std::{
+    env,
+    io::{self, Write},
+    process::Stdio,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::debug;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The program, and its arguments, that puts its input on the system
+/// clipboard on `os`, if there is one. `set` says whether an environment
+/// variable is set, which tells the display servers on Linux apart.
+fn clipboard_program(
+    os: &str,
+    set: impl Fn(&str) -> bool,
+) -> Option<(&'static str, &'static [&'static str])> {
+    match os {
+        "macos" => Some(("pbcopy", &[])),
+        "windows" => Some(("clip", &[])),
+        _ if set("WAYLAND_DISPLAY") => Some(("wl-copy", &[])),
+        _ if set("DISPLAY") => Some(("xclip", &["-selection", "clipboard"])),
+        _ => None,
+    }
+}
+
+/// `text` as the clipboard program on `os` reads it. `clip` takes the
+/// console's code page unless given UTF-16 with a byte order mark.
+fn clipboard_input(os: &str, text: &str) -> Vec<u8> {
+    match os {
+        "windows" => std::iter::once(0xfeff)
+            .chain(text.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+/// Puts `text` on the clipboard. The OSC 52 escape sequence reaches the
+/// local terminal even over SSH; terminals that don't support it ignore it,
+/// so the system clipboard command is tried as well when there is one. A
+/// Windows console that takes no escape sequences would print it instead,
+/// so it isn't sent there.
+pub fn copy(text: &str) {
+    if screen::escapes_supported() {
+        let mut stdout = io::stdout();
+        // Nothing useful can be done if the terminal can't be written to
+        let _ = write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))
+            .and_then(|_| stdout.flush());
+    }
+
+    if let Some((program, args)) =
+        clipboard_program(env::consts::OS, |name| env::var_os(name).is_some())
+    {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let text = clipboard_input(env::consts::OS, text);
+        tokio::spawn(async move {
+            let result = async {
+                let mut child = command.spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&text).await?;
+                }
+                child.wait().await
+            };
+            match result.await {
+                Ok(status) if !status.success() => debug!(%status, "clipboard command failed"),
+                Err(err) => debug!(%err, "clipboard command failed"),
+                Ok(_) => {}
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, clipboard_input, clipboard_program};
+
+    #[test]
+    fn base64_pads_partial_chunks() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(
+            base64("Once upon a time…".as_bytes()),
+            "T25jZSB1cG9uIGEgdGltZeKApg=="
+        );
+    }
+
+    #[test]
+    fn each_platform_has_its_own_clipboard_program() {
+        let nothing_set = |_: &str| false;
+        let set = |var: &'static str| move |name: &str| name == var;
+        assert_eq!(
+            clipboard_program("macos", nothing_set),
+            Some(("pbcopy", &[][..]))
+        );
+        assert_eq!(
+            clipboard_program("windows", nothing_set),
+            Some(("clip", &[][..]))
+        );
+        assert_eq!(
+            clipboard_program("linux", set("WAYLAND_DISPLAY")),
+            Some(("wl-copy", &[][..]))
+        );
+        assert_eq!(
+            clipboard_program("freebsd", set("DISPLAY")),
+            Some(("xclip", &["-selection", "clipboard"][..]))
+        );
+        assert_eq!(clipboard_program("linux", nothing_set), None);
+    }
+
+    #[test]
+    fn clip_is_given_utf16_with_a_byte_order_mark() {
+        assert_eq!(clipboard_input("linux", "Café"), "Café".as_bytes());
+        assert_eq!(
+            clipboard_input("windows", "Café"),
+            [0xff, 0xfe, b'C', 0, b'a', 0, b'f', 0, 0xe9, 0]
+        );
+    }
+}
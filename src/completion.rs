@@ -0,0 +1,71 @@
+use crate::counter;
+use std::{collections::BTreeSet, fs, io, path::Path};
+
+/// Words shorter than this aren't worth completing.
+const MIN_LENGTH: usize = 4;
+
+/// Word completions from the story so far and an optional dictionary.
+#[derive(Debug, Default)]
+pub struct Completer {
+    /// Lowercased, so prefixes match regardless of case.
+    words: BTreeSet<String>,
+}
+
+impl Completer {
+    /// Starts from the words in `path`, one per line.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut completer = Self::default();
+        for line in fs::read_to_string(path)?.lines() {
+            completer.add(line.trim());
+        }
+        Ok(completer)
+    }
+
+    /// Adds the words of `text`, e.g. a sentence as it arrives.
+    pub fn add_text(&mut self, text: &str) {
+        for word in counter::words(text) {
+            self.add(word);
+        }
+    }
+
+    fn add(&mut self, word: &str) {
+        if word.chars().count() >= MIN_LENGTH {
+            self.words.insert(word.to_lowercase());
+        }
+    }
+
+    /// Up to `count` words starting with `prefix`, shortest first, keeping
+    /// the case of what was typed.
+    pub fn suggest(&self, prefix: &str, count: usize) -> Vec<String> {
+        let lower = prefix.to_lowercase();
+        let mut matches: Vec<&String> = self
+            .words
+            .range(lower.clone()..)
+            .take_while(|word| word.starts_with(&lower))
+            .filter(|word| **word != lower)
+            .collect();
+        matches.sort_by_key(|word| word.len());
+        matches
+            .into_iter()
+            .take(count)
+            .filter_map(|word| Some(format!("{}{}", prefix, word.get(lower.len()..)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Completer;
+
+    #[test]
+    fn suggests_shortest_matches_in_typed_case() {
+        let mut completer = Completer::default();
+        completer.add_text("The lantern lit the lane; lanterns, lanes and a lantern-maker.");
+        assert_eq!(
+            completer.suggest("Lan", 3),
+            vec!["Lane", "Lanes", "Lantern"]
+        );
+        assert_eq!(completer.suggest("lantern", 3), vec!["lanterns"]);
+        assert!(completer.suggest("zeb", 3).is_empty());
+    }
+}
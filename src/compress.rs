@@ -0,0 +1,180 @@
+//! A small LZ77 compressor for the frames carrying whole stories, which are
+//! mostly repeated JSON keys and English.
+//!
+//! The output is a run of sequences, each a token byte, some literal bytes
+//! copied as they are, then a match: bytes repeated from earlier in the
+//! output, as a two-byte little-endian offset back. The token's high nibble
+//! is the number of literals and its low nibble how much longer than
+//! [`MIN_MATCH`] the match is, each 15 meaning more follows in bytes of
+//! 255 until one that's less. The last sequence has literals only.
+
+/// The shortest repeat worth a match.
+const MIN_MATCH: usize = 4;
+/// How far back a match can point.
+const WINDOW: usize = u16::MAX as usize;
+/// Bits of the hash of four bytes that earlier positions are looked up by.
+const HASH_BITS: u32 = 14;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    // Where each hash of four bytes was last seen
+    let mut seen = vec![None; 1 << HASH_BITS];
+    let mut literals = 0;
+    let mut at = 0;
+    while at + MIN_MATCH <= input.len() {
+        let next = &input[at..at + MIN_MATCH];
+        let earlier = seen[hash(next)].replace(at);
+        match earlier.filter(|earlier| {
+            at - earlier <= WINDOW && input[*earlier..*earlier + MIN_MATCH] == *next
+        }) {
+            Some(earlier) => {
+                let length = MIN_MATCH
+                    + input[at + MIN_MATCH..]
+                        .iter()
+                        .zip(&input[earlier + MIN_MATCH..])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                put_sequence(&mut out, &input[literals..at], Some((at - earlier, length)));
+                at += length;
+                literals = at;
+            }
+            None => at += 1,
+        }
+    }
+    put_sequence(&mut out, &input[literals..], None);
+    out
+}
+
+/// Undoes [`compress`], failing on input it couldn't have made or that
+/// would come to more than `limit` bytes.
+pub fn decompress(input: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let too_long = || format!("it comes to over the limit of {} bytes", limit);
+    let mut out = Vec::new();
+    let mut rest = input;
+    while let Some((&token, after)) = rest.split_first() {
+        rest = after;
+        let literals = read_length(&mut rest, token >> 4)?;
+        if literals > rest.len() {
+            return Err(String::from("it ends partway through"));
+        }
+        if out.len() + literals > limit {
+            return Err(too_long());
+        }
+        out.extend_from_slice(&rest[..literals]);
+        rest = &rest[literals..];
+        if rest.is_empty() {
+            break;
+        }
+        if rest.len() < 2 {
+            return Err(String::from("it ends partway through"));
+        }
+        let offset = usize::from(u16::from_le_bytes([rest[0], rest[1]]));
+        rest = &rest[2..];
+        if offset == 0 || offset > out.len() {
+            return Err(format!("it repeats from {} bytes back", offset));
+        }
+        let length = read_length(&mut rest, token & 0xf)? + MIN_MATCH;
+        if out.len() + length > limit {
+            return Err(too_long());
+        }
+        // Byte by byte, as a match can run on into what it's repeating
+        let start = out.len() - offset;
+        for index in start..start + length {
+            out.push(out[index]);
+        }
+    }
+    Ok(out)
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn put_sequence(out: &mut Vec<u8>, literals: &[u8], repeat: Option<(usize, usize)>) {
+    let extra = repeat.map_or(0, |(_, length)| length - MIN_MATCH);
+    out.push((literals.len().min(15) as u8) << 4 | extra.min(15) as u8);
+    put_length(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = repeat {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        put_length(out, extra);
+    }
+}
+
+/// The bytes after the token for a length of 15 or more.
+fn put_length(out: &mut Vec<u8>, length: usize) {
+    if let Some(mut rest) = length.checked_sub(15) {
+        while rest >= 255 {
+            out.push(255);
+            rest -= 255;
+        }
+        out.push(rest as u8);
+    }
+}
+
+fn read_length(rest: &mut &[u8], nibble: u8) -> Result<usize, String> {
+    let mut length = usize::from(nibble);
+    if nibble == 15 {
+        loop {
+            let (&byte, after) = rest
+                .split_first()
+                .ok_or_else(|| String::from("it ends partway through"))?;
+            *rest = after;
+            length = length
+                .checked_add(usize::from(byte))
+                .ok_or_else(|| String::from("it has a length too long to count"))?;
+            if byte < 255 {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn whatever_is_compressed_comes_back() {
+        let mut rng = StdRng::seed_from_u64(417);
+        let story = r#"{"text":"The lighthouse was dark.","timestamp":"2021-05-01T20:01:00Z"},"#
+            .repeat(300);
+        let mut inputs = vec![
+            vec![],
+            b"abc".to_vec(),
+            vec![b'a'; 100_000],
+            story.clone().into_bytes(),
+        ];
+        for _ in 0..200 {
+            // Random bytes, and random runs of a few letters with plenty
+            // to repeat, of every length around the token's limits
+            let length = rng.gen_range(0..600);
+            inputs.push((0..length).map(|_| rng.gen()).collect());
+            inputs.push((0..length).map(|_| b"ab c"[rng.gen_range(0..4)]).collect());
+        }
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+        assert!(compress(story.as_bytes()).len() < story.len() / 20);
+    }
+
+    #[test]
+    fn bad_input_is_refused_without_panicking() {
+        let mut rng = StdRng::seed_from_u64(418);
+        for _ in 0..2000 {
+            let input: Vec<u8> = (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect();
+            if let Ok(out) = decompress(&input, 1024) {
+                assert!(out.len() <= 1024);
+            }
+        }
+        // Repeating from before the start
+        assert!(decompress(&[0x10, b'a', 2, 0], 1024).is_err());
+        // Running past the limit
+        let compressed = compress(&[b'a'; 2000]);
+        assert!(decompress(&compressed, 1999).unwrap_err().contains("limit"));
+    }
+}
@@ -0,0 +1,561 @@
+use crate::{
+    access::Cidr,
+    filter::Incoming,
+    notify::Notify,
+    theme::{ColorChoice, ThemeName},
+};
+use std::{
+    convert::TryFrom,
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+/// Settings from the config file. Anything left out falls back to the
+/// command line flag, or the flag's default.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub port: Option<u16>,
+    pub bind: Option<IpAddr>,
+    pub allow: Option<Vec<Cidr>>,
+    pub block: Option<Vec<Cidr>>,
+    pub save_dir: Option<PathBuf>,
+    pub constraints: Option<bool>,
+    pub constraints_file: Option<PathBuf>,
+    pub theme: Option<ThemeName>,
+    pub color: Option<ColorChoice>,
+    pub keys: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub notify: Option<Notify>,
+    pub desktop_notify: Option<bool>,
+    pub dict: Option<PathBuf>,
+    pub spell_dict: Option<PathBuf>,
+    pub complete: Option<bool>,
+    pub write_timeout: Option<u64>,
+    pub tick_ms: Option<u64>,
+    pub typewriter: Option<u64>,
+    pub confirm_queued: Option<bool>,
+    pub sentence_history: Option<bool>,
+    pub wordlist: Option<PathBuf>,
+    pub wordlist_incoming: Option<Incoming>,
+    pub on_turn_hook: Option<PathBuf>,
+    pub on_turn_hook_timeout: Option<u64>,
+    pub git_export: Option<PathBuf>,
+    pub away_after: Option<u64>,
+    pub remind_after: Option<u64>,
+    pub remind_peer: Option<bool>,
+    pub rematch_timeout: Option<u64>,
+    pub cooldown: Option<u64>,
+    pub idle_timeout: Option<u64>,
+    pub idle_pause_away: Option<bool>,
+    pub invite_hint: Option<String>,
+    pub lang: Option<String>,
+    pub milestones: Option<bool>,
+    pub milestone_words: Option<u64>,
+    pub word_goal: Option<u64>,
+    pub export_notes: Option<bool>,
+    pub export_timeline: Option<bool>,
+    /// Problems worth mentioning that didn't stop the file from loading.
+    pub warnings: Vec<String>,
+}
+
+/// The template printed by `--print-default-config`, with every setting at
+/// its default and commented out.
+pub const DEFAULT: &str = r#"# write_together configuration. Command line flags override these settings.
+
+# Port to listen on for a peer. Without one, only outgoing connections are made
+# port = 4000
+
+# Address to listen on. 0.0.0.0 listens on every interface, for peers on other machines
+# bind = "127.0.0.1"
+
+# Addresses, or blocks of them such as "10.0.0.0/8", that may connect. Anyone unless some are listed
+# allow = []
+
+# Addresses, or blocks of them, turned away before they can say anything. Peers
+# blocked while playing are kept in ~/.config/write_together/blocklist instead
+# block = []
+
+# Directory sessions are saved to after every turn
+# save_dir = "~/.local/share/write_together/sessions"
+
+# Set a constraint for the peer's sentence every time you pass the turn
+# constraints = false
+
+# File listing the constraints to roll from, one per line
+# constraints_file = "constraints.txt"
+
+# Color theme: default, high-contrast or monochrome
+# theme = "default"
+
+# Whether to draw in color: always, auto or never. With auto, color is left
+# out when NO_COLOR is set or the terminal is dumb
+# color = "auto"
+
+# File of key bindings such as `quit = ctrl+q`, one per line
+# keys = "~/.config/write_together/keys"
+
+# Write diagnostics to this file, filtered with RUST_LOG
+# log_file = "write_together.log"
+
+# How to alert you when it becomes your turn: bell, flash or none
+# notify = "none"
+
+# Show a desktop notification when it becomes your turn while you're away
+# desktop_notify = false
+
+# Word list to suggest completions from, besides the story so far
+# dict = "/usr/share/dict/words"
+
+# Suggest word completions while typing
+# complete = true
+
+# Dictionary to check the spelling of what you type against, one word per line. No checking unless set
+# spell_dict = "/usr/share/dict/words"
+
+# Seconds a write to the peer may take before the connection counts as lost
+# write_timeout = 15
+
+# Milliseconds between redraws of anything animated, such as the spinner
+# tick_ms = 100
+
+# Characters a second the peer's sentences are typed out at as they arrive. 0 shows them at once
+# typewriter = 0
+
+# Wait for a sentence written during the peer's turn to be submitted again, rather than sending it
+# confirm_queued = false
+
+# Keep the sentences you write across runs, to recall with Up or search with Alt+R
+# sentence_history = false
+
+# File of words, one per line, that neither side's sentences may have in them
+# wordlist = "wordlist.txt"
+
+# What to do with the peer's sentences that have a listed word: mask or reject
+# wordlist_incoming = "mask"
+
+# Command run with every sentence on stdin, and WRITE_TOGETHER_AUTHOR and WRITE_TOGETHER_TURN set
+# on_turn_hook = "/path/to/script"
+
+# Seconds the turn hook may run before it's killed
+# on_turn_hook_timeout = 10
+
+# Git repository to commit every sentence to, appended to its story.md
+# git_export = "/path/to/repo"
+
+# Seconds without a key press before the peer is told you're away. Never unless set
+# away_after = 300
+
+# Minutes your turn can go on before you're reminded of it, and again every so many. Never unless set
+# remind_after = 5
+
+# Whether the peer is told you're still thinking when you're reminded
+# remind_peer = false
+
+# Seconds both of you have to agree on writing another story once one ends
+# rematch_timeout = 60
+
+# Seconds to leave between one sentence and the next, or the peer's if longer. 0 for none
+# cooldown = 0
+
+# Minutes a connection can go with no sentence or anything else from the peer
+# before it's closed, after a warning. 0 keeps it open however long
+# idle_timeout = 0
+
+# Leave time either of you is away out of the idle timeout
+# idle_pause_away = false
+
+# A reminder put in your invite for the peer, such as which passphrase to use
+# invite_hint = "the usual one"
+
+# Language to show everything in. Taken from LANG if not set
+# lang = "en"
+
+# Celebrate with a banner on both sides when the story reaches a milestone
+# milestones = true
+
+# Words between milestones
+# milestone_words = 500
+
+# Word count to aim for, celebrated once reached. None unless set
+# word_goal = 2000
+
+# End exports with your scratchpad notes, which are otherwise left out
+# export_notes = false
+
+# End Markdown and HTML exports with when the players connected, left and
+# took sentences back
+# export_timeline = false
+"#;
+
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    /// Only lists of strings are needed.
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "true or false",
+            Value::Array(_) => "a list",
+        }
+    }
+
+    /// Parses a TOML value, returning it with whatever follows it.
+    fn parse(text: &str) -> Option<(Self, &str)> {
+        if let Some(quoted) = text.strip_prefix('"') {
+            let mut string = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '"' => return Some((Value::String(string), &quoted[index + 1..])),
+                    '\\' => string.push(match chars.next()?.1 {
+                        'n' => '\n',
+                        't' => '\t',
+                        c @ ('"' | '\\') => c,
+                        _ => return None,
+                    }),
+                    c => string.push(c),
+                }
+            }
+            return None;
+        }
+        if let Some(mut rest) = text.strip_prefix('[') {
+            let mut items = vec![];
+            loop {
+                rest = rest.trim_start();
+                if let Some(after) = rest.strip_prefix(']') {
+                    return Some((Value::Array(items), after));
+                }
+                match Value::parse(rest)? {
+                    (Value::String(item), after) => {
+                        items.push(item);
+                        rest = after.trim_start();
+                    }
+                    _ => return None,
+                }
+                if let Some(after) = rest.strip_prefix(',') {
+                    rest = after;
+                } else if !rest.starts_with(']') {
+                    return None;
+                }
+            }
+        }
+        let end = text.find(|c: char| c.is_whitespace() || c == '#');
+        let (word, rest) = text.split_at(end.unwrap_or(text.len()));
+        let value = match word {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ => Value::Integer(word.replace('_', "").parse().ok()?),
+        };
+        Some((value, rest))
+    }
+}
+
+impl Config {
+    /// Loads the config file at `path`. A missing file is only an error when
+    /// it was asked for, rather than being the default location.
+    pub fn load(path: &Path, required: bool) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents, path),
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(err) => Err(io::Error::new(
+                err.kind(),
+                format!("{}: {}", path.display(), err),
+            )),
+        }
+    }
+
+    /// Parses the flat `key = value` subset of TOML the settings need.
+    fn parse(contents: &str, path: &Path) -> io::Result<Self> {
+        let mut config = Self::default();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path.display(), index + 1, message),
+                )
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected `key = value`, found '{}'", line)))?;
+            let key = key.trim();
+            let value = match Value::parse(value.trim()) {
+                Some((value, rest)) if rest.trim().is_empty() || rest.trim().starts_with('#') => {
+                    value
+                }
+                _ => return Err(invalid(format!("invalid value for {}", key))),
+            };
+            let mistyped = |expected: &str| {
+                invalid(format!(
+                    "{} should be {}, not {}",
+                    key,
+                    expected,
+                    value.kind()
+                ))
+            };
+            let string = || match &value {
+                Value::String(string) => Ok(string.clone()),
+                _ => Err(mistyped("a string")),
+            };
+            let home_path = || string().map(|path| expand_home(&path));
+            let positive = || match value {
+                Value::Integer(number) if number > 0 => Ok(number as u64),
+                Value::Integer(number) => {
+                    Err(invalid(format!("{} should be over 0, not {}", key, number)))
+                }
+                _ => Err(mistyped("an integer")),
+            };
+            let non_negative = || match value {
+                Value::Integer(number) if number >= 0 => Ok(number as u64),
+                Value::Integer(number) => Err(invalid(format!(
+                    "{} should be 0 or over, not {}",
+                    key, number
+                ))),
+                _ => Err(mistyped("an integer")),
+            };
+            let boolean = || match value {
+                Value::Boolean(boolean) => Ok(boolean),
+                _ => Err(mistyped("true or false")),
+            };
+            let addresses = || match &value {
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| item.parse::<Cidr>().map_err(invalid))
+                    .collect(),
+                _ => Err(mistyped("a list")),
+            };
+            match key {
+                "port" => {
+                    config.port = match value {
+                        Value::Integer(port) => Some(
+                            u16::try_from(port)
+                                .map_err(|_| invalid(format!("port {} is out of range", port)))?,
+                        ),
+                        _ => return Err(mistyped("an integer")),
+                    }
+                }
+                "bind" => {
+                    let address = string()?;
+                    config.bind = Some(address.parse().map_err(|_| {
+                        invalid(format!("bind should be an IP address, not '{}'", address))
+                    })?)
+                }
+                "allow" => config.allow = Some(addresses()?),
+                "block" => config.block = Some(addresses()?),
+                "save_dir" => config.save_dir = Some(home_path()?),
+                "constraints" => config.constraints = Some(boolean()?),
+                "constraints_file" => config.constraints_file = Some(home_path()?),
+                "theme" => config.theme = Some(string()?.parse().map_err(invalid)?),
+                "color" => config.color = Some(string()?.parse().map_err(invalid)?),
+                "keys" => config.keys = Some(home_path()?),
+                "log_file" => config.log_file = Some(home_path()?),
+                "notify" => config.notify = Some(string()?.parse().map_err(invalid)?),
+                "desktop_notify" => config.desktop_notify = Some(boolean()?),
+                "dict" => config.dict = Some(home_path()?),
+                "spell_dict" => config.spell_dict = Some(home_path()?),
+                "complete" => config.complete = Some(boolean()?),
+                "write_timeout" => config.write_timeout = Some(positive()?),
+                "tick_ms" => config.tick_ms = Some(positive()?),
+                "typewriter" => config.typewriter = Some(non_negative()?),
+                "confirm_queued" => config.confirm_queued = Some(boolean()?),
+                "sentence_history" => config.sentence_history = Some(boolean()?),
+                "wordlist" => config.wordlist = Some(home_path()?),
+                "wordlist_incoming" => {
+                    config.wordlist_incoming = Some(string()?.parse().map_err(invalid)?)
+                }
+                "on_turn_hook" => config.on_turn_hook = Some(home_path()?),
+                "on_turn_hook_timeout" => config.on_turn_hook_timeout = Some(positive()?),
+                "git_export" => config.git_export = Some(home_path()?),
+                "away_after" => config.away_after = Some(positive()?),
+                "remind_after" => config.remind_after = Some(positive()?),
+                "remind_peer" => config.remind_peer = Some(boolean()?),
+                "rematch_timeout" => config.rematch_timeout = Some(positive()?),
+                "cooldown" => config.cooldown = Some(non_negative()?),
+                "idle_timeout" => config.idle_timeout = Some(non_negative()?),
+                "idle_pause_away" => config.idle_pause_away = Some(boolean()?),
+                "invite_hint" => config.invite_hint = Some(string()?),
+                "lang" => config.lang = Some(string()?),
+                "milestones" => config.milestones = Some(boolean()?),
+                "export_notes" => config.export_notes = Some(boolean()?),
+                "export_timeline" => config.export_timeline = Some(boolean()?),
+                "milestone_words" => config.milestone_words = Some(positive()?),
+                "word_goal" => config.word_goal = Some(positive()?),
+                _ => config.warnings.push(format!(
+                    "{}:{}: unknown setting '{}' ignored",
+                    path.display(),
+                    index + 1,
+                    key
+                )),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// `path` with a leading `~/` standing for the home directory, as it would
+/// in a shell.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Where settings are read from when no `--config` is given.
+pub fn default_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("write_together")
+            .join("config.toml"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, DEFAULT};
+    use crate::{
+        filter::Incoming,
+        notify::Notify,
+        theme::{ColorChoice, ThemeName},
+    };
+    use std::path::{Path, PathBuf};
+
+    fn parse(contents: &str) -> std::io::Result<Config> {
+        Config::parse(contents, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn default_template_parses_once_uncommented() {
+        assert_eq!(parse(DEFAULT).unwrap(), Config::default());
+        let uncommented = DEFAULT
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting)
+                    if setting.split_once(" = ").is_some_and(|(key, _)| {
+                        key.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+                    }) =>
+                {
+                    setting
+                }
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = parse(&uncommented).unwrap();
+        assert_eq!(config.port, Some(4000));
+        assert_eq!(config.bind, Some([127, 0, 0, 1].into()));
+        assert_eq!(config.allow, Some(vec![]));
+        assert_eq!(config.block, Some(vec![]));
+        assert_eq!(config.theme, Some(ThemeName::Default));
+        assert_eq!(config.color, Some(ColorChoice::Auto));
+        assert_eq!(config.notify, Some(Notify::None));
+        assert_eq!(config.complete, Some(true));
+        assert_eq!(config.write_timeout, Some(15));
+        assert_eq!(config.tick_ms, Some(100));
+        assert_eq!(config.typewriter, Some(0));
+        assert_eq!(config.confirm_queued, Some(false));
+        assert_eq!(config.sentence_history, Some(false));
+        assert_eq!(config.wordlist_incoming, Some(Incoming::Mask));
+        assert_eq!(config.on_turn_hook_timeout, Some(10));
+        assert_eq!(config.git_export, Some(PathBuf::from("/path/to/repo")));
+        assert_eq!(config.away_after, Some(300));
+        assert_eq!(config.remind_after, Some(5));
+        assert_eq!(config.remind_peer, Some(false));
+        assert_eq!(config.rematch_timeout, Some(60));
+        assert_eq!(config.cooldown, Some(0));
+        assert_eq!(config.idle_timeout, Some(0));
+        assert_eq!(config.idle_pause_away, Some(false));
+        assert_eq!(config.invite_hint.as_deref(), Some("the usual one"));
+        assert_eq!(config.lang.as_deref(), Some("en"));
+        assert_eq!(config.milestones, Some(true));
+        assert_eq!(config.milestone_words, Some(500));
+        assert_eq!(config.word_goal, Some(2000));
+        assert_eq!(config.export_notes, Some(false));
+        assert_eq!(config.export_timeline, Some(false));
+        assert!(config.warnings.is_empty());
+    }
+
+    #[test]
+    fn reads_values_and_warns_about_unknown_keys() {
+        let config = parse(
+            "port = 4_001 # trailing comment\n\
+             save_dir = \"C:\\\\stories\"\n\
+             colour = \"blue\"\n\
+             block = [ \"203.0.113.0/24\", \"2001:db8::1\", ] # trailing comma\n",
+        )
+        .unwrap();
+        assert_eq!(config.port, Some(4001));
+        assert_eq!(config.save_dir, Some(PathBuf::from("C:\\stories")));
+        assert_eq!(
+            config.block,
+            Some(vec![
+                "203.0.113.0/24".parse().unwrap(),
+                "2001:db8::1".parse().unwrap()
+            ])
+        );
+        assert_eq!(
+            config.warnings,
+            vec!["config.toml:3: unknown setting 'colour' ignored"]
+        );
+    }
+
+    #[test]
+    fn paths_can_start_from_the_home_directory() {
+        let config = parse(
+            "save_dir = \"~/.local/share/write_together/sessions\"\n\
+             keys = \"~user/keys\"\n",
+        )
+        .unwrap();
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            config.save_dir,
+            Some(home.join(".local/share/write_together/sessions"))
+        );
+        // Only our own home directory is known
+        assert_eq!(config.keys, Some(PathBuf::from("~user/keys")));
+    }
+
+    #[test]
+    fn type_errors_name_the_line() {
+        let error = |contents| parse(contents).unwrap_err().to_string();
+        assert_eq!(
+            error("\nport = \"4000\""),
+            "config.toml:2: port should be an integer, not a string"
+        );
+        assert_eq!(
+            error("port = 70000"),
+            "config.toml:1: port 70000 is out of range"
+        );
+        assert!(error("theme = \"sepia\"").starts_with("config.toml:1: unknown theme 'sepia'"));
+        assert!(error("color = \"sometimes\"")
+            .starts_with("config.toml:1: unknown color setting 'sometimes'"));
+        assert_eq!(
+            error("tick_ms = 0"),
+            "config.toml:1: tick_ms should be over 0, not 0"
+        );
+        assert!(error("allow = [\"203.0.113.0/33\"]")
+            .starts_with("config.toml:1: '203.0.113.0/33' isn't an address"));
+        assert_eq!(
+            error("block = \"203.0.113.1\""),
+            "config.toml:1: block should be a list, not a string"
+        );
+        assert_eq!(
+            error("block = [\"203.0.113.1\" \"203.0.113.2\"]"),
+            "config.toml:1: invalid value for block"
+        );
+        assert_eq!(
+            error("notify"),
+            "config.toml:1: expected `key = value`, found 'notify'"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{counter, i18n::Message};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+/// A challenge the next writer's sentence has to satisfy.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Constraint {
+    IncludeWord(String),
+    AvoidLetter(char),
+    MaxWords(usize),
+    StartsWith(char),
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl Constraint {
+    /// What the constraint asks for, to show in the user's language.
+    pub fn message(&self) -> Message {
+        match self {
+            Constraint::IncludeWord(word) => Message::new("constraint-include").arg("word", word),
+            Constraint::AvoidLetter(letter) => {
+                Message::new("constraint-avoid").arg("letter", letter.to_ascii_uppercase())
+            }
+            Constraint::MaxWords(count) => Message::new("constraint-max-words").arg("count", count),
+            Constraint::StartsWith(letter) => {
+                Message::new("constraint-starts-with").arg("letter", letter.to_ascii_uppercase())
+            }
+        }
+    }
+
+    /// Checks `sentence` against the constraint, describing why it fails.
+    pub fn check(&self, sentence: &str) -> Result<(), Message> {
+        let mut words = counter::words(sentence);
+        let satisfied = match self {
+            Constraint::IncludeWord(word) => words.any(|w| w.eq_ignore_ascii_case(word)),
+            Constraint::AvoidLetter(letter) => {
+                !sentence.chars().any(|c| c.eq_ignore_ascii_case(letter))
+            }
+            Constraint::MaxWords(count) => words.count() <= *count,
+            Constraint::StartsWith(letter) => sentence
+                .chars()
+                .find(|c| c.is_alphabetic())
+                .is_some_and(|c| c.eq_ignore_ascii_case(letter)),
+        };
+        if satisfied {
+            Ok(())
+        } else {
+            Err(Message::new("breaks-constraint").arg_message("constraint", self.message()))
+        }
+    }
+
+    /// Parses a line of a constraint list file, e.g. `include lantern`,
+    /// `avoid e`, `max-words 8` or `starts-with t`.
+    fn parse(line: &str) -> Option<Self> {
+        let (kind, argument) = line.split_once(' ')?;
+        let argument = argument.trim();
+        let letter = || {
+            let mut chars = argument.chars();
+            match (chars.next(), chars.next()) {
+                (Some(letter), None) if letter.is_alphabetic() => Some(letter),
+                _ => None,
+            }
+        };
+        match kind {
+            "include" if !argument.is_empty() => {
+                Some(Constraint::IncludeWord(argument.to_string()))
+            }
+            "avoid" => letter().map(Constraint::AvoidLetter),
+            "max-words" => argument.parse().ok().map(Constraint::MaxWords),
+            "starts-with" => letter().map(Constraint::StartsWith),
+            _ => None,
+        }
+    }
+}
+
+const WORDS: &[&str] = &[
+    "lantern", "river", "clock", "whisper", "mirror", "storm", "key", "feather", "shadow",
+    "garden", "engine", "letter",
+];
+
+const LETTERS: &[char] = &['e', 'a', 'o', 't', 'i', 's'];
+
+const STARTING_LETTERS: &[char] = &['t', 's', 'w', 'b', 'm', 'h', 'a'];
+
+/// Where constraints are rolled from: either the built-in generators or the
+/// list loaded from a user's file.
+#[derive(Clone, Debug)]
+pub enum ConstraintPool {
+    BuiltIn,
+    List(Vec<Constraint>),
+}
+
+impl ConstraintPool {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let constraints = fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(index, line)| {
+                Constraint::parse(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}:{}: invalid constraint '{}'",
+                            path.display(),
+                            index + 1,
+                            line
+                        ),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if constraints.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: no constraints listed", path.display()),
+            ));
+        }
+        Ok(ConstraintPool::List(constraints))
+    }
+
+    pub fn roll(&self) -> Constraint {
+        let mut rng = rand::thread_rng();
+        match self {
+            ConstraintPool::BuiltIn => match rng.gen_range(0..4) {
+                0 => Constraint::IncludeWord(WORDS.choose(&mut rng).unwrap().to_string()),
+                1 => Constraint::AvoidLetter(*LETTERS.choose(&mut rng).unwrap()),
+                2 => Constraint::MaxWords(rng.gen_range(5..=12)),
+                _ => Constraint::StartsWith(*STARTING_LETTERS.choose(&mut rng).unwrap()),
+            },
+            ConstraintPool::List(constraints) => constraints.choose(&mut rng).unwrap().clone(),
+        }
+    }
+}
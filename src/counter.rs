@@ -0,0 +1,91 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The words of `text`, the way constraints and counters see them:
+/// letters, digits and apostrophes, split on everything else.
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+}
+
+/// How long a piece of text is, in user-perceived characters and words.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Counts {
+    pub chars: usize,
+    pub words: usize,
+}
+
+impl Counts {
+    pub fn of(text: &str) -> Self {
+        Self {
+            chars: text.graphemes(true).count(),
+            words: words(text).count(),
+        }
+    }
+}
+
+/// `count` with its digits in groups of three, such as `12,500`.
+pub fn thousands(count: usize) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// How close a count is to its limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Nearness {
+    Under,
+    /// Within a fifth of the limit.
+    Near,
+    /// At the limit or over it.
+    At,
+}
+
+pub fn nearness(count: usize, limit: usize) -> Nearness {
+    if count >= limit {
+        Nearness::At
+    } else if count * 5 >= limit * 4 {
+        Nearness::Near
+    } else {
+        Nearness::Under
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearness, thousands, Counts, Nearness};
+
+    #[test]
+    fn counts_graphemes_and_words() {
+        assert_eq!(
+            Counts::of("Don't stop—the cafe\u{301} is 24/7."),
+            Counts {
+                chars: 28,
+                words: 7
+            }
+        );
+        assert_eq!(Counts::of("  "), Counts { chars: 2, words: 0 });
+        // Characters, not the columns they take
+        assert_eq!(Counts::of("漢字ok").chars, 4);
+    }
+
+    #[test]
+    fn groups_thousands() {
+        assert_eq!(thousands(500), "500");
+        assert_eq!(thousands(1000), "1,000");
+        assert_eq!(thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn nearness_to_limit() {
+        assert_eq!(nearness(7, 10), Nearness::Under);
+        assert_eq!(nearness(8, 10), Nearness::Near);
+        assert_eq!(nearness(10, 10), Nearness::At);
+        assert_eq!(nearness(12, 10), Nearness::At);
+    }
+}
@@ -0,0 +1,22 @@
+use std::{fs::OpenOptions, io, panic, path::Path, sync::Mutex};
+use tracing_subscriber::EnvFilter;
+
+/// Sends diagnostics to the file at `path`, filtered by `RUST_LOG` (`info`
+/// when it isn't set), since stdout belongs to the terminal UI.
+///
+/// Also logs panics, so a task that dies leaves a trace behind.
+pub fn init(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(Mutex::new(file))
+        .init();
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        tracing::error!(thread = ?std::thread::current().name(), "{}", info);
+        default_hook(info);
+    }));
+    Ok(())
+}
@@ -0,0 +1,394 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A single line of editable text with a cursor, shared by the Input and
+/// Connect fields.
+///
+/// The text is held as grapheme clusters so that the cursor and deletions
+/// always move over whole user-perceived characters.
+#[derive(Debug, Default)]
+pub struct EditBuffer {
+    graphemes: Vec<String>,
+    /// Index into `graphemes` the next character is inserted at.
+    cursor: usize,
+}
+
+fn segment(text: &str) -> Vec<String> {
+    text.graphemes(true).map(String::from).collect()
+}
+
+fn is_whitespace(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+impl EditBuffer {
+    pub fn text(&self) -> String {
+        self.graphemes.concat()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The number of user-perceived characters.
+    pub fn len(&self) -> usize {
+        self.graphemes.len()
+    }
+
+    /// The number of terminal columns the text takes up.
+    pub fn width(&self) -> usize {
+        self.graphemes.iter().map(|grapheme| grapheme.width()).sum()
+    }
+
+    /// How many bytes of the text are before the cursor.
+    pub fn cursor_offset(&self) -> usize {
+        self.graphemes[..self.cursor].iter().map(String::len).sum()
+    }
+
+    /// The number of terminal columns before the cursor.
+    pub fn cursor_width(&self) -> usize {
+        self.graphemes[..self.cursor]
+            .iter()
+            .map(|grapheme| grapheme.width())
+            .sum()
+    }
+
+    /// Replaces the contents, leaving the cursor at the end.
+    pub fn set(&mut self, text: &str) {
+        self.graphemes = segment(text);
+        self.cursor = self.graphemes.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.graphemes.clear();
+        self.cursor = 0;
+    }
+
+    /// Inserts `c` at the cursor, joining it onto the grapheme before the
+    /// cursor when it extends it, e.g. with a combining accent.
+    pub fn insert(&mut self, c: char) {
+        let mut before = self.graphemes[..self.cursor].concat();
+        before.push(c);
+        let after = self.graphemes[self.cursor..].concat();
+        self.cursor = before.graphemes(true).count();
+        self.graphemes = segment(&(before + &after));
+    }
+
+    /// Removes the character before the cursor.
+    pub fn backspace(&mut self) -> Option<String> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        Some(self.graphemes.remove(self.cursor))
+    }
+
+    /// Removes the character under the cursor.
+    pub fn delete(&mut self) -> Option<String> {
+        if self.cursor == self.graphemes.len() {
+            return None;
+        }
+        Some(self.graphemes.remove(self.cursor))
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.graphemes.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.graphemes.len();
+    }
+
+    /// Breaks the text into lines at most `width` columns wide, returning
+    /// them with the column and line the cursor is on.
+    pub fn wrap(&self, width: usize) -> (Vec<String>, (usize, usize)) {
+        let mut lines = vec![String::new()];
+        let mut line_width = 0;
+        let mut cursor = (0, 0);
+        for (index, grapheme) in self.graphemes.iter().enumerate() {
+            let grapheme_width = grapheme.width();
+            if line_width + grapheme_width > width && line_width > 0 {
+                lines.push(String::new());
+                line_width = 0;
+            }
+            if index == self.cursor {
+                cursor = (line_width, lines.len() - 1);
+            }
+            lines.last_mut().unwrap().push_str(grapheme);
+            line_width += grapheme_width;
+        }
+        if self.cursor == self.graphemes.len() {
+            cursor = if line_width >= width && line_width > 0 {
+                (0, lines.len())
+            } else {
+                (line_width, lines.len() - 1)
+            };
+        }
+        (lines, cursor)
+    }
+
+    /// The part of the text that fits on one line `width` columns wide,
+    /// scrolled to keep the cursor in view, with the cursor's column in it.
+    /// A wide character under the cursor is kept whole.
+    pub fn window(&self, width: usize) -> (String, usize) {
+        let width = width.max(1);
+        let under_cursor = self
+            .graphemes
+            .get(self.cursor)
+            .map_or(1, |grapheme| grapheme.width().max(1));
+        let mut start = 0;
+        let mut cursor_x = self.cursor_width();
+        while cursor_x + under_cursor > width && start < self.cursor {
+            cursor_x -= self.graphemes[start].width();
+            start += 1;
+        }
+        let mut text = String::new();
+        let mut used = 0;
+        for grapheme in &self.graphemes[start..] {
+            if used + grapheme.width() > width {
+                break;
+            }
+            used += grapheme.width();
+            text.push_str(grapheme);
+        }
+        (text, cursor_x)
+    }
+
+    /// Where the word the cursor is at the end of starts, if it's at the end
+    /// of one.
+    fn word_start(&self) -> Option<usize> {
+        let in_word =
+            |grapheme: &String| grapheme.starts_with(|c: char| c.is_alphanumeric() || c == '\'');
+        if self.graphemes.get(self.cursor).is_some_and(in_word) {
+            return None;
+        }
+        let start = self.cursor
+            - self.graphemes[..self.cursor]
+                .iter()
+                .rev()
+                .take_while(|grapheme| in_word(grapheme))
+                .count();
+        Some(start).filter(|start| *start < self.cursor)
+    }
+
+    /// The word the cursor is at the end of, if any.
+    pub fn word_at_cursor(&self) -> Option<String> {
+        Some(self.graphemes[self.word_start()?..self.cursor].concat())
+    }
+
+    /// Puts `word` in place of the word the cursor is at the end of, with
+    /// the cursor after it.
+    pub fn replace_word(&mut self, word: &str) {
+        if let Some(start) = self.word_start() {
+            let after = self.graphemes.split_off(self.cursor);
+            self.graphemes.truncate(start);
+            self.graphemes.extend(segment(word));
+            self.cursor = self.graphemes.len();
+            self.graphemes.extend(after);
+        }
+    }
+
+    /// Where the word before the cursor starts, skipping whitespace first.
+    fn previous_word_start(&self) -> usize {
+        let mut index = self.cursor;
+        while index > 0 && is_whitespace(&self.graphemes[index - 1]) {
+            index -= 1;
+        }
+        while index > 0 && !is_whitespace(&self.graphemes[index - 1]) {
+            index -= 1;
+        }
+        index
+    }
+
+    /// Where the word after the cursor ends, skipping whitespace first.
+    fn next_word_end(&self) -> usize {
+        let mut index = self.cursor;
+        while index < self.graphemes.len() && is_whitespace(&self.graphemes[index]) {
+            index += 1;
+        }
+        while index < self.graphemes.len() && !is_whitespace(&self.graphemes[index]) {
+            index += 1;
+        }
+        index
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.previous_word_start();
+    }
+
+    pub fn word_right(&mut self) {
+        self.cursor = self.next_word_end();
+    }
+
+    /// Deletes the word before the cursor (Ctrl+W).
+    pub fn delete_word(&mut self) {
+        let start = self.previous_word_start();
+        self.graphemes.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Deletes everything before the cursor (Ctrl+U).
+    pub fn delete_to_start(&mut self) {
+        self.graphemes.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    /// Deletes everything from the cursor on (Ctrl+K).
+    pub fn delete_to_end(&mut self) {
+        self.graphemes.truncate(self.cursor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EditBuffer;
+
+    fn buffer(text: &str, cursor: usize) -> EditBuffer {
+        let mut buffer = EditBuffer::default();
+        buffer.set(text);
+        buffer.cursor = cursor;
+        buffer
+    }
+
+    #[test]
+    fn insert_and_backspace_at_cursor() {
+        let mut buffer = buffer("helo", 3);
+        buffer.insert('l');
+        assert_eq!(buffer.text(), "hello");
+        assert_eq!(buffer.cursor(), 4);
+
+        buffer.home();
+        assert_eq!(buffer.backspace(), None);
+        assert_eq!(buffer.delete().as_deref(), Some("h"));
+        assert_eq!(buffer.text(), "ello");
+    }
+
+    #[test]
+    fn delete_word_skips_trailing_whitespace() {
+        let mut buffer = buffer("the quick  brown", 11);
+        buffer.delete_word();
+        assert_eq!(buffer.text(), "the brown");
+        assert_eq!(buffer.cursor(), 4);
+
+        buffer.delete_word();
+        assert_eq!(buffer.text(), "brown");
+        assert_eq!(buffer.cursor(), 0);
+
+        buffer.delete_word();
+        assert_eq!(buffer.text(), "brown");
+    }
+
+    #[test]
+    fn delete_to_end_keeps_cursor() {
+        let mut buffer = buffer("once upon a time", 5);
+        buffer.delete_to_end();
+        assert_eq!(buffer.text(), "once ");
+        assert_eq!(buffer.cursor(), 5);
+    }
+
+    #[test]
+    fn delete_to_start_moves_cursor_home() {
+        let mut buffer = buffer("once upon a time", 5);
+        buffer.delete_to_start();
+        assert_eq!(buffer.text(), "upon a time");
+        assert_eq!(buffer.cursor(), 0);
+    }
+
+    #[test]
+    fn window_scrolls_to_the_cursor() {
+        assert_eq!(buffer("once upon", 2).window(4), (String::from("once"), 2));
+        assert_eq!(buffer("once upon", 9).window(4), (String::from("pon"), 3));
+        assert_eq!(buffer("漢字漢字", 3).window(4), (String::from("漢字"), 2));
+        // Scrolled on until the wide character the cursor is on fits
+        assert_eq!(buffer("ab漢字", 2).window(3), (String::from("b漢"), 1));
+    }
+
+    #[test]
+    fn word_at_cursor_needs_the_end_of_a_word() {
+        assert_eq!(buffer("(lant", 5).word_at_cursor().as_deref(), Some("lant"));
+        assert_eq!(buffer("lantern", 4).word_at_cursor(), None);
+        assert_eq!(buffer("lant ", 5).word_at_cursor(), None);
+    }
+
+    #[test]
+    fn replacing_the_word_at_the_cursor() {
+        let mut buffer = buffer("The lihgt went out", 9);
+        assert_eq!(buffer.cursor_offset(), 9);
+        buffer.replace_word("light");
+        assert_eq!(buffer.text(), "The light went out");
+        assert_eq!(buffer.cursor(), 9);
+        // Only at the end of a word
+        buffer.left();
+        buffer.replace_word("night");
+        assert_eq!(buffer.text(), "The light went out");
+    }
+
+    #[test]
+    fn word_movement() {
+        let mut buffer = buffer("once upon  a time", 0);
+        buffer.word_right();
+        assert_eq!(buffer.cursor(), 4);
+        buffer.word_right();
+        assert_eq!(buffer.cursor(), 9);
+        buffer.word_right();
+        assert_eq!(buffer.cursor(), 12);
+
+        buffer.word_left();
+        assert_eq!(buffer.cursor(), 11);
+        buffer.word_left();
+        assert_eq!(buffer.cursor(), 5);
+
+        buffer.end();
+        buffer.word_right();
+        assert_eq!(buffer.cursor(), 17);
+    }
+
+    #[test]
+    fn backspace_removes_whole_graphemes() {
+        for grapheme in ["👩\u{200d}👩\u{200d}👧", "👍🏽", "e\u{301}", "漢"] {
+            let mut buffer = buffer(&format!("a{}", grapheme), 2);
+            assert_eq!(buffer.len(), 2);
+            assert_eq!(buffer.backspace().as_deref(), Some(grapheme));
+            assert_eq!(buffer.text(), "a");
+        }
+    }
+
+    #[test]
+    fn combining_mark_joins_previous_grapheme() {
+        let mut buffer = buffer("cafe", 4);
+        buffer.insert('\u{301}');
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.cursor(), 4);
+        buffer.left();
+        assert_eq!(buffer.delete().as_deref(), Some("e\u{301}"));
+    }
+
+    #[test]
+    fn cursor_width_counts_wide_characters() {
+        let mut buffer = buffer("漢字ok", 2);
+        assert_eq!(buffer.cursor_width(), 4);
+        assert_eq!(buffer.width(), 6);
+        buffer.backspace();
+        assert_eq!(buffer.text(), "漢ok");
+        assert_eq!(buffer.cursor_width(), 2);
+    }
+
+    #[test]
+    fn wrap_keeps_wide_characters_whole() {
+        let (lines, cursor) = buffer("ab漢字", 3).wrap(3);
+        assert_eq!(lines, vec!["ab", "漢", "字"]);
+        assert_eq!(cursor, (0, 2));
+
+        let (lines, cursor) = buffer("abc", 3).wrap(3);
+        assert_eq!(lines, vec!["abc"]);
+        assert_eq!(cursor, (0, 1));
+    }
+}
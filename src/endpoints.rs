@@ -0,0 +1,145 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+use tracing::debug;
+
+/// The addresses a peer could reach us at while listening on `bound`: the
+/// address of every interface but loopback when bound to all of them, or
+/// else just `bound`, which is also what's left if the interfaces can't be
+/// listed.
+pub fn candidates(bound: SocketAddr) -> Vec<SocketAddr> {
+    if !bound.ip().is_unspecified() {
+        return vec![bound];
+    }
+    let addresses = match interface_addresses() {
+        Ok(addresses) => addresses,
+        Err(err) => {
+            debug!(%err, "couldn't list network interfaces");
+            return vec![bound];
+        }
+    };
+    let candidates = reachable(bound, addresses);
+    if candidates.is_empty() {
+        vec![bound]
+    } else {
+        candidates
+    }
+}
+
+/// The addresses out of `addresses` that a listener on the unspecified
+/// address `bound` accepts connections on from elsewhere, IPv4 first.
+/// Link-local IPv6 addresses are left out, as they can't be dialled without
+/// the interface's scope id.
+fn reachable(bound: SocketAddr, mut addresses: Vec<IpAddr>) -> Vec<SocketAddr> {
+    addresses
+        .retain(|ip| !ip.is_loopback() && !is_link_local(ip) && (bound.is_ipv6() || ip.is_ipv4()));
+    addresses.sort_by_key(|ip| (ip.is_ipv6(), *ip));
+    addresses.dedup();
+    addresses
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, bound.port()))
+        .collect()
+}
+
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(_) => false,
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// The address of every network interface that has one.
+#[cfg(unix)]
+fn interface_addresses() -> io::Result<Vec<IpAddr>> {
+    let mut list = std::ptr::null_mut();
+    // SAFETY: getifaddrs only writes the head of a list it allocates, which
+    // is freed below once read
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut addresses = vec![];
+    let mut entry = list;
+    while !entry.is_null() {
+        // SAFETY: every entry in the list is valid until freeifaddrs, and
+        // ifa_addr points at a sockaddr of the family it names
+        unsafe {
+            let address = (*entry).ifa_addr;
+            if !address.is_null() {
+                match i32::from((*address).sa_family) {
+                    libc::AF_INET => {
+                        let address = &*(address as *const libc::sockaddr_in);
+                        addresses.push(IpAddr::from(address.sin_addr.s_addr.to_ne_bytes()));
+                    }
+                    libc::AF_INET6 => {
+                        let address = &*(address as *const libc::sockaddr_in6);
+                        addresses.push(IpAddr::from(address.sin6_addr.s6_addr));
+                    }
+                    _ => {}
+                }
+            }
+            entry = (*entry).ifa_next;
+        }
+    }
+    // SAFETY: the list came from getifaddrs and isn't used after this
+    unsafe { libc::freeifaddrs(list) };
+    Ok(addresses)
+}
+
+#[cfg(not(unix))]
+fn interface_addresses() -> io::Result<Vec<IpAddr>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing interfaces is only supported on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{candidates, reachable};
+    use std::net::IpAddr;
+
+    #[test]
+    fn a_specific_address_is_the_only_candidate() {
+        let bound = "127.0.0.1:4000".parse().unwrap();
+        assert_eq!(candidates(bound), vec![bound]);
+    }
+
+    #[test]
+    fn all_interfaces_but_loopback_are_candidates() {
+        let addresses: Vec<IpAddr> = [
+            "2001:db8::1",
+            "127.0.0.1",
+            "192.168.1.20",
+            "::1",
+            "10.0.0.2",
+        ]
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+        let ports = |bound: &str| {
+            reachable(bound.parse().unwrap(), addresses.clone())
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ports("0.0.0.0:4000"),
+            ["10.0.0.2:4000", "192.168.1.20:4000"]
+        );
+        assert_eq!(
+            ports("[::]:4000"),
+            ["10.0.0.2:4000", "192.168.1.20:4000", "[2001:db8::1]:4000"]
+        );
+        // Link-local addresses need a scope id to be dialled
+        let bound = "[::]:4000".parse().unwrap();
+        let link_local = vec!["fe80::1".parse().unwrap(), "febf::1".parse().unwrap()];
+        assert!(reachable(bound, link_local).is_empty());
+        let not_link_local = vec!["fec0::1".parse().unwrap()];
+        assert_eq!(reachable(bound, not_link_local).len(), 1);
+        // Falls back to the bound address when there's nothing else
+        let bound = "0.0.0.0:4000".parse().unwrap();
+        assert!(reachable(bound, vec![]).is_empty());
+        assert!(!candidates(bound).is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    export::{escape, summary},
+    sessions::SessionInstance,
+    zip::ZipWriter,
+};
+use std::fmt::Write;
+
+/// Sentences in each chapter, so that e-readers needn't lay out a long
+/// story all at once.
+const CHAPTER_SENTENCES: usize = 100;
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+/// Renders a session as an EPUB 3 book: the story in chapters of
+/// [`CHAPTER_SENTENCES`], and the local player's notes as a last chapter if
+/// the session has them. Players are numbered as in
+/// [`to_markdown`](crate::export::to_markdown).
+pub fn to_epub(session: &SessionInstance) -> Vec<u8> {
+    let title = format!("Story {}", session.id);
+    let mut chapters: Vec<(String, String)> = vec![];
+    let parts = session.turns().chunks(CHAPTER_SENTENCES).len();
+    for (index, turns) in session.turns().chunks(CHAPTER_SENTENCES).enumerate() {
+        let heading = if parts > 1 {
+            format!("Part {}", index + 1)
+        } else {
+            title.clone()
+        };
+        let mut body = String::new();
+        if index == 0 {
+            writeln!(body, "<p class=\"meta\">{}</p>", escape(&summary(session))).unwrap();
+        }
+        let text: Vec<_> = turns.iter().map(|turn| escape(&turn.text)).collect();
+        writeln!(body, "<p>{}</p>", text.join(" ")).unwrap();
+        chapters.push((heading, body));
+    }
+    if chapters.is_empty() {
+        let meta = format!("<p class=\"meta\">{}</p>\n", escape(&summary(session)));
+        chapters.push((title.clone(), meta));
+    }
+    if !session.notes().is_empty() {
+        let body = format!("<p>{}</p>\n", escape(session.notes()));
+        chapters.push((String::from("Notes"), body));
+    }
+
+    let modified = session
+        .turns()
+        .last()
+        .map_or(session.started_at, |turn| turn.timestamp);
+    let mut zip = ZipWriter::new(modified);
+    // First and uncompressed, so that it identifies the file
+    zip.add("mimetype", b"application/epub+zip");
+    zip.add("META-INF/container.xml", CONTAINER.as_bytes());
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut toc = String::new();
+    for (index, (heading, body)) in chapters.iter().enumerate() {
+        let name = format!("chapter-{}", index + 1);
+        writeln!(
+            manifest,
+            "<item id=\"{0}\" href=\"{0}.xhtml\" media-type=\"application/xhtml+xml\"/>",
+            name
+        )
+        .unwrap();
+        writeln!(spine, "<itemref idref=\"{}\"/>", name).unwrap();
+        writeln!(
+            toc,
+            "<li><a href=\"{}.xhtml\">{}</a></li>",
+            name,
+            escape(heading)
+        )
+        .unwrap();
+        let page = xhtml(heading, &format!("<h1>{}</h1>\n{}", escape(heading), body));
+        zip.add(&format!("OEBPS/{}.xhtml", name), page.as_bytes());
+    }
+    let nav = xhtml(
+        &title,
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{}</ol>\n</nav>\n",
+            toc
+        ),
+    );
+    zip.add("OEBPS/nav.xhtml", nav.as_bytes());
+
+    let mut package = String::new();
+    writeln!(package, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(
+        package,
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">"
+    )
+    .unwrap();
+    writeln!(
+        package,
+        "<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">"
+    )
+    .unwrap();
+    writeln!(
+        package,
+        "<dc:identifier id=\"id\">urn:write-together:{}</dc:identifier>",
+        escape(&session.id)
+    )
+    .unwrap();
+    writeln!(package, "<dc:title>{}</dc:title>", escape(&title)).unwrap();
+    writeln!(package, "<dc:creator>Player 1</dc:creator>").unwrap();
+    writeln!(package, "<dc:creator>Player 2</dc:creator>").unwrap();
+    writeln!(package, "<dc:language>en</dc:language>").unwrap();
+    writeln!(
+        package,
+        "<dc:date>{}</dc:date>",
+        session.started_at.format("%Y-%m-%dT%H:%M:%SZ")
+    )
+    .unwrap();
+    writeln!(
+        package,
+        "<meta property=\"dcterms:modified\">{}</meta>",
+        modified.format("%Y-%m-%dT%H:%M:%SZ")
+    )
+    .unwrap();
+    writeln!(package, "</metadata>").unwrap();
+    writeln!(package, "<manifest>").unwrap();
+    writeln!(
+        package,
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>"
+    )
+    .unwrap();
+    write!(package, "{}", manifest).unwrap();
+    writeln!(package, "</manifest>").unwrap();
+    writeln!(package, "<spine>").unwrap();
+    write!(package, "{}", spine).unwrap();
+    writeln!(package, "</spine>").unwrap();
+    writeln!(package, "</package>").unwrap();
+    zip.add("OEBPS/content.opf", package.as_bytes());
+    zip.finish()
+}
+
+/// An XHTML page titled `title`, with `body` already escaped.
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"en\" xml:lang=\"en\">
+<head>
+<meta charset=\"utf-8\"/>
+<title>{}</title>
+</head>
+<body>
+{}</body>
+</html>
+",
+        escape(title),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_epub, CHAPTER_SENTENCES};
+    use crate::{
+        sessions::{SessionInstance, Turn},
+        zip,
+    };
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn session(texts: &[&str]) -> SessionInstance {
+        let started_at = Utc.with_ymd_and_hms(2021, 5, 1, 20, 0, 0).unwrap();
+        let mut session = SessionInstance::new(true);
+        session.id = String::from("20210501-200000");
+        session.started_at = started_at;
+        for (minutes, text) in texts.iter().enumerate() {
+            session.push(Turn {
+                text: String::from(*text),
+                timestamp: started_at + Duration::minutes(minutes as i64 + 1),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        session
+    }
+
+    /// Checks that every tag in `xml` is closed in order, and that nothing
+    /// but entities follows an `&`.
+    fn assert_well_formed(name: &str, xml: &str) {
+        let mut open = vec![];
+        let mut rest = xml;
+        while let Some(at) = rest.find(['<', '&']) {
+            rest = &rest[at..];
+            if rest.starts_with('&') {
+                let end = rest.find(';').expect("entity is ended");
+                assert!(
+                    ["&amp;", "&lt;", "&gt;", "&quot;", "&#39;"].contains(&&rest[..=end]),
+                    "{}: stray {}",
+                    name,
+                    &rest[..=end]
+                );
+                rest = &rest[end..];
+                continue;
+            }
+            let end = rest.find('>').expect("tag is ended");
+            let tag = &rest[1..end];
+            if let Some(closing) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(closing), "{}: closes in order", name);
+            } else if !(tag.starts_with('?') || tag.starts_with('!') || tag.ends_with('/')) {
+                open.push(tag.split(' ').next().unwrap());
+            }
+            rest = &rest[end..];
+        }
+        assert!(open.is_empty(), "{}: left open {:?}", name, open);
+    }
+
+    #[test]
+    fn matches_the_fixture() {
+        let session = session(&[
+            "The lighthouse was dark.",
+            "Nobody had kept it since <the storm> & the \"wreck\".",
+            "It's still there.",
+        ]);
+        let entries = zip::read(&to_epub(&session));
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "mimetype",
+                "META-INF/container.xml",
+                "OEBPS/chapter-1.xhtml",
+                "OEBPS/nav.xhtml",
+                "OEBPS/content.opf",
+            ]
+        );
+        assert_eq!(entries[0].1, b"application/epub+zip");
+        for (name, data) in &entries[1..] {
+            assert_well_formed(name, std::str::from_utf8(data).unwrap());
+        }
+        assert_eq!(
+            std::str::from_utf8(&entries[2].1).unwrap(),
+            include_str!("../tests/fixtures/story.xhtml")
+        );
+        let package = std::str::from_utf8(&entries[4].1).unwrap();
+        assert!(package.contains("<dc:title>Story 20210501-200000</dc:title>\n"));
+        assert!(package
+            .contains("<dc:creator>Player 1</dc:creator>\n<dc:creator>Player 2</dc:creator>\n"));
+        assert!(package.contains("<dc:date>2021-05-01T20:00:00Z</dc:date>\n"));
+        assert!(
+            package.contains("<meta property=\"dcterms:modified\">2021-05-01T20:03:00Z</meta>\n")
+        );
+    }
+
+    #[test]
+    fn long_stories_and_notes_are_chapters_of_their_own() {
+        let texts = vec!["Again."; CHAPTER_SENTENCES + 1];
+        let mut session = session(&texts);
+        session.set_notes(String::from("The keeper is <her> brother"));
+        let entries = zip::read(&to_epub(&session));
+        let chapter = |name: &str| {
+            let (_, data) = entries.iter().find(|(entry, _)| entry == name).unwrap();
+            String::from_utf8(data.clone()).unwrap()
+        };
+        assert!(chapter("OEBPS/chapter-1.xhtml").contains("<h1>Part 1</h1>"));
+        assert!(chapter("OEBPS/chapter-2.xhtml").contains("<h1>Part 2</h1>\n<p>Again.</p>\n"));
+        assert!(
+            chapter("OEBPS/chapter-3.xhtml").contains("<p>The keeper is &lt;her&gt; brother</p>")
+        );
+        let nav = chapter("OEBPS/nav.xhtml");
+        assert_well_formed("nav", &nav);
+        assert!(nav.contains("<li><a href=\"chapter-3.xhtml\">Notes</a></li>"));
+        assert!(chapter("OEBPS/content.opf").contains("<itemref idref=\"chapter-3\"/>"));
+    }
+}
@@ -1,31 +1,173 @@
-use std::fmt::{Debug, Display, Formatter};
+use crate::i18n::Message;
+use std::{
+    fmt::{Debug, Display, Formatter},
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+};
 use tokio::sync::mpsc::error::SendError;
 
 #[derive(Debug)]
 pub enum Error {
-    IO(std::io::Error),
+    IO(io::Error),
+    /// Listening for a peer failed.
+    Bind {
+        addr: SocketAddr,
+        source: io::Error,
+    },
+    Connect {
+        addr: SocketAddr,
+        source: io::Error,
+    },
+    /// Reading from or writing to the peer failed.
+    PeerIo(io::Error),
+    /// Saving a session or exporting a story failed.
+    Save {
+        path: PathBuf,
+        source: io::Error,
+    },
     Send(Box<dyn std::error::Error + Send>),
+    Protocol(serde_json::Error),
+    /// The app actor panicked, as it says.
+    Panicked(String),
+}
+
+impl Error {
+    /// Whether the app can't carry on after this: it can no longer listen,
+    /// or no longer reach the UI.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Bind { .. } | Error::Send(_))
+    }
+
+    /// What went wrong with a connection, in words for the user.
+    pub fn reason(&self) -> Message {
+        match self {
+            Error::IO(source)
+            | Error::Bind { source, .. }
+            | Error::Connect { source, .. }
+            | Error::PeerIo(source) => reason(source),
+            Error::Protocol(_) => Message::new("reason-protocol"),
+            Error::Save { .. } | Error::Send(_) | Error::Panicked(_) => {
+                Message::new("reason-other").arg("error", self)
+            }
+        }
+    }
+}
+
+/// Why connecting, accepting or talking to a peer failed, with what to try
+/// next where there's something to try.
+pub fn reason(err: &io::Error) -> Message {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused => Message::new("reason-refused"),
+        io::ErrorKind::TimedOut => Message::new("reason-timed-out"),
+        io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable => {
+            Message::new("reason-unreachable")
+        }
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::UnexpectedEof => Message::new("reason-reset"),
+        io::ErrorKind::AddrInUse => Message::new("reason-address-in-use"),
+        io::ErrorKind::AddrNotAvailable => Message::new("reason-address-unavailable"),
+        io::ErrorKind::PermissionDenied => Message::new("reason-permission"),
+        _ => Message::new("reason-other").arg("error", err),
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::IO(err) => write!(f, "IO error: {}", err),
+            Error::Bind { addr, source } => write!(f, "Couldn't listen on {}: {}", addr, source),
+            Error::Connect { addr, source } => {
+                write!(f, "Couldn't connect to {}: {}", addr, source)
+            }
+            Error::PeerIo(err) => write!(f, "Lost the connection to the peer: {}", err),
+            Error::Save { path, source } => {
+                write!(f, "Couldn't save to {}: {}", path.display(), source)
+            }
             Error::Send(err) => write!(f, "Send error: {}", err),
+            Error::Protocol(err) => write!(f, "The peer sent something unreadable: {}", err),
+            Error::Panicked(panic) => write!(f, "The app stopped unexpectedly: {}", panic),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(source)
+            | Error::Bind { source, .. }
+            | Error::Connect { source, .. }
+            | Error::PeerIo(source)
+            | Error::Save { source, .. } => Some(source),
+            Error::Send(_) | Error::Panicked(_) => None,
+            Error::Protocol(err) => Some(err),
+        }
+    }
+}
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
         Error::IO(err)
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Protocol(err)
+    }
+}
+
 impl<T: 'static + Debug + Display + Send> From<SendError<T>> for Error {
     fn from(err: SendError<T>) -> Self {
         Error::Send(Box::new(err))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{reason, Error};
+    use std::io;
+
+    #[test]
+    fn connection_errors_have_reasons_to_act_on() {
+        let reason_for = |kind| reason(&io::Error::from(kind)).to_string();
+        assert_eq!(
+            reason_for(io::ErrorKind::ConnectionRefused),
+            "connection refused (is the peer listening on that port?)"
+        );
+        assert_eq!(
+            reason_for(io::ErrorKind::TimedOut),
+            "timed out (is a firewall or NAT in the way?)"
+        );
+        assert_eq!(
+            reason_for(io::ErrorKind::HostUnreachable),
+            reason_for(io::ErrorKind::NetworkUnreachable)
+        );
+        assert_eq!(
+            reason_for(io::ErrorKind::BrokenPipe),
+            reason_for(io::ErrorKind::ConnectionReset)
+        );
+        // Anything else is passed on as it is
+        assert_eq!(
+            reason(&io::Error::other("out of cheese")).to_string(),
+            "out of cheese"
+        );
+
+        let garbage = serde_json::from_str::<u8>("{").unwrap_err();
+        assert_eq!(
+            Error::Protocol(garbage).reason().to_string(),
+            "the peer sent something unreadable (is it running the same version of write_together?)"
+        );
+        let addr = "127.0.0.1:4000".parse().unwrap();
+        let refused = Error::Connect {
+            addr,
+            source: io::ErrorKind::ConnectionRefused.into(),
+        };
+        assert_eq!(
+            refused.reason(),
+            reason(&io::ErrorKind::ConnectionRefused.into())
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use crate::{
+    counter, epub,
+    sessions::{format_duration, Event, Milestone, Moment, SessionInstance},
+};
+use std::fmt::Write;
+
+/// What a story can be exported as.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Epub,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Epub => "epub",
+        }
+    }
+}
+
+/// The appendices exports only end with when asked to, unlike the
+/// milestones.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Appendices {
+    /// The local player's scratchpad notes.
+    pub notes: bool,
+    /// What happened besides the sentences.
+    pub timeline: bool,
+}
+
+impl Appendices {
+    /// `session` with only the appendices asked for.
+    pub fn apply(self, session: &SessionInstance) -> SessionInstance {
+        let session = if self.notes {
+            session.clone()
+        } else {
+            session.shared()
+        };
+        if self.timeline {
+            session
+        } else {
+            session.without_timeline()
+        }
+    }
+}
+
+/// Renders a session in `format`.
+pub fn render(session: &SessionInstance, format: Format) -> Vec<u8> {
+    match format {
+        Format::Markdown => to_markdown(session).into_bytes(),
+        Format::Html => to_html(session).into_bytes(),
+        Format::Epub => epub::to_epub(session),
+    }
+}
+
+/// Renders a session as Markdown: the story as prose, followed by every turn
+/// with its author and the time it was written, the milestones reached if
+/// there were any, the timeline and the local player's notes if the session
+/// has them.
+///
+/// Players are numbered by who wrote the first sentence rather than named
+/// relative to the local side, so both peers export identical files.
+pub fn to_markdown(session: &SessionInstance) -> String {
+    let mut out = String::new();
+    writeln!(out, "# Story {}", session.id).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "{}", summary(session)).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "{}", session.content()).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "## Turns").unwrap();
+    writeln!(out).unwrap();
+    for (index, turn) in session.turns().iter().enumerate() {
+        writeln!(
+            out,
+            "{}. [{}] {}: {}",
+            index + 1,
+            turn.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            author(session, index),
+            turn.text
+        )
+        .unwrap();
+    }
+    if !session.milestones().is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "## Milestones").unwrap();
+        writeln!(out).unwrap();
+        for milestone in session.milestones() {
+            writeln!(out, "- {}", milestone_text(milestone)).unwrap();
+        }
+    }
+    if session.has_timeline() {
+        writeln!(out).unwrap();
+        writeln!(out, "## Timeline").unwrap();
+        writeln!(out).unwrap();
+        for moment in session.timeline() {
+            writeln!(
+                out,
+                "- [{}] {}",
+                moment.at.format("%Y-%m-%d %H:%M:%S UTC"),
+                moment_text(&moment)
+            )
+            .unwrap();
+        }
+    }
+    if !session.notes().is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "## Notes").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "{}", session.notes()).unwrap();
+    }
+    out
+}
+
+/// What a milestone was and when, e.g. "1,000 words at 2021-05-01 20:04:00 UTC".
+fn milestone_text(milestone: &Milestone) -> String {
+    format!(
+        "{} words{} at {}",
+        counter::thousands(milestone.words),
+        if milestone.goal { ", the goal," } else { "" },
+        milestone.reached_at.format("%Y-%m-%d %H:%M:%S UTC")
+    )
+}
+
+/// What happened, e.g. "Player 2 connected after 3 turns".
+fn moment_text(moment: &Moment) -> String {
+    let player = match moment.by {
+        Some(player) => format!("Player {}", player),
+        None => String::from("Nobody"),
+    };
+    let event = match &moment.event {
+        Event::Connected => format!("{} connected", player),
+        Event::Resumed => format!("{} connected again", player),
+        Event::Disconnected => format!("{} disconnected", player),
+        Event::Lost => String::from("The connection was lost"),
+        Event::Undone { text } => format!("{}'s \"{}\" was taken back", player, text),
+        Event::Milestone { words, goal } => format!(
+            "The story reached {} words{}",
+            counter::thousands(*words),
+            if *goal { ", the goal" } else { "" }
+        ),
+    };
+    match moment.turns {
+        0 => format!("{} before the first turn", event),
+        1 => format!("{} after 1 turn", event),
+        turns => format!("{} after {} turns", event, turns),
+    }
+}
+
+/// Who wrote the turn at `index`, e.g. "Player 1".
+pub(crate) fn author(session: &SessionInstance, index: usize) -> String {
+    match session.player(index) {
+        Some(player) => format!("Player {}", player),
+        None => String::from("Imported"),
+    }
+}
+
+/// The class styling the turn at `index` as its author's.
+fn class(session: &SessionInstance, index: usize) -> String {
+    match session.player(index) {
+        Some(player) => format!("player-{}", player),
+        None => String::from("imported"),
+    }
+}
+
+/// Styles for the HTML export. Checking "Plain prose" drops the players'
+/// colors without needing a script.
+const STYLE: &str = "body { max-width: 40em; margin: 2em auto; padding: 0 1em; font: 18px/1.6 Georgia, serif; color: #222; }
+.meta { color: #666; font-size: 0.9em; }
+.player-1 { color: #1f5fa8; }
+.player-2 { color: #b0471c; }
+.imported { color: #666; }
+#plain:checked ~ .story span { color: inherit; }
+ol { font-size: 0.9em; }
+time { color: #666; }
+";
+
+/// Renders a session as a standalone HTML page: the story with each
+/// player's sentences in their own color, followed by every turn and the
+/// same appendices as [`to_markdown`]. Players are numbered as there.
+pub fn to_html(session: &SessionInstance) -> String {
+    let mut out = String::new();
+    let title = format!("Story {}", escape(&session.id));
+    writeln!(out, "<!DOCTYPE html>").unwrap();
+    writeln!(out, "<html lang=\"en\">").unwrap();
+    writeln!(out, "<head>").unwrap();
+    writeln!(out, "<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>{}</title>", title).unwrap();
+    write!(out, "<style>\n{}</style>\n", STYLE).unwrap();
+    writeln!(out, "</head>").unwrap();
+    writeln!(out, "<body>").unwrap();
+    writeln!(out, "<h1>{}</h1>", title).unwrap();
+    writeln!(
+        out,
+        "<p class=\"meta\">{} Written by <span class=\"player-1\">Player 1</span> and <span class=\"player-2\">Player 2</span>.</p>",
+        escape(&summary(session))
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<input type=\"checkbox\" id=\"plain\"> <label for=\"plain\">Plain prose</label>"
+    )
+    .unwrap();
+    writeln!(out, "<p class=\"story\">").unwrap();
+    for (index, turn) in session.turns().iter().enumerate() {
+        writeln!(
+            out,
+            "<span class=\"{}\">{}</span>",
+            class(session, index),
+            escape(&turn.text)
+        )
+        .unwrap();
+    }
+    writeln!(out, "</p>").unwrap();
+    writeln!(out, "<h2>Turns</h2>").unwrap();
+    writeln!(out, "<ol>").unwrap();
+    for (index, turn) in session.turns().iter().enumerate() {
+        writeln!(
+            out,
+            "<li><time datetime=\"{}\">{}</time> <span class=\"{}\">{}</span>: {}</li>",
+            turn.timestamp.to_rfc3339(),
+            turn.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            class(session, index),
+            author(session, index),
+            escape(&turn.text)
+        )
+        .unwrap();
+    }
+    writeln!(out, "</ol>").unwrap();
+    if !session.milestones().is_empty() {
+        writeln!(out, "<h2>Milestones</h2>").unwrap();
+        writeln!(out, "<ul>").unwrap();
+        for milestone in session.milestones() {
+            writeln!(out, "<li>{}</li>", escape(&milestone_text(milestone))).unwrap();
+        }
+        writeln!(out, "</ul>").unwrap();
+    }
+    if session.has_timeline() {
+        writeln!(out, "<h2>Timeline</h2>").unwrap();
+        writeln!(out, "<ul>").unwrap();
+        for moment in session.timeline() {
+            writeln!(
+                out,
+                "<li><time datetime=\"{}\">{}</time> {}</li>",
+                moment.at.to_rfc3339(),
+                moment.at.format("%Y-%m-%d %H:%M:%S UTC"),
+                escape(&moment_text(&moment))
+            )
+            .unwrap();
+        }
+        writeln!(out, "</ul>").unwrap();
+    }
+    if !session.notes().is_empty() {
+        writeln!(out, "<h2>Notes</h2>").unwrap();
+        writeln!(out, "<p>{}</p>", escape(session.notes())).unwrap();
+    }
+    writeln!(out, "</body>").unwrap();
+    writeln!(out, "</html>").unwrap();
+    out
+}
+
+/// When the story was started, how long it is, and how long each player
+/// spent writing it if that was timed.
+pub(crate) fn summary(session: &SessionInstance) -> String {
+    let mut summary = format!(
+        "Started {}, {} sentences over {}.",
+        session.started_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        session.turns().len(),
+        format_duration(session.duration())
+    );
+    if let Some([first, second]) = session.writing_times() {
+        write!(
+            summary,
+            " Player 1 spent {} writing, Player 2 {}.",
+            format_duration(first),
+            format_duration(second)
+        )
+        .unwrap();
+    }
+    summary
+}
+
+/// Escapes text for use in HTML, in element content or quoted attributes.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn file_name(session: &SessionInstance, format: Format) -> String {
+    format!("story-{}.{}", session.id, format.extension())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, to_html, to_markdown, Appendices};
+    use crate::sessions::{Event, Milestones, SessionInstance, Turn};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn html_matches_the_fixture() {
+        let started_at = Utc.with_ymd_and_hms(2021, 5, 1, 20, 0, 0).unwrap();
+        let mut session = SessionInstance::new(true);
+        session.id = String::from("20210501-200000");
+        session.started_at = started_at;
+        for (minutes, text) in [
+            (1, "The lighthouse was dark."),
+            (3, "Nobody had kept it since <the storm> & the \"wreck\"."),
+            (4, "It's still there."),
+        ] {
+            session.push(Turn {
+                text: String::from(text),
+                timestamp: started_at + Duration::minutes(minutes),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        assert_eq!(
+            to_html(&session),
+            include_str!("../tests/fixtures/story.html")
+        );
+    }
+
+    #[test]
+    fn writing_time_is_summed_per_player() {
+        let started_at = Utc.with_ymd_and_hms(2021, 5, 1, 20, 0, 0).unwrap();
+        let mut session = SessionInstance::new(true);
+        session.started_at = started_at;
+        for (writing_secs, text) in [
+            (Some(80), "One."),
+            (Some(542), "Two."),
+            (Some(20), "Three."),
+        ] {
+            session.push(Turn {
+                text: String::from(text),
+                timestamp: started_at,
+                next_constraint: None,
+                writing_secs,
+            });
+        }
+        assert!(to_markdown(&session)
+            .contains("sentences over 0s. Player 1 spent 1m 40s writing, Player 2 9m 02s.\n"));
+    }
+
+    #[test]
+    fn milestones_are_listed_once_reached() {
+        let started_at = Utc.with_ymd_and_hms(2021, 5, 1, 20, 0, 0).unwrap();
+        let mut session = SessionInstance::new(true);
+        session.started_at = started_at;
+        let milestones = Milestones {
+            every: Some(4),
+            goal: Some(6),
+        };
+        for (minutes, text) in [(1, "One two three."), (2, "Four five six seven.")] {
+            session.push(Turn {
+                text: String::from(text),
+                timestamp: started_at + Duration::minutes(minutes),
+                next_constraint: None,
+                writing_secs: None,
+            });
+            session.reach_milestones(&milestones);
+        }
+        assert!(to_markdown(&session).ends_with(
+            "## Milestones\n\n- 4 words at 2021-05-01 20:02:00 UTC\n- 6 words, the goal, at 2021-05-01 20:02:00 UTC\n"
+        ));
+        assert!(to_html(&session).contains("<li>4 words at 2021-05-01 20:02:00 UTC</li>"));
+    }
+
+    #[test]
+    fn notes_are_an_appendix_only_when_kept() {
+        let mut session = SessionInstance::new(true);
+        session.set_notes(String::from("The keeper is <her> brother"));
+        assert!(to_markdown(&session).ends_with("## Notes\n\nThe keeper is <her> brother\n"));
+        assert!(
+            to_html(&session).contains("<h2>Notes</h2>\n<p>The keeper is &lt;her&gt; brother</p>")
+        );
+        assert!(!to_markdown(&session.shared()).contains("## Notes"));
+        assert!(!to_html(&session.shared()).contains("Notes"));
+    }
+
+    #[test]
+    fn the_timeline_is_an_appendix_only_when_asked_for() {
+        let mut session = SessionInstance::new(true);
+        session.record(Some(2), Event::Connected);
+        session.push(Turn {
+            text: String::from("The lighthouse was dark."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        });
+        session.record(
+            Some(1),
+            Event::Undone {
+                text: String::from("It <was> lit."),
+            },
+        );
+        session.record(None, Event::Lost);
+        let timeline = Appendices {
+            notes: false,
+            timeline: true,
+        };
+        let markdown = to_markdown(&timeline.apply(&session));
+        let appendix = &markdown[markdown.find("## Timeline\n\n").unwrap()..];
+        let lines: Vec<_> = appendix
+            .lines()
+            .skip(2)
+            .map(|line| line.split_once("] ").unwrap().1)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Player 2 connected before the first turn",
+                "Player 1's \"It <was> lit.\" was taken back after 1 turn",
+                "The connection was lost after 1 turn",
+            ]
+        );
+        assert!(to_html(&timeline.apply(&session)).contains(
+            " Player 1&#39;s &quot;It &lt;was&gt; lit.&quot; was taken back after 1 turn</li>"
+        ));
+
+        let markdown = to_markdown(&Appendices::default().apply(&session));
+        assert!(!markdown.contains("Timeline"));
+        assert!(!to_html(&Appendices::default().apply(&session)).contains("Timeline"));
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            escape("<script>alert('hi & \"bye\"')</script>"),
+            "&lt;script&gt;alert(&#39;hi &amp; &quot;bye&quot;&#39;)&lt;/script&gt;"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    fs, io,
+    ops::Range,
+    path::Path,
+    str::FromStr,
+};
+
+/// What to do with the peer's sentences that have a listed word in them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Incoming {
+    /// Show them with the listed words starred out.
+    Mask,
+    /// Turn them down, so the peer writes the turn again.
+    Reject,
+}
+
+impl Display for Incoming {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Incoming::Mask => write!(f, "mask"),
+            Incoming::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+impl FromStr for Incoming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mask" => Ok(Incoming::Mask),
+            "reject" => Ok(Incoming::Reject),
+            _ => Err(format!(
+                "unknown word list action '{}', expected mask or reject",
+                s
+            )),
+        }
+    }
+}
+
+/// Words that may not be written, matched whole and ignoring case.
+#[derive(Clone, Debug)]
+pub struct WordFilter {
+    words: HashSet<String>,
+    pub incoming: Incoming,
+}
+
+impl WordFilter {
+    /// Loads a list of one word per line.
+    pub fn load(path: &Path, incoming: Incoming) -> io::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        Ok(Self::parse(&text, incoming))
+    }
+
+    /// Parses a list of one word per line. Blank lines and lines starting
+    /// with `#` are skipped.
+    pub fn parse(text: &str, incoming: Incoming) -> Self {
+        Self {
+            words: text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_lowercase)
+                .collect(),
+            incoming,
+        }
+    }
+
+    /// Where the listed words appear in `text`.
+    pub fn find(&self, text: &str) -> Vec<Range<usize>> {
+        words(text)
+            .filter(|range| {
+                let word = text[range.clone()].to_lowercase();
+                // A listed word still counts when possessive
+                self.words.contains(&word)
+                    || word
+                        .strip_suffix("'s")
+                        .is_some_and(|stem| self.words.contains(stem))
+            })
+            .collect()
+    }
+
+    /// `text` with all but the first letter of every listed word starred out.
+    pub fn mask(&self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut end = 0;
+        for range in self.find(text) {
+            masked.push_str(&text[end..range.start]);
+            let mut chars = text[range.clone()].chars();
+            masked.extend(chars.next());
+            masked.extend(chars.map(|_| '*'));
+            end = range.end;
+        }
+        masked.push_str(&text[end..]);
+        masked
+    }
+}
+
+/// Where each word of `text` is, a word being a run of letters, digits and
+/// apostrophes as for the word count.
+pub(crate) fn words(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let is_word = |c: char| c.is_alphanumeric() || c == '\'';
+    let mut start = None;
+    text.char_indices()
+        .chain(Some((text.len(), ' ')))
+        .filter_map(move |(index, c)| match (start, is_word(c)) {
+            (None, true) => {
+                start = Some(index);
+                None
+            }
+            (Some(from), false) => {
+                start = None;
+                // Quotes around a word aren't part of it
+                let word = &text[from..index];
+                let trimmed = word.trim_start_matches('\'');
+                let from = from + word.len() - trimmed.len();
+                let to = from + trimmed.trim_end_matches('\'').len();
+                Some(from..to).filter(|range| !range.is_empty())
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Incoming, WordFilter};
+
+    fn filter() -> WordFilter {
+        WordFilter::parse("# rude words\nass\n\nDarn\n", Incoming::Mask)
+    }
+
+    #[test]
+    fn matches_whole_words_ignoring_case() {
+        let filter = filter();
+        assert_eq!(
+            filter.find("DARN it, the class is an ass."),
+            vec![0..4, 25..28]
+        );
+        assert!(filter.find("A classic, passable assortment.").is_empty());
+        assert_eq!(filter.find("'ass' and the ass's cart"), vec![1..4, 14..19]);
+    }
+
+    #[test]
+    fn masks_all_but_the_first_letter() {
+        assert_eq!(
+            filter().mask("Darn it, said the ass."),
+            "D*** it, said the a**."
+        );
+        assert_eq!(filter().mask("Nothing to hide."), "Nothing to hide.");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{i18n::Message, sessions::Turn, ui_actor::UISink};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Output,
+};
+use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    process::Command,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+use tracing::debug;
+
+/// The file in the repository the story is written to.
+const STORY_FILE: &str = "story.md";
+
+/// Keeps the story in a git repository: each sentence added to it is
+/// appended to `story.md` and committed, authored by the player who wrote
+/// it when they wrote it. Commits are made one at a time on a task of their
+/// own, and the first failure stops them for good.
+#[derive(Clone, Debug)]
+pub struct GitExport {
+    turns: UnboundedSender<(Turn, String)>,
+}
+
+impl GitExport {
+    /// Starts committing to the repository at `repo`, reporting a failure
+    /// through `ui_handle`.
+    pub fn spawn(repo: PathBuf, ui_handle: impl UISink) -> Self {
+        let (turns, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(commit_all(repo, receiver, ui_handle));
+        Self { turns }
+    }
+
+    /// Commits `turn`, written by `author`, after those before it.
+    pub fn commit(&self, turn: &Turn, author: String) {
+        // Only fails once committing has stopped
+        let _ = self.turns.send((turn.clone(), author));
+    }
+}
+
+async fn commit_all(
+    repo: PathBuf,
+    mut turns: UnboundedReceiver<(Turn, String)>,
+    ui_handle: impl UISink,
+) {
+    while let Some((turn, author)) = turns.recv().await {
+        if let Err(err) = commit(&repo, &turn, &author).await {
+            debug!(repo = %repo.display(), %err, "git export failed");
+            let _ = ui_handle
+                .warn(
+                    Message::new("git-export-failed")
+                        .arg("repo", repo.display())
+                        .arg("error", err),
+                )
+                .await;
+            return;
+        }
+    }
+}
+
+/// Appends `turn` to the story in `repo` and commits it, as long as there
+/// was nothing else left uncommitted.
+async fn commit(repo: &Path, turn: &Turn, author: &str) -> io::Result<()> {
+    let status = git(repo, &["status", "--porcelain"]).await?;
+    if !status.stdout.is_empty() {
+        return Err(io::Error::other("it has uncommitted changes"));
+    }
+    let mut story = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(repo.join(STORY_FILE))
+        .await?;
+    story
+        .write_all(format!("{}\n", turn.text).as_bytes())
+        .await?;
+    git(repo, &["add", STORY_FILE]).await?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["commit", "--quiet", "--message", &turn.text])
+        .env("GIT_AUTHOR_NAME", author)
+        .env("GIT_AUTHOR_EMAIL", "")
+        .env("GIT_AUTHOR_DATE", turn.timestamp.to_rfc3339())
+        .output()
+        .await?;
+    succeeded(output).map(|_| ())
+}
+
+/// Runs git in `repo` with `args`.
+async fn git(repo: &Path, args: &[&str]) -> io::Result<Output> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .await?;
+    succeeded(output)
+}
+
+/// `output` if git succeeded, or else what it said went wrong.
+fn succeeded(output: Output) -> io::Result<Output> {
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::other(
+            stderr.lines().next().unwrap_or("git failed").to_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::commit;
+    use crate::sessions::Turn;
+    use chrono::{TimeZone, Utc};
+    use std::{fs, path::PathBuf, process::Command};
+
+    fn repo(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "write_together-git-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn git(repo: &PathBuf, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn turn(text: &str, minute: u32) -> Turn {
+        Turn {
+            text: String::from(text),
+            timestamp: Utc.with_ymd_and_hms(2021, 5, 1, 20, minute, 0).unwrap(),
+            next_constraint: None,
+            writing_secs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn each_turn_is_a_commit_by_its_writer() {
+        let dir = repo("commits");
+        git(&dir, &["init", "--quiet"]);
+        git(&dir, &["config", "user.name", "Tester"]);
+        git(&dir, &["config", "user.email", "tester@example.com"]);
+        commit(&dir, &turn("It was a dark night.", 0), "Player 1")
+            .await
+            .unwrap();
+        commit(&dir, &turn("The lantern flickered.", 3), "Player 2")
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("story.md")).unwrap(),
+            "It was a dark night.\nThe lantern flickered.\n"
+        );
+        assert_eq!(
+            git(&dir, &["log", "--format=%an|%aI|%s"]),
+            "Player 2|2021-05-01T20:03:00+00:00|The lantern flickered.\n\
+             Player 1|2021-05-01T20:00:00+00:00|It was a dark night.\n"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn refuses_a_dirty_tree_or_no_repository() {
+        let dir = repo("dirty");
+        assert!(commit(&dir, &turn("Lost.", 0), "Player 1").await.is_err());
+        git(&dir, &["init", "--quiet"]);
+        fs::write(dir.join("draft.txt"), "mine").unwrap();
+        let err = commit(&dir, &turn("Lost.", 0), "Player 1")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "it has uncommitted changes");
+        assert!(!dir.join("story.md").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,365 @@
+use chrono::{DateTime, Utc};
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+const CAPACITY: usize = 20;
+const SENTENCE_CAPACITY: usize = 200;
+
+/// Addresses we successfully connected to, most recent first.
+#[derive(Debug, Default)]
+pub struct AddressHistory {
+    entries: Vec<SocketAddr>,
+    /// Where the history is saved, if anywhere.
+    path: Option<PathBuf>,
+}
+
+impl AddressHistory {
+    /// Loads the history at `path`, skipping lines that aren't addresses. A
+    /// missing file is an empty history.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .take(CAPACITY)
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            entries,
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<SocketAddr> {
+        self.entries.get(index).copied()
+    }
+
+    /// Moves `address` to the front, dropping the oldest entry when full.
+    pub fn record(&mut self, address: SocketAddr) -> io::Result<()> {
+        self.entries.retain(|entry| *entry != address);
+        self.entries.insert(0, address);
+        self.entries.truncate(CAPACITY);
+        self.save()
+    }
+
+    pub fn remove(&mut self, index: usize) -> io::Result<()> {
+        if index < self.entries.len() {
+            self.entries.remove(index);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let contents: String = self
+                .entries
+                .iter()
+                .map(|entry| format!("{}\n", entry))
+                .collect();
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// A peer we connected out to, with what we've done together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecentPeer {
+    pub address: SocketAddr,
+    /// What the peer goes by, once peers tell each other.
+    pub nickname: Option<String>,
+    pub last_connected: DateTime<Utc>,
+    /// How many stories were finished with them.
+    pub stories: usize,
+}
+
+impl RecentPeer {
+    /// One line of the file: the address, when we last connected, how many
+    /// stories were written and the nickname, separated by tabs.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let address = fields.next()?.trim().parse().ok()?;
+        let last_connected = DateTime::parse_from_rfc3339(fields.next()?)
+            .ok()?
+            .with_timezone(&Utc);
+        let stories = fields.next()?.parse().ok()?;
+        let nickname = fields
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(String::from);
+        Some(Self {
+            address,
+            nickname,
+            last_connected,
+            stories,
+        })
+    }
+}
+
+/// The peers we connected out to, most recent first, kept apart from the
+/// plain address history to remember more about each.
+#[derive(Debug, Default)]
+pub struct RecentPeers {
+    entries: Vec<RecentPeer>,
+    /// Where the list is saved, if anywhere.
+    path: Option<PathBuf>,
+}
+
+impl RecentPeers {
+    /// Loads the list at `path`, skipping lines that don't parse. A missing
+    /// file is an empty list.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(RecentPeer::parse)
+                .take(CAPACITY)
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            entries,
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&RecentPeer> {
+        self.entries.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Moves `address` to the front as connected to just now, dropping the
+    /// oldest entry when full.
+    pub fn connected(&mut self, address: SocketAddr) -> io::Result<()> {
+        let mut peer = match self.entries.iter().position(|peer| peer.address == address) {
+            Some(index) => self.entries.remove(index),
+            None => RecentPeer {
+                address,
+                nickname: None,
+                last_connected: Utc::now(),
+                stories: 0,
+            },
+        };
+        peer.last_connected = Utc::now();
+        self.entries.insert(0, peer);
+        self.entries.truncate(CAPACITY);
+        self.save()
+    }
+
+    /// Counts a story finished with `address`, if it's one we connected out
+    /// to.
+    pub fn story_written(&mut self, address: SocketAddr) -> io::Result<()> {
+        match self.entries.iter_mut().find(|peer| peer.address == address) {
+            Some(peer) => {
+                peer.stories += 1;
+                self.save()
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> io::Result<()> {
+        if index < self.entries.len() {
+            self.entries.remove(index);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let contents: String = self
+                .entries
+                .iter()
+                .map(|peer| {
+                    format!(
+                        "{}\t{}\t{}\t{}\n",
+                        peer.address,
+                        peer.last_connected.to_rfc3339(),
+                        peer.stories,
+                        peer.nickname.as_deref().unwrap_or_default()
+                    )
+                })
+                .collect();
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sentences we submitted, most recent first, whether or not they made it
+/// into the story.
+#[derive(Debug, Default)]
+pub struct SentenceHistory {
+    entries: Vec<String>,
+    /// Where the history is saved, if it is kept across runs.
+    path: Option<PathBuf>,
+}
+
+impl SentenceHistory {
+    /// Loads the history at `path`, one sentence per line. A missing file is
+    /// an empty history.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(String::from)
+                .take(SENTENCE_CAPACITY)
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            entries,
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// The first sentence from `index` on that contains `query`, ignoring case.
+    pub fn find(&self, query: &str, index: usize) -> Option<usize> {
+        let query = query.to_lowercase();
+        (index..self.entries.len())
+            .find(|&index| self.entries[index].to_lowercase().contains(&query))
+    }
+
+    /// Moves `sentence` to the front, dropping the oldest entry when full.
+    pub fn record(&mut self, sentence: &str) -> io::Result<()> {
+        // Kept one to a line
+        let sentence = sentence.replace('\n', " ");
+        self.entries.retain(|entry| *entry != sentence);
+        self.entries.insert(0, sentence);
+        self.entries.truncate(SENTENCE_CAPACITY);
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let contents: String = self
+                .entries
+                .iter()
+                .map(|entry| format!("{}\n", entry))
+                .collect();
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the address history is kept.
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("write_together").join("history"))
+}
+
+/// Where the recent peers are kept.
+pub fn recent_peers_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("write_together")
+            .join("recent_peers"),
+    )
+}
+
+/// Where the sentence history is kept, when it is kept at all.
+pub fn sentences_path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("write_together").join("sentences"))
+}
+
+/// Deletes the history at `path`, if there is one.
+pub fn forget(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressHistory, RecentPeers, SentenceHistory, CAPACITY};
+    use std::{net::SocketAddr, path::PathBuf};
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn record_moves_repeats_to_the_front_and_caps_length() {
+        let mut history = AddressHistory::default();
+        for port in 0..CAPACITY as u16 + 5 {
+            history.record(address(port)).unwrap();
+        }
+        history.record(address(10)).unwrap();
+
+        assert_eq!(history.entries.len(), CAPACITY);
+        assert_eq!(history.get(0), Some(address(10)));
+        assert_eq!(history.get(1), Some(address(CAPACITY as u16 + 4)));
+        assert_eq!(
+            history
+                .entries
+                .iter()
+                .filter(|a| **a == address(10))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn recent_peers_count_stories_and_survive_a_reload() {
+        let dir = std::env::temp_dir().join(format!("recent_peers_{}", std::process::id()));
+        let path: PathBuf = dir.join("recent_peers");
+        let mut peers = RecentPeers::load(path.clone()).unwrap();
+        peers.connected(address(1)).unwrap();
+        peers.connected(address(2)).unwrap();
+        peers.story_written(address(1)).unwrap();
+        peers.story_written(address(1)).unwrap();
+        // Only peers we connected out to are listed
+        peers.story_written(address(3)).unwrap();
+        peers.connected(address(1)).unwrap();
+
+        let mut peers = RecentPeers::load(path.clone()).unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers.get(0).unwrap().address, address(1));
+        assert_eq!(peers.get(0).unwrap().stories, 2);
+        assert_eq!(peers.get(1).unwrap().stories, 0);
+        assert_eq!(peers.get(1).unwrap().nickname, None);
+
+        peers.remove(0).unwrap();
+        let peers = RecentPeers::load(path).unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers.get(0).unwrap().address, address(2));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finds_sentences_newest_first_ignoring_case() {
+        let mut history = SentenceHistory::default();
+        for sentence in ["The gull flew off.", "It rained.", "A gull landed."] {
+            history.record(sentence).unwrap();
+        }
+        assert_eq!(history.find("GULL", 0), Some(0));
+        assert_eq!(history.find("gull", 1), Some(2));
+        assert_eq!(history.find("gull", 3), None);
+        assert_eq!(history.get(2), Some("The gull flew off."));
+    }
+}
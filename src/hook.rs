@@ -0,0 +1,124 @@
+use crate::{i18n::Message, sessions::Turn, ui_actor::UISink};
+use std::{
+    io,
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    time::{timeout, Duration},
+};
+use tracing::debug;
+
+/// Who wrote the sentence a hook is run for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Author {
+    Me,
+    Peer,
+}
+
+impl Author {
+    fn as_str(self) -> &'static str {
+        match self {
+            Author::Me => "me",
+            Author::Peer => "peer",
+        }
+    }
+}
+
+/// A command run for every sentence added to the story, for integrations
+/// such as text-to-speech. It gets the sentence on stdin, and who wrote it
+/// and the turn number in `WRITE_TOGETHER_AUTHOR` and `WRITE_TOGETHER_TURN`.
+#[derive(Clone, Debug)]
+pub struct TurnHook {
+    command: PathBuf,
+    /// How long a run may take before it's killed.
+    timeout: Duration,
+    /// Set by the first failed run, as later ones only go to the debug log.
+    failed: Arc<AtomicBool>,
+}
+
+impl TurnHook {
+    pub fn new(command: PathBuf, timeout: Duration) -> Self {
+        Self {
+            command,
+            timeout,
+            failed: Arc::default(),
+        }
+    }
+
+    /// Runs the command for `turn`, the `number`th of session `session`, on a
+    /// task of its own. A failure is reported through `ui_handle` only the
+    /// first time.
+    pub fn run(
+        &self,
+        turn: &Turn,
+        author: Author,
+        number: usize,
+        session: &str,
+        ui_handle: &impl UISink,
+    ) {
+        let mut command = Command::new(&self.command);
+        command
+            .env("WRITE_TOGETHER_AUTHOR", author.as_str())
+            .env("WRITE_TOGETHER_TURN", number.to_string())
+            .env("WRITE_TOGETHER_SESSION", session)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let text = turn.text.clone();
+        let hook = self.clone();
+        let ui_handle = ui_handle.clone();
+        tokio::spawn(async move {
+            let ran = match timeout(hook.timeout, output(command, text)).await {
+                Ok(ran) => ran,
+                // Dropping the child kills it
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("killed after {}s", hook.timeout.as_secs_f32()),
+                )),
+            };
+            if let Err(err) = ran {
+                debug!(command = %hook.command.display(), %err, "turn hook failed");
+                if !hook.failed.swap(true, Ordering::Relaxed) {
+                    let _ = ui_handle
+                        .warn(
+                            Message::new("hook-failed")
+                                .arg("command", hook.command.display())
+                                .arg("error", err),
+                        )
+                        .await;
+                }
+            }
+        });
+    }
+}
+
+/// Runs `command` with `text` on its stdin, logging what it prints.
+async fn output(mut command: Command, text: String) -> io::Result<()> {
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its input closes the pipe early, which is fine
+        match stdin.write_all(format!("{}\n", text).as_bytes()).await {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+            _ => {}
+        }
+    }
+    let output = child.wait_with_output().await?;
+    debug!(
+        stdout = %String::from_utf8_lossy(&output.stdout).trim_end(),
+        stderr = %String::from_utf8_lossy(&output.stderr).trim_end(),
+        "turn hook finished"
+    );
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(io::Error::other(format!("it exited with code {}", code))),
+        None => Err(io::Error::other("it was stopped by a signal")),
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::OnceLock,
+};
+
+/// The catalogs built in, by language code. English is the fallback for
+/// anything the others are missing.
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.txt")),
+    ("es", include_str!("../locales/es.txt")),
+];
+
+/// Something to show the user, as a key into the catalog and the values
+/// for its placeholders, so that whoever shows it picks the language.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Message {
+    key: &'static str,
+    args: Vec<(&'static str, Arg)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Arg {
+    Text(String),
+    /// Shown in the same language as the message it's in.
+    Message(Message),
+}
+
+impl Message {
+    pub fn new(key: &'static str) -> Self {
+        Self { key, args: vec![] }
+    }
+
+    /// The catalog key, which says what kind of message this is.
+    pub fn key(&self) -> &'static str {
+        self.key
+    }
+
+    /// Fills the placeholder `{name}` with `value`.
+    pub fn arg(mut self, name: &'static str, value: impl Display) -> Self {
+        self.args.push((name, Arg::Text(value.to_string())));
+        self
+    }
+
+    /// Fills the placeholder `{name}` with another message.
+    pub fn arg_message(mut self, name: &'static str, message: Message) -> Self {
+        self.args.push((name, Arg::Message(message)));
+        self
+    }
+}
+
+/// In English, for logs and tests.
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&Catalog::english().render(self))
+    }
+}
+
+/// The user-facing strings in one language, read from lines of
+/// `key = value`. `\n` in a value is a line break, and `{name}` a
+/// placeholder.
+#[derive(Clone, Debug)]
+pub struct Catalog {
+    strings: &'static HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn english() -> Self {
+        Self { strings: english() }
+    }
+
+    /// The catalog for `lang`, such as `es` or `es_ES.UTF-8`, or English if
+    /// there isn't one.
+    pub fn for_lang(lang: &str) -> Self {
+        let code = lang
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match CATALOGS.iter().position(|(name, _)| *name == code) {
+            Some(index) => Self {
+                strings: &loaded()[index],
+            },
+            None => Self::english(),
+        }
+    }
+
+    /// The catalog for the language the environment asks for, the way
+    /// gettext picks it.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|lang| !lang.is_empty())
+            .map_or_else(Self::english, |lang| Self::for_lang(&lang))
+    }
+
+    /// The string for `key`, in English if this catalog is missing it, or
+    /// the key itself if no catalog has it.
+    pub fn get<'a>(&self, key: &'a str) -> &'a str {
+        match self.strings.get(key).or_else(|| english().get(key)) {
+            Some(string) => string,
+            None => key,
+        }
+    }
+
+    /// Fills in the placeholders in one pass, so that braces in the values
+    /// are left alone.
+    pub fn render(&self, message: &Message) -> String {
+        let mut rest = self.get(message.key);
+        let mut string = String::new();
+        while let Some(start) = rest.find('{') {
+            string.push_str(&rest[..start]);
+            let value = rest[start + 1..].split_once('}').and_then(|(name, after)| {
+                let (_, value) = message.args.iter().find(|(arg, _)| *arg == name)?;
+                Some((value, after))
+            });
+            match value {
+                Some((Arg::Text(text), after)) => {
+                    string.push_str(text);
+                    rest = after;
+                }
+                Some((Arg::Message(message), after)) => {
+                    string.push_str(&self.render(message));
+                    rest = after;
+                }
+                None => {
+                    string.push('{');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        string.push_str(rest);
+        string
+    }
+
+    /// The string for `key`, which has no placeholders.
+    pub fn tr(&self, key: &'static str) -> String {
+        self.get(key).to_string()
+    }
+}
+
+/// Every catalog, parsed the first time one is needed.
+fn loaded() -> &'static [HashMap<String, String>] {
+    static LOADED: OnceLock<Vec<HashMap<String, String>>> = OnceLock::new();
+    LOADED.get_or_init(|| CATALOGS.iter().map(|(_, catalog)| parse(catalog)).collect())
+}
+
+fn english() -> &'static HashMap<String, String> {
+    &loaded()[0]
+}
+
+fn parse(catalog: &str) -> HashMap<String, String> {
+    catalog
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once(" = "))
+        .map(|(key, value)| (key.trim().to_string(), value.replace("\\n", "\n")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Catalog, Message, CATALOGS};
+    use std::{collections::BTreeSet, path::Path};
+
+    /// Every key the source asks for, found by looking for the ways it
+    /// asks.
+    fn keys_used() -> BTreeSet<String> {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut keys = BTreeSet::new();
+        for entry in std::fs::read_dir(src).unwrap() {
+            let source = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            for call in ["Message::new(\"", ".tr(\""] {
+                for (start, _) in source.match_indices(call) {
+                    let rest = &source[start + call.len()..];
+                    keys.insert(rest[..rest.find('"').unwrap()].to_string());
+                }
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn no_key_is_missing_from_the_default_catalog() {
+        let english = parse(CATALOGS[0].1);
+        let used = keys_used();
+        assert!(used.len() > 50, "only found {} keys", used.len());
+        let missing: Vec<_> = used
+            .iter()
+            .filter(|key| !english.contains_key(*key))
+            .collect();
+        assert!(missing.is_empty(), "missing from en.txt: {:?}", missing);
+    }
+
+    #[test]
+    fn translations_only_have_known_keys_and_placeholders() {
+        let english = parse(CATALOGS[0].1);
+        for (lang, catalog) in &CATALOGS[1..] {
+            for (key, translated) in parse(catalog) {
+                let original = english
+                    .get(&key)
+                    .unwrap_or_else(|| panic!("{} has unknown key {}", lang, key));
+                let placeholders = |string: &str| {
+                    string
+                        .split('{')
+                        .skip(1)
+                        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name.to_string()))
+                        .collect::<BTreeSet<_>>()
+                };
+                assert_eq!(
+                    placeholders(&translated),
+                    placeholders(original),
+                    "{} {}",
+                    lang,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        let spanish = Catalog::for_lang("es_ES.UTF-8");
+        let message = Message::new("connected-to").arg("address", "192.0.2.4:4000");
+        assert_eq!(spanish.render(&message), "Conectado a 192.0.2.4:4000");
+        assert_eq!(message.to_string(), "Connected to 192.0.2.4:4000");
+        assert_eq!(
+            Catalog::for_lang("tlh").tr("status-waiting"),
+            "Waiting for a peer"
+        );
+        assert_eq!(spanish.get("no-such-key"), "no-such-key");
+        let braces = Message::new("plain-you").arg("text", "{text} stays");
+        assert_eq!(spanish.render(&braces), "Tú: {text} stays");
+    }
+}
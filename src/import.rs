@@ -0,0 +1,102 @@
+use crate::sessions::SessionInstance;
+use std::{fs, io, path::Path};
+
+/// Words ending in a full stop that don't end a sentence.
+const ABBREVIATIONS: [&str; 8] = ["Mr", "Mrs", "Ms", "Dr", "St", "Jr", "Sr", "vs"];
+
+/// Loads a plain text file as the opening of a story, continued by us first
+/// if `our_turn_first`.
+pub fn load(path: &Path, our_turn_first: bool) -> io::Result<SessionInstance> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    };
+    let bytes = fs::read(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+    let text = String::from_utf8(bytes).map_err(|err| {
+        invalid(format!(
+            "not UTF-8 text, from byte {}",
+            err.utf8_error().valid_up_to()
+        ))
+    })?;
+    let sentences = split_sentences(&text);
+    if sentences.is_empty() {
+        return Err(invalid(String::from("there are no sentences to import")));
+    }
+    Ok(SessionInstance::imported(sentences, our_turn_first))
+}
+
+/// Splits prose into sentences, ending one at a full stop, question or
+/// exclamation mark followed by a space, and at every blank line. Line
+/// breaks within a paragraph are joined up.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    let mut sentence = String::new();
+    for line in text.lines().chain(Some("")) {
+        if line.trim().is_empty() && !sentence.is_empty() {
+            sentences.push(std::mem::take(&mut sentence));
+        }
+        for word in line.split_whitespace() {
+            if !sentence.is_empty() {
+                sentence.push(' ');
+            }
+            sentence.push_str(word);
+            if ends_sentence(word) {
+                sentences.push(std::mem::take(&mut sentence));
+            }
+        }
+    }
+    sentences
+}
+
+fn ends_sentence(word: &str) -> bool {
+    // Quotes and brackets can close after the punctuation
+    let word = word.trim_end_matches(&['"', '\'', ')', ']', '”', '’'][..]);
+    match word.strip_suffix(&['.', '!', '?'][..]) {
+        Some(rest) => {
+            let rest = rest.trim_start_matches(&['"', '\'', '(', '[', '“', '‘'][..]);
+            // Initials such as the J. in J. Smith
+            let initial = rest.chars().count() == 1 && rest.chars().all(char::is_uppercase);
+            !ABBREVIATIONS.contains(&rest) && !initial
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, split_sentences};
+    use std::fs;
+
+    #[test]
+    fn splits_at_sentence_ends_and_blank_lines() {
+        let text = "The Lighthouse\n\n\
+                    Mr. Smith climbed the stairs. \"Was anyone there?\" Nobody\n\
+                    answered. J. Smith waited below!\n\n\
+                    The end";
+        assert_eq!(
+            split_sentences(text),
+            vec![
+                "The Lighthouse",
+                "Mr. Smith climbed the stairs.",
+                "\"Was anyone there?\"",
+                "Nobody answered.",
+                "J. Smith waited below!",
+                "The end",
+            ]
+        );
+        assert!(split_sentences(" \n\n ").is_empty());
+    }
+
+    #[test]
+    fn refuses_text_that_isnt_utf8() {
+        let path = std::env::temp_dir().join("write_together-import-latin1.txt");
+        fs::write(&path, b"Caf\xe9 au lait.").unwrap();
+        let error = load(&path, false).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("not UTF-8 text, from byte 3"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::i18n::Message;
+use std::{
+    fmt::{Display, Formatter},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+/// What every invite starts with, to tell it apart from an address.
+const PREFIX: &str = "WT-";
+const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// The longest hint kept, in bytes, so the QR code stays small.
+pub const MAX_HINT: usize = 24;
+
+/// An address to connect to, in a form that's easy to read out: base32 in
+/// groups of four, such as `WT-ATAK-QAIU-B6QL-A`. Letters that sound or look
+/// alike are forgiven when it's typed back in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Invite {
+    pub address: SocketAddr,
+    /// A reminder for the peer, such as which passphrase to use.
+    pub hint: Option<String>,
+}
+
+impl Invite {
+    /// Whether `text` looks like an invite rather than an address.
+    pub fn is_invite(text: &str) -> bool {
+        text.trim()
+            .get(..PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(PREFIX))
+    }
+
+    pub fn decode(text: &str) -> Result<Self, Message> {
+        let text = text.trim();
+        if !Self::is_invite(text) {
+            return Err(Message::new("not-an-invite"));
+        }
+        let invalid = || Message::new("invalid-invite");
+        let bytes = from_base32(&text[PREFIX.len()..]).ok_or_else(invalid)?;
+        let (check, bytes) = bytes.split_last().ok_or_else(invalid)?;
+        if crc8(bytes) != *check {
+            return Err(Message::new("mistyped-invite"));
+        }
+        let (ip, rest): (IpAddr, _) = match bytes.split_first() {
+            Some((4, rest)) if rest.len() >= 6 => {
+                let mut octets = [0; 4];
+                octets.copy_from_slice(&rest[..4]);
+                (Ipv4Addr::from(octets).into(), &rest[4..])
+            }
+            Some((6, rest)) if rest.len() >= 18 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&rest[..16]);
+                (Ipv6Addr::from(octets).into(), &rest[16..])
+            }
+            _ => return Err(invalid()),
+        };
+        let port = u16::from_be_bytes([rest[0], rest[1]]);
+        let hint = match &rest[2..] {
+            [] => None,
+            hint => Some(String::from_utf8(hint.to_vec()).map_err(|_| invalid())?),
+        };
+        Ok(Self {
+            address: SocketAddr::new(ip, port),
+            hint,
+        })
+    }
+}
+
+impl Display for Invite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut bytes = match self.address.ip() {
+            IpAddr::V4(ip) => [&[4][..], &ip.octets()].concat(),
+            IpAddr::V6(ip) => [&[6][..], &ip.octets()].concat(),
+        };
+        bytes.extend_from_slice(&self.address.port().to_be_bytes());
+        if let Some(hint) = &self.hint {
+            let mut end = hint.len().min(MAX_HINT);
+            while !hint.is_char_boundary(end) {
+                end -= 1;
+            }
+            bytes.extend_from_slice(&hint.as_bytes()[..end]);
+        }
+        bytes.push(crc8(&bytes));
+        let encoded = to_base32(&bytes);
+        let groups = encoded
+            .as_bytes()
+            .chunks(4)
+            .map(|group| std::str::from_utf8(group).expect("base32 is ASCII"));
+        write!(f, "{}{}", PREFIX, groups.collect::<Vec<_>>().join("-"))
+    }
+}
+
+fn to_base32(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = buffer << 8 | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[(buffer >> bits & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[(buffer << (5 - bits) & 31) as usize] as char);
+    }
+    encoded
+}
+
+/// Decodes base32, skipping dashes and spaces and reading 0, 1 and 8 as the
+/// letters they're mistaken for.
+fn from_base32(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars().filter(|c| *c != '-' && !c.is_whitespace()) {
+        let c = match c.to_ascii_uppercase() {
+            '0' => 'O',
+            '1' => 'I',
+            '8' => 'B',
+            c => c,
+        };
+        let value = ALPHABET.iter().position(|&letter| letter as char == c)?;
+        buffer = (buffer << 5 | value as u32) & 0xFFF;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// CRC-8 with polynomial 0x07, to catch a misheard letter.
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_base32, Invite};
+    use crate::i18n::Message;
+
+    #[test]
+    fn round_trips_addresses_and_hints() {
+        for (address, hint) in [
+            ("192.168.1.20:4000", None),
+            ("[2001:db8::1]:65535", None),
+            ("10.0.0.2:4000", Some("the usual password")),
+        ] {
+            let invite = Invite {
+                address: address.parse().unwrap(),
+                hint: hint.map(String::from),
+            };
+            let text = invite.to_string();
+            assert!(text.starts_with("WT-"), "{}", text);
+            assert_eq!(Invite::decode(&text), Ok(invite));
+        }
+    }
+
+    #[test]
+    fn is_short_enough_to_read_out() {
+        let invite = Invite {
+            address: "192.168.1.20:4000".parse().unwrap(),
+            hint: None,
+        };
+        assert_eq!(invite.to_string(), "WT-ATAK-QAIU-B6QL-A");
+    }
+
+    #[test]
+    fn forgives_how_it_is_typed_back() {
+        let address = "192.168.1.20:4000".parse().unwrap();
+        let typed = " wt-atak qai-ub6q-la ";
+        assert_eq!(Invite::decode(typed).unwrap().address, address);
+        assert_eq!(from_base32("O0I1B8"), from_base32("OOIIBB"));
+    }
+
+    #[test]
+    fn catches_mistakes() {
+        assert_eq!(
+            Invite::decode("WT-ATAK-QAIU-B6QM-A"),
+            Err(Message::new("mistyped-invite"))
+        );
+        let invalid = Err(Message::new("invalid-invite"));
+        assert_eq!(Invite::decode("WT-AIAK-!"), invalid);
+        assert_eq!(Invite::decode("WT-"), invalid);
+        assert_eq!(
+            Invite::decode("192.168.1.20:4000"),
+            Err(Message::new("not-an-invite"))
+        );
+    }
+
+    #[test]
+    fn long_hints_are_cut_between_characters() {
+        let invite = Invite {
+            address: "10.0.0.2:4000".parse().unwrap(),
+            hint: Some("é".repeat(20)),
+        };
+        let decoded = Invite::decode(&invite.to_string()).unwrap();
+        assert_eq!(decoded.hint, Some("é".repeat(12)));
+    }
+}
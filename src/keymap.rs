@@ -0,0 +1,435 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use itertools::Itertools;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Everything a key can be bound to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    Quit,
+    Submit,
+    Connect,
+    Disconnect,
+    NextFocus,
+    PreviousFocus,
+    ScrollUp,
+    ScrollDown,
+    ToggleTimestamps,
+    Export,
+    Reroll,
+    NewStory,
+    ClearStory,
+    VerifyStory,
+    BlockPeer,
+    Archive,
+    NextTheme,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    DeleteWord,
+    DeleteToStart,
+    DeleteToEnd,
+    HistoryPrevious,
+    HistoryNext,
+    HistorySearch,
+    ForgetAddress,
+    LogLevel,
+    Notify,
+    CopyStory,
+    CopySentence,
+    Search,
+    GoToTurn,
+    ContentView,
+    GrowContent,
+    ShrinkContent,
+    Zen,
+    CollapseLog,
+    Layout,
+    NextAddress,
+    CopyAddress,
+    Scratchpad,
+    Preview,
+    Import,
+    Stats,
+    Metrics,
+    Away,
+    Spell,
+    Palette,
+}
+
+impl Action {
+    const ALL: [(Action, &'static str, &'static str); 54] = [
+        (Action::Quit, "quit", "esc"),
+        (Action::Submit, "submit", "."),
+        (Action::Connect, "connect", "enter"),
+        (Action::Disconnect, "disconnect", "ctrl+d"),
+        (Action::NextFocus, "switch-focus", "tab"),
+        (Action::PreviousFocus, "switch-focus-back", "backtab"),
+        (Action::ScrollUp, "scroll-up", "shift+pageup"),
+        (Action::ScrollDown, "scroll-down", "shift+pagedown"),
+        (Action::ToggleTimestamps, "timestamps", "ctrl+t"),
+        (Action::Export, "export", "ctrl+e"),
+        (Action::Reroll, "reroll", "ctrl+r"),
+        (Action::NewStory, "new-story", "ctrl+n"),
+        (Action::ClearStory, "clear-story", "alt+c"),
+        (Action::VerifyStory, "verify-story", "alt+h"),
+        (Action::BlockPeer, "block-peer", "alt+k"),
+        (Action::Archive, "archive", "ctrl+o"),
+        (Action::NextTheme, "theme", "f2"),
+        (Action::Backspace, "backspace", "backspace"),
+        (Action::Delete, "delete", "delete"),
+        (Action::Left, "left", "left"),
+        (Action::Right, "right", "right"),
+        (Action::Home, "home", "home"),
+        (Action::End, "end", "end"),
+        (Action::WordLeft, "word-left", "alt+b"),
+        (Action::WordRight, "word-right", "alt+f"),
+        (Action::DeleteWord, "delete-word", "ctrl+w"),
+        (Action::DeleteToStart, "delete-to-start", "ctrl+u"),
+        (Action::DeleteToEnd, "delete-to-end", "ctrl+k"),
+        (Action::HistoryPrevious, "history-previous", "up"),
+        (Action::HistoryNext, "history-next", "down"),
+        (Action::HistorySearch, "history-search", "alt+r"),
+        (Action::ForgetAddress, "forget-address", "ctrl+x"),
+        (Action::LogLevel, "log-level", "ctrl+l"),
+        (Action::Notify, "notify", "ctrl+b"),
+        (Action::CopyStory, "copy-story", "ctrl+y"),
+        (Action::CopySentence, "copy-sentence", "alt+y"),
+        (Action::Search, "search", "ctrl+f"),
+        (Action::GoToTurn, "go-to-turn", "alt+g"),
+        (Action::ContentView, "view", "alt+v"),
+        (Action::GrowContent, "grow-content", "ctrl+up"),
+        (Action::ShrinkContent, "shrink-content", "ctrl+down"),
+        (Action::Zen, "zen", "f11"),
+        (Action::CollapseLog, "collapse-log", "alt+l"),
+        (Action::Layout, "layout", "alt+w"),
+        (Action::NextAddress, "next-address", "alt+n"),
+        (Action::CopyAddress, "copy-address", "alt+e"),
+        (Action::Scratchpad, "scratchpad", "alt+p"),
+        (Action::Preview, "preview", "alt+d"),
+        (Action::Import, "import", "alt+i"),
+        (Action::Stats, "stats", "alt+s"),
+        (Action::Metrics, "metrics", "f12"),
+        (Action::Away, "away", "alt+a"),
+        (Action::Spell, "spell", "f7"),
+        (Action::Palette, "palette", "ctrl+p"),
+    ];
+
+    /// What the action is called in the key bindings and the command
+    /// palette.
+    pub fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(action, ..)| *action == self)
+            .map(|(_, name, _)| *name)
+            .unwrap()
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(_, action_name, _)| *action_name == name)
+            .map(|(action, ..)| *action)
+    }
+
+    /// The actions that do something by themselves rather than to the text
+    /// or pane focused, for the command palette, in the order above.
+    pub fn commands() -> impl Iterator<Item = Action> {
+        Self::ALL
+            .iter()
+            .map(|(action, ..)| *action)
+            .filter(|action| {
+                !matches!(
+                    action,
+                    Action::Submit
+                        | Action::Connect
+                        | Action::NextFocus
+                        | Action::PreviousFocus
+                        | Action::ScrollUp
+                        | Action::ScrollDown
+                        | Action::Backspace
+                        | Action::Delete
+                        | Action::Left
+                        | Action::Right
+                        | Action::Home
+                        | Action::End
+                        | Action::WordLeft
+                        | Action::WordRight
+                        | Action::DeleteWord
+                        | Action::DeleteToStart
+                        | Action::DeleteToEnd
+                        | Action::HistoryPrevious
+                        | Action::HistoryNext
+                        | Action::HistorySearch
+                        | Action::ForgetAddress
+                        | Action::Palette
+                )
+            })
+    }
+}
+
+/// A key together with the modifiers held down with it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Terminals disagree on whether shift is reported for characters that
+    /// already include it, so it is ignored for those.
+    fn normalized(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+
+    /// Parses keys such as `esc`, `ctrl+q`, `shift+pageup`, `f2` or `.`.
+    fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::empty();
+        let mut key = text;
+        while let Some((modifier, rest)) = key.split_once('+').filter(|(_, rest)| !rest.is_empty())
+        {
+            modifiers |= match modifier {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            key = rest;
+        }
+        let code = match key {
+            "esc" => KeyCode::Esc,
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "space" => KeyCode::Char(' '),
+            _ if key.len() > 1 && key.starts_with('f') => KeyCode::F(key[1..].parse().ok()?),
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return None,
+                }
+            }
+        };
+        Some(Self::normalized(code, modifiers))
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Which action each key triggers.
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+    bindings: HashMap<KeyBinding, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .iter()
+                .map(|(action, _, key)| (KeyBinding::parse(key).unwrap(), *action))
+                .collect(),
+        }
+    }
+}
+
+impl KeyMap {
+    /// Loads bindings from lines such as `quit = ctrl+q`. An action listed in
+    /// the file loses its default keys; it can be listed more than once to
+    /// bind several keys. Keys bound to more than one action are an error.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, path)
+    }
+
+    fn parse(config: &str, path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut configured: Vec<(KeyBinding, Action)> = vec![];
+        for (index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let binding = line.split_once('=').and_then(|(action, key)| {
+                Some((
+                    KeyBinding::parse(&key.trim().to_lowercase())?,
+                    Action::from_name(action.trim())?,
+                ))
+            });
+            match binding {
+                Some(binding) => configured.push(binding),
+                None => {
+                    return Err(invalid(format!(
+                        "{}:{}: invalid key binding '{}'",
+                        path.display(),
+                        index + 1,
+                        line
+                    )))
+                }
+            }
+        }
+
+        let mut bindings: Vec<(KeyBinding, Action)> = Self::default()
+            .bindings
+            .into_iter()
+            .filter(|(_, action)| !configured.iter().any(|(_, other)| other == action))
+            .collect();
+        bindings.extend(configured);
+
+        let conflicts = bindings
+            .iter()
+            .into_group_map_by(|(key, _)| *key)
+            .into_iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .map(|(key, actions)| {
+                format!(
+                    "{} is bound to {}",
+                    key,
+                    actions
+                        .iter()
+                        .map(|(_, action)| action.name())
+                        .join(" and ")
+                )
+            })
+            .sorted()
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            return Err(invalid(format!(
+                "{}: conflicting key bindings: {}",
+                path.display(),
+                conflicts.join("; ")
+            )));
+        }
+
+        Ok(Self {
+            bindings: bindings.into_iter().collect(),
+        })
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .get(&KeyBinding::normalized(event.code, event.modifiers))
+            .copied()
+    }
+
+    /// The key bound to `action`, for showing in hints.
+    pub fn key(&self, action: Action) -> Option<KeyBinding> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| *key)
+            .min_by_key(|key| key.to_string())
+    }
+}
+
+/// Where key bindings are read from when no `--keys` is given.
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("write_together").join("keys"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, KeyMap};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use std::path::Path;
+
+    fn parse(config: &str) -> std::io::Result<KeyMap> {
+        KeyMap::parse(config, Path::new("keys"))
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent { code, modifiers }
+    }
+
+    #[test]
+    fn configured_action_replaces_its_default() {
+        let keymap = parse("# no more accidental quits\nquit = ctrl+q\n").unwrap();
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('q'), KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+        assert_eq!(keymap.action(&key(KeyCode::Esc, KeyModifiers::NONE)), None);
+        assert_eq!(
+            keymap.action(&key(KeyCode::Tab, KeyModifiers::NONE)),
+            Some(Action::NextFocus)
+        );
+    }
+
+    #[test]
+    fn shift_is_ignored_for_characters() {
+        let keymap = KeyMap::default();
+        assert_eq!(
+            keymap.action(&key(KeyCode::BackTab, KeyModifiers::SHIFT)),
+            Some(Action::PreviousFocus)
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::PageUp, KeyModifiers::SHIFT)),
+            Some(Action::ScrollUp)
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::PageUp, KeyModifiers::NONE)),
+            None
+        );
+    }
+
+    #[test]
+    fn conflicts_are_listed() {
+        let err = parse("quit = ctrl+e\nnew-story = tab\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "keys: conflicting key bindings: Ctrl+e is bound to export and quit; \
+             Tab is bound to switch-focus and new-story"
+        );
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        let err = parse("quit = hyper+q\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "keys:1: invalid key binding 'quit = hyper+q'"
+        );
+        assert!(parse("teleport = ctrl+x\n").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+/// How the screen is shared out between the panes, cycled with a key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LayoutMode {
+    /// Content over Input, with Connect and the Log beside Input.
+    Balanced,
+    /// The most room for Content and Input.
+    Writing,
+    /// The most room for the Log.
+    Debugging,
+}
+
+/// Where each pane goes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Panes {
+    pub content: Rect,
+    /// The line under Content for the story's statistics.
+    pub stats: Rect,
+    /// Input, along with the lines for its constraint, preview and counter.
+    pub input: Rect,
+    pub connect: Rect,
+    /// The line under Connect saying what's wrong with the address.
+    pub reason: Rect,
+    pub log: Rect,
+    pub status_bar: Rect,
+}
+
+impl LayoutMode {
+    pub fn next(self) -> Self {
+        match self {
+            LayoutMode::Balanced => LayoutMode::Writing,
+            LayoutMode::Writing => LayoutMode::Debugging,
+            LayoutMode::Debugging => LayoutMode::Balanced,
+        }
+    }
+
+    /// The catalog key for the mode's name.
+    pub fn name(self) -> &'static str {
+        match self {
+            LayoutMode::Balanced => "layout-balanced",
+            LayoutMode::Writing => "layout-writing",
+            LayoutMode::Debugging => "layout-debugging",
+        }
+    }
+
+    /// How much of the height Content starts with in this mode.
+    pub fn content_percent(self) -> u16 {
+        match self {
+            LayoutMode::Balanced => 60,
+            LayoutMode::Writing => 75,
+            LayoutMode::Debugging => 35,
+        }
+    }
+
+    /// How much of the width under Content goes to Input, the rest going
+    /// to Connect and the Log.
+    fn input_percent(self) -> u16 {
+        match self {
+            LayoutMode::Balanced => 60,
+            LayoutMode::Writing => 70,
+            LayoutMode::Debugging => 40,
+        }
+    }
+
+    /// Shares `area` out between the panes, Content taking `content_percent`
+    /// of the height. A collapsed Log is a single line under Connect, which
+    /// has a line under it for `reason` if there is one.
+    pub fn split(
+        self,
+        area: Rect,
+        content_percent: u16,
+        log_collapsed: bool,
+        reason: bool,
+    ) -> Panes {
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(area);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(2)
+            .constraints(
+                [
+                    Constraint::Percentage(content_percent),
+                    Constraint::Percentage(100 - content_percent),
+                ]
+                .as_ref(),
+            )
+            .split(outer[0]);
+        let content = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(rows[0]);
+        let input_percent = self.input_percent();
+        let bottom = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                [
+                    Constraint::Percentage(input_percent),
+                    Constraint::Percentage(100 - input_percent),
+                ]
+                .as_ref(),
+            )
+            .split(rows[1]);
+        let mut constraints = vec![
+            Constraint::Length(3),
+            Constraint::Length(if reason { 1 } else { 0 }),
+        ];
+        if log_collapsed {
+            constraints.extend([Constraint::Length(1), Constraint::Min(0)]);
+        } else {
+            constraints.push(Constraint::Min(1));
+        }
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(bottom[1]);
+        Panes {
+            content: content[0],
+            stats: content[1],
+            input: bottom[0],
+            connect: side[0],
+            reason: side[1],
+            log: side[2],
+            status_bar: outer[1],
+        }
+    }
+}
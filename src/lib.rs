@@ -0,0 +1,49 @@
+//! Two people writing a story together over TCP, a sentence each in turn.
+//!
+//! The [`app`] actor owns the connection to the peer and the story, and the
+//! [`ui_actor`] draws it and takes input. Each is driven through its handle,
+//! [`app::AppHandle`] and [`ui_actor::UIHandle`]. The [`plain`] and [`bot`]
+//! front ends stand in for the UI actor without a terminal UI.
+
+pub mod access;
+pub mod app;
+pub mod bot;
+mod clipboard;
+pub mod completion;
+mod compress;
+pub mod config;
+pub mod constraints;
+mod counter;
+pub mod debug_log;
+mod editor;
+mod endpoints;
+mod epub;
+pub mod error;
+pub mod export;
+pub mod filter;
+mod git_export;
+pub mod history;
+pub mod hook;
+pub mod i18n;
+pub mod import;
+mod invite;
+pub mod keymap;
+mod layout;
+pub mod log_buffer;
+mod metrics;
+pub mod notify;
+mod peer;
+pub mod plain;
+pub mod protocol;
+mod qr;
+pub mod screen;
+mod search;
+pub mod sessions;
+mod signals;
+pub mod spelling;
+mod stats;
+pub mod theme;
+mod throttle;
+pub mod ui_actor;
+mod wrap;
+mod zip;
@@ -0,0 +1,220 @@
+use crate::theme::Theme;
+use chrono::{DateTime, Local};
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+};
+use tui::text::{Span, Spans};
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn next(self) -> Self {
+        match self {
+            Level::Info => Level::Warn,
+            Level::Warn => Level::Error,
+            Level::Error => Level::Info,
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Info => write!(f, "info"),
+            Level::Warn => write!(f, "warn"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+struct LogLine {
+    level: Level,
+    /// When it was last logged.
+    time: DateTime<Local>,
+    text: String,
+    /// How many times in a row it was logged.
+    count: usize,
+}
+
+/// The most recent log lines, scrollable from the newest line backwards and
+/// filtered by level.
+pub struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    /// How many shown lines the view is scrolled up from the newest one.
+    scroll: usize,
+    min_level: Level,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            scroll: 0,
+            min_level: Level::Info,
+        }
+    }
+}
+
+impl LogBuffer {
+    const CAPACITY: usize = 500;
+    const PAGE: usize = 10;
+
+    /// Adds a line, or counts another of the newest line when it's the
+    /// same again.
+    pub fn push(&mut self, level: Level, text: String) {
+        if let Some(last) = self.lines.back_mut() {
+            if last.level == level && last.text == text {
+                last.count += 1;
+                last.time = Local::now();
+                return;
+            }
+        }
+        if self.lines.len() == Self::CAPACITY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(LogLine {
+            level,
+            time: Local::now(),
+            text,
+            count: 1,
+        });
+        // Keep the lines being read in place while scrolled back
+        if self.scroll > 0 && level >= self.min_level {
+            self.scroll = (self.scroll + 1).min(self.shown_count() - 1);
+        }
+    }
+
+    pub fn info(&mut self, text: String) {
+        self.push(Level::Info, text);
+    }
+
+    pub fn warn(&mut self, text: String) {
+        self.push(Level::Warn, text);
+    }
+
+    pub fn error(&mut self, text: String) {
+        self.push(Level::Error, text);
+    }
+
+    fn shown(&self) -> impl DoubleEndedIterator<Item = &LogLine> {
+        let min_level = self.min_level;
+        self.lines
+            .iter()
+            .filter(move |line| line.level >= min_level)
+    }
+
+    fn shown_count(&self) -> usize {
+        self.shown().count()
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = (self.scroll + Self::PAGE).min(self.shown_count().saturating_sub(1));
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_sub(Self::PAGE);
+    }
+
+    pub fn scroll_to_newest(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Shows only lines at the next level up, wrapping back to everything.
+    pub fn cycle_min_level(&mut self) -> Level {
+        self.min_level = self.min_level.next();
+        self.scroll = 0;
+        self.min_level
+    }
+
+    /// The lines that fit in `height` rows, ending at the scroll position.
+    pub fn visible(&self, height: usize, theme: &Theme) -> Vec<Spans<'static>> {
+        let mut lines: Vec<Spans> = self
+            .shown()
+            .rev()
+            .skip(self.scroll)
+            .take(height)
+            .map(|line| {
+                let mut spans = vec![
+                    Span::raw(format!("{} ", line.time.format("%H:%M:%S"))),
+                    Span::styled(line.text.clone(), theme.log_level(line.level)),
+                ];
+                if line.count > 1 {
+                    spans.push(Span::styled(format!(" (×{})", line.count), theme.border));
+                }
+                Spans::from(spans)
+            })
+            .collect();
+        lines.reverse();
+        lines
+    }
+
+    pub fn title(&self) -> String {
+        let mut title = String::from("Log");
+        if self.min_level > Level::Info {
+            title.push_str(&format!(" ({} and up)", self.min_level));
+        }
+        if self.scroll > 0 {
+            title.push_str(&format!(" ({} newer, Shift+PageDown)", self.scroll));
+        }
+        title
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Level, LogBuffer};
+    use crate::theme::Theme;
+
+    fn texts(log: &LogBuffer, height: usize) -> Vec<String> {
+        log.visible(height, &Theme::default())
+            .into_iter()
+            .map(|spans| spans.0[1].content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_lines_past_capacity() {
+        let mut log = LogBuffer::default();
+        for n in 0..LogBuffer::CAPACITY + 3 {
+            log.info(n.to_string());
+        }
+        assert_eq!(log.lines.len(), LogBuffer::CAPACITY);
+        assert_eq!(texts(&log, 2), vec!["501", "502"]);
+    }
+
+    #[test]
+    fn min_level_filters_and_scrolls_shown_lines() {
+        let mut log = LogBuffer::default();
+        log.info(String::from("connected"));
+        log.warn(String::from("conflict"));
+        log.error(String::from("broken"));
+        log.info(String::from("exported"));
+
+        assert_eq!(log.cycle_min_level(), Level::Warn);
+        assert_eq!(texts(&log, 10), vec!["conflict", "broken"]);
+
+        log.scroll_up();
+        assert_eq!(texts(&log, 10), vec!["conflict"]);
+        assert_eq!(log.title(), "Log (warn and up) (1 newer, Shift+PageDown)");
+    }
+
+    #[test]
+    fn repeats_in_a_row_share_a_line() {
+        let mut log = LogBuffer::default();
+        for _ in 0..23 {
+            log.info(String::from("Accepting connection"));
+        }
+        log.warn(String::from("Accepting connection"));
+        log.info(String::from("Accepting connection"));
+        assert_eq!(log.lines.len(), 3);
+        let lines = log.visible(10, &Theme::default());
+        assert_eq!(lines[0].0[2].content, " (×23)");
+        assert_eq!(lines[1].0.len(), 2);
+    }
+}
@@ -1,42 +1,560 @@
-use std::io;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
 
-use crate::{app::AppHandle, error::Error, ui_actor::UIHandle};
 use clap::Clap;
-use crossterm::{
-    event::EventStream,
-    terminal::{disable_raw_mode, enable_raw_mode},
-};
+use crossterm::event::EventStream;
 use tui::{backend::CrosstermBackend, Terminal};
-
-mod app;
-mod error;
-mod ui_actor;
+use write_together::{
+    access::{self, AccessList, Cidr},
+    app::{self, AppConfig, AppHandle},
+    bot::{self, Script},
+    completion::Completer,
+    config::{self, Config},
+    constraints::ConstraintPool,
+    debug_log,
+    error::Error,
+    export::{Appendices, Format},
+    filter::{Incoming, WordFilter},
+    history::{self, AddressHistory, RecentPeers, SentenceHistory},
+    hook::TurnHook,
+    i18n::Catalog,
+    import,
+    keymap::{self, KeyMap},
+    notify::{Alerts, Notify},
+    plain,
+    protocol::TurnPreference,
+    screen::{self, ScreenGuard},
+    sessions::{self, Milestones, SessionInstance},
+    spelling::Speller,
+    theme::{ColorChoice, Theme, ThemeName},
+    ui_actor::{UIConfig, UIHandle},
+};
 
 #[derive(Clap)]
 struct Opts {
+    /// Port to listen on for a peer. Without one, only outgoing connections are made
     #[clap(short, long)]
-    port: u16,
+    port: Option<u16>,
+    /// Address to listen on. 0.0.0.0 listens on every interface, for peers on other machines
+    #[clap(long)]
+    bind: Option<IpAddr>,
+    /// Address, or block of them such as 10.0.0.0/8, that may connect. Anyone unless some are given
+    #[clap(long, number_of_values = 1)]
+    allow: Vec<Cidr>,
+    /// Address, or block of them, turned away before it can say anything
+    #[clap(long, number_of_values = 1)]
+    block: Vec<Cidr>,
+    /// Don't listen for a peer even if a port is configured
+    #[clap(long)]
+    no_listen: bool,
+    /// Address of a peer to connect to straight away
+    #[clap(long)]
+    connect: Option<SocketAddr>,
+    /// Config file to read instead of the default one
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Print a commented config file with every setting at its default, then exit
+    #[clap(long)]
+    print_default_config: bool,
+    /// Directory sessions are saved to after every turn
+    #[clap(long)]
+    save_dir: Option<PathBuf>,
+    /// Saved session file to continue when a peer connects
+    #[clap(long)]
+    resume: Option<PathBuf>,
+    /// Text file to continue as a story when a peer connects. The peer writes next unless --first is given
+    #[clap(long, conflicts_with = "resume")]
+    import: Option<PathBuf>,
+    /// Saved session file to export as an EPUB book to the working directory, then exit
+    #[clap(long)]
+    epub: Option<PathBuf>,
+    /// Set a constraint for the peer's sentence every time you pass the turn
+    #[clap(long, overrides_with = "no_constraints")]
+    constraints: bool,
+    /// Don't set constraints, even if the config file does
+    #[clap(long, overrides_with = "constraints")]
+    no_constraints: bool,
+    /// File listing the constraints to roll from, one per line (implies --constraints)
+    #[clap(long)]
+    constraints_file: Option<PathBuf>,
+    /// Color theme: default, high-contrast or monochrome
+    #[clap(long)]
+    theme: Option<ThemeName>,
+    /// Whether to draw in color: always, auto or never. Auto goes without when NO_COLOR is set or the terminal is dumb
+    #[clap(long)]
+    color: Option<ColorChoice>,
+    /// File of key bindings such as `quit = ctrl+q`, one per line
+    #[clap(long)]
+    keys: Option<PathBuf>,
+    /// Keep the sentences you write across runs, to recall with Up or search with Alt+R
+    #[clap(long, overrides_with = "no_sentence_history")]
+    sentence_history: bool,
+    /// Don't keep the sentences you write, even if the config file does
+    #[clap(long, overrides_with = "sentence_history")]
+    no_sentence_history: bool,
+    /// Delete the history of addresses connected to, recent peers and sentences written
+    #[clap(long)]
+    forget_history: bool,
+    /// Write diagnostics to this file, filtered with RUST_LOG
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+    /// How to alert you when it becomes your turn: bell, flash or none
+    #[clap(long)]
+    notify: Option<Notify>,
+    /// Show a desktop notification when it becomes your turn after 30s without a key press
+    #[clap(long, overrides_with = "no_desktop_notify")]
+    desktop_notify: bool,
+    /// Don't show desktop notifications, even if the config file does
+    #[clap(long, overrides_with = "desktop_notify")]
+    no_desktop_notify: bool,
+    /// Word list to suggest completions from, besides the story so far
+    #[clap(long)]
+    dict: Option<PathBuf>,
+    /// Suggest word completions while typing, even if the config file doesn't
+    #[clap(long, overrides_with = "no_complete")]
+    complete: bool,
+    /// Don't suggest word completions while typing
+    #[clap(long, overrides_with = "complete")]
+    no_complete: bool,
+    /// Dictionary to check the spelling of what you type against, one word per line
+    #[clap(long)]
+    spell_dict: Option<PathBuf>,
+    /// Ask to write the first sentence of a new story. Left to a coin flip if both ask
+    #[clap(long, conflicts_with = "second")]
+    first: bool,
+    /// Ask for the peer to write the first sentence of a new story
+    #[clap(long)]
+    second: bool,
+    /// Seconds a write to the peer may take before the connection counts as lost
+    #[clap(long)]
+    write_timeout: Option<u64>,
+    /// Milliseconds between redraws of anything animated, such as the spinner
+    #[clap(long)]
+    tick_ms: Option<u64>,
+    /// Characters a second the peer's sentences are typed out at as they arrive. 0 shows them at once
+    #[clap(long)]
+    typewriter: Option<u64>,
+    /// Wait for a sentence written during the peer's turn to be submitted again, rather than sending it
+    #[clap(long, overrides_with = "no_confirm_queued")]
+    confirm_queued: bool,
+    /// Send a sentence written during the peer's turn once it's yours, even if the config file says to wait
+    #[clap(long, overrides_with = "confirm_queued")]
+    no_confirm_queued: bool,
+    /// File of words, one per line, that neither side's sentences may have in them
+    #[clap(long)]
+    wordlist: Option<PathBuf>,
+    /// What to do with the peer's sentences that have a listed word: mask or reject
+    #[clap(long)]
+    wordlist_incoming: Option<Incoming>,
+    /// Command run with every sentence on stdin, and WRITE_TOGETHER_AUTHOR and WRITE_TOGETHER_TURN set
+    #[clap(long)]
+    on_turn_hook: Option<PathBuf>,
+    /// Seconds the turn hook may run before it's killed
+    #[clap(long)]
+    on_turn_hook_timeout: Option<u64>,
+    /// Git repository to commit every sentence to, appended to its story.md
+    #[clap(long)]
+    git_export: Option<PathBuf>,
+    /// Seconds without a key press before the peer is told you're away
+    #[clap(long)]
+    away_after: Option<u64>,
+    /// Minutes your turn can go on before you're reminded of it, and again every so many
+    #[clap(long)]
+    remind_after: Option<u64>,
+    /// Tell the peer you're still thinking when you're reminded it's your turn
+    #[clap(long, overrides_with = "no_remind_peer")]
+    remind_peer: bool,
+    /// Don't tell the peer you're still thinking, even if the config file does
+    #[clap(long, overrides_with = "remind_peer")]
+    no_remind_peer: bool,
+    /// Seconds both of you have to agree on writing another story once one ends
+    #[clap(long)]
+    rematch_timeout: Option<u64>,
+    /// Seconds to leave between one sentence and the next, or the peer's if longer
+    #[clap(long)]
+    cooldown: Option<u64>,
+    /// Minutes a connection can go with nothing happening before it's closed, after a warning
+    #[clap(long)]
+    idle_timeout: Option<u64>,
+    /// Leave time either of you is away out of the idle timeout
+    #[clap(long, overrides_with = "no_idle_pause_away")]
+    idle_pause_away: bool,
+    /// Count time away towards the idle timeout, even if the config file doesn't
+    #[clap(long, overrides_with = "idle_pause_away")]
+    no_idle_pause_away: bool,
+    /// A reminder put in your invite for the peer, such as which passphrase to use
+    #[clap(long)]
+    invite_hint: Option<String>,
+    /// Celebrate when the story reaches a milestone, even if the config file doesn't
+    #[clap(long, overrides_with = "no_milestones")]
+    milestones: bool,
+    /// Don't celebrate when the story reaches a milestone
+    #[clap(long, overrides_with = "milestones")]
+    no_milestones: bool,
+    /// Words between milestones
+    #[clap(long)]
+    milestone_words: Option<u64>,
+    /// Word count to aim for, celebrated once reached
+    #[clap(long)]
+    word_goal: Option<u64>,
+    /// End exports with your scratchpad notes, which are otherwise left out
+    #[clap(long, overrides_with = "no_export_notes")]
+    export_notes: bool,
+    /// Leave your scratchpad notes out of exports, even if the config file doesn't
+    #[clap(long, overrides_with = "export_notes")]
+    no_export_notes: bool,
+    /// End Markdown and HTML exports with when the players connected, left and took sentences back
+    #[clap(long, overrides_with = "no_export_timeline")]
+    export_timeline: bool,
+    /// Leave the timeline out of exports, even if the config file doesn't
+    #[clap(long, overrides_with = "export_timeline")]
+    no_export_timeline: bool,
+    /// Language to show everything in, such as es. Taken from LANG if not set
+    #[clap(long)]
+    lang: Option<String>,
+    /// Play without a terminal UI, answering each sentence with the next line of this file
+    #[clap(long)]
+    bot: Option<PathBuf>,
+    /// Milliseconds the bot waits before answering
+    #[clap(long, default_value = "1000")]
+    bot_delay: u64,
+    /// Write what happens as plain lines and read sentences and /commands a line at a time, for screen readers
+    #[clap(long, conflicts_with = "bot")]
+    plain: bool,
 }
 
 #[tokio::main]
-pub async fn main() -> Result<(), Error> {
-    let opts = Opts::parse();
+pub async fn main() {
+    // Before any other hook, which then report panics through this one
+    screen::restore_on_panic();
+    // The terminal is restored by the time `run` returns, so this is readable
+    if let Err(err) = run(Opts::parse()).await {
+        eprintln!("write_together: {}", err);
+        // The exit code of a panic, as the app's is one
+        std::process::exit(if matches!(err, Error::Panicked(_)) {
+            101
+        } else {
+            1
+        });
+    }
+}
+
+/// A setting a flag turns on and its `--no-` counterpart off, either of
+/// which wins over the config file. Off unless something turns it on.
+fn switch(on: bool, off: bool, configured: Option<bool>) -> bool {
+    match (on, off) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => configured.unwrap_or(false),
+    }
+}
+
+async fn run(opts: Opts) -> Result<(), Error> {
+    if opts.print_default_config {
+        print!("{}", config::DEFAULT);
+        return Ok(());
+    }
+    // Settings given on the command line win over the config file
+    let config = match &opts.config {
+        Some(path) => Config::load(path, true)?,
+        None => match config::default_path() {
+            Some(path) => Config::load(&path, false)?,
+            None => Config::default(),
+        },
+    };
+    let port = opts.port.or(config.port).filter(|_| !opts.no_listen);
+    if let Some(path) = opts.log_file.or(config.log_file) {
+        debug_log::init(&path)?;
+    }
+    for warning in &config.warnings {
+        tracing::warn!("{}", warning);
+    }
+    if let Some(path) = &opts.epub {
+        let session = SessionInstance::load(path)?;
+        let appendices = Appendices {
+            notes: switch(opts.export_notes, opts.no_export_notes, config.export_notes),
+            timeline: switch(
+                opts.export_timeline,
+                opts.no_export_timeline,
+                config.export_timeline,
+            ),
+        };
+        println!("{}", app::export(&session, Format::Epub, appendices)?);
+        return Ok(());
+    }
+    let save_dir = opts
+        .save_dir
+        .or(config.save_dir)
+        .unwrap_or_else(sessions::default_save_dir);
+    let imported = match &opts.import {
+        Some(path) => Some(import::load(path, opts.first)?),
+        None => None,
+    };
+    let resume = match opts.resume {
+        Some(path) => Some(SessionInstance::load(&path)?),
+        None => imported.clone(),
+    };
+    let word_filter = match opts.wordlist.or(config.wordlist) {
+        Some(path) => Some(WordFilter::load(
+            &path,
+            opts.wordlist_incoming
+                .or(config.wordlist_incoming)
+                .unwrap_or(Incoming::Mask),
+        )?),
+        None => None,
+    };
+    let hook_timeout = opts
+        .on_turn_hook_timeout
+        .or(config.on_turn_hook_timeout)
+        .unwrap_or(10);
+    let turn_hook = opts
+        .on_turn_hook
+        .or(config.on_turn_hook)
+        .map(|command| TurnHook::new(command, Duration::from_secs(hook_timeout)));
+    // Both the config file's lists and the command line's count
+    let allow = opts
+        .allow
+        .into_iter()
+        .chain(config.allow.unwrap_or_default());
+    let block = opts
+        .block
+        .into_iter()
+        .chain(config.block.unwrap_or_default());
+    let mut access = AccessList::new(allow.collect(), block.collect());
+    if let Some(path) = access::default_path() {
+        access = access.load_blocklist(path)?;
+    }
+    let mut app_config = AppConfig {
+        listen_port: port,
+        bind: opts
+            .bind
+            .or(config.bind)
+            .unwrap_or_else(|| AppConfig::default().bind),
+        access,
+        save_dir,
+        resume,
+        turn_preference: match (opts.first, opts.second) {
+            (true, _) => Some(TurnPreference::First),
+            (_, true) => Some(TurnPreference::Second),
+            _ => None,
+        },
+        word_filter: word_filter.clone(),
+        turn_hook,
+        git_export: opts.git_export.or(config.git_export),
+        ..AppConfig::default()
+    };
+    if let Some(seconds) = opts.write_timeout.or(config.write_timeout) {
+        app_config.write_timeout = Duration::from_secs(seconds);
+    }
+    if let Some(seconds) = opts.rematch_timeout.or(config.rematch_timeout) {
+        app_config.rematch_timeout = Duration::from_secs(seconds);
+    }
+    if let Some(seconds) = opts.cooldown.or(config.cooldown) {
+        app_config.cooldown = Duration::from_secs(seconds);
+    }
+    app_config.idle_timeout = opts
+        .idle_timeout
+        .or(config.idle_timeout)
+        .filter(|minutes| *minutes > 0)
+        .map(|minutes| Duration::from_secs(minutes * 60));
+    app_config.idle_pause_away = switch(
+        opts.idle_pause_away,
+        opts.no_idle_pause_away,
+        config.idle_pause_away,
+    );
+    let appendices = Appendices {
+        notes: switch(opts.export_notes, opts.no_export_notes, config.export_notes),
+        timeline: switch(
+            opts.export_timeline,
+            opts.no_export_timeline,
+            config.export_timeline,
+        ),
+    };
+    app_config.appendices = appendices;
+    app_config.milestones = if !switch(
+        opts.milestones,
+        opts.no_milestones,
+        config.milestones.or(Some(true)),
+    ) {
+        None
+    } else {
+        let defaults = Milestones::default();
+        Some(Milestones {
+            every: opts
+                .milestone_words
+                .or(config.milestone_words)
+                .map_or(defaults.every, |words| Some(words as usize)),
+            goal: opts
+                .word_goal
+                .or(config.word_goal)
+                .map(|words| words as usize),
+        })
+    };
+
+    let catalog = opts
+        .lang
+        .or(config.lang)
+        .map_or_else(Catalog::from_env, |lang| Catalog::for_lang(&lang));
+
+    if let Some(path) = opts.bot {
+        let script = Script::load(&path)?;
+        if port.is_none() && opts.connect.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a bot needs a --port to listen on or a peer to --connect to",
+            )
+            .into());
+        }
+        let (ui_handle, ui) = UIHandle::channel();
+        let app_handle = AppHandle::new(app_config, ui_handle);
+        let delay = Duration::from_millis(opts.bot_delay);
+        return bot::run(app_handle, ui, script, delay, opts.connect).await;
+    }
+
+    if opts.plain {
+        let (ui_handle, ui) = UIHandle::channel();
+        let app_handle = AppHandle::new(app_config, ui_handle);
+        let input = tokio::io::BufReader::new(tokio::io::stdin());
+        let output = tokio::io::stdout();
+        return plain::run(app_handle, ui, catalog, input, output, opts.connect).await;
+    }
+
+    let constraints = match opts.constraints_file.or(config.constraints_file) {
+        Some(path) => Some(ConstraintPool::load(&path)?),
+        None if switch(opts.constraints, opts.no_constraints, config.constraints) => {
+            Some(ConstraintPool::BuiltIn)
+        }
+        None => None,
+    };
+
+    let keymap = match opts
+        .keys
+        .or(config.keys)
+        .or_else(|| keymap::default_path().filter(|path| path.exists()))
+    {
+        Some(path) => KeyMap::load(&path)?,
+        None => KeyMap::default(),
+    };
+
+    let history_path = history::default_path();
+    if let (true, Some(path)) = (opts.forget_history, &history_path) {
+        history::forget(path)?;
+    }
+    let history = match history_path {
+        Some(path) => AddressHistory::load(path)?,
+        None => AddressHistory::default(),
+    };
+    let recent_peers_path = history::recent_peers_path();
+    if let (true, Some(path)) = (opts.forget_history, &recent_peers_path) {
+        history::forget(path)?;
+    }
+    let recent_peers = match recent_peers_path {
+        Some(path) => RecentPeers::load(path)?,
+        None => RecentPeers::default(),
+    };
+    let sentences_path = history::sentences_path();
+    if let (true, Some(path)) = (opts.forget_history, &sentences_path) {
+        history::forget(path)?;
+    }
+    let sentences = match sentences_path {
+        Some(path)
+            if switch(
+                opts.sentence_history,
+                opts.no_sentence_history,
+                config.sentence_history,
+            ) =>
+        {
+            SentenceHistory::load(path)?
+        }
+        _ => SentenceHistory::default(),
+    };
+
+    let completer = match opts.dict.or(config.dict) {
+        _ if !switch(
+            opts.complete,
+            opts.no_complete,
+            config.complete.or(Some(true)),
+        ) =>
+        {
+            None
+        }
+        Some(path) => Some(Completer::load(&path)?),
+        None => Some(Completer::default()),
+    };
+    let speller = match opts.spell_dict.or(config.spell_dict) {
+        Some(path) => Some(Speller::load(&path)?),
+        None => None,
+    };
 
+    let _screen = ScreenGuard::enter()?;
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend).unwrap();
-    enable_raw_mode().unwrap();
-    terminal.clear().unwrap();
+    let mut terminal = Terminal::new(backend)?;
+    terminal.clear()?;
 
     let reader = EventStream::new();
 
     {
-        let (ui_handle, ui_starter) = UIHandle::new();
-        let app_handle = AppHandle::new(opts.port, ui_handle);
+        let (ui_handle, ui_starter) = UIHandle::new(UIConfig {
+            constraints,
+            theme: Theme::new(
+                opts.theme.or(config.theme).unwrap_or(ThemeName::Default),
+                opts.color
+                    .or(config.color)
+                    .unwrap_or(ColorChoice::Auto)
+                    .enabled(),
+            ),
+            keymap,
+            alerts: Alerts {
+                notify: opts.notify.or(config.notify).unwrap_or(Notify::None),
+                desktop: switch(
+                    opts.desktop_notify,
+                    opts.no_desktop_notify,
+                    config.desktop_notify,
+                ),
+            },
+            history,
+            recent_peers,
+            sentences,
+            completer,
+            speller,
+            warnings: config.warnings,
+            tick_rate: Duration::from_millis(opts.tick_ms.or(config.tick_ms).unwrap_or(100).max(1)),
+            confirm_queued: switch(
+                opts.confirm_queued,
+                opts.no_confirm_queued,
+                config.confirm_queued,
+            ),
+            import_first: opts.first,
+            imported,
+            word_filter,
+            away_after: opts
+                .away_after
+                .or(config.away_after)
+                .map(Duration::from_secs),
+            remind_after: opts
+                .remind_after
+                .or(config.remind_after)
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+            remind_peer: switch(opts.remind_peer, opts.no_remind_peer, config.remind_peer),
+            typewriter: opts
+                .typewriter
+                .or(config.typewriter)
+                .filter(|speed| *speed > 0),
+            invite_hint: opts.invite_hint.or(config.invite_hint),
+            appendices,
+            catalog,
+        });
+        let app_handle = AppHandle::new(app_config, ui_handle);
+        if let Some(address) = opts.connect {
+            app_handle.connect(address).await?;
+        }
         ui_starter(reader, app_handle, &mut terminal).await?;
     }
 
-    disable_raw_mode().unwrap();
-    terminal.clear().unwrap();
     Ok(())
 }
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::time::{Duration, Instant};
+
+/// Counters an actor keeps as it handles messages, cheap enough to keep all
+/// the time, for the metrics overlay to show.
+#[derive(Debug, Default)]
+pub struct ActorMetrics {
+    /// Messages sent to the actor that it hasn't taken yet.
+    queued: AtomicUsize,
+    handled: AtomicU64,
+    /// The longest anything took to handle, in microseconds.
+    slowest_micros: AtomicU64,
+}
+
+impl ActorMetrics {
+    /// Counts a message about to be sent, to be taken back with
+    /// [`received`](Self::received) if sending fails.
+    pub fn sent(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn received(&self) {
+        // Not every sender counts, such as those in tests
+        let _ = self
+            .queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                Some(queued.saturating_sub(1))
+            });
+    }
+
+    /// Counts a message that took `took` to handle.
+    pub fn handled(&self, took: Duration) {
+        self.handled.fetch_add(1, Ordering::Relaxed);
+        self.slowest_micros
+            .fetch_max(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn handled_count(&self) -> u64 {
+        self.handled.load(Ordering::Relaxed)
+    }
+
+    pub fn slowest(&self) -> Duration {
+        Duration::from_micros(self.slowest_micros.load(Ordering::Relaxed))
+    }
+}
+
+/// How fast a count goes up, from samples taken at least a second apart.
+#[derive(Debug)]
+pub struct Rate {
+    sampled_at: Instant,
+    count: u64,
+    per_second: f64,
+}
+
+impl Rate {
+    pub fn new(count: u64, now: Instant) -> Self {
+        Self {
+            sampled_at: now,
+            count,
+            per_second: 0.0,
+        }
+    }
+
+    /// Takes `count` as a new sample if a second has passed since the last,
+    /// returning whether it did.
+    pub fn sample(&mut self, count: u64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.sampled_at);
+        if elapsed < Duration::from_secs(1) {
+            return false;
+        }
+        self.per_second = count.saturating_sub(self.count) as f64 / elapsed.as_secs_f64();
+        self.sampled_at = now;
+        self.count = count;
+        true
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActorMetrics, Rate};
+    use tokio::time::{Duration, Instant};
+
+    #[test]
+    fn counts_what_an_actor_handles() {
+        let metrics = ActorMetrics::default();
+        metrics.received();
+        metrics.sent();
+        metrics.sent();
+        metrics.received();
+        assert_eq!(metrics.queued(), 1);
+        metrics.handled(Duration::from_millis(3));
+        metrics.handled(Duration::from_millis(40));
+        metrics.handled(Duration::from_millis(5));
+        assert_eq!(metrics.handled_count(), 3);
+        assert_eq!(metrics.slowest(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn rates_are_sampled_a_second_apart() {
+        let start = Instant::now();
+        let mut rate = Rate::new(10, start);
+        assert!(!rate.sample(15, start + Duration::from_millis(500)));
+        assert!(rate.sample(40, start + Duration::from_secs(2)));
+        assert_eq!(rate.per_second(), 15.0);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    fmt::{Display, Formatter},
+    process::Stdio,
+    str::FromStr,
+};
+use tokio::process::Command;
+use tracing::debug;
+
+/// Everything that can draw attention when it becomes our turn.
+#[derive(Clone, Copy, Debug)]
+pub struct Alerts {
+    pub notify: Notify,
+    /// Also show a desktop notification when the user seems to be away.
+    pub desktop: bool,
+}
+
+/// How to draw attention in the terminal when it becomes our turn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Notify {
+    None,
+    /// Ring the terminal bell, which terminals may turn into their own alert.
+    Bell,
+    /// Briefly invert the whole screen.
+    Flash,
+}
+
+impl Notify {
+    const ALL: [Notify; 3] = [Notify::None, Notify::Bell, Notify::Flash];
+
+    /// The next setting, for switching at runtime.
+    pub fn next(self) -> Self {
+        let position = Self::ALL
+            .iter()
+            .position(|notify| *notify == self)
+            .unwrap_or(0);
+        Self::ALL[(position + 1) % Self::ALL.len()]
+    }
+}
+
+impl Display for Notify {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Notify::None => write!(f, "none"),
+            Notify::Bell => write!(f, "bell"),
+            Notify::Flash => write!(f, "flash"),
+        }
+    }
+}
+
+impl FromStr for Notify {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Notify::ALL
+            .iter()
+            .copied()
+            .find(|notify| notify.to_string() == s)
+            .ok_or_else(|| format!("unknown notification '{}', expected bell, flash or none", s))
+    }
+}
+
+/// Shows a desktop notification with the platform's notifier command.
+/// Failures, such as there being no notification daemon, are ignored.
+pub fn desktop(message: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {:?} with title \"write_together\"",
+            message
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg("write_together").arg(message);
+        command
+    };
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    tokio::spawn(async move {
+        match command.status().await {
+            Ok(status) if !status.success() => debug!(%status, "desktop notification failed"),
+            Err(err) => debug!(%err, "desktop notification failed"),
+            Ok(_) => {}
+        }
+    });
+}
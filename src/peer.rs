@@ -0,0 +1,222 @@
+use crate::{
+    error::Error,
+    protocol::{self, Decoder, PeerMessage},
+};
+use std::io;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    task::JoinHandle,
+    time::{timeout, Duration},
+};
+use tracing::{debug, trace};
+
+/// How many frames can wait to be written before the peer counts as stuck.
+const QUEUE: usize = 32;
+/// How much is read from the socket at once, into a buffer kept for the
+/// whole connection.
+const READ_CHUNK: usize = 1024;
+/// How long to wait for the peer to close its side once we've closed ours.
+const LINGER: Duration = Duration::from_secs(2);
+
+/// What a connection's tasks report back to the app, tagged with the
+/// connection's id.
+#[derive(Debug)]
+pub(crate) enum PeerEvent {
+    Message(PeerMessage),
+    /// The peer closed the connection.
+    Closed,
+    /// Reading or writing failed, and the connection can't be used any more.
+    Failed(Error),
+}
+
+/// A connection to the peer, read and written on tasks of its own so that a
+/// slow peer never holds up the app.
+#[derive(Debug)]
+pub(crate) struct Peer {
+    pub id: u64,
+    queue: Sender<Vec<u8>>,
+    /// Whether the peer said it can read compressed frames.
+    compression: bool,
+    reader: JoinHandle<()>,
+    writer: JoinHandle<()>,
+}
+
+impl Peer {
+    /// Starts reading and writing `stream`, reporting what happens to `events`.
+    /// A write taking longer than `write_timeout` fails the connection.
+    pub fn spawn(
+        stream: TcpStream,
+        id: u64,
+        events: Sender<(u64, PeerEvent)>,
+        write_timeout: Duration,
+    ) -> Self {
+        let (read, write) = stream.into_split();
+        let (queue, frames) = mpsc::channel(QUEUE);
+        Self {
+            id,
+            queue,
+            compression: false,
+            reader: tokio::spawn(read_frames(read, id, events.clone())),
+            writer: tokio::spawn(write_frames(write, frames, write_timeout, id, events)),
+        }
+    }
+
+    /// Compresses what's worth it from now on, once the peer says it can
+    /// read that.
+    pub fn set_compression(&mut self, compression: bool) {
+        self.compression = compression;
+    }
+
+    /// Queues `message` for the writer, without waiting for it to be written.
+    pub fn send(&self, message: &PeerMessage) -> Result<(), Error> {
+        self.queue
+            .try_send(protocol::encode_for(message, self.compression))
+            .map_err(|err| {
+                Error::PeerIo(match err {
+                    TrySendError::Full(_) => {
+                        io::Error::new(io::ErrorKind::TimedOut, "the peer isn't keeping up")
+                    }
+                    TrySendError::Closed(_) => io::ErrorKind::BrokenPipe.into(),
+                })
+            })
+    }
+
+    /// Lets the writer finish what is queued and close our side of the
+    /// connection, then keeps reading until the peer closes theirs, for up
+    /// to [`LINGER`]. Closing the socket with anything it sent unread would
+    /// reset the connection, throwing away whatever we wrote that it hadn't
+    /// taken yet. What is read meanwhile still goes to the events.
+    pub async fn close(self) {
+        let Self {
+            queue,
+            mut reader,
+            writer,
+            ..
+        } = self;
+        drop(queue);
+        let _ = writer.await;
+        if timeout(LINGER, &mut reader).await.is_err() {
+            debug!("the peer didn't close its side in time");
+            reader.abort();
+        }
+    }
+}
+
+/// Reads messages until the connection ends. Reads are never raced against
+/// anything else, so bytes taken off the socket always reach the decoder.
+async fn read_frames(mut read: OwnedReadHalf, id: u64, events: Sender<(u64, PeerEvent)>) {
+    let mut decoder = Decoder::default();
+    let mut buf = vec![0; READ_CHUNK];
+    let end = 'reading: loop {
+        let count = match read.read(&mut buf).await {
+            Ok(0) => break PeerEvent::Closed,
+            Ok(count) => count,
+            Err(err) => break PeerEvent::Failed(Error::PeerIo(err)),
+        };
+        trace!(bytes = count, "read from peer");
+        decoder.extend(&buf[..count]);
+        loop {
+            match decoder.next_message() {
+                Ok(Some(message)) => {
+                    if events
+                        .send((id, PeerEvent::Message(message)))
+                        .await
+                        .is_err()
+                    {
+                        // The app is gone
+                        return;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => break 'reading PeerEvent::Failed(Error::Protocol(err)),
+            }
+        }
+    };
+    let _ = events.send((id, end)).await;
+}
+
+async fn write_frames(
+    mut write: OwnedWriteHalf,
+    mut frames: Receiver<Vec<u8>>,
+    write_timeout: Duration,
+    id: u64,
+    events: Sender<(u64, PeerEvent)>,
+) {
+    while let Some(frame) = frames.recv().await {
+        // A peer that vanished without closing the connection stops taking
+        // bytes once its buffers are full, and TCP takes minutes to notice
+        let written = match timeout(write_timeout, write.write_all(&frame)).await {
+            Ok(written) => written,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "the peer took over {}s to take a message",
+                    write_timeout.as_secs()
+                ),
+            )),
+        };
+        if let Err(err) = written {
+            // Without waiting, as the app may be waiting on us. If this is
+            // lost, the next send finds the queue closed instead.
+            let _ = events.try_send((id, PeerEvent::Failed(Error::PeerIo(err))));
+            return;
+        }
+    }
+    // Everything queued has been written, so let the peer see us go
+    if let Err(err) = write.shutdown().await {
+        debug!(%err, "couldn't shut down the connection");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Peer, PeerEvent};
+    use crate::{error::Error, protocol::PeerMessage, sessions::Turn};
+    use chrono::Utc;
+    use std::io;
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        time::{timeout, Duration},
+    };
+
+    #[tokio::test]
+    async fn a_peer_that_stops_reading_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        // Connected, but never read from
+        let _silent = listener.accept().await.unwrap();
+        let (events, mut receiver) = mpsc::channel(8);
+        let peer = Peer::spawn(stream, 1, events, Duration::from_millis(100));
+
+        let message = PeerMessage::Sentence {
+            index: 0,
+            turn: Turn {
+                text: "word ".repeat(200_000),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            },
+        };
+        // Far more than the socket buffers hold
+        for _ in 0..20 {
+            peer.send(&message).unwrap();
+        }
+        let event = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("the write never timed out");
+        assert!(matches!(
+            event,
+            Some((1, PeerEvent::Failed(Error::PeerIo(err)))) if err.kind() == io::ErrorKind::TimedOut
+        ));
+        // The writer is gone, so nothing more can be queued
+        assert!(peer.send(&message).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{
+    app::AppHandle,
+    constraints::Constraint,
+    error::Error,
+    i18n::{Catalog, Message},
+    invite::Invite,
+    log_buffer::Level,
+    sessions::SessionInstance,
+    ui_actor::UIMessage,
+};
+use std::{io, net::SocketAddr};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::Receiver,
+};
+
+/// Plays without a terminal UI, for screen readers and scripts: what
+/// happens is written to `output` a line at a time, in the language of
+/// `catalog`, and each line read from `input` is a sentence or a command
+/// such as `/connect`. Connects to `connect` if given. Returns on `/quit` or
+/// once `input` ends.
+pub async fn run<R, W>(
+    app: AppHandle,
+    ui: Receiver<UIMessage>,
+    catalog: Catalog,
+    input: R,
+    output: W,
+    connect: Option<SocketAddr>,
+) -> Result<(), Error>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut front = Plain {
+        app: &app,
+        catalog,
+        output,
+        connected: false,
+        our_turn: false,
+        constraint: None,
+        rematch: false,
+        clear: false,
+        resync: false,
+    };
+    let played = front.play(ui, input, connect).await;
+    let stopped = app.shutdown().await;
+    played.and(stopped)
+}
+
+struct Plain<'a, W> {
+    app: &'a AppHandle,
+    catalog: Catalog,
+    output: W,
+    connected: bool,
+    our_turn: bool,
+    /// What our next sentence has to do, set by the peer's last one.
+    constraint: Option<Constraint>,
+    /// Whether we've been asked to write another story.
+    rematch: bool,
+    /// Whether the peer has asked to scrap the story.
+    clear: bool,
+    /// Whether we've been asked to take the peer's story in place of ours.
+    resync: bool,
+}
+
+impl<W: AsyncWrite + Unpin> Plain<'_, W> {
+    async fn play<R: AsyncBufRead + Unpin>(
+        &mut self,
+        mut ui: Receiver<UIMessage>,
+        input: R,
+        connect: Option<SocketAddr>,
+    ) -> Result<(), Error> {
+        if let Some(address) = connect {
+            self.app.connect(address).await?;
+        }
+        let mut lines = input.lines();
+        loop {
+            tokio::select! {
+                message = ui.recv() => match message {
+                    Some(message) => self.show(message).await?,
+                    None => {
+                        return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the app stopped").into())
+                    }
+                },
+                line = lines.next_line() => match line? {
+                    Some(line) => {
+                        if self.command(line.trim()).await? {
+                            return Ok(());
+                        }
+                    }
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    async fn say(&mut self, line: String) -> io::Result<()> {
+        self.output
+            .write_all(format!("{}\n", line).as_bytes())
+            .await?;
+        self.output.flush().await
+    }
+
+    async fn say_message(&mut self, message: Message) -> io::Result<()> {
+        let line = self.catalog.render(&message);
+        self.say(line).await
+    }
+
+    /// Says whose turn it is, and what the constraint is if there is one.
+    async fn say_turn(&mut self) -> io::Result<()> {
+        let message = match (&self.constraint, self.our_turn) {
+            (Some(constraint), true) => Message::new("plain-your-turn-constraint")
+                .arg_message("constraint", constraint.message()),
+            (None, true) => Message::new("plain-your-turn"),
+            (_, false) => Message::new("plain-peers-turn"),
+        };
+        self.say_message(message).await
+    }
+
+    async fn start_story(&mut self, session: &SessionInstance) -> io::Result<()> {
+        if !session.turns().is_empty() {
+            self.say_message(Message::new("plain-story-so-far")).await?;
+            for turn in session.turns() {
+                self.say(turn.text.clone()).await?;
+            }
+        }
+        self.our_turn = session.is_our_turn();
+        self.constraint = session.current_constraint().cloned();
+        self.rematch = false;
+        self.clear = false;
+        self.resync = false;
+        self.say_turn().await
+    }
+
+    async fn show(&mut self, message: UIMessage) -> Result<(), Error> {
+        match message {
+            UIMessage::Log(Level::Info, message) | UIMessage::Toast(message) => {
+                self.say_message(message).await?
+            }
+            UIMessage::Log(Level::Warn, message) => {
+                self.say_message(Message::new("plain-warning").arg_message("message", message))
+                    .await?
+            }
+            UIMessage::Log(Level::Error, message) => {
+                self.say_message(Message::new("plain-error").arg_message("message", message))
+                    .await?
+            }
+            UIMessage::SentenceReceived(turn) => {
+                self.say_message(Message::new("plain-peer").arg("text", turn.text))
+                    .await?;
+                self.our_turn = true;
+                self.constraint = turn.next_constraint;
+                self.say_turn().await?;
+            }
+            UIMessage::SentenceAccepted(turn) => {
+                self.say_message(Message::new("plain-you").arg("text", turn.text))
+                    .await?
+            }
+            UIMessage::SentenceBounced {
+                index,
+                kept,
+                bounced,
+            } => {
+                let bounced: Vec<_> = bounced.into_iter().map(|turn| turn.text).collect();
+                self.say_message(
+                    Message::new("plain-conflict")
+                        .arg("turn", index + 1)
+                        .arg("kept", &kept.text)
+                        .arg("bounced", bounced.join(" ")),
+                )
+                .await?;
+                self.our_turn = true;
+                self.constraint = kept.next_constraint;
+                self.say_turn().await?;
+            }
+            UIMessage::SentenceRefused { turn, .. } => {
+                self.say_message(Message::new("plain-refused").arg("text", turn.text))
+                    .await?;
+                self.our_turn = true;
+                self.say_turn().await?;
+            }
+            UIMessage::Listening(address) => {
+                self.say_message(Message::new("status-listening").arg("address", address))
+                    .await?
+            }
+            UIMessage::Connecting(address) => {
+                self.say_message(Message::new("status-connecting").arg("address", address))
+                    .await?
+            }
+            UIMessage::ConnectFailed { address, reason } => {
+                self.say_message(
+                    Message::new("connect-failed")
+                        .arg("address", address)
+                        .arg_message("reason", reason),
+                )
+                .await?
+            }
+            UIMessage::Connected { session, peer } => {
+                self.connected = true;
+                self.say_message(Message::new("connected-to").arg("address", peer))
+                    .await?;
+                self.start_story(&session).await?;
+            }
+            UIMessage::StoryEnded => {
+                self.rematch = true;
+                self.our_turn = false;
+                self.say_message(Message::new("plain-story-finished"))
+                    .await?;
+            }
+            UIMessage::NewStory(session) => {
+                self.say_message(Message::new("plain-new-story")).await?;
+                self.start_story(&session).await?;
+            }
+            UIMessage::Milestone(milestone) => self.say_message(milestone.message()).await?,
+            UIMessage::ClearProposed => {
+                self.clear = true;
+                self.say_message(Message::new("plain-clear-proposed"))
+                    .await?;
+            }
+            UIMessage::Diverged(true) => self.say_message(Message::new("diverged-kept")).await?,
+            UIMessage::Diverged(false) => {
+                self.resync = true;
+                self.say_message(Message::new("plain-diverged")).await?;
+            }
+            UIMessage::Resynced(session) => {
+                self.say_message(Message::new("resynced").arg("turns", session.turns().len()))
+                    .await?;
+                self.start_story(&session).await?;
+            }
+            UIMessage::SavedSessions(_) => {}
+            UIMessage::Cooldown(wait) => {
+                self.say_message(
+                    Message::new("plain-cooldown").arg("seconds", wait.as_secs_f32().ceil()),
+                )
+                .await?
+            }
+            UIMessage::PeerAway(away) => {
+                self.say_message(Message::new(if away { "peer-away" } else { "peer-back" }))
+                    .await?
+            }
+            UIMessage::Disconnected => {
+                self.connected = false;
+                self.our_turn = false;
+                self.rematch = false;
+                self.clear = false;
+                self.resync = false;
+                self.say_message(Message::new("plain-disconnected")).await?;
+            }
+            UIMessage::AppStopped(reason) => {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, reason).into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Acts on a line of input, returning whether to quit.
+    async fn command(&mut self, line: &str) -> Result<bool, Error> {
+        let (command, argument) = match line.strip_prefix('/') {
+            Some(command) => command
+                .split_once(' ')
+                .map_or((command, ""), |(command, argument)| {
+                    (command, argument.trim())
+                }),
+            None => return self.sentence(line).await.map(|_| false),
+        };
+        match command {
+            "quit" => return Ok(true),
+            "help" => self.say_message(Message::new("plain-help")).await?,
+            "connect" => {
+                let address = if Invite::is_invite(argument) {
+                    Invite::decode(argument).map(|invite| invite.address)
+                } else {
+                    argument
+                        .parse()
+                        .map_err(|_| Message::new("invalid-address"))
+                };
+                match address {
+                    Ok(address) => self.app.connect(address).await?,
+                    Err(reason) => {
+                        self.say_message(Message::new("cant-connect").arg_message("reason", reason))
+                            .await?
+                    }
+                }
+            }
+            "disconnect" if self.connected => self.app.disconnect().await?,
+            "end" if self.connected => self.app.end_story().await?,
+            "clear" if self.connected => self.app.propose_clear().await?,
+            "verify" if self.connected => self.app.verify_story().await?,
+            "block" if self.connected => self.app.block_peer().await?,
+            "yes" | "no" if self.clear => {
+                self.clear = false;
+                self.app.answer_clear(command == "yes").await?;
+            }
+            "yes" | "no" if self.resync => {
+                self.resync = false;
+                self.app.answer_resync(command == "yes").await?;
+            }
+            "yes" | "no" if self.rematch => {
+                self.rematch = false;
+                self.app.rematch(command == "yes").await?;
+            }
+            "disconnect" | "end" | "clear" | "verify" | "block" | "yes" | "no" => {
+                self.say_message(Message::new("plain-nothing-to").arg("command", line))
+                    .await?
+            }
+            _ => {
+                self.say_message(Message::new("plain-unknown-command").arg("command", line))
+                    .await?
+            }
+        }
+        Ok(false)
+    }
+
+    async fn sentence(&mut self, sentence: &str) -> Result<(), Error> {
+        if sentence.is_empty() {
+            return Ok(());
+        }
+        if !self.connected {
+            return Ok(self
+                .say_message(Message::new("plain-not-connected"))
+                .await?);
+        }
+        if !self.our_turn {
+            return Ok(self
+                .say_message(Message::new("plain-not-your-turn"))
+                .await?);
+        }
+        if let Some(Err(reason)) = self
+            .constraint
+            .as_ref()
+            .map(|constraint| constraint.check(sentence))
+        {
+            return Ok(self.say_message(reason).await?);
+        }
+        self.our_turn = false;
+        self.app.send_sentence(String::from(sentence), None).await?;
+        Ok(())
+    }
+}
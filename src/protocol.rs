@@ -0,0 +1,379 @@
+use crate::{
+    compress,
+    sessions::{Digest, SessionInstance, Turn},
+};
+use serde::{de::Error as _, Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// Which turn a player would like to write, when a fresh story starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnPreference {
+    First,
+    Second,
+}
+
+/// Messages exchanged between the two peers.
+///
+/// Each message is sent as a JSON frame prefixed with its length as a
+/// big-endian `u32`. Between peers that both said in `Hello` that they can
+/// read them, frames carrying a whole story may be compressed instead,
+/// which sets the length's top bit.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PeerMessage {
+    /// Sent by both sides straight after connecting, to agree on who writes
+    /// first. Each side sends a random nonce, and where their preferences
+    /// don't settle it, the low bit of the two nonces XORed does. The
+    /// longer of the two cooldowns is kept between turns.
+    Hello {
+        nonce: u64,
+        preference: Option<TurnPreference>,
+        /// Seconds the sender wants between one sentence and the next.
+        #[serde(default)]
+        cooldown_secs: u64,
+        /// Whether the sender can read compressed frames.
+        #[serde(default)]
+        compression: bool,
+    },
+    /// A sentence together with the index of the turn it fills, so that
+    /// sentences submitted simultaneously by both sides can be detected.
+    Sentence { index: usize, turn: Turn },
+    /// Sent instead of taking the sentence for turn `index`, when it has a
+    /// word on the receiver's word list. The sender writes the turn again.
+    SentenceRefused { index: usize },
+    /// Sent by the connecting side once it has the other side's `Hello`, so
+    /// that both sides share the same session id and start time. The accepting side
+    /// answers with its own session only when it is resuming and the
+    /// connecting side is not. The session is from the sender's point of view.
+    Session {
+        session: SessionInstance,
+        resumed: bool,
+    },
+    /// The sender ended the story. Both sides then ask their player whether
+    /// to write another.
+    StoryEnded,
+    /// A player's answer to writing another story. A no is the last message
+    /// before the sender closes the connection.
+    Rematch { accept: bool },
+    /// Sent by the connecting side once both players want another story,
+    /// carrying the fresh session from the sender's point of view. Whoever
+    /// did not start the previous story writes first.
+    NewStory { session: SessionInstance },
+    /// Whether the sender has stepped away from the keyboard, sent whenever
+    /// that changes.
+    Status { away: bool },
+    /// Sent when the sender has been reminded that it's their turn, so that
+    /// the receiver knows they haven't vanished.
+    StillThinking { minutes: u64 },
+    /// Asks to scrap the story and start again without disconnecting, with
+    /// the sender's half of a fresh coin flip for who writes first.
+    ProposeClear { nonce: u64 },
+    /// The answer to `ProposeClear`, with the other half of the coin flip.
+    ClearAnswer { accept: bool, nonce: u64 },
+    /// Sent by whoever proposed clearing once the peer agrees, carrying the
+    /// fresh session from the sender's point of view. Only then do both
+    /// sides archive and export the story and start the new one.
+    Cleared { session: SessionInstance },
+    /// The sender's story, for the receiver to check that it has the same.
+    /// Sent by whoever received every so many sentences, and whenever a
+    /// player asks.
+    Digest { digest: Digest },
+    /// The answer to `Digest`: whether the receiver's story agreed, and how
+    /// many turns it has.
+    Checked { matched: bool, turns: usize },
+    /// Asks for the sender's story to be replaced with the receiver's, once
+    /// they were found to differ. The story kept is the one with more turns,
+    /// or the connecting side's when they have as many.
+    RequestResync,
+    /// The answer to `RequestResync`: the whole story, from the sender's
+    /// point of view.
+    Resync { session: SessionInstance },
+    /// Sent when nothing has happened for most of the sender's idle
+    /// timeout: it closes the connection in `secs_left` unless something
+    /// does.
+    IdleWarning { secs_left: u64 },
+    /// The last message before the sender closes a connection on which
+    /// nothing happened for its whole idle timeout.
+    IdleTimeout,
+}
+
+/// Whether we write first in a fresh story. A preference wins unless both
+/// sides asked for the same turn, when the coin flip of the two nonces
+/// decides, from the point of view of the connecting side.
+pub fn we_write_first(
+    ours: Option<TurnPreference>,
+    theirs: Option<TurnPreference>,
+    our_nonce: u64,
+    their_nonce: u64,
+    initiator: bool,
+) -> bool {
+    use TurnPreference::{First, Second};
+    match (ours, theirs) {
+        (Some(First), Some(First)) | (Some(Second), Some(Second)) | (None, None) => {
+            let initiator_first = (our_nonce ^ their_nonce) & 1 == 1;
+            initiator_first == initiator
+        }
+        (Some(First), _) | (_, Some(Second)) => true,
+        (Some(Second), _) | (_, Some(First)) => false,
+    }
+}
+
+impl PeerMessage {
+    /// Whether the message is worth compressing when it's big enough:
+    /// only those carrying a whole story, never a single sentence.
+    fn compressible(&self) -> bool {
+        matches!(
+            self,
+            PeerMessage::Session { .. }
+                | PeerMessage::NewStory { .. }
+                | PeerMessage::Cleared { .. }
+                | PeerMessage::Resync { .. }
+        )
+    }
+}
+
+const LENGTH_PREFIX: usize = 4;
+/// The length's bit marking a compressed frame.
+const COMPRESSED: u32 = 1 << 31;
+/// The smallest payload worth compressing.
+pub const COMPRESS_OVER: usize = 4096;
+/// The largest frame accepted, well over any real session, so that a peer
+/// can't make us buffer gigabytes by sending a huge length.
+pub const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+pub fn encode(message: &PeerMessage) -> Vec<u8> {
+    encode_for(message, false)
+}
+
+/// Encodes `message` for a peer that can read compressed frames if
+/// `compression`, compressing it if it carries a whole story over
+/// [`COMPRESS_OVER`] bytes long and that makes it shorter.
+pub fn encode_for(message: &PeerMessage, compression: bool) -> Vec<u8> {
+    let payload = serde_json::to_vec(message).expect("peer messages always serialize");
+    let compressed = (compression && message.compressible() && payload.len() > COMPRESS_OVER)
+        .then(|| compress::compress(&payload))
+        .filter(|compressed| compressed.len() < payload.len());
+    let (flag, payload) = match compressed {
+        Some(compressed) => (COMPRESSED, compressed),
+        None => (0, payload),
+    };
+    let mut frame = Vec::with_capacity(LENGTH_PREFIX + payload.len());
+    frame.extend_from_slice(&(flag | payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Reassembles frames from bytes as they arrive from the socket.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the next complete message, if one has been fully received.
+    /// Fails on a frame that can't be read, or that is over [`MAX_FRAME`]
+    /// compressed or not.
+    pub fn next_message(&mut self) -> Result<Option<PeerMessage>, serde_json::Error> {
+        if self.buffer.len() < LENGTH_PREFIX {
+            return Ok(None);
+        }
+        let prefix = u32::from_be_bytes(self.buffer[..LENGTH_PREFIX].try_into().unwrap());
+        let length = (prefix & !COMPRESSED) as usize;
+        if length > MAX_FRAME {
+            return Err(serde_json::Error::custom(format!(
+                "a frame of {} bytes is over the limit of {}",
+                length, MAX_FRAME
+            )));
+        }
+        if self.buffer.len() < LENGTH_PREFIX + length {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = self.buffer.drain(..LENGTH_PREFIX + length).collect();
+        if prefix & COMPRESSED == 0 {
+            return serde_json::from_slice(&frame[LENGTH_PREFIX..]).map(Some);
+        }
+        let payload = compress::decompress(&frame[LENGTH_PREFIX..], MAX_FRAME).map_err(|err| {
+            serde_json::Error::custom(format!("a compressed frame can't be read: {}", err))
+        })?;
+        serde_json::from_slice(&payload).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode, encode_for, we_write_first, Decoder, PeerMessage,
+        TurnPreference::{self, First, Second},
+        COMPRESS_OVER, LENGTH_PREFIX, MAX_FRAME,
+    };
+    use crate::sessions::{SessionInstance, Turn};
+    use chrono::Utc;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_text(rng: &mut StdRng) -> String {
+        let alphabet = [
+            'a', 'Z', ' ', '.', '"', '\\', '\n', 'é', '世', '🦀', '\u{0}',
+        ];
+        (0..rng.gen_range(0..200))
+            .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+            .collect()
+    }
+
+    fn random_message(rng: &mut StdRng) -> PeerMessage {
+        let turn = |rng: &mut StdRng| Turn {
+            text: random_text(rng),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        };
+        match rng.gen_range(0..7) {
+            0 => PeerMessage::Sentence {
+                index: rng.gen(),
+                turn: turn(rng),
+            },
+            1 => {
+                let mut session = SessionInstance::new(rng.gen());
+                for _ in 0..rng.gen_range(0..5) {
+                    session.push(turn(rng));
+                }
+                PeerMessage::Session {
+                    session,
+                    resumed: rng.gen(),
+                }
+            }
+            2 => PeerMessage::StoryEnded,
+            5 => PeerMessage::Rematch { accept: rng.gen() },
+            3 => PeerMessage::Hello {
+                nonce: rng.gen(),
+                preference: [None, Some(First), Some(Second)][rng.gen_range(0..3)],
+                cooldown_secs: rng.gen_range(0..10),
+                compression: rng.gen(),
+            },
+            4 => PeerMessage::Status { away: rng.gen() },
+            _ => PeerMessage::NewStory {
+                session: SessionInstance::new(rng.gen()),
+            },
+        }
+    }
+
+    #[test]
+    fn messages_survive_being_split_anywhere() {
+        let mut rng = StdRng::seed_from_u64(368);
+        for _ in 0..200 {
+            let frames: Vec<Vec<u8>> = (0..rng.gen_range(1..6))
+                .map(|_| encode(&random_message(&mut rng)))
+                .collect();
+            let bytes = frames.concat();
+
+            let mut decoder = Decoder::default();
+            let mut decoded = Vec::new();
+            let mut rest = &bytes[..];
+            while !rest.is_empty() {
+                let (chunk, after) = rest.split_at(rng.gen_range(1..=rest.len()));
+                rest = after;
+                decoder.extend(chunk);
+                while let Some(message) = decoder.next_message().unwrap() {
+                    decoded.push(encode(&message));
+                }
+            }
+            assert_eq!(decoded, frames);
+        }
+    }
+
+    #[test]
+    fn random_bytes_never_panic_or_pile_up() {
+        let mut rng = StdRng::seed_from_u64(373);
+        for _ in 0..1000 {
+            let mut decoder = Decoder::default();
+            for _ in 0..rng.gen_range(1..10) {
+                let chunk: Vec<u8> = (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect();
+                decoder.extend(&chunk);
+                // Like the reader, give up on the connection at the first error
+                match decoder.next_message() {
+                    Ok(_) => assert!(decoder.buffer.len() <= LENGTH_PREFIX + MAX_FRAME),
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_long_whole_stories_are_compressed() {
+        let turn = |text: &str| Turn {
+            text: String::from(text),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        };
+        let mut session = SessionInstance::new(true);
+        while serde_json::to_vec(&session).unwrap().len() <= COMPRESS_OVER {
+            session.push(turn("The lighthouse was dark."));
+        }
+        let compressed = |message: &PeerMessage, compression| {
+            let frame = encode_for(message, compression);
+            let mut decoder = Decoder::default();
+            decoder.extend(&frame);
+            let decoded = decoder.next_message().unwrap().unwrap();
+            // What comes out is what went in
+            assert_eq!(encode(&decoded), encode(message));
+            frame[0] & 0x80 != 0
+        };
+        let resync = PeerMessage::Resync { session };
+        assert!(compressed(&resync, true));
+        assert!(encode_for(&resync, true).len() < encode(&resync).len() / 5);
+        // Not for a peer that can't read it
+        assert!(!compressed(&resync, false));
+        // Nor a short story, nor a single sentence however long
+        let short = PeerMessage::Resync {
+            session: SessionInstance::new(true),
+        };
+        assert!(!compressed(&short, true));
+        let sentence = PeerMessage::Sentence {
+            index: 0,
+            turn: turn(&"The lighthouse was dark. ".repeat(400)),
+        };
+        assert!(!compressed(&sentence, true));
+    }
+
+    #[test]
+    fn huge_lengths_are_refused_before_buffering() {
+        let mut decoder = Decoder::default();
+        decoder.extend(&u32::MAX.to_be_bytes());
+        let err = decoder.next_message().unwrap_err();
+        assert!(err.to_string().contains("over the limit"));
+
+        let mut decoder = Decoder::default();
+        decoder.extend(&(MAX_FRAME as u32).to_be_bytes());
+        assert!(decoder.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn both_sides_agree_on_who_writes_first() {
+        let choices: [Option<TurnPreference>; 3] = [None, Some(First), Some(Second)];
+        let mut rng = StdRng::seed_from_u64(375);
+        for &initiator_wants in &choices {
+            for &acceptor_wants in &choices {
+                let mut firsts = [false, false];
+                for _ in 0..64 {
+                    let (a, b) = (rng.gen(), rng.gen());
+                    let initiator = we_write_first(initiator_wants, acceptor_wants, a, b, true);
+                    let acceptor = we_write_first(acceptor_wants, initiator_wants, b, a, false);
+                    assert_ne!(initiator, acceptor);
+                    firsts[initiator as usize] = true;
+                }
+                let settled = initiator_wants != acceptor_wants;
+                if settled {
+                    let initiator_first =
+                        initiator_wants == Some(First) || acceptor_wants == Some(Second);
+                    assert_eq!(firsts, [!initiator_first, initiator_first]);
+                } else {
+                    // Left to the coin, which comes up both ways
+                    assert_eq!(firsts, [true, true]);
+                }
+            }
+        }
+    }
+}
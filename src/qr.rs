@@ -0,0 +1,361 @@
+/// Data and error correction codewords of each version at error correction
+/// level L, up to the largest that is still a single block.
+const VERSIONS: [(usize, usize); 4] = [(19, 7), (34, 10), (55, 15), (80, 20)];
+
+/// A QR code, just big enough for an invite: byte mode at error correction
+/// level L, versions 1 to 4.
+#[derive(Clone, Debug)]
+pub struct QrCode {
+    size: usize,
+    /// Row by row, true where dark.
+    modules: Vec<bool>,
+    /// Which modules are finder, timing, alignment or format modules, which
+    /// data and masks leave alone.
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encodes `data` at the smallest version it fits, or None if it's too
+    /// long for any of them.
+    pub fn encode(data: &[u8]) -> Option<Self> {
+        // The mode and count take 12 bits
+        let (version, &(data_len, ecc_len)) = VERSIONS
+            .iter()
+            .enumerate()
+            .find(|(_, (data_len, _))| 12 + data.len() * 8 <= data_len * 8)?;
+        let mut bits = vec![];
+        let mut push = |value: u32, count: usize| {
+            bits.extend((0..count).rev().map(|i| value >> i & 1 != 0));
+        };
+        push(0b0100, 4);
+        push(data.len() as u32, 8);
+        for &byte in data {
+            push(byte.into(), 8);
+        }
+        let capacity = data_len * 8;
+        let terminator = (capacity - bits.len()).min(4);
+        bits.extend(std::iter::repeat_n(false, terminator));
+        bits.extend(std::iter::repeat_n(false, (8 - bits.len() % 8) % 8));
+        let mut codewords: Vec<u8> = bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+            .collect();
+        for pad in [0xEC, 0x11].iter().cycle().take(data_len - codewords.len()) {
+            codewords.push(*pad);
+        }
+        let ecc = remainder(&codewords, &divisor(ecc_len));
+        codewords.extend(ecc);
+
+        let size = 17 + 4 * (version + 1);
+        let mut code = Self {
+            size,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+        code.draw_function_patterns(version + 1);
+        code.draw_codewords(&codewords);
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                let mut masked = code.clone();
+                masked.apply_mask(mask);
+                masked.draw_format(mask);
+                masked.penalty()
+            })
+            .expect("there are masks to choose from");
+        code.apply_mask(mask);
+        code.draw_format(mask);
+        Some(code)
+    }
+
+    /// How many modules wide and high it is.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module in column `x` and row `y` is dark. Anything
+    /// outside is light, like the quiet zone around it.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                    if (0..size as i32).contains(&xx) && (0..size as i32).contains(&yy) {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(xx as usize, yy as usize, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+        // Versions 2 to 6 have a single alignment pattern
+        if version > 1 {
+            let center = size - 7;
+            for dy in -2i32..=2 {
+                for dx in -2i32..=2 {
+                    self.set_function(
+                        (center as i32 + dx) as usize,
+                        (center as i32 + dy) as usize,
+                        dx.abs().max(dy.abs()) != 1,
+                    );
+                }
+            }
+        }
+        // Reserve the format modules until the mask is chosen
+        self.draw_format(0);
+    }
+
+    /// Draws both copies of the error correction level and mask, along with
+    /// the dark module.
+    fn draw_format(&mut self, mask: u32) {
+        let bits = format_bits(mask);
+        let bit = |i: usize| bits >> i & 1 != 0;
+        let size = self.size;
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Fills the data modules in the zigzag order, two columns at a time
+    /// from the bottom right.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        loop {
+            // The vertical timing pattern takes a whole column
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward {
+                        size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.function[y * size + x] && i < codewords.len() * 8 {
+                        self.modules[y * size + x] = codewords[i / 8] >> (7 - i % 8) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if flip && !self.function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// How hard the code would be to scan, by the standard's rules: long runs,
+    /// 2×2 blocks, look-alikes of the finder patterns and lopsided darkness.
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+        let finder_like: [&[bool]; 2] = [
+            &[
+                true, false, true, true, true, false, true, false, false, false, false,
+            ],
+            &[
+                false, false, false, false, true, false, true, true, true, false, true,
+            ],
+        ];
+        for transposed in [false, true] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size)
+                    .map(|b| {
+                        let (x, y) = if transposed { (a, b) } else { (b, a) };
+                        self.is_dark(x, y)
+                    })
+                    .collect();
+                let mut run = 1;
+                for b in 1..=size {
+                    if b < size && line[b] == line[b - 1] {
+                        run += 1;
+                        continue;
+                    }
+                    if run >= 5 {
+                        penalty += 3 + run - 5;
+                    }
+                    run = 1;
+                }
+                penalty += line
+                    .windows(11)
+                    .filter(|window| finder_like.contains(window))
+                    .count()
+                    * 40;
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.is_dark(x, y);
+                if [(x + 1, y), (x, y + 1), (x + 1, y + 1)]
+                    .iter()
+                    .all(|&(x, y)| self.is_dark(x, y) == dark)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|dark| **dark).count();
+        let percent = dark * 100 / (size * size);
+        penalty + percent.abs_diff(50) / 5 * 10
+    }
+}
+
+/// The 15 format bits for level L and `mask`, with their BCH error
+/// correction.
+fn format_bits(mask: u32) -> u32 {
+    // Level L is 01
+    let data = 1 << 3 | mask;
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    (data << 10 | remainder) ^ 0x5412
+}
+
+/// Multiplies in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn multiply(x: u8, y: u8) -> u8 {
+    let mut product = 0u8;
+    for i in (0..8).rev() {
+        product = (product << 1) ^ ((product >> 7) * 0x1D);
+        product ^= (y >> i & 1) * x;
+    }
+    product
+}
+
+/// The Reed-Solomon generator polynomial of `degree`, highest term first
+/// and leaving out its leading 1.
+fn divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = multiply(root, 2);
+    }
+    result
+}
+
+/// The error correction codewords for `data`.
+fn remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (term, coefficient) in result.iter_mut().zip(divisor) {
+            *term ^= multiply(*coefficient, factor);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{divisor, format_bits, remainder, QrCode};
+
+    #[test]
+    fn error_correction_matches_the_standard_example() {
+        // "HELLO WORLD" at 1-M
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        assert_eq!(
+            remainder(&data, &divisor(10)),
+            vec![196, 35, 39, 119, 235, 215, 231, 226, 93, 23]
+        );
+    }
+
+    #[test]
+    fn format_bits_match_the_standard_table() {
+        assert_eq!(format_bits(0), 0b111011111000100);
+        assert_eq!(format_bits(7), 0b110100101110110);
+    }
+
+    #[test]
+    fn picks_the_smallest_version_that_fits() {
+        assert_eq!(QrCode::encode(&[b'a'; 17]).unwrap().size(), 21);
+        assert_eq!(QrCode::encode(&[b'a'; 18]).unwrap().size(), 25);
+        assert_eq!(QrCode::encode(&[b'a'; 78]).unwrap().size(), 33);
+        assert!(QrCode::encode(&[b'a'; 79]).is_none());
+    }
+
+    #[test]
+    fn has_finder_patterns_in_three_corners() {
+        let code = QrCode::encode(b"WT-ATAK-QAIU-B6QL-A").unwrap();
+        let size = code.size();
+        for (left, top) in [(0, 0), (size - 7, 0), (0, size - 7)] {
+            for y in 0..7 {
+                for x in 0..7 {
+                    let ring = x == 0 || x == 6 || y == 0 || y == 6;
+                    let inner = (2..=4).contains(&x) && (2..=4).contains(&y);
+                    assert_eq!(
+                        code.is_dark(left + x, top + y),
+                        ring || inner,
+                        "finder at {},{}",
+                        left,
+                        top
+                    );
+                }
+            }
+        }
+        // Both copies of the format bits agree
+        let first: Vec<bool> = (0..6).map(|y| code.is_dark(8, y)).collect();
+        let second: Vec<bool> = (0..6).map(|i| code.is_dark(size - 1 - i, 8)).collect();
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,81 @@
+use crossterm::{
+    cursor::{Hide, Show},
+    execute,
+    style::ResetColor,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::{
+    backtrace::Backtrace,
+    io::{self, Write},
+    panic,
+};
+
+/// Keeps the terminal in raw mode on the alternate screen while alive, and
+/// puts it back however the UI ends, including by returning an error.
+pub struct ScreenGuard(());
+
+impl ScreenGuard {
+    pub fn enter() -> io::Result<Self> {
+        enable_raw_mode().map_err(other)?;
+        // The guard only restores once it exists
+        let guard = ScreenGuard(());
+        execute!(io::stdout(), EnterAlternateScreen).map_err(other)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for ScreenGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leaves the alternate screen and raw mode, leaving the user's scrollback
+/// as it was. Doing so twice is harmless. Colors are reset first, as the
+/// Windows console otherwise keeps the last ones drawn for the shell.
+pub fn restore() {
+    let _ = execute!(io::stdout(), ResetColor, LeaveAlternateScreen, Show);
+    let _ = disable_raw_mode();
+}
+
+/// Whether the terminal takes escape sequences that crossterm has no
+/// command for. The Windows console only does once asked to, which older
+/// versions refuse.
+pub fn escapes_supported() -> bool {
+    #[cfg(windows)]
+    let supported = crossterm::ansi_support::supports_ansi();
+    #[cfg(not(windows))]
+    let supported = true;
+    supported
+}
+
+/// Hands the terminal back to the shell and stops the process, as Ctrl+Z
+/// would outside raw mode, then takes the terminal back once continued.
+/// The whole process group is stopped, as the shell's job control expects.
+/// Other tasks, like the connection to the peer, carry on again once it
+/// continues.
+#[cfg(unix)]
+pub fn suspend() -> io::Result<()> {
+    restore();
+    if unsafe { libc::kill(0, libc::SIGTSTP) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    enable_raw_mode().map_err(other)?;
+    execute!(io::stdout(), EnterAlternateScreen, Hide).map_err(other)
+}
+
+/// Restores the terminal before reporting a panic, so the message is readable
+/// rather than smeared across the UI and the shell is left usable.
+pub fn restore_on_panic() {
+    panic::set_hook(Box::new(|info| {
+        restore();
+        eprintln!("{}\n\n{}", info, Backtrace::force_capture());
+    }));
+}
+
+fn other(err: crossterm::ErrorKind) -> io::Error {
+    match err {
+        crossterm::ErrorKind::IoError(err) => err,
+        err => io::Error::other(err),
+    }
+}
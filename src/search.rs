@@ -0,0 +1,135 @@
+use std::ops::Range;
+use tui::{
+    style::Style,
+    text::{Span, Spans},
+};
+
+/// Where `query` appears in `text`, ignoring case.
+pub fn find(text: &str, query: &str) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return vec![];
+    }
+    // Lowercasing only keeps byte offsets when every character keeps its length
+    let same_length = text.chars().all(|c| {
+        let mut lower = c.to_lowercase();
+        lower.len() == 1 && lower.next().is_some_and(|l| l.len_utf8() == c.len_utf8())
+    });
+    if !same_length {
+        return text
+            .match_indices(query)
+            .map(|(index, found)| index..index + found.len())
+            .collect();
+    }
+    let query = query.to_lowercase();
+    text.to_lowercase()
+        .match_indices(&query)
+        .map(|(index, _)| index..index + query.len())
+        .collect()
+}
+
+/// How well `query` matches `name` with its characters in order but not
+/// necessarily together, as "ns" does "new-story", ignoring case. Lower is
+/// better: a character costs nothing at the start of a word or straight
+/// after the one before it, and otherwise as many as were skipped to reach
+/// it. `None` if they aren't all there in order.
+pub fn fuzzy(name: &str, query: &str) -> Option<usize> {
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let starts_word = |at: usize| at == 0 || !name[at - 1].is_alphanumeric();
+    // The cheapest match of the query so far, by where in the name the last
+    // character matched is
+    let mut best: Option<Vec<Option<usize>>> = None;
+    for c in query.to_lowercase().chars() {
+        let cost = |at: usize, gap: usize| if gap == 0 || starts_word(at) { 0 } else { gap };
+        best = Some(
+            (0..name.len())
+                .map(|at| match &best {
+                    _ if name[at] != c => None,
+                    None => Some(cost(at, at)),
+                    Some(best) => (0..at)
+                        .filter_map(|before| Some(best[before]? + cost(at, at - before - 1)))
+                        .min(),
+                })
+                .collect(),
+        );
+    }
+    best.map_or(Some(0), |best| best.into_iter().flatten().min())
+}
+
+/// Restyles the parts of `line` covered by `ranges`, splitting spans where a
+/// range starts or ends inside them.
+pub fn highlight(line: Spans<'static>, ranges: &[(Range<usize>, Style)]) -> Spans<'static> {
+    let mut spans = vec![];
+    let mut offset = 0;
+    for span in line.0 {
+        let end = offset + span.content.len();
+        let mut cuts = vec![offset, end];
+        for (range, _) in ranges {
+            cuts.extend(
+                [range.start, range.end]
+                    .iter()
+                    .filter(|cut| **cut > offset && **cut < end),
+            );
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for piece in cuts.windows(2) {
+            let style = ranges
+                .iter()
+                .find(|(range, _)| range.start <= piece[0] && piece[1] <= range.end)
+                .map_or(span.style, |(_, style)| span.style.patch(*style));
+            spans.push(Span::styled(
+                span.content[piece[0] - offset..piece[1] - offset].to_string(),
+                style,
+            ));
+        }
+        offset = end;
+    }
+    Spans::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, fuzzy, highlight};
+    use tui::{
+        style::{Modifier, Style},
+        text::{Span, Spans},
+    };
+
+    #[test]
+    fn finds_ignoring_case() {
+        assert_eq!(
+            find("The Lighthouse. A lighthouse!", "lighthouse"),
+            vec![4..14, 18..28]
+        );
+        assert!(find("anything", "").is_empty());
+    }
+
+    #[test]
+    fn fuzzy_matches_prefer_word_starts_and_runs() {
+        assert_eq!(fuzzy("new-story", "ns"), Some(0));
+        assert_eq!(fuzzy("new-story", "NEW"), Some(0));
+        assert_eq!(fuzzy("export", "xp"), Some(1));
+        assert!(fuzzy("export", "px").is_none());
+        assert!(fuzzy("zen", "zenith").is_none());
+        assert_eq!(fuzzy("anything", ""), Some(0));
+        // Word starts beat letters somewhere in the middle
+        assert_eq!(fuzzy("chess", "cs"), Some(2));
+        assert_eq!(fuzzy("copy-story", "cs"), Some(0));
+    }
+
+    #[test]
+    fn highlight_splits_spans() {
+        let reversed = Style::default().add_modifier(Modifier::REVERSED);
+        let line = Spans::from(vec![Span::raw("the light"), Span::raw("house")]);
+        let highlighted = highlight(line, &[(4..14, reversed)]);
+        let pieces: Vec<(&str, bool)> = highlighted
+            .0
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style == reversed))
+            .collect();
+        assert_eq!(
+            pieces,
+            vec![("the ", false), ("light", true), ("house", true)]
+        );
+    }
+}
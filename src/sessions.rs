@@ -0,0 +1,654 @@
+use crate::{constraints::Constraint, counter, i18n::Message};
+use chrono::{DateTime, Duration, Utc};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A single sentence accepted into a session, stamped by its author.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Turn {
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    /// The constraint the author set for the following turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_constraint: Option<Constraint>,
+    /// How many seconds the author spent writing it, leaving out time away.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writing_secs: Option<u64>,
+}
+
+/// A word count the story reached, celebrated once when it did.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Milestone {
+    pub words: usize,
+    pub reached_at: DateTime<Utc>,
+    /// Whether this was the goal set for the session.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub goal: bool,
+}
+
+impl Milestone {
+    pub fn message(&self) -> Message {
+        let key = if self.goal {
+            "milestone-goal"
+        } else {
+            "milestone"
+        };
+        Message::new(key).arg("words", counter::thousands(self.words))
+    }
+}
+
+/// Something that happened in a session besides a sentence being written.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// The players connected to write the story.
+    Connected,
+    /// The players connected again to carry on with it.
+    Resumed,
+    /// The connection was closed.
+    Disconnected,
+    /// The connection was lost without either player closing it.
+    Lost,
+    /// A sentence was taken back out of the story, turned down by the
+    /// peer's word list or displaced by theirs for the same turn.
+    Undone { text: String },
+    /// The story reached a milestone.
+    Milestone { words: usize, goal: bool },
+}
+
+/// An [`Event`], when it happened and who it was down to.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Moment {
+    pub at: DateTime<Utc>,
+    /// The player, numbered as by [`SessionInstance::player`], or None if
+    /// neither.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub by: Option<usize>,
+    /// How many turns the story had by then.
+    pub turns: usize,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl Moment {
+    pub fn message(&self) -> Message {
+        let player = self.by.unwrap_or_default();
+        match &self.event {
+            Event::Connected => Message::new("timeline-connected").arg("player", player),
+            Event::Resumed => Message::new("timeline-resumed").arg("player", player),
+            Event::Disconnected => Message::new("timeline-disconnected").arg("player", player),
+            Event::Lost => Message::new("timeline-lost"),
+            Event::Undone { text } => Message::new("timeline-undone")
+                .arg("player", player)
+                .arg("text", text),
+            Event::Milestone { words, goal } => Milestone {
+                words: *words,
+                reached_at: self.at,
+                goal: *goal,
+            }
+            .message(),
+        }
+    }
+}
+
+/// Which word counts are milestones.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Milestones {
+    /// Every multiple of this many words.
+    pub every: Option<usize>,
+    /// The story's goal, in words.
+    pub goal: Option<usize>,
+}
+
+impl Default for Milestones {
+    fn default() -> Self {
+        Self {
+            every: Some(500),
+            goal: None,
+        }
+    }
+}
+
+/// What the two sides compare to make sure they have the same story: how
+/// many turns it has, and a hash of their text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Digest {
+    pub turns: usize,
+    pub hash: u64,
+}
+
+/// The story written during one connection (or several, when resumed).
+///
+/// Turns alternate between the two players after any imported ones;
+/// `offset` is the parity of the turn indices that belong to the local
+/// player, counted from the first turn that wasn't imported, so a peer's view
+/// of the same session is the one with the opposite offset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionInstance {
+    pub id: String,
+    pub started_at: DateTime<Utc>,
+    offset: usize,
+    /// How many turns at the start were imported rather than written by
+    /// either player.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    imported: usize,
+    turns: Vec<Turn>,
+    /// Recorded as they're reached, so that each is only celebrated once
+    /// however many times the session is resumed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    milestones: Vec<Milestone>,
+    /// What happened besides the sentences, oldest first. Sessions saved
+    /// before this was kept have none, and milestones are kept above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    events: Vec<Moment>,
+    /// The local player's scratchpad, saved with the session but never
+    /// sent to the peer.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+impl SessionInstance {
+    pub fn new(our_turn_first: bool) -> Self {
+        let started_at = Utc::now();
+        Self {
+            id: started_at.format("%Y%m%d-%H%M%S").to_string(),
+            started_at,
+            offset: if our_turn_first { 0 } else { 1 },
+            imported: 0,
+            turns: Vec::new(),
+            milestones: Vec::new(),
+            events: Vec::new(),
+            notes: String::new(),
+        }
+    }
+
+    /// A session opening with `sentences` written elsewhere, continued by
+    /// us first if `our_turn_first`.
+    pub fn imported(sentences: Vec<String>, our_turn_first: bool) -> Self {
+        let mut session = Self::new(our_turn_first);
+        session.imported = sentences.len();
+        session.turns = sentences
+            .into_iter()
+            .map(|text| Turn {
+                text,
+                timestamp: session.started_at,
+                next_constraint: None,
+                writing_secs: None,
+            })
+            .collect();
+        session
+    }
+
+    pub fn turns(&self) -> &[Turn] {
+        &self.turns
+    }
+
+    pub fn push(&mut self, turn: Turn) {
+        self.turns.push(turn);
+    }
+
+    pub fn is_our_turn(&self) -> bool {
+        self.is_ours(self.turns.len())
+    }
+
+    /// Whether the turn at `index` canonically belongs to the local player.
+    pub fn is_ours(&self, index: usize) -> bool {
+        index >= self.imported && (index - self.imported) % 2 == self.offset
+    }
+
+    pub fn is_imported(&self, index: usize) -> bool {
+        index < self.imported
+    }
+
+    /// Who wrote the turn at `index`: player 1 wrote the first sentence that
+    /// wasn't imported, and player 2 the next. Imported turns have no player.
+    pub fn player(&self, index: usize) -> Option<usize> {
+        index
+            .checked_sub(self.imported)
+            .map(|written| written % 2 + 1)
+    }
+
+    /// The local player's number.
+    pub fn our_player(&self) -> usize {
+        self.offset + 1
+    }
+
+    /// Takes back the latest turn.
+    pub fn pop(&mut self) -> Option<Turn> {
+        // Imported turns stay
+        if self.turns.len() > self.imported {
+            self.turns.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Puts `turn` at `index`, returning the turns it displaced.
+    pub fn replace_from(&mut self, index: usize, turn: Turn) -> Vec<Turn> {
+        let displaced = self.turns.split_off(index);
+        self.turns.push(turn);
+        displaced
+    }
+
+    /// Records `event` as happening now, down to player `by`.
+    pub fn record(&mut self, by: Option<usize>, event: Event) {
+        self.events.push(Moment {
+            at: Utc::now(),
+            by,
+            turns: self.turns.len(),
+            event,
+        });
+    }
+
+    /// What happened besides the sentences, with the milestones the story
+    /// reached, oldest first.
+    pub fn timeline(&self) -> Vec<Moment> {
+        let mut words = 0;
+        let written: Vec<usize> = self
+            .turns
+            .iter()
+            .map(|turn| {
+                words += counter::words(&turn.text).count();
+                words
+            })
+            .collect();
+        let mut timeline = self.events.clone();
+        timeline.extend(self.milestones.iter().map(|milestone| {
+            // Reached with the turn that brought the story to it
+            let index = written.iter().position(|words| *words >= milestone.words);
+            Moment {
+                at: milestone.reached_at,
+                by: index.and_then(|index| self.player(index)),
+                turns: index.map_or(self.turns.len(), |index| index + 1),
+                event: Event::Milestone {
+                    words: milestone.words,
+                    goal: milestone.goal,
+                },
+            }
+        }));
+        timeline.sort_by_key(|moment| moment.at);
+        timeline
+    }
+
+    /// Whether anything has been recorded as happening besides the
+    /// sentences.
+    pub fn has_timeline(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    /// The session without what happened besides the sentences, to export
+    /// without a timeline.
+    pub fn without_timeline(&self) -> Self {
+        Self {
+            events: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// The constraint the current turn's sentence has to satisfy.
+    pub fn current_constraint(&self) -> Option<&Constraint> {
+        self.turns.last()?.next_constraint.as_ref()
+    }
+
+    /// The same session as seen from the other side of the connection.
+    pub fn for_peer(&self) -> Self {
+        Self {
+            offset: 1 - self.offset,
+            ..self.shared()
+        }
+    }
+
+    /// The session without our notes, to send to the peer or export.
+    pub fn shared(&self) -> Self {
+        Self {
+            notes: String::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn notes(&self) -> &str {
+        &self.notes
+    }
+
+    pub fn set_notes(&mut self, notes: String) {
+        self.notes = notes;
+    }
+
+    /// Keeps what we recorded happening in place of what came with the
+    /// same session from the peer.
+    pub fn keep_events(&mut self, ours: &SessionInstance) {
+        self.events = ours.events.clone();
+    }
+
+    /// Whether the local player wrote the first sentence after any imported
+    /// ones.
+    pub fn we_started(&self) -> bool {
+        self.offset == 0
+    }
+
+    /// The opening words of the story, for listing saved sessions.
+    pub fn title(&self) -> String {
+        const TITLE_WORDS: usize = 6;
+        let content = self.content();
+        let mut words = content.split_whitespace();
+        let title = words.by_ref().take(TITLE_WORDS).join(" ");
+        if title.is_empty() {
+            String::from("(empty)")
+        } else if words.next().is_some() {
+            format!("{}…", title)
+        } else {
+            title
+        }
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.turns
+            .iter()
+            .map(|turn| counter::words(&turn.text).count())
+            .sum()
+    }
+
+    /// How long player 1 and player 2 spent writing their turns, or None
+    /// if nobody's writing was timed.
+    pub fn writing_times(&self) -> Option<[Duration; 2]> {
+        let mut times = [Duration::zero(); 2];
+        let mut timed = false;
+        for (index, turn) in self.turns.iter().enumerate() {
+            if let (Some(player), Some(secs)) = (self.player(index), turn.writing_secs) {
+                times[player - 1] += Duration::seconds(secs as i64);
+                timed = true;
+            }
+        }
+        timed.then_some(times)
+    }
+
+    pub fn milestones(&self) -> &[Milestone] {
+        &self.milestones
+    }
+
+    /// Records the milestones the story's word count has passed that
+    /// haven't been yet, returning the highest of them to celebrate. Both
+    /// sides count the same story, so they agree on these without saying.
+    pub fn reach_milestones(&mut self, milestones: &Milestones) -> Option<Milestone> {
+        let words = self.word_count();
+        let mut counts: Vec<_> = milestones
+            .every
+            .filter(|every| *every > 0)
+            .map(|every| (1..=words / every).map(|n| (n * every, false)).collect())
+            .unwrap_or_default();
+        counts.extend(
+            milestones
+                .goal
+                .filter(|goal| *goal > 0 && *goal <= words)
+                .map(|goal| (goal, true)),
+        );
+        let reached_at = self
+            .turns
+            .last()
+            .map_or(self.started_at, |turn| turn.timestamp);
+        let new: Vec<_> = counts
+            .into_iter()
+            .filter(|(words, goal)| {
+                !self
+                    .milestones
+                    .iter()
+                    .any(|milestone| milestone.words == *words && milestone.goal == *goal)
+            })
+            .map(|(words, goal)| Milestone {
+                words,
+                reached_at,
+                goal,
+            })
+            .collect();
+        self.milestones.extend(new.iter().cloned());
+        new.into_iter()
+            .max_by_key(|milestone| (milestone.words, milestone.goal))
+    }
+
+    /// The digest of the first `turns` turns, or None if there aren't that
+    /// many. The hash is FNV-1a over each turn's text and a byte that UTF-8
+    /// never has between them, so that it's the same on every build and
+    /// where one turn ends counts.
+    pub fn digest(&self, turns: usize) -> Option<Digest> {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+        let hash = self.turns.get(..turns)?.iter().fold(OFFSET, |hash, turn| {
+            turn.text.bytes().chain([0xff]).fold(hash, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            })
+        });
+        Some(Digest { turns, hash })
+    }
+
+    /// Whether the story starts with the turns the peer's `digest` is of.
+    /// Turns past those may not have reached the peer yet, but having fewer
+    /// means the stories differ.
+    pub fn agrees_with(&self, digest: Digest) -> bool {
+        self.digest(digest.turns) == Some(digest)
+    }
+
+    pub fn content(&self) -> String {
+        self.turns
+            .iter()
+            .map(|turn| turn.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Time from the start of the session to its latest turn.
+    pub fn duration(&self) -> Duration {
+        self.turns
+            .last()
+            .map(|turn| turn.timestamp - self.started_at)
+            .unwrap_or_else(Duration::zero)
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}.json", self.id)
+    }
+
+    pub fn save(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(self.file_name());
+        fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
+
+/// A session found in the save directory.
+#[derive(Clone, Debug)]
+pub struct SavedSession {
+    pub path: PathBuf,
+    pub session: SessionInstance,
+}
+
+/// Loads every session saved in `dir`, newest first, along with a
+/// description of each file that could not be read.
+pub fn scan_saved(dir: &Path) -> (Vec<SavedSession>, Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return (vec![], vec![]),
+        Err(err) => return (vec![], vec![format!("{}: {}", dir.display(), err)]),
+    };
+
+    let mut saved = vec![];
+    let mut unreadable = vec![];
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => {
+                unreadable.push(format!("{}: {}", dir.display(), err));
+                continue;
+            }
+        };
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        match SessionInstance::load(&path) {
+            Ok(session) => saved.push(SavedSession { path, session }),
+            Err(err) => unreadable.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+    saved.sort_by_key(|saved| std::cmp::Reverse(saved.session.started_at));
+    (saved, unreadable)
+}
+
+/// Where sessions are saved when no `--save-dir` is given.
+pub fn default_save_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("write_together")
+        .join("sessions")
+}
+
+/// Formats a duration as e.g. `1h 04m`, `14m 02s` or `9s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Formats how long ago `timestamp` was, e.g. `3m ago`.
+pub fn format_relative(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - timestamp).num_seconds().max(0);
+    if seconds < 60 {
+        format!("{}s ago", seconds)
+    } else if seconds < 3600 {
+        format!("{}m ago", seconds / 60)
+    } else if seconds < 86400 {
+        format!("{}h ago", seconds / 3600)
+    } else {
+        format!("{}d ago", seconds / 86400)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Milestones, SessionInstance, Turn};
+    use chrono::Utc;
+
+    fn session(texts: &[&str]) -> SessionInstance {
+        let mut session = SessionInstance::new(true);
+        for text in texts {
+            session.push(Turn {
+                text: String::from(*text),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        session
+    }
+
+    #[test]
+    fn digests_tell_diverged_stories_apart() {
+        let ours = session(&["The lighthouse was dark.", "Nobody kept it."]);
+        // The peer's own view of the story, written at other times
+        let theirs = session(&["The lighthouse was dark.", "Nobody kept it."]).for_peer();
+        assert_eq!(ours.digest(2), theirs.digest(2));
+        assert!(ours.agrees_with(theirs.digest(2).unwrap()));
+        // A sentence the peer hasn't had yet doesn't count against them
+        assert!(ours.agrees_with(theirs.digest(1).unwrap()));
+        assert_eq!(ours.digest(3), None);
+
+        // One word changed
+        let edited = session(&["The lighthouse was dark.", "Nobody kept it!"]);
+        assert!(!ours.agrees_with(edited.digest(2).unwrap()));
+        // The same text, split into turns differently
+        let resplit = session(&["The lighthouse was dark. Nobody", "kept it."]);
+        assert_eq!(ours.content(), resplit.content());
+        assert!(!ours.agrees_with(resplit.digest(2).unwrap()));
+        // A turn we never got
+        let longer = session(&["The lighthouse was dark.", "Nobody kept it.", "Still."]);
+        assert!(!ours.agrees_with(longer.digest(3).unwrap()));
+    }
+
+    #[test]
+    fn digests_are_the_same_on_every_build() {
+        assert_eq!(session(&[]).digest(0).unwrap().hash, 0xcbf2_9ce4_8422_2325);
+        assert_eq!(
+            session(&["a"]).digest(1).unwrap().hash,
+            0x089b_c907_b544_c769
+        );
+    }
+
+    #[test]
+    fn the_timeline_is_saved_and_old_saves_have_only_milestones() {
+        let mut session = session(&["One two three."]);
+        session.record(Some(2), Event::Connected);
+        session.push(Turn {
+            text: String::from("Four five six seven."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        });
+        session.reach_milestones(&Milestones {
+            every: Some(4),
+            goal: None,
+        });
+        session.record(
+            Some(1),
+            Event::Undone {
+                text: String::from("Eight."),
+            },
+        );
+        let json = serde_json::to_string(&session).unwrap();
+        assert!(json.contains(r#""events":[{"at":"#));
+        assert!(json.contains(r#""by":1,"turns":2,"kind":"undone","text":"Eight.""#));
+        let loaded: SessionInstance = serde_json::from_str(&json).unwrap();
+        let events: Vec<_> = loaded
+            .timeline()
+            .into_iter()
+            .map(|moment| (moment.by, moment.turns, moment.event))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (Some(2), 1, Event::Connected),
+                // Reached with the second turn, which player 2 wrote
+                (
+                    Some(2),
+                    2,
+                    Event::Milestone {
+                        words: 4,
+                        goal: false
+                    }
+                ),
+                (
+                    Some(1),
+                    2,
+                    Event::Undone {
+                        text: String::from("Eight.")
+                    }
+                ),
+            ]
+        );
+
+        // Saved before events were kept
+        let old = r#"{"id":"20210501-200000","started_at":"2021-05-01T20:00:00Z","offset":0,
+            "turns":[{"text":"One two three four.","timestamp":"2021-05-01T20:01:00Z"}],
+            "milestones":[{"words":4,"reached_at":"2021-05-01T20:01:00Z"}]}"#;
+        let old: SessionInstance = serde_json::from_str(old).unwrap();
+        assert!(!old.has_timeline());
+        assert_eq!(old.timeline().len(), 1);
+        assert_eq!(old.timeline()[0].by, Some(1));
+    }
+}
@@ -0,0 +1,60 @@
+use std::io;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+#[cfg(windows)]
+use tokio::signal::windows::{ctrl_break, CtrlBreak};
+
+/// The signals that ask the process to stop: SIGINT, SIGTERM and SIGHUP on
+/// Unix, Ctrl+C and Ctrl+Break on Windows, and Ctrl+C elsewhere. In raw mode
+/// Ctrl+C arrives as a key instead, but Ctrl+Break still arrives here.
+pub struct Signals {
+    #[cfg(unix)]
+    interrupt: Signal,
+    #[cfg(unix)]
+    terminate: Signal,
+    #[cfg(unix)]
+    hangup: Signal,
+    #[cfg(windows)]
+    ctrl_break: CtrlBreak,
+}
+
+impl Signals {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            interrupt: signal(SignalKind::interrupt())?,
+            #[cfg(unix)]
+            terminate: signal(SignalKind::terminate())?,
+            #[cfg(unix)]
+            hangup: signal(SignalKind::hangup())?,
+            #[cfg(windows)]
+            ctrl_break: ctrl_break()?,
+        })
+    }
+
+    /// Waits for the next signal, returning its name.
+    #[cfg(unix)]
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.hangup.recv() => "SIGHUP",
+        }
+    }
+
+    /// Waits for the next signal, returning its name.
+    #[cfg(windows)]
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "Ctrl+C",
+            _ = self.ctrl_break.recv() => "Ctrl+Break",
+        }
+    }
+
+    /// Waits for the next signal, returning its name.
+    #[cfg(not(any(unix, windows)))]
+    pub async fn recv(&mut self) -> &'static str {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl+C"
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+};
+
+/// How many characters a suggestion can have added, removed or changed
+/// from the word it's for. Only words within this many characters of its
+/// length are compared with it.
+const MAX_DISTANCE: usize = 2;
+
+/// Words known to be spelled right, from a dictionary of one per line.
+#[derive(Debug, Default)]
+pub struct Speller {
+    /// Lowercased, and kept by how many characters they have so that
+    /// suggestions only look at words about as long.
+    words: BTreeMap<usize, BTreeSet<String>>,
+}
+
+impl Speller {
+    /// Loads a dictionary of one word per line.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut speller = Self::default();
+        for word in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let word = word.to_lowercase();
+            speller
+                .words
+                .entry(word.chars().count())
+                .or_default()
+                .insert(word);
+        }
+        speller
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.words
+            .get(&word.chars().count())
+            .is_some_and(|words| words.contains(word))
+    }
+
+    /// Whether `word` is in the dictionary, whatever its case and whether
+    /// or not it's possessive. Anything with a digit in it is taken as
+    /// right.
+    pub fn check(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        word.chars().any(|c| c.is_numeric())
+            || self.contains(&word)
+            || word
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.contains(stem))
+    }
+
+    /// Up to `count` words from the dictionary that `word` may have been
+    /// meant as, closest first, capitalized if it was.
+    pub fn suggest(&self, word: &str, count: usize) -> Vec<String> {
+        let lower: Vec<char> = word.to_lowercase().chars().collect();
+        let lengths = lower.len().saturating_sub(MAX_DISTANCE)..=lower.len() + MAX_DISTANCE;
+        let mut close: Vec<(usize, &String)> = self
+            .words
+            .range(lengths)
+            .flat_map(|(_, words)| words)
+            .filter_map(|candidate| {
+                let chars: Vec<char> = candidate.chars().collect();
+                Some((distance(&lower, &chars)?, candidate))
+            })
+            .filter(|(distance, _)| *distance > 0)
+            .collect();
+        close.sort_by_key(|(distance, _)| *distance);
+        let capitalized = word.starts_with(char::is_uppercase);
+        close
+            .into_iter()
+            .take(count)
+            .map(|(_, candidate)| {
+                if capitalized {
+                    let mut chars = candidate.chars();
+                    chars.next().map_or_else(String::new, |first| {
+                        first.to_uppercase().chain(chars).collect()
+                    })
+                } else {
+                    candidate.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+/// How many characters have to be added, removed or changed to turn `a`
+/// into `b`, or `None` once that's more than [`MAX_DISTANCE`].
+fn distance(a: &[char], b: &[char]) -> Option<usize> {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.iter().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let changed = previous[j] + usize::from(a != b);
+            row.push(changed.min(previous[j + 1] + 1).min(row[j] + 1));
+        }
+        // Every way on goes through this row, and none gets any shorter
+        if row.iter().all(|distance| *distance > MAX_DISTANCE) {
+            return None;
+        }
+        previous = row;
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= MAX_DISTANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Speller;
+
+    fn speller() -> Speller {
+        Speller::parse("lighthouse\nkeeper\nkept\nkeep\nstorm\nstore\nstop\nthe\n")
+    }
+
+    #[test]
+    fn checks_words_whatever_their_case() {
+        let speller = speller();
+        assert!(speller.check("lighthouse"));
+        assert!(speller.check("Lighthouse"));
+        assert!(speller.check("keeper's"));
+        assert!(speller.check("1920s"));
+        assert!(!speller.check("lighthuose"));
+        assert!(!speller.check("keepers"));
+    }
+
+    #[test]
+    fn suggests_the_closest_words_first() {
+        let speller = speller();
+        assert_eq!(speller.suggest("stom", 3), vec!["stop", "storm", "store"]);
+        assert_eq!(speller.suggest("Kep", 2), vec!["Keep", "Kept"]);
+        assert_eq!(speller.suggest("lighthuose", 3), vec!["lighthouse"]);
+        // Too far from anything
+        assert!(speller.suggest("xylophone", 3).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::i18n::Message;
+use std::collections::HashMap;
+
+/// Words too common to be worth pointing out when repeated, and whose
+/// presence suggests the story is in English.
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "an", "and", "any", "are", "as", "at", "be", "been", "before",
+    "but", "by", "could", "did", "do", "down", "for", "from", "had", "has", "have", "he", "her",
+    "him", "his", "i", "if", "in", "into", "is", "it", "its", "it's", "just", "me", "my", "no",
+    "not", "of", "on", "one", "or", "out", "over", "said", "she", "so", "than", "that", "the",
+    "their", "them", "then", "there", "they", "this", "to", "up", "was", "we", "were", "what",
+    "when", "which", "who", "will", "with", "would", "you", "your",
+];
+
+/// How many of the most repeated words are listed.
+const REPEATED: usize = 5;
+
+/// Readability and vocabulary of a story, worked out when asked for.
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub sentences: usize,
+    pub words: usize,
+    pub words_per_sentence: f64,
+    /// Distinct words over all words, from 0 to 1.
+    pub unique_ratio: f64,
+    /// Flesch reading ease, where higher is easier. Only for text that looks
+    /// English, as syllables are guessed from English spelling.
+    pub reading_ease: Option<f64>,
+    /// The words used most more than once, leaving out stopwords, with how
+    /// often each was used.
+    pub repeated: Vec<(String, usize)>,
+}
+
+impl Stats {
+    pub fn new<'a>(sentences: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut sentence_count = 0;
+        let mut words = vec![];
+        for sentence in sentences {
+            sentence_count += 1;
+            words.extend(
+                sentence
+                    .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’')
+                    .map(|word| word.trim_matches(|c| c == '\'' || c == '’'))
+                    .filter(|word| !word.is_empty())
+                    .map(str::to_lowercase),
+            );
+        }
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for word in &words {
+            *counts.entry(word).or_default() += 1;
+        }
+        let mut repeated = counts
+            .iter()
+            .filter(|(word, count)| **count > 1 && !STOPWORDS.contains(word))
+            .map(|(word, count)| (word.to_string(), *count))
+            .collect::<Vec<_>>();
+        repeated.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        repeated.truncate(REPEATED);
+
+        let per = |count: usize, of: usize| {
+            if of == 0 {
+                0.0
+            } else {
+                count as f64 / of as f64
+            }
+        };
+        let words_per_sentence = per(words.len(), sentence_count);
+        let reading_ease = if looks_english(&words) {
+            let syllables = words.iter().map(|word| syllables(word)).sum();
+            Some(206.835 - 1.015 * words_per_sentence - 84.6 * per(syllables, words.len()))
+        } else {
+            None
+        };
+        Self {
+            sentences: sentence_count,
+            words: words.len(),
+            words_per_sentence,
+            unique_ratio: per(counts.len(), words.len()),
+            reading_ease,
+            repeated,
+        }
+    }
+}
+
+/// How hard a Flesch reading ease score reads, in words.
+pub fn reading_level(ease: f64) -> Message {
+    Message::new(match ease {
+        ease if ease >= 90.0 => "reading-very-easy",
+        ease if ease >= 80.0 => "reading-easy",
+        ease if ease >= 70.0 => "reading-fairly-easy",
+        ease if ease >= 60.0 => "reading-plain",
+        ease if ease >= 50.0 => "reading-fairly-hard",
+        ease if ease >= 30.0 => "reading-hard",
+        _ => "reading-very-hard",
+    })
+}
+
+/// Whether the words are spelled in plain ASCII and common English words
+/// turn up among them, as they do in any English text of some length.
+fn looks_english(words: &[String]) -> bool {
+    let ascii = words
+        .iter()
+        .filter(|word| word.chars().all(|c| c.is_ascii_alphabetic() || c == '\''))
+        .count();
+    let stopwords = words
+        .iter()
+        .filter(|word| STOPWORDS.contains(&word.as_str()))
+        .count();
+    !words.is_empty() && ascii * 10 >= words.len() * 9 && stopwords * 10 >= words.len()
+}
+
+/// Guesses the syllables in an English word by counting its groups of
+/// vowels, not counting a silent final e or -ed.
+fn syllables(word: &str) -> usize {
+    let vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous = None;
+    for c in word.chars() {
+        if vowel(c) && !previous.is_some_and(vowel) {
+            count += 1;
+        }
+        previous = Some(c);
+    }
+    let silent = (word.ends_with('e') && !word.ends_with("le"))
+        || (word.ends_with("ed") && !word.ends_with("ted") && !word.ends_with("ded"));
+    if silent && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{syllables, Stats};
+
+    #[test]
+    fn counts_words_and_repeats() {
+        let stats = Stats::new(vec![
+            "Suddenly the door opened.",
+            "Suddenly, the lights went out!",
+            "The door creaked, suddenly.",
+        ]);
+        assert_eq!(stats.sentences, 3);
+        assert_eq!(stats.words, 13);
+        assert!((stats.words_per_sentence - 13.0 / 3.0).abs() < 1e-9);
+        assert!((stats.unique_ratio - 8.0 / 13.0).abs() < 1e-9);
+        assert_eq!(
+            stats.repeated,
+            vec![(String::from("suddenly"), 3), (String::from("door"), 2)]
+        );
+        // 20 syllables
+        let ease = 206.835 - 1.015 * 13.0 / 3.0 - 84.6 * 20.0 / 13.0;
+        assert!((stats.reading_ease.unwrap() - ease).abs() < 1e-9);
+    }
+
+    #[test]
+    fn long_words_read_harder() {
+        let easy = Stats::new(vec!["The cat sat on the mat and it was glad."]);
+        let hard = Stats::new(vec![
+            "The administration's unprecedented reorganisation of the department was controversial.",
+        ]);
+        assert!(hard.reading_ease.unwrap() < easy.reading_ease.unwrap());
+    }
+
+    #[test]
+    fn guesses_syllables() {
+        assert_eq!(syllables("cat"), 1);
+        assert_eq!(syllables("make"), 1);
+        assert_eq!(syllables("table"), 2);
+        assert_eq!(syllables("suddenly"), 3);
+        assert_eq!(syllables("rhythm"), 1);
+        assert_eq!(syllables("opened"), 2);
+        assert_eq!(syllables("wanted"), 2);
+    }
+
+    #[test]
+    fn other_languages_get_only_the_counts() {
+        let stats = Stats::new(vec!["Le chat était très fatigué.", "Il dormait déjà."]);
+        assert_eq!(stats.words, 8);
+        assert_eq!(stats.reading_ease, None);
+        let stats = Stats::new(vec!["El perro come la comida del gato."]);
+        assert_eq!(stats.reading_ease, None);
+    }
+
+    #[test]
+    fn an_empty_story_has_no_averages() {
+        let stats = Stats::new(vec![]);
+        assert_eq!(stats.words_per_sentence, 0.0);
+        assert_eq!(stats.unique_ratio, 0.0);
+        assert_eq!(stats.reading_ease, None);
+        assert!(stats.repeated.is_empty());
+    }
+}
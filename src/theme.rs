@@ -0,0 +1,278 @@
+use crate::log_buffer::Level;
+use std::{
+    ffi::OsString,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+use tui::style::{Color, Modifier, Style};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ThemeName {
+    Default,
+    HighContrast,
+    Monochrome,
+}
+
+impl ThemeName {
+    const ALL: [ThemeName; 3] = [
+        ThemeName::Default,
+        ThemeName::HighContrast,
+        ThemeName::Monochrome,
+    ];
+}
+
+impl Display for ThemeName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThemeName::Default => write!(f, "default"),
+            ThemeName::HighContrast => write!(f, "high-contrast"),
+            ThemeName::Monochrome => write!(f, "monochrome"),
+        }
+    }
+}
+
+impl FromStr for ThemeName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ThemeName::ALL
+            .iter()
+            .copied()
+            .find(|name| name.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown theme '{}', expected one of: default, high-contrast, monochrome",
+                    s
+                )
+            })
+    }
+}
+
+/// Whether to draw in color.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorChoice {
+    Always,
+    /// Unless `NO_COLOR` is set or the terminal can't show color.
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    const ALL: [ColorChoice; 3] = [ColorChoice::Always, ColorChoice::Auto, ColorChoice::Never];
+
+    /// Whether to draw in color, asking the environment if it's up to us.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => color_supported(
+                std::env::var_os("NO_COLOR"),
+                std::env::var("TERM").ok().as_deref(),
+            ),
+        }
+    }
+}
+
+/// Whether a terminal called `term` shows color when `NO_COLOR` is
+/// `no_color`. Any value but an empty one asks for none, as no-color.org
+/// has it.
+fn color_supported(no_color: Option<OsString>, term: Option<&str>) -> bool {
+    no_color.is_none_or(|value| value.is_empty())
+        && term != Some("dumb")
+        && crossterm::style::available_color_count() >= 8
+}
+
+impl Display for ColorChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ColorChoice::ALL
+            .iter()
+            .copied()
+            .find(|choice| choice.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown color setting '{}', expected one of: always, auto, never",
+                    s
+                )
+            })
+    }
+}
+
+/// The styles every part of the UI is drawn with.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: ThemeName,
+    /// Whether color may be drawn at all. Without it the theme stays
+    /// monochrome, and focus shows only in borders and markers.
+    pub color: bool,
+    pub border: Style,
+    pub focused: Style,
+    pub own_sentence: Style,
+    pub peer_sentence: Style,
+    pub status_bar: Style,
+    pub status_turn: Style,
+    pub constraint: Style,
+    pub selection: Style,
+    pub valid: Style,
+    pub near_limit: Style,
+    pub warning: Style,
+    pub log_warn: Style,
+    pub log_error: Style,
+}
+
+impl Theme {
+    pub fn named(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Default => Self {
+                name,
+                color: true,
+                border: Style::default(),
+                focused: Style::default().fg(Color::Green),
+                own_sentence: Style::default(),
+                peer_sentence: Style::default().fg(Color::Cyan),
+                status_bar: Style::default(),
+                status_turn: Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+                constraint: Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                selection: Style::default().add_modifier(Modifier::REVERSED),
+                valid: Style::default().fg(Color::Green),
+                near_limit: Style::default().fg(Color::Yellow),
+                warning: Style::default().fg(Color::Red),
+                log_warn: Style::default().fg(Color::Yellow),
+                log_error: Style::default().fg(Color::Red),
+            },
+            ThemeName::HighContrast => Self {
+                name,
+                color: true,
+                border: Style::default().fg(Color::White),
+                focused: Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                own_sentence: Style::default().fg(Color::White),
+                peer_sentence: Style::default().fg(Color::LightCyan),
+                status_bar: Style::default().fg(Color::Black).bg(Color::White),
+                status_turn: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                constraint: Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                selection: Style::default().fg(Color::Black).bg(Color::LightYellow),
+                valid: Style::default()
+                    .fg(Color::LightGreen)
+                    .add_modifier(Modifier::BOLD),
+                near_limit: Style::default().fg(Color::LightYellow),
+                warning: Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+                log_warn: Style::default().fg(Color::LightYellow),
+                log_error: Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+            },
+            // Only text modifiers, for terminals without color support
+            ThemeName::Monochrome => Self {
+                name,
+                color: true,
+                border: Style::default(),
+                focused: Style::default().add_modifier(Modifier::BOLD),
+                own_sentence: Style::default(),
+                peer_sentence: Style::default().add_modifier(Modifier::ITALIC),
+                status_bar: Style::default(),
+                status_turn: Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+                constraint: Style::default().add_modifier(Modifier::BOLD),
+                selection: Style::default().add_modifier(Modifier::REVERSED),
+                valid: Style::default(),
+                near_limit: Style::default().add_modifier(Modifier::BOLD),
+                warning: Style::default().add_modifier(Modifier::UNDERLINED),
+                log_warn: Style::default().add_modifier(Modifier::ITALIC),
+                log_error: Style::default().add_modifier(Modifier::BOLD),
+            },
+        }
+    }
+
+    /// The theme called `name`, or the monochrome one if there's to be no
+    /// `color`.
+    pub fn new(name: ThemeName, color: bool) -> Self {
+        if color {
+            Self::named(name)
+        } else {
+            Self {
+                color,
+                ..Self::named(ThemeName::Monochrome)
+            }
+        }
+    }
+
+    /// The next built-in theme, for switching at runtime.
+    pub fn next(&self) -> Self {
+        let position = ThemeName::ALL
+            .iter()
+            .position(|name| *name == self.name)
+            .unwrap_or(0);
+        Self::new(
+            ThemeName::ALL[(position + 1) % ThemeName::ALL.len()],
+            self.color,
+        )
+    }
+
+    pub fn log_level(&self, level: Level) -> Style {
+        match level {
+            Level::Info => Style::default(),
+            Level::Warn => self.log_warn,
+            Level::Error => self.log_error,
+        }
+    }
+
+    pub fn sentence(&self, ours: bool) -> Style {
+        if ours {
+            self.own_sentence
+        } else {
+            self.peer_sentence
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::named(ThemeName::Default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{color_supported, Theme, ThemeName};
+    use std::ffi::OsString;
+
+    #[test]
+    fn no_color_and_dumb_terminals_go_without() {
+        assert!(color_supported(None, Some("xterm-256color")));
+        assert!(color_supported(Some(OsString::new()), Some("xterm")));
+        assert!(!color_supported(Some(OsString::from("1")), Some("xterm")));
+        assert!(!color_supported(None, Some("dumb")));
+    }
+
+    #[test]
+    fn themes_stay_monochrome_without_color() {
+        let theme = Theme::new(ThemeName::Default, false);
+        assert_eq!(theme.name, ThemeName::Monochrome);
+        assert_eq!(theme.next().name, ThemeName::Monochrome);
+        assert_eq!(
+            Theme::named(ThemeName::Monochrome).next().name,
+            ThemeName::Default
+        );
+    }
+}
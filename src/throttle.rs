@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+/// Lets through at most `limit` messages of each kind a `window`, so that a
+/// flapping peer or a port scanner can't flood the Log.
+#[derive(Debug)]
+pub struct Throttle {
+    limit: usize,
+    window: Duration,
+    /// When each kind's window started, and how many were seen in it.
+    seen: HashMap<&'static str, (Instant, usize)>,
+}
+
+/// What to do with a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    Pass,
+    /// The first over the limit, to say that the rest are being left out.
+    Quiet,
+    Drop,
+}
+
+impl Throttle {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Counts a message of kind `key` at `now`.
+    pub fn check(&mut self, key: &'static str, now: Instant) -> Verdict {
+        let (started, count) = self.seen.entry(key).or_insert((now, 0));
+        if now.duration_since(*started) >= self.window {
+            *started = now;
+            *count = 0;
+        }
+        *count += 1;
+        if *count <= self.limit {
+            Verdict::Pass
+        } else if *count == self.limit + 1 {
+            Verdict::Quiet
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Throttle, Verdict};
+    use tokio::time::{Duration, Instant};
+
+    #[test]
+    fn each_kind_is_limited_for_the_rest_of_its_window() {
+        let mut throttle = Throttle::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        let verdicts: Vec<_> = (0..4)
+            .map(|second| throttle.check("accepting", start + Duration::from_secs(second)))
+            .collect();
+        assert_eq!(
+            verdicts,
+            [Verdict::Pass, Verdict::Pass, Verdict::Quiet, Verdict::Drop]
+        );
+        // Other kinds have their own count
+        assert_eq!(throttle.check("already-connected", start), Verdict::Pass);
+        // And a new window starts afresh
+        let later = start + Duration::from_secs(60);
+        assert_eq!(throttle.check("accepting", later), Verdict::Pass);
+    }
+}
@@ -1,44 +1,155 @@
 use crate::{
     app::AppHandle,
+    clipboard,
+    completion::Completer,
+    constraints::{self, ConstraintPool},
+    counter::{self, Counts, Nearness},
+    editor::EditBuffer,
+    endpoints,
     error::Error,
-    ui_actor::AppState::{InSession, Waiting},
+    export::{self, Appendices, Format},
+    filter::{self, WordFilter},
+    history::{AddressHistory, RecentPeers, SentenceHistory},
+    i18n::{Catalog, Message},
+    import,
+    invite::Invite,
+    keymap::{Action, KeyMap},
+    layout::LayoutMode,
+    log_buffer::{Level, LogBuffer},
+    metrics::{ActorMetrics, Rate},
+    notify::{self, Alerts, Notify},
+    qr::QrCode,
+    search,
+    sessions::{
+        format_duration, format_relative, Milestone, Moment, SavedSession, SessionInstance, Turn,
+    },
+    signals::Signals,
+    spelling::Speller,
+    stats::{self, Stats},
+    theme::Theme,
+    ui_actor::AppState::{Connecting, InSession, Replay, Waiting},
+    wrap,
+};
+use chrono::{Local, Utc};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers};
+use futures::{
+    future::{self, OptionFuture},
+    FutureExt, Stream,
 };
-use crossterm::event::{Event, EventStream, KeyCode, KeyEvent};
 use itertools::Itertools;
 use std::{
+    collections::{HashMap, VecDeque},
     fmt::{Display, Formatter},
-    iter::FromIterator,
-    net::SocketAddr,
+    io::{self, Write},
+    net::{IpAddr, SocketAddr},
+    ops::Range,
+    path::Path,
     str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use tokio::{
     macros::support::{Future, Pin},
-    sync::{mpsc, mpsc::Sender},
+    sync::{
+        mpsc,
+        mpsc::{error::TrySendError, Sender},
+    },
+    time::{interval, sleep_until, Duration, Instant},
 };
 use tokio_stream::StreamExt;
+use tracing::{debug, info, trace, warn};
 use tui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout},
-    style::{Color, Style},
-    widgets::{Block, BorderType, Borders, Paragraph, Wrap},
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{
+        Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph, Widget, Wrap,
+    },
     Frame, Terminal,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+/// What the app tells the UI, through a [`UIHandle`].
 #[derive(Debug)]
-enum UIMessage {
-    Log(String),
-    SentenceReceived(String),
-    Connected(bool),
+pub enum UIMessage {
+    Log(Level, Message),
+    /// Logged, and also shown in a toast.
+    Toast(Message),
+    SentenceReceived(Turn),
+    SentenceAccepted(Turn),
+    SentenceBounced {
+        index: usize,
+        kept: Turn,
+        bounced: Vec<Turn>,
+    },
+    /// The peer turned down our sentence for turn `index`, which is no
+    /// longer in the story.
+    SentenceRefused {
+        index: usize,
+        turn: Turn,
+    },
+    Listening(SocketAddr),
+    Connecting(SocketAddr),
+    ConnectFailed {
+        address: SocketAddr,
+        reason: Message,
+    },
+    Connected {
+        session: SessionInstance,
+        peer: SocketAddr,
+    },
+    /// The story ended, and whether to write another needs answering.
+    StoryEnded,
+    NewStory(SessionInstance),
+    /// The peer asked to scrap the story and start again, which needs an
+    /// answer.
+    ClearProposed,
+    SavedSessions(Vec<SavedSession>),
+    /// Whether the peer has stepped away.
+    PeerAway(bool),
+    /// The story reached a milestone, recorded in the session.
+    Milestone(Milestone),
+    /// Our sentence is sent once this long has passed since the last one.
+    Cooldown(Duration),
+    /// Our story and the peer's differ. Ours is kept if true, and otherwise
+    /// whether to take theirs needs an answer.
+    Diverged(bool),
+    /// The peer's story replaced ours, which differed.
+    Resynced(SessionInstance),
     Disconnected,
+    /// The app failed and can't be reached any more.
+    AppStopped(String),
 }
 
 impl Display for UIMessage {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            UIMessage::Log(_) => write!(f, "Log"),
+            UIMessage::Log(..) => write!(f, "Log"),
+            UIMessage::Toast(_) => write!(f, "Toast"),
             UIMessage::SentenceReceived(_) => write!(f, "SentenceReceived"),
-            UIMessage::Connected(_) => write!(f, "Connected"),
+            UIMessage::SentenceAccepted(_) => write!(f, "SentenceAccepted"),
+            UIMessage::SentenceBounced { .. } => write!(f, "SentenceBounced"),
+            UIMessage::SentenceRefused { .. } => write!(f, "SentenceRefused"),
+            UIMessage::Listening(_) => write!(f, "Listening"),
+            UIMessage::Connecting(_) => write!(f, "Connecting"),
+            UIMessage::ConnectFailed { .. } => write!(f, "ConnectFailed"),
+            UIMessage::Connected { .. } => write!(f, "Connected"),
+            UIMessage::StoryEnded => write!(f, "StoryEnded"),
+            UIMessage::NewStory(_) => write!(f, "NewStory"),
+            UIMessage::ClearProposed => write!(f, "ClearProposed"),
+            UIMessage::SavedSessions(_) => write!(f, "SavedSessions"),
+            UIMessage::PeerAway(_) => write!(f, "PeerAway"),
+            UIMessage::Milestone(_) => write!(f, "Milestone"),
+            UIMessage::Cooldown(_) => write!(f, "Cooldown"),
+            UIMessage::Diverged(_) => write!(f, "Diverged"),
+            UIMessage::Resynced(_) => write!(f, "Resynced"),
             UIMessage::Disconnected => write!(f, "Disconnected"),
+            UIMessage::AppStopped(_) => write!(f, "AppStopped"),
         }
     }
 }
@@ -46,316 +157,5813 @@ impl Display for UIMessage {
 enum AppState {
     InSession {
         is_our_turn: bool,
-        content_log: Vec<String>,
+        session: SessionInstance,
     },
+    /// Reading a saved session while not connected.
+    Replay(SessionInstance),
     Waiting,
+    /// Waiting for a connection we asked for to succeed or fail.
+    Connecting(SocketAddr),
+}
+
+/// What a line of the Content pane holds.
+#[derive(Clone, Debug)]
+enum ContentLine {
+    /// These turns' sentences, run together as prose, or the one turn's.
+    Turns(Range<usize>),
+    /// Something that happened between them, shown when replaying.
+    Moment(Moment),
 }
 
 impl AppState {
-    fn content_log(&self) -> Option<String> {
+    /// What each line of the Content pane holds. The story is one line
+    /// when read as prose, broken only where the timeline comes between
+    /// its sentences.
+    fn content_layout(&self, view: ContentView, show_timestamps: bool) -> Vec<ContentLine> {
+        let (session, timeline) = match self {
+            AppState::InSession { session, .. } => (session, vec![]),
+            Replay(session) => (session, session.timeline()),
+            Waiting | Connecting(_) => return vec![],
+        };
+        let prose = view == ContentView::Prose && !show_timestamps;
+        let count = session.turns().len();
+        let mut moments = timeline
+            .into_iter()
+            // Whatever came after turns that were since taken back goes at the end
+            .sorted_by_key(|moment| moment.turns.min(count))
+            .peekable();
+        let mut lines = vec![];
+        let mut start = 0;
+        for turn in 0..=count {
+            while let Some(moment) = moments.next_if(|moment| moment.turns.min(count) == turn) {
+                if start < turn {
+                    lines.push(ContentLine::Turns(start..turn));
+                    start = turn;
+                }
+                lines.push(ContentLine::Moment(moment));
+            }
+            if !prose && turn < count {
+                lines.push(ContentLine::Turns(turn..turn + 1));
+                start = turn + 1;
+            }
+        }
+        if prose && (start < count || lines.is_empty()) {
+            lines.push(ContentLine::Turns(start..count));
+        }
+        lines
+    }
+
+    fn content_log(
+        &self,
+        view: ContentView,
+        show_timestamps: bool,
+        theme: &Theme,
+        catalog: &Catalog,
+    ) -> Option<Text<'static>> {
+        let session = match self {
+            AppState::InSession { session, .. } | Replay(session) => session,
+            Waiting | Connecting(_) => return None,
+        };
+        // Imported sentences weren't written by either of us
+        let style = |index| {
+            if session.is_imported(index) {
+                theme.border
+            } else {
+                theme.sentence(session.is_ours(index))
+            }
+        };
+        let now = Utc::now();
+        let number_width = session.turns().len().to_string().len();
+        let line = |content| match content {
+            ContentLine::Turns(turns) if view == ContentView::Prose && !show_timestamps => {
+                Spans::from(
+                    Itertools::intersperse(
+                        turns.map(|index| {
+                            Span::styled(session.turns()[index].text.clone(), style(index))
+                        }),
+                        Span::raw(" "),
+                    )
+                    .collect::<Vec<_>>(),
+                )
+            }
+            // One sentence per line
+            ContentLine::Turns(turns) => {
+                let index = turns.start;
+                let turn = &session.turns()[index];
+                let mut spans = vec![];
+                if view == ContentView::Numbered {
+                    spans.push(Span::styled(
+                        format!("{:>width$}. ", index + 1, width = number_width),
+                        theme.border,
+                    ));
+                }
+                if show_timestamps {
+                    spans.push(Span::styled(
+                        format!("[{}] ", format_relative(turn.timestamp, now)),
+                        style(index),
+                    ));
+                }
+                spans.push(Span::styled(turn.text.clone(), style(index)));
+                Spans::from(spans)
+            }
+            ContentLine::Moment(moment) => Spans::from(Span::styled(
+                format!(
+                    "· {} ({})",
+                    catalog.render(&moment.message()),
+                    format_relative(moment.at, now)
+                ),
+                theme.border.add_modifier(Modifier::DIM),
+            )),
+        };
+        Some(Text::from(
+            self.content_layout(view, show_timestamps)
+                .into_iter()
+                .map(line)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    fn stats(&self, catalog: &Catalog) -> Option<String> {
+        match self {
+            AppState::InSession { session, .. } | Replay(session) => Some(
+                catalog.render(
+                    &Message::new("story-stats")
+                        .arg("count", session.turns().len())
+                        .arg("duration", format_duration(session.duration())),
+                ),
+            ),
+            Waiting | Connecting(_) => None,
+        }
+    }
+}
+
+/// How the story is laid out in the Content pane.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ContentView {
+    /// The sentences run together as one paragraph.
+    Prose,
+    /// Each sentence on its own line.
+    List,
+    /// Each sentence on its own line, after its turn number.
+    Numbered,
+}
+
+impl ContentView {
+    fn next(self) -> Self {
         match self {
-            AppState::InSession { content_log, .. } => Some(content_log.join(" ")),
-            Waiting => None,
+            ContentView::Prose => ContentView::List,
+            ContentView::List => ContentView::Numbered,
+            ContentView::Numbered => ContentView::Prose,
         }
     }
 }
 
+impl ContentView {
+    fn message(self) -> Message {
+        Message::new(match self {
+            ContentView::Prose => "view-prose",
+            ContentView::List => "view-list",
+            ContentView::Numbered => "view-numbered",
+        })
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum Element {
     Input,
     Connect,
+    Scratchpad,
+    Archive,
+    RecentPeers,
+    Log,
+}
+
+/// The list of saved sessions shown in place of the Content pane.
+struct ArchiveBrowser {
+    sessions: Vec<SavedSession>,
+    selected: usize,
+    /// The session marked to be resumed when a peer connects.
+    marked: Option<String>,
+}
+
+impl ArchiveBrowser {
+    fn selected(&self) -> Option<&SavedSession> {
+        self.sessions.get(self.selected)
+    }
+
+    fn items(&self, catalog: &Catalog) -> Vec<ListItem<'static>> {
+        self.sessions
+            .iter()
+            .map(|saved| {
+                let session = &saved.session;
+                let marker = if self.marked.as_ref() == Some(&session.id) {
+                    catalog.tr("archive-resume-marker")
+                } else {
+                    String::new()
+                };
+                let starter = if session.we_started() {
+                    catalog.tr("archive-you")
+                } else {
+                    catalog.tr("archive-peer")
+                };
+                ListItem::new(
+                    catalog.render(
+                        &Message::new("archive-item")
+                            .arg("title", session.title())
+                            .arg("started", session.started_at.format("%Y-%m-%d %H:%M"))
+                            .arg("words", session.word_count())
+                            .arg("starter", starter),
+                    ) + &marker,
+                )
+            })
+            .collect()
+    }
+}
+
+/// A message shown over the top-right corner for a few seconds.
+struct Toast {
+    level: Level,
+    text: String,
+    until: Instant,
+    /// Shown across the whole width, for something to celebrate.
+    banner: bool,
+}
+
+/// Finding text in the story.
+struct Search {
+    query: EditBuffer,
+    /// Whether keys still go to the query rather than moving between matches.
+    editing: bool,
+    /// The match scrolled to, once the query is entered.
+    current: Option<usize>,
+}
+
+/// How fast each actor handles messages and the screen is drawn, sampled
+/// while the metrics overlay is open.
+struct MetricsOverlay {
+    ui: Rate,
+    app: Rate,
+    draws: Rate,
+}
+
+/// Spellings suggested for a word in the Input, one of which is in its
+/// place.
+struct Respelling {
+    /// The word as it was typed.
+    original: String,
+    spellings: Vec<String>,
+    /// Which spelling is in its place.
+    index: usize,
+}
+
+/// Going to a sentence by its turn number.
+#[derive(Default)]
+struct GoTo {
+    number: EditBuffer,
+    /// The turn scrolled to, once a number of one is entered.
+    turn: Option<usize>,
+    /// Until when the turn scrolled to stands out.
+    highlight_until: Option<Instant>,
+    /// Whether the number entered last isn't a turn of the story.
+    out_of_range: bool,
+}
+
+impl GoTo {
+    /// Scrolls to the turn numbered, if a story of `count` turns has one.
+    fn jump(&mut self, count: usize) {
+        match self.number.text().parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => {
+                self.turn = Some(number - 1);
+                self.highlight_until = Some(Instant::now() + JUMP_HIGHLIGHT);
+            }
+            _ => self.out_of_range = true,
+        }
+    }
+}
+
+/// Picking a command by typing part of its name.
+#[derive(Default)]
+struct Palette {
+    query: EditBuffer,
+    /// Which of the commands matching the query is picked.
+    selected: usize,
+}
+
+/// A question that takes over the keyboard until it is answered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Prompt {
+    /// Quitting would leave the story unexported.
+    Quit,
+    /// Which format to export the story in.
+    Export,
+    /// Whether to write another story with the same peer.
+    Rematch,
+    /// Whether to scrap the story and start again, as the peer asked.
+    Clear,
+    /// Whether to take the peer's story in place of ours, which differs.
+    Resync,
+    /// Whether to disconnect from the peer and never let them connect again.
+    BlockPeer,
+}
+
+/// Everything the UI starts with that the user can configure.
+pub struct UIConfig {
+    pub constraints: Option<ConstraintPool>,
+    pub theme: Theme,
+    pub keymap: KeyMap,
+    pub alerts: Alerts,
+    pub history: AddressHistory,
+    pub recent_peers: RecentPeers,
+    /// Sentences submitted before, to recall into the Input.
+    pub sentences: SentenceHistory,
+    /// Word completions while typing, unless turned off.
+    pub completer: Option<Completer>,
+    /// Spell checking of the Input, when there's a dictionary for it.
+    pub speller: Option<Speller>,
+    /// Problems with the settings, shown in the Log at startup.
+    pub warnings: Vec<String>,
+    /// How often the screen may be drawn, and animations move on.
+    pub tick_rate: Duration,
+    /// Whether a sentence queued during the peer's turn waits to be
+    /// submitted again once it's ours, rather than being sent straight away.
+    pub confirm_queued: bool,
+    /// Whether we write first after an imported opening, rather than the peer.
+    pub import_first: bool,
+    /// An opening imported on the command line, shown until a peer connects.
+    pub imported: Option<SessionInstance>,
+    /// Words our sentences may not have in them.
+    pub word_filter: Option<WordFilter>,
+    /// How long without a key press before we're marked away, if ever.
+    pub away_after: Option<Duration>,
+    /// How long our turn can go on before we're reminded of it, if ever.
+    pub remind_after: Option<Duration>,
+    /// Whether the peer is told we're still thinking when we're reminded.
+    pub remind_peer: bool,
+    /// Characters a second the peer's sentences are typed out at as they
+    /// arrive, unless they're shown at once.
+    pub typewriter: Option<u64>,
+    /// A reminder put in our invite for the peer.
+    pub invite_hint: Option<String>,
+    /// What the story is exported with besides its milestones.
+    pub appendices: Appendices,
+    /// The language everything is shown in.
+    pub catalog: Catalog,
 }
 
 struct UIActor {
     app_state: AppState,
+    catalog: Catalog,
 
-    log_buffer: Vec<String>,
+    log_buffer: LogBuffer,
 
-    input_buffer: Vec<char>,
-    address_buffer: Vec<char>,
+    input_buffer: EditBuffer,
+    completer: Option<Completer>,
+    /// Which of the completions shown is highlighted.
+    suggestion: usize,
+    /// The word completions were dismissed for.
+    dismissed_word: Option<String>,
+    speller: Option<Speller>,
+    /// Whether each word in the Input that's been looked up is spelled
+    /// right, so that typing only looks up the word being typed.
+    spelled: HashMap<String, bool>,
+    respelling: Option<Respelling>,
+    sentences: SentenceHistory,
+    /// The history entry shown in the Input, if it hasn't been edited.
+    sentence_index: Option<usize>,
+    /// What the Input held when searching the history began.
+    sentence_query: Option<String>,
+    address_buffer: EditBuffer,
+    history: AddressHistory,
+    /// The history entry shown in the Connect box, if it hasn't been edited.
+    history_index: Option<usize>,
+    recent_peers: RecentPeers,
+    /// Which of the recent peers shown is highlighted.
+    recent_selected: usize,
+    /// The address we asked to connect to, recorded once connected.
+    pending_address: Option<SocketAddr>,
+    /// Why the last connection attempt failed, until the address is edited.
+    connect_error: Option<String>,
+    /// Advances while connecting, to animate the spinner.
+    spinner: usize,
     selected_element: Element,
+    show_timestamps: bool,
+    content_view: ContentView,
+    /// How the screen is shared out between the panes.
+    layout: LayoutMode,
+    /// How much of the height the Content pane gets, starting from what
+    /// the layout gives it.
+    content_percent: u16,
+    /// Whether the Log is down to its latest line.
+    log_collapsed: bool,
+    /// Notes for ourselves, kept beside the Content pane while shown.
+    scratchpad: EditBuffer,
+    show_scratchpad: bool,
+    /// Whether the notes changed since the app last saved them.
+    notes_edited: bool,
+    appendices: Appendices,
+    /// Whether only the Content pane and a one-line input are shown.
+    zen: bool,
+    /// Whether the sentence being written is previewed after the story.
+    show_preview: bool,
+    theme: Theme,
+    keymap: KeyMap,
+    archive: Option<ArchiveBrowser>,
+    prompt: Option<Prompt>,
+    search: Option<Search>,
+    go_to: Option<GoTo>,
+    palette: Option<Palette>,
+    typewriter: Option<u64>,
+    /// The turn of the peer's sentence being typed out, and when it arrived.
+    typing: Option<(usize, Instant)>,
+    /// The path typed into the import box, while it's open.
+    import_path: Option<EditBuffer>,
+    import_first: bool,
+    /// The story's statistics while they're shown, worked out on opening.
+    stats: Option<Stats>,
+    metrics_overlay: Option<MetricsOverlay>,
+    /// How many sentences the story had when it was last exported.
+    exported_turns: usize,
+
+    constraints: Option<ConstraintPool>,
+    next_constraint: Option<constraints::Constraint>,
+    word_filter: Option<WordFilter>,
+
+    listening: Option<SocketAddr>,
+    /// Every address a peer could reach us at, `listening` being the one
+    /// highlighted.
+    endpoints: Vec<SocketAddr>,
+    invite_hint: Option<String>,
+    /// What we're listening on as an invite, along with its QR code.
+    invite: Option<(String, QrCode)>,
+    peer: Option<SocketAddr>,
+    /// How to tell the user it became their turn.
+    alerts: Alerts,
+    /// When a key was last pressed, to guess whether the user is away.
+    last_key: Instant,
+    /// When the screen stops being drawn inverted.
+    flash_until: Option<Instant>,
+    /// Newest last.
+    toasts: VecDeque<Toast>,
+    /// Whether the sentence in the Input was submitted during the peer's
+    /// turn, to be sent when ours comes.
+    queued: bool,
+    confirm_queued: bool,
+    /// Whether we've stepped away, until the next key press.
+    away: bool,
+    away_after: Option<Duration>,
+    peer_away: bool,
+    remind_after: Option<Duration>,
+    remind_peer: bool,
+    /// How long the story was when our turn came, and when that was.
+    turn_since: Option<(usize, Instant)>,
+    /// When we're next reminded that it's our turn.
+    next_reminder: Option<Instant>,
+    /// When our sentence held for the cooldown is sent.
+    cooldown_until: Option<Instant>,
+
+    /// Set when the terminal was resized, so the next draw starts from a
+    /// cleared screen.
+    resized: bool,
+    /// Set by Ctrl+Z, for the run loop to give the terminal back to the shell.
+    suspending: bool,
+    /// Whether anything shown changed since the last draw. The terminal
+    /// already diffs what is drawn cell by cell, so this only has to say
+    /// whether drawing is worth it at all.
+    dirty: bool,
+    /// The status bar as last drawn, to tell when its clocks have moved on.
+    drawn_status: String,
+    /// How often the screen may be drawn, and animations move on.
+    tick_rate: Duration,
+    drawn_this_tick: bool,
 
     receiver: mpsc::Receiver<UIMessage>,
+    /// How many messages the app dropped because we were too busy for them.
+    dropped: Arc<AtomicUsize>,
+    metrics: Arc<ActorMetrics>,
+    /// How many times the screen was drawn.
+    draws: u64,
 
-    event_stream: EventStream,
     app_handle: AppHandle,
 }
 
 impl UIActor {
     fn new(
         receiver: mpsc::Receiver<UIMessage>,
-        event_stream: EventStream,
+        dropped: Arc<AtomicUsize>,
+        metrics: Arc<ActorMetrics>,
         app_handle: AppHandle,
+        config: UIConfig,
     ) -> Self {
+        // Start from the address we last connected to
+        let mut address_buffer = EditBuffer::default();
+        let history_index = config.history.get(0).map(|address| {
+            address_buffer.set(&address.to_string());
+            0
+        });
+        let mut log_buffer = LogBuffer::default();
+        for warning in config.warnings {
+            log_buffer.warn(warning);
+        }
         Self {
-            app_state: Waiting,
-            log_buffer: vec![],
-            input_buffer: vec![],
-            address_buffer: vec![],
+            app_state: config.imported.map_or(Waiting, Replay),
+            log_buffer,
+            input_buffer: EditBuffer::default(),
+            completer: config.completer,
+            speller: config.speller,
+            spelled: HashMap::new(),
+            respelling: None,
+            suggestion: 0,
+            dismissed_word: None,
+            sentences: config.sentences,
+            sentence_index: None,
+            sentence_query: None,
+            address_buffer,
+            history_index,
+            history: config.history,
+            recent_peers: config.recent_peers,
+            recent_selected: 0,
+            pending_address: None,
+            connect_error: None,
+            spinner: 0,
             selected_element: Element::Connect,
+            show_timestamps: false,
+            content_view: ContentView::Prose,
+            layout: LayoutMode::Balanced,
+            content_percent: LayoutMode::Balanced.content_percent(),
+            log_collapsed: false,
+            scratchpad: EditBuffer::default(),
+            show_scratchpad: false,
+            notes_edited: false,
+            appendices: config.appendices,
+            zen: false,
+            show_preview: true,
+            theme: config.theme,
+            keymap: config.keymap,
+            archive: None,
+            prompt: None,
+            search: None,
+            go_to: None,
+            palette: None,
+            typewriter: config.typewriter,
+            typing: None,
+            import_path: None,
+            import_first: config.import_first,
+            stats: None,
+            metrics_overlay: None,
+            exported_turns: 0,
+            constraints: config.constraints,
+            next_constraint: None,
+            word_filter: config.word_filter,
+            listening: None,
+            endpoints: vec![],
+            invite_hint: config.invite_hint,
+            catalog: config.catalog,
+            invite: None,
+            peer: None,
+            alerts: config.alerts,
+            last_key: Instant::now(),
+            flash_until: None,
+            toasts: VecDeque::new(),
+            queued: false,
+            confirm_queued: config.confirm_queued,
+            away: false,
+            away_after: config.away_after,
+            peer_away: false,
+            remind_after: config.remind_after,
+            remind_peer: config.remind_peer,
+            turn_since: None,
+            next_reminder: None,
+            cooldown_until: None,
+            resized: false,
+            suspending: false,
+            dirty: true,
+            drawn_status: String::new(),
+            tick_rate: config.tick_rate,
+            drawn_this_tick: false,
             receiver,
-            event_stream,
+            dropped,
+            metrics,
+            draws: 0,
             app_handle,
         }
     }
 
     fn handle_message(&mut self, msg: UIMessage) {
+        self.handle_app_message(msg);
+        // Reported after the message that made it through before them
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let key = if dropped == 1 {
+                "missed-message"
+            } else {
+                "missed-messages"
+            };
+            self.log_buffer.warn(
+                self.catalog
+                    .render(&Message::new(key).arg("count", dropped)),
+            );
+        }
+    }
+
+    fn handle_app_message(&mut self, msg: UIMessage) {
+        debug!(message = %msg, "handling ui message");
+        self.dirty = true;
         match msg {
-            UIMessage::Log(message) => {
-                self.log_buffer.push(message);
+            UIMessage::Log(level, message) => {
+                let message = self.catalog.render(&message);
+                if level == Level::Error {
+                    self.toast(level, message.clone());
+                }
+                self.log_buffer.push(level, message);
+            }
+            UIMessage::Toast(message) => {
+                let message = self.catalog.render(&message);
+                self.toast(Level::Info, message.clone());
+                self.log_buffer.info(message);
             }
-            UIMessage::SentenceReceived(sentence) => {
+            UIMessage::Milestone(milestone) => {
+                let message = self.catalog.render(&milestone.message());
+                self.push_toast(Level::Info, message.clone(), true);
+                self.log_buffer.info(message);
+            }
+            UIMessage::SentenceReceived(turn) => {
                 if let InSession {
                     is_our_turn,
-                    content_log,
+                    session,
                 } = &mut self.app_state
                 {
-                    *is_our_turn = true;
-                    content_log.push(sentence);
-                }
-            }
-            UIMessage::Connected(is_our_turn) => {
-                self.app_state = InSession {
-                    is_our_turn,
-                    content_log: Vec::new(),
+                    if let Some(completer) = &mut self.completer {
+                        completer.add_text(&turn.text);
+                    }
+                    session.push(turn);
+                    if self.typewriter.is_some() {
+                        self.typing = Some((session.turns().len() - 1, Instant::now()));
+                    }
+                    if session.is_our_turn() && !*is_our_turn {
+                        if self.queued && self.confirm_queued {
+                            self.queued = false;
+                            self.log_buffer.info(self.catalog.tr("queued-ready"));
+                        }
+                        alert(self.alerts.notify, &mut self.flash_until);
+                        if self.alerts.desktop && self.last_key.elapsed() > IDLE {
+                            notify::desktop(&self.catalog.tr("desktop-your-turn"));
+                        }
+                    }
+                    *is_our_turn = session.is_our_turn();
                 }
             }
-            UIMessage::Disconnected => self.app_state = Waiting,
-        }
-    }
-
-    // Check for input that is independent of state
-    fn handle_independent_event(&mut self, event: Event) -> Option<bool> {
-        if let Event::Key(KeyEvent { code, .. }) = event {
-            match code {
-                KeyCode::Esc => Some(true),
-                KeyCode::Backspace => {
-                    match self.selected_element {
-                        Element::Input => self.input_buffer.pop(),
-                        Element::Connect => self.address_buffer.pop(),
-                    };
-                    Some(false)
-                }
-                KeyCode::Left => {
-                    if self.selected_element == Element::Connect {
-                        self.selected_element = Element::Input;
+            UIMessage::SentenceAccepted(turn) => {
+                self.cooldown_until = None;
+                if let InSession { session, .. } = &mut self.app_state {
+                    if let Some(completer) = &mut self.completer {
+                        completer.add_text(&turn.text);
                     }
-                    None
+                    session.push(turn);
                 }
-                KeyCode::Right => {
-                    if self.selected_element == Element::Input {
-                        self.selected_element = Element::Connect;
+            }
+            UIMessage::SentenceBounced {
+                index,
+                kept,
+                bounced,
+            } => {
+                if let InSession {
+                    is_our_turn,
+                    session,
+                } = &mut self.app_state
+                {
+                    let bounced = bounced.iter().map(|turn| turn.text.as_str()).join(" ");
+                    self.log_buffer.warn(
+                        self.catalog.render(
+                            &Message::new("conflict-kept-theirs")
+                                .arg("turn", index + 1)
+                                .arg("theirs", &kept.text)
+                                .arg("ours", &bounced),
+                        ),
+                    );
+                    if let Some(completer) = &mut self.completer {
+                        completer.add_text(&kept.text);
                     }
-                    None
+                    session.replace_from(index, kept);
+                    *is_our_turn = session.is_our_turn();
+                    self.input_buffer.set(bounced.trim_end_matches('.'));
+                    self.input_edited();
                 }
-                _ => None,
             }
-        } else {
-            None
-        }
-    }
-
-    async fn handle_input_event(&mut self, event: Event) -> Result<bool, Error> {
-        if Some(true) == self.handle_independent_event(event) {
-            return Ok(true);
-        }
-
-        match &mut self.app_state {
-            InSession {
-                is_our_turn,
-                content_log,
-            } => {
-                if let Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    ..
-                }) = event
+            UIMessage::SentenceRefused { index, turn } => {
+                if let InSession {
+                    is_our_turn,
+                    session,
+                } = &mut self.app_state
                 {
-                    if self.selected_element == Element::Input && *is_our_turn {
-                        self.input_buffer.push(c);
-                        if c == '.' {
-                            self.app_handle
-                                .send_sentence(String::from_iter(&self.input_buffer))
-                                .await?;
-                            content_log.push(String::from_iter(&self.input_buffer));
-                            *is_our_turn = false;
-                            self.input_buffer.clear();
-                        }
+                    self.log_buffer.warn(
+                        self.catalog
+                            .render(&Message::new("refused-by-peer").arg("sentence", &turn.text)),
+                    );
+                    if session.turns().len() == index + 1 {
+                        session.pop();
                     }
+                    *is_our_turn = session.is_our_turn();
+                    self.input_buffer.set(turn.text.trim_end_matches('.'));
+                    self.input_edited();
                 }
             }
-            Waiting => {
-                if let Event::Key(KeyEvent { code, .. }) = event {
-                    match code {
-                        KeyCode::Enter => {
-                            if self.selected_element == Element::Connect {
-                                let address = SocketAddr::from_str(
-                                    String::from_iter(&self.address_buffer).as_str(),
-                                );
-
-                                if let Ok(address) = address {
-                                    self.app_handle.connect(address).await?;
-                                }
-                            }
-                        }
-                        KeyCode::Char(c) => {
-                            if self.selected_element == Element::Connect {
-                                self.address_buffer.push(c)
-                            }
-                        }
-                        _ => {}
+            UIMessage::SavedSessions(sessions) => {
+                let marked = self
+                    .archive
+                    .as_mut()
+                    .and_then(|archive| archive.marked.take());
+                self.archive = Some(ArchiveBrowser {
+                    sessions,
+                    selected: 0,
+                    marked,
+                });
+                self.selected_element = Element::Archive;
+            }
+            UIMessage::PeerAway(away) if away != self.peer_away => {
+                self.peer_away = away;
+                self.log_buffer.info(
+                    self.catalog
+                        .tr(if away { "peer-away" } else { "peer-back" }),
+                );
+            }
+            UIMessage::PeerAway(_) => {}
+            UIMessage::Cooldown(wait) => self.cooldown_until = Some(Instant::now() + wait),
+            UIMessage::Listening(address) => {
+                self.endpoints = endpoints::candidates(address);
+                self.highlight_endpoint(self.endpoints[0]);
+            }
+            UIMessage::Connecting(address) => {
+                // Connecting from the command line skips the Connect box, which
+                // should still have the address in it if the connect fails
+                if validate_address(&self.address_buffer.text()).ok() != Some(address) {
+                    self.address_buffer.set(&address.to_string());
+                    self.history_index = None;
+                }
+                self.pending_address = Some(address);
+                self.connect_error = None;
+                self.close_archive();
+                self.app_state = Connecting(address);
+            }
+            UIMessage::ConnectFailed { address, reason } => {
+                let message = self.catalog.render(
+                    &Message::new("connect-failed")
+                        .arg("address", address)
+                        .arg_message("reason", reason.clone()),
+                );
+                self.toast(Level::Error, message.clone());
+                self.log_buffer.error(message);
+                self.pending_address = None;
+                self.connect_error = Some(self.catalog.render(&reason));
+                self.app_state = Waiting;
+            }
+            UIMessage::Connected { session, peer } => {
+                info!(%peer, session = %session.id, "in session");
+                self.peer = Some(peer);
+                if self.pending_address.take() == Some(peer) {
+                    self.history_index = Some(0);
+                    if let Err(err) = self.history.record(peer) {
+                        self.log_buffer.warn(self.catalog.render(
+                            &Message::new("save-address-history-failed").arg("error", err),
+                        ));
+                    }
+                    if let Err(err) = self.recent_peers.connected(peer) {
+                        self.log_buffer.warn(
+                            self.catalog.render(
+                                &Message::new("save-recent-peers-failed").arg("error", err),
+                            ),
+                        );
                     }
+                    self.recent_selected = 0;
                 }
+                self.close_archive();
+                self.learn_story(&session);
+                self.load_notes(&session);
+                self.next_constraint = self.constraints.as_ref().map(ConstraintPool::roll);
+                self.exported_turns = 0;
+                self.search = None;
+                self.go_to = None;
+                self.queued = false;
+                self.app_state = InSession {
+                    is_our_turn: session.is_our_turn(),
+                    session,
+                };
+                self.refocus();
+            }
+            UIMessage::StoryEnded => {
+                self.log_buffer.info(self.catalog.tr("story-finished"));
+                self.search = None;
+                self.go_to = None;
+                self.prompt = Some(Prompt::Rematch);
+            }
+            UIMessage::ClearProposed => {
+                self.search = None;
+                self.go_to = None;
+                self.prompt = Some(Prompt::Clear);
+            }
+            UIMessage::Diverged(kept) => {
+                let message = self
+                    .catalog
+                    .tr(if kept { "diverged-kept" } else { "diverged" });
+                self.push_toast(Level::Warn, message.clone(), true);
+                self.log_buffer.warn(message);
+                if !kept {
+                    self.search = None;
+                    self.go_to = None;
+                    self.prompt = Some(Prompt::Resync);
+                }
+            }
+            UIMessage::Resynced(session) => {
+                self.log_buffer.info(
+                    self.catalog
+                        .render(&Message::new("resynced").arg("turns", session.turns().len())),
+                );
+                self.learn_story(&session);
+                self.search = None;
+                self.go_to = None;
+                self.queued = false;
+                self.app_state = InSession {
+                    is_our_turn: session.is_our_turn(),
+                    session,
+                };
+            }
+            UIMessage::NewStory(session) => {
+                info!(session = %session.id, "new story");
+                self.story_finished();
+                self.log_buffer.info(
+                    self.catalog
+                        .render(&Message::new("new-story-marker").arg("id", &session.id)),
+                );
+                self.input_buffer.clear();
+                self.input_edited();
+                self.learn_story(&session);
+                self.load_notes(&session);
+                self.next_constraint = self.constraints.as_ref().map(ConstraintPool::roll);
+                self.exported_turns = 0;
+                self.search = None;
+                self.go_to = None;
+                self.app_state = InSession {
+                    is_our_turn: session.is_our_turn(),
+                    session,
+                }
+            }
+            UIMessage::AppStopped(reason) => {
+                let message = self
+                    .catalog
+                    .render(&Message::new("networking-stopped").arg("reason", reason));
+                self.toast(Level::Error, message.clone());
+                self.log_buffer.error(message);
+                self.listening = None;
+                self.endpoints.clear();
+                self.invite = None;
+                self.handle_message(UIMessage::Disconnected);
+            }
+            UIMessage::Disconnected => {
+                info!("waiting");
+                self.story_finished();
+                self.peer = None;
+                self.prompt = None;
+                self.search = None;
+                self.go_to = None;
+                self.queued = false;
+                self.peer_away = false;
+                self.cooldown_until = None;
+                self.app_state = Waiting;
+                self.refocus();
             }
         }
+    }
 
-        Ok(false)
+    /// Counts the story towards the peer's entry in the recent peers, if it
+    /// got anywhere.
+    fn story_finished(&mut self) {
+        let written =
+            matches!(&self.app_state, InSession { session, .. } if !session.turns().is_empty());
+        if let (true, Some(peer)) = (written, self.peer) {
+            if let Err(err) = self.recent_peers.story_written(peer) {
+                self.log_buffer.warn(
+                    self.catalog
+                        .render(&Message::new("save-recent-peers-failed").arg("error", err)),
+                );
+            }
+        }
     }
 
-    fn draw<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), Error> {
-        terminal.draw(|frame| self.draw_view(frame))?;
-        Ok(())
+    fn toast(&mut self, level: Level, text: String) {
+        self.push_toast(level, text, false);
     }
 
-    fn draw_view<B: Backend>(&self, frame: &mut Frame<B>) {
-        let size = frame.size();
+    fn push_toast(&mut self, level: Level, text: String, banner: bool) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            level,
+            text,
+            until: Instant::now() + TOAST,
+            banner,
+        });
+    }
 
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(2)
-            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
-            .split(size);
+    /// Whether something on screen moves by itself, and needs drawing on
+    /// every tick.
+    fn animating(&self) -> bool {
+        matches!(self.app_state, Connecting(_)) || !self.toasts.is_empty() || self.typing.is_some()
+    }
 
-        let para = Paragraph::new(self.app_state.content_log().unwrap_or_default())
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_type(BorderType::Rounded)
-                    .title("Content"),
-            )
-            .wrap(Wrap { trim: false });
+    /// Whether nothing was pressed for long enough to count as away.
+    fn idle(&self) -> bool {
+        !self.away
+            && self
+                .away_after
+                .is_some_and(|after| self.last_key.elapsed() >= after)
+    }
 
-        frame.render_widget(para, chunks[0]);
+    /// Marks us away or back, and tells the peer.
+    async fn set_away(&mut self, away: bool) -> Result<(), Error> {
+        self.away = away;
+        self.dirty = true;
+        self.log_buffer.info(
+            self.catalog
+                .tr(if away { "you-away" } else { "welcome-back" }),
+        );
+        self.app_handle.set_away(away).await
+    }
 
-        let bottom_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
-            .split(chunks[1]);
+    /// Reminds us once our turn has gone on for `remind_after`, and again
+    /// every `remind_after` until it ends, telling the peer we're still
+    /// thinking if asked to. Time away doesn't count.
+    async fn remind(&mut self) -> Result<(), Error> {
+        let (after, turns) = match (self.remind_after, &self.app_state) {
+            (
+                Some(after),
+                InSession {
+                    session,
+                    is_our_turn: true,
+                },
+            ) => (after, session.turns().len()),
+            _ => {
+                self.turn_since = None;
+                self.next_reminder = None;
+                return Ok(());
+            }
+        };
+        let now = Instant::now();
+        let since = match self.turn_since {
+            Some((at_turns, since)) if at_turns == turns => since,
+            _ => {
+                self.turn_since = Some((turns, now));
+                self.next_reminder = Some(now + after);
+                return Ok(());
+            }
+        };
+        if self.away {
+            self.next_reminder = Some(now + after);
+            return Ok(());
+        }
+        if self.next_reminder.is_some_and(|at| at > now) {
+            return Ok(());
+        }
+        self.next_reminder = Some(now + after);
+        let minutes = (now - since).as_secs() / 60;
+        let message = self
+            .catalog
+            .render(&Message::new("turn-reminder").arg("minutes", minutes));
+        self.toast(Level::Info, message.clone());
+        self.log_buffer.info(message);
+        alert(self.alerts.notify, &mut self.flash_until);
+        self.dirty = true;
+        if self.remind_peer {
+            self.app_handle.still_thinking(minutes).await?;
+        }
+        Ok(())
+    }
 
-        let input_para = Paragraph::new(String::from_iter(&self.input_buffer))
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_type(BorderType::Rounded)
-                    .style(get_style(Element::Input, self.selected_element))
-                    .title("Input"),
-            )
-            .wrap(Wrap { trim: false });
-        frame.render_widget(input_para, bottom_chunks[0]);
+    /// Starts a new frame: animations move on, the clocks are checked, and
+    /// anything that changed since the last draw can be drawn again.
+    fn tick(&mut self) {
+        self.drawn_this_tick = false;
+        if self.animating() {
+            self.animate();
+        }
+        self.tick_clock();
+    }
 
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(3), Constraint::Min(1)])
-            .split(bottom_chunks[1]);
+    /// Advances animations and drops toasts that have been shown long enough.
+    fn animate(&mut self) {
+        if let Connecting(_) = self.app_state {
+            self.spinner += 1;
+            self.dirty = true;
+        }
+        let now = Instant::now();
+        let toasts = self.toasts.len();
+        self.toasts.retain(|toast| toast.until > now);
+        self.dirty |= self.toasts.len() != toasts;
+        if let Some(overlay) = &mut self.metrics_overlay {
+            let ui = overlay.ui.sample(self.metrics.handled_count(), now);
+            let app = overlay
+                .app
+                .sample(self.app_handle.metrics().handled_count(), now);
+            let draws = overlay.draws.sample(self.draws, now);
+            self.dirty |= ui || app || draws;
+        }
+        if let Some(go_to) = &mut self.go_to {
+            if go_to.highlight_until.is_some_and(|until| until <= now) {
+                go_to.highlight_until = None;
+                self.dirty = true;
+            }
+        }
+        if self.typing.is_some() {
+            self.dirty = true;
+            if self.typed().is_none() {
+                self.typing = None;
+            }
+        }
+    }
 
-        let address_input = Paragraph::new(String::from_iter(&self.address_buffer))
-            .block(
-                Block::default()
-                    .title("Connect")
-                    .borders(Borders::ALL)
-                    .style(get_style(Element::Connect, self.selected_element))
-                    .border_type(BorderType::Plain),
-            )
-            .alignment(Alignment::Center);
+    /// The turn of the sentence being typed out and how much of it is
+    /// shown so far, in bytes, until all of it is.
+    fn typed(&self) -> Option<(usize, usize)> {
+        let (turn, since) = self.typing?;
+        let shown = since.elapsed().as_secs_f64() * self.typewriter? as f64;
+        let text = &self.story()?.turns().get(turn)?.text;
+        let (typed, _) = text.grapheme_indices(true).nth(shown as usize)?;
+        Some((turn, typed))
+    }
 
-        frame.render_widget(address_input, chunks[0]);
-        let log_block = Paragraph::new(self.log_buffer.iter().rev().join("\n")).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_type(BorderType::Rounded)
-                .title("Log"),
-        );
+    /// Marks the screen for drawing once the clocks in the status bar show
+    /// something new.
+    fn tick_clock(&mut self) {
+        self.dirty |= status_text(&self.status_bar()) != self.drawn_status;
+    }
 
-        frame.render_widget(log_block, chunks[1])
+    /// Shows the notes saved with a story we've joined, unless some were
+    /// jotted since the last were saved, which go with this story instead.
+    fn load_notes(&mut self, session: &SessionInstance) {
+        if !self.notes_edited {
+            self.scratchpad.set(session.notes());
+        }
     }
-}
 
-fn get_style(this_element: Element, selected_element: Element) -> Style {
-    if selected_element == this_element {
-        Style::default().fg(Color::Green)
-    } else {
-        Style::default()
+    /// Hands the notes to the app to save with the story once they've been
+    /// edited, when the Scratchpad loses focus or `now`.
+    async fn save_notes(&mut self, now: bool) -> Result<(), Error> {
+        let in_session = matches!(self.app_state, InSession { .. });
+        if in_session && self.notes_edited && (now || self.selected_element != Element::Scratchpad)
+        {
+            self.notes_edited = false;
+            self.app_handle.save_notes(self.scratchpad.text()).await?;
+        }
+        Ok(())
     }
-}
 
-async fn run_ui_actor<B: Backend>(
-    mut actor: UIActor,
-    terminal: &mut Terminal<B>,
-) -> Result<(), Error> {
-    loop {
-        actor.draw(terminal)?;
-        tokio::select! {
-            Some(msg) = actor.receiver.recv() => {
-                actor.handle_message(msg);
-            }
-            Some(Ok(event)) = actor.event_stream.next() => {
-                if actor.handle_input_event(event).await.unwrap_or(false) {
-                    break;
-                }
+    /// Adds the words of a story joined part way through, e.g. when resumed.
+    fn learn_story(&mut self, session: &SessionInstance) {
+        if let Some(completer) = &mut self.completer {
+            for turn in session.turns() {
+                completer.add_text(&turn.text);
             }
-            else => {
-                break;
+        }
+    }
+
+    /// Completions for the word being typed, when there are any to show.
+    fn suggestions(&self) -> Vec<String> {
+        let completer = match &self.completer {
+            Some(completer) => completer,
+            None => return vec![],
+        };
+        let writing = matches!(self.app_state, InSession { .. })
+            && !self.queued
+            && self.sentence_index.is_none();
+        if !writing || self.selected_element != Element::Input {
+            return vec![];
+        }
+        match self.input_buffer.word_at_cursor() {
+            Some(word)
+                if word.chars().count() >= COMPLETE_AFTER
+                    && self.dismissed_word.as_ref() != Some(&word) =>
+            {
+                completer.suggest(&word, SUGGESTIONS)
             }
+            _ => vec![],
         }
     }
 
-    Ok(())
-}
+    /// Looks up the words in the Input that haven't been yet, which while
+    /// typing is only the word being typed.
+    fn check_spelling(&mut self) {
+        let speller = match &self.speller {
+            Some(speller) => speller,
+            None => return,
+        };
+        if self.spelled.len() > SPELLED {
+            self.spelled.clear();
+        }
+        let text = self.input_buffer.text();
+        for range in filter::words(&text) {
+            let word = &text[range];
+            if !self.spelled.contains_key(word) {
+                self.spelled.insert(word.to_string(), speller.check(word));
+            }
+        }
+    }
 
-#[derive(Clone, Debug)]
-pub struct UIHandle {
-    sender: Sender<UIMessage>,
-}
+    /// Where the words in the Input that aren't in the dictionary are,
+    /// leaving out the one being typed.
+    fn misspelled(&self, text: &str) -> Vec<Range<usize>> {
+        let cursor = self.input_buffer.cursor_offset();
+        filter::words(text)
+            .filter(|range| {
+                range.end != cursor && self.spelled.get(&text[range.clone()]) == Some(&false)
+            })
+            .collect()
+    }
 
-type UIStarter<'a, B> = Box<
-    dyn FnOnce(
-        EventStream,
-        AppHandle,
-        &'a mut Terminal<B>,
-    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + 'a>>,
->;
+    /// Puts the next spelling suggested for the word at the cursor in its
+    /// place, and the word as typed back after the last.
+    fn respell(&mut self) {
+        let (speller, word) = match (&self.speller, self.input_buffer.word_at_cursor()) {
+            (Some(speller), Some(word)) => (speller, word),
+            _ => return,
+        };
+        let respelling = match self.respelling.take() {
+            Some(respelling) if respelling.spellings.get(respelling.index) == Some(&word) => {
+                Respelling {
+                    index: respelling.index + 1,
+                    ..respelling
+                }
+            }
+            _ => Respelling {
+                spellings: speller.suggest(&word, SPELLINGS),
+                original: word,
+                index: 0,
+            },
+        };
+        if respelling.spellings.is_empty() {
+            let message = Message::new("no-spellings").arg("word", respelling.original);
+            self.toast(Level::Info, self.catalog.render(&message));
+            return;
+        }
+        let spelling = respelling
+            .spellings
+            .get(respelling.index)
+            .unwrap_or(&respelling.original)
+            .clone();
+        self.input_buffer.replace_word(&spelling);
+        self.input_edited();
+        // The popup would cover the word just put in
+        self.dismissed_word = Some(spelling);
+        self.respelling = Some(respelling);
+    }
 
-impl UIHandle {
-    pub fn new<'a, B: Backend>() -> (Self, UIStarter<'a, B>) {
-        let (sender, receiver) = mpsc::channel(8);
+    /// Lets the completion popup take the keys it uses over their usual
+    /// actions, returning whether the key was used.
+    fn handle_suggestion_event(&mut self, action: Option<Action>) -> bool {
+        let suggestions = self.suggestions();
+        if suggestions.is_empty() {
+            return false;
+        }
+        let highlighted = self.suggestion.min(suggestions.len() - 1);
+        match action {
+            Some(Action::NextFocus) => {
+                let word = self.input_buffer.word_at_cursor().unwrap_or_default();
+                for c in suggestions[highlighted][word.len()..].chars() {
+                    self.input_buffer.insert(c);
+                }
+                self.suggestion = 0;
+            }
+            Some(Action::Quit) => {
+                self.dismissed_word = self.input_buffer.word_at_cursor();
+                self.suggestion = 0;
+            }
+            Some(Action::HistoryPrevious) => {
+                self.suggestion = (highlighted + suggestions.len() - 1) % suggestions.len();
+            }
+            Some(Action::HistoryNext) => {
+                self.suggestion = (highlighted + 1) % suggestions.len();
+            }
+            _ => return false,
+        }
+        true
+    }
 
-        (
-            Self { sender },
-            Box::new(move |event_stream, app_handle, terminal| {
-                let actor = UIActor::new(receiver, event_stream, app_handle);
-                Box::pin(run_ui_actor(actor, terminal))
+    /// What to show above the Input box when playing with constraints.
+    fn constraint_line(&self) -> Option<String> {
+        match &self.app_state {
+            InSession {
+                is_our_turn: true,
+                session,
+            } if session.current_constraint().is_some() => Some(self.catalog.render(
+                &Message::new("your-constraint").arg_message(
+                    "constraint",
+                    session.current_constraint().unwrap().message(),
+                ),
+            )),
+            InSession { .. } => self.next_constraint.as_ref().map(|constraint| {
+                let message = match self.keymap.key(Action::Reroll) {
+                    Some(key) => Message::new("peer-constraint-reroll").arg("key", key),
+                    None => Message::new("peer-constraint"),
+                };
+                self.catalog
+                    .render(&message.arg_message("constraint", constraint.message()))
             }),
-        )
+            Replay(_) | Waiting | Connecting(_) => None,
+        }
     }
 
-    pub async fn log(&self, message: String) -> Result<(), Error> {
-        self.sender.send(UIMessage::Log(message)).await?;
-        Ok(())
+    /// The live length of the sentence being written, against the word
+    /// limit when the constraint sets one.
+    fn counter_line(&self) -> Option<Span<'static>> {
+        let limit = match &self.app_state {
+            InSession {
+                is_our_turn: true,
+                session,
+            } => match session.current_constraint() {
+                Some(constraints::Constraint::MaxWords(limit)) => Some(*limit),
+                _ => None,
+            },
+            InSession { .. } => None,
+            Replay(_) | Waiting | Connecting(_) => return None,
+        };
+        let counts = Counts::of(&self.input_buffer.text());
+        let (words, style) = match limit {
+            Some(limit) => (
+                self.catalog.render(
+                    &Message::new("word-count-limit")
+                        .arg("count", counts.words)
+                        .arg("limit", limit),
+                ),
+                match counter::nearness(counts.words, limit) {
+                    Nearness::Under => Style::default(),
+                    Nearness::Near => self.theme.near_limit,
+                    Nearness::At => self.theme.warning,
+                },
+            ),
+            None => (
+                self.catalog
+                    .render(&Message::new("word-count").arg("count", counts.words)),
+                Style::default(),
+            ),
+        };
+        Some(Span::styled(
+            self.catalog.render(
+                &Message::new("char-count")
+                    .arg("count", counts.chars)
+                    .arg("words", words),
+            ),
+            style,
+        ))
     }
 
-    pub async fn sentence_received(&self, new_sentence: String) -> Result<(), Error> {
-        self.sender
-            .send(UIMessage::SentenceReceived(new_sentence))
-            .await?;
+    /// The end of the story with the sentence being written after it, as it
+    /// would read once submitted. Only ever drawn, never sent.
+    fn preview_line(&self, width: usize) -> Option<Spans<'static>> {
+        let session = match &self.app_state {
+            InSession { session, .. } if self.show_preview && self.input_buffer.len() > 0 => {
+                session
+            }
+            _ => return None,
+        };
+        let mut draft = self.input_buffer.text();
+        if !session.turns().is_empty() {
+            draft.insert(0, ' ');
+        }
+        // Counted in columns, stopping short of a wide character with
+        // only one left for it
+        let tail = |text: &str, width: usize| {
+            let mut used = 0;
+            let mut graphemes: Vec<&str> = text
+                .graphemes(true)
+                .rev()
+                .take_while(|grapheme| {
+                    let fits = used + grapheme.width() <= width;
+                    if fits {
+                        used += grapheme.width();
+                    }
+                    fits
+                })
+                .collect();
+            graphemes.reverse();
+            (graphemes.concat(), used)
+        };
+        let (draft, used) = tail(&draft, width);
+        let (story, _) = tail(&session.content(), width - used);
+        Some(Spans::from(vec![
+            Span::raw(story),
+            Span::styled(draft, Style::default().add_modifier(Modifier::DIM)),
+        ]))
+    }
+
+    fn close_archive(&mut self) {
+        self.archive = None;
+        self.refocus();
+    }
+
+    /// Whether the recent peers are listed in place of the Content pane.
+    fn showing_recent_peers(&self) -> bool {
+        matches!(self.app_state, Waiting) && self.archive.is_none() && !self.recent_peers.is_empty()
+    }
+
+    /// Up and Down pick a recent peer and Enter connects to it, as does its
+    /// number. Forgetting an address forgets it.
+    async fn handle_recent_peers_event(
+        &mut self,
+        code: KeyCode,
+        action: Option<Action>,
+    ) -> Result<(), Error> {
+        let shown = self.recent_peers.len().min(RECENT_PEERS);
+        let chosen = match code {
+            _ if action == Some(Action::ForgetAddress) => {
+                self.forget_recent_peer();
+                None
+            }
+            KeyCode::Up => {
+                self.recent_selected = self.recent_selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down => {
+                self.recent_selected = (self.recent_selected + 1).min(shown - 1);
+                None
+            }
+            KeyCode::Enter => Some(self.recent_selected),
+            KeyCode::Char(c) => c
+                .to_digit(10)
+                .map(|digit| digit as usize)
+                .filter(|digit| (1..=shown).contains(digit))
+                .map(|digit| digit - 1),
+            _ => None,
+        };
+        if let Some(peer) = chosen.and_then(|index| self.recent_peers.get(index)) {
+            let address = peer.address;
+            self.pending_address = Some(address);
+            self.app_handle.connect(address).await?;
+        }
         Ok(())
     }
 
-    pub async fn connected(&self, our_turn: bool) -> Result<(), Error> {
-        self.sender.send(UIMessage::Connected(our_turn)).await?;
+    fn forget_recent_peer(&mut self) {
+        if let Err(err) = self.recent_peers.remove(self.recent_selected) {
+            self.log_buffer.warn(
+                self.catalog
+                    .render(&Message::new("save-recent-peers-failed").arg("error", err)),
+            );
+        }
+        let shown = self.recent_peers.len().min(RECENT_PEERS);
+        self.recent_selected = self.recent_selected.min(shown.saturating_sub(1));
+        self.refocus();
+    }
+
+    async fn handle_archive_event(&mut self, code: KeyCode) -> Result<(), Error> {
+        let archive = match &mut self.archive {
+            Some(archive) => archive,
+            None => return Ok(()),
+        };
+        match code {
+            KeyCode::Up => archive.selected = archive.selected.saturating_sub(1),
+            KeyCode::Down if archive.selected + 1 < archive.sessions.len() => archive.selected += 1,
+            KeyCode::Enter => {
+                if let Some(saved) = archive.selected() {
+                    self.log_buffer.info(
+                        self.catalog.render(
+                            &Message::new("replaying-file").arg("path", saved.path.display()),
+                        ),
+                    );
+                    self.app_state = Replay(saved.session.clone());
+                    self.close_archive();
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(session) = archive.selected().map(|saved| saved.session.clone()) {
+                    archive.marked = Some(session.id.clone());
+                    self.app_handle.resume(session).await?;
+                }
+            }
+            _ => {}
+        }
         Ok(())
     }
 
-    pub async fn disconnected(&self) -> Result<(), Error> {
-        self.sender.send(UIMessage::Disconnected).await?;
+    fn peer_name(&self) -> String {
+        self.peer
+            .map(|peer| peer.to_string())
+            .unwrap_or_else(|| self.catalog.tr("the-peer"))
+    }
+
+    /// Makes `address` the one shown and put in the invite.
+    fn highlight_endpoint(&mut self, address: SocketAddr) {
+        self.listening = Some(address);
+        let invite = Invite {
+            address,
+            hint: self.invite_hint.clone(),
+        }
+        .to_string();
+        self.invite = QrCode::encode(invite.as_bytes()).map(|code| (invite, code));
+    }
+
+    /// The one-line summary of the connection shown at the bottom.
+    fn status_bar(&self) -> Spans<'static> {
+        let separator = || Span::raw(" │ ");
+        let mut spans = vec![Span::raw(match self.listening {
+            Some(address) if self.endpoints.len() > 1 => self.catalog.render(
+                &Message::new("status-listening-of")
+                    .arg("address", address)
+                    .arg(
+                        "index",
+                        self.endpoints
+                            .iter()
+                            .position(|a| *a == address)
+                            .unwrap_or(0)
+                            + 1,
+                    )
+                    .arg("count", self.endpoints.len()),
+            ),
+            Some(address) => self
+                .catalog
+                .render(&Message::new("status-listening").arg("address", address)),
+            None => self.catalog.tr("status-not-listening"),
+        })];
+        spans.push(separator());
+        match &self.app_state {
+            InSession {
+                is_our_turn,
+                session,
+            } => {
+                spans.push(Span::raw(match self.peer {
+                    Some(peer) => self
+                        .catalog
+                        .render(&Message::new("status-connected-to").arg("address", peer)),
+                    None => self.catalog.tr("status-connected"),
+                }));
+                spans.push(separator());
+                spans.push(if *is_our_turn {
+                    Span::styled(self.catalog.tr("status-your-turn"), self.theme.status_turn)
+                } else {
+                    Span::raw(self.catalog.tr("status-peers-turn"))
+                });
+                let cooldown = self
+                    .cooldown_until
+                    .and_then(|until| until.checked_duration_since(Instant::now()));
+                if let Some(cooldown) = cooldown {
+                    spans.push(separator());
+                    spans.push(Span::styled(
+                        self.catalog.render(
+                            &Message::new("status-cooldown")
+                                .arg("seconds", cooldown.as_secs_f32().ceil()),
+                        ),
+                        self.theme.border,
+                    ));
+                }
+                if self.peer_away {
+                    spans.push(separator());
+                    spans.push(Span::styled(
+                        self.catalog.tr("status-peer-away"),
+                        self.theme.warning,
+                    ));
+                }
+                spans.push(separator());
+                spans.push(Span::raw(self.catalog.render(
+                    &Message::new("status-sentences").arg("count", session.turns().len()),
+                )));
+                let now = Utc::now();
+                spans.push(separator());
+                spans.push(Span::raw(
+                    self.catalog.render(
+                        &Message::new("status-elapsed")
+                            .arg("duration", format_duration(now - session.started_at)),
+                    ),
+                ));
+                if let Some(turn) = session.turns().last() {
+                    spans.push(separator());
+                    spans.push(Span::raw(
+                        self.catalog.render(
+                            &Message::new("status-last-turn")
+                                .arg("when", format_relative(turn.timestamp, now)),
+                        ),
+                    ));
+                }
+            }
+            Replay(session) => spans
+                .push(Span::raw(self.catalog.render(
+                    &Message::new("status-replaying").arg("id", &session.id),
+                ))),
+            Waiting => spans.push(Span::raw(self.catalog.tr("status-waiting"))),
+            Connecting(address) => spans
+                .push(Span::raw(self.catalog.render(
+                    &Message::new("status-connecting").arg("address", address),
+                ))),
+        }
+        if self.away {
+            spans.push(separator());
+            spans.push(Span::styled(
+                self.catalog.tr("status-away"),
+                self.theme.warning,
+            ));
+        }
+        spans.push(separator());
+        spans.push(Span::raw(Local::now().format("%H:%M").to_string()));
+        Spans::from(spans)
+    }
+
+    /// A bordered block for `element`, drawn with `border_type` unless it
+    /// has focus.
+    fn focus_block(
+        &self,
+        element: Element,
+        border_type: BorderType,
+        title: impl Into<String>,
+    ) -> Block<'static> {
+        let look = focus_look(&self.theme, element, self.selected_element);
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(look.border_type.unwrap_or(border_type))
+            .style(look.style)
+            .title(format!("{}{}", look.marker, title.into()))
+    }
+
+    /// The text field that has focus, if any.
+    fn focused_buffer(&mut self) -> Option<&mut EditBuffer> {
+        match self.selected_element {
+            Element::Input => Some(&mut self.input_buffer),
+            Element::Connect => Some(&mut self.address_buffer),
+            Element::Scratchpad => Some(&mut self.scratchpad),
+            Element::Archive | Element::RecentPeers | Element::Log => None,
+        }
+    }
+
+    /// The elements focus cycles through, skipping those that can't be used
+    /// in the current state.
+    fn focus_order(&self) -> Vec<Element> {
+        let mut order = vec![];
+        if self.archive.is_some() {
+            order.push(Element::Archive);
+        }
+        match self.app_state {
+            InSession { .. } => order.push(Element::Input),
+            Replay(_) | Waiting | Connecting(_) => order.push(Element::Connect),
+        }
+        if self.show_scratchpad && !self.zen {
+            order.push(Element::Scratchpad);
+        }
+        if self.showing_recent_peers() {
+            order.push(Element::RecentPeers);
+        }
+        if !self.zen && !self.log_collapsed {
+            order.push(Element::Log);
+        }
+        order
+    }
+
+    /// Moves focus to the main text field when the focused element stopped
+    /// being usable.
+    fn refocus(&mut self) {
+        if !self.focus_order().contains(&self.selected_element) {
+            self.selected_element = match self.app_state {
+                InSession { .. } => Element::Input,
+                Replay(_) | Waiting | Connecting(_) => Element::Connect,
+            };
+        }
+    }
+
+    fn cycle_focus(&mut self, forward: bool) {
+        let order = self.focus_order();
+        let position = order
+            .iter()
+            .position(|element| *element == self.selected_element)
+            .unwrap_or(0);
+        let next = if forward {
+            (position + 1) % order.len()
+        } else {
+            (position + order.len() - 1) % order.len()
+        };
+        self.selected_element = order[next];
+    }
+
+    /// The story being written or replayed.
+    fn story(&self) -> Option<&SessionInstance> {
+        match &self.app_state {
+            InSession { session, .. } | Replay(session) => Some(session),
+            Waiting | Connecting(_) => None,
+        }
+    }
+
+    /// The story's lines as shown in the Content pane.
+    fn content_lines(&self) -> Vec<Spans<'static>> {
+        self.app_state
+            .content_log(
+                self.content_view,
+                self.show_timestamps,
+                &self.theme,
+                &self.catalog,
+            )
+            .map(|text| text.lines)
+            .unwrap_or_default()
+    }
+
+    /// Where the search query appears, as line indexes and byte ranges.
+    fn search_matches(&self, lines: &[Spans<'static>]) -> Vec<(usize, Range<usize>)> {
+        let query = match &self.search {
+            Some(search) => search.query.text(),
+            None => return vec![],
+        };
+        lines
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let text: String = line.0.iter().map(|span| span.content.as_ref()).collect();
+                search::find(&text, &query)
+                    .into_iter()
+                    .map(move |range| (index, range))
+            })
+            .collect()
+    }
+
+    /// Lets an open search take the keys it uses, returning whether the key
+    /// was used.
+    fn handle_search_event(&mut self, key: KeyEvent) -> bool {
+        let count = self.search_matches(&self.content_lines()).len();
+        let action = self.keymap.action(&key);
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return false,
+        };
+        if action == Some(Action::Quit) {
+            self.search = None;
+            return true;
+        }
+        if search.editing {
+            match key.code {
+                KeyCode::Enter => {
+                    search.editing = false;
+                    search.current = Some(0);
+                }
+                KeyCode::Backspace => {
+                    search.query.backspace();
+                }
+                KeyCode::Left => search.query.left(),
+                KeyCode::Right => search.query.right(),
+                KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    search.query.insert(c)
+                }
+                _ => return false,
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('n') if count > 0 => {
+                search.current = search.current.map(|current| (current + 1) % count);
+            }
+            KeyCode::Char('N') if count > 0 => {
+                search.current = search.current.map(|current| (current + count - 1) % count);
+            }
+            KeyCode::Char('/') => search.editing = true,
+            _ if action == Some(Action::Search) => search.editing = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Lets an open "go to turn" prompt take the keys it uses, returning
+    /// whether the key was used. Once at the turn, only closing it is left.
+    fn handle_go_to_event(&mut self, key: KeyEvent) -> bool {
+        let count = self.story().map_or(0, |story| story.turns().len());
+        let action = self.keymap.action(&key);
+        let go_to = match &mut self.go_to {
+            Some(go_to) => go_to,
+            None => return false,
+        };
+        if action == Some(Action::Quit) {
+            self.go_to = None;
+            return true;
+        }
+        if go_to.turn.is_some() {
+            return false;
+        }
+        match key.code {
+            KeyCode::Enter => go_to.jump(count),
+            KeyCode::Backspace => {
+                go_to.number.backspace();
+                go_to.out_of_range = false;
+            }
+            KeyCode::Char(c) if c.is_ascii_digit() => {
+                go_to.number.insert(c);
+                go_to.out_of_range = false;
+            }
+            // Anything else typed would otherwise end up in the Input
+            KeyCode::Char(_) if key.modifiers.is_empty() => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Lets the open command palette take every key, returning whether to
+    /// quit, or `None` if it isn't open. A number goes to that turn, as
+    /// `:12` does in vi.
+    async fn handle_palette_event(&mut self, key: KeyEvent) -> Result<Option<bool>, Error> {
+        let action = self.keymap.action(&key);
+        let palette = match &mut self.palette {
+            Some(palette) => palette,
+            None => return Ok(None),
+        };
+        let query = palette.query.text();
+        let matches = palette_matches(&query);
+        match key.code {
+            _ if action == Some(Action::Quit) => self.palette = None,
+            KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+            KeyCode::Down => {
+                palette.selected = (palette.selected + 1).min(matches.len().saturating_sub(1));
+            }
+            KeyCode::Backspace => {
+                palette.query.backspace();
+                palette.selected = 0;
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                palette.query.insert(c);
+                palette.selected = 0;
+            }
+            KeyCode::Enter => {
+                let selected = palette.selected;
+                if let (Ok(number), Some(story)) = (query.trim().parse::<usize>(), self.story()) {
+                    let mut go_to = GoTo::default();
+                    go_to.number.set(&number.to_string());
+                    go_to.jump(story.turns().len());
+                    self.search = None;
+                    self.go_to = Some(go_to);
+                    self.palette = None;
+                    return Ok(Some(false));
+                }
+                if let Some(&chosen) = matches.get(selected) {
+                    match self.unavailable(chosen) {
+                        Some(reason) => self.log_buffer.warn(
+                            self.catalog.render(
+                                &Message::new("palette-unavailable")
+                                    .arg("action", chosen.name())
+                                    .arg_message("reason", reason),
+                            ),
+                        ),
+                        None => {
+                            self.palette = None;
+                            return self.perform(chosen).await.map(Some);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(Some(false))
+    }
+
+    /// Why `action` can't be done now, for the command palette to refuse it
+    /// with, or `None` if it can.
+    fn unavailable(&self, action: Action) -> Option<Message> {
+        let reason = match action {
+            Action::Export
+            | Action::NewStory
+            | Action::ClearStory
+            | Action::VerifyStory
+            | Action::Spell
+            | Action::BlockPeer
+            | Action::Disconnect
+            | Action::Reroll
+                if !matches!(self.app_state, InSession { .. }) =>
+            {
+                "unavailable-not-connected"
+            }
+            Action::Import | Action::Archive if !matches!(self.app_state, Replay(_) | Waiting) => {
+                "unavailable-connected"
+            }
+            Action::Search
+            | Action::GoToTurn
+            | Action::CopyStory
+            | Action::CopySentence
+            | Action::Stats
+                if self.story().is_none() =>
+            {
+                "unavailable-no-story"
+            }
+            Action::GrowContent
+            | Action::ShrinkContent
+            | Action::CollapseLog
+            | Action::Scratchpad
+            | Action::Layout
+                if self.zen =>
+            {
+                "unavailable-zen"
+            }
+            Action::NextAddress if self.endpoints.len() < 2 => "unavailable-one-address",
+            Action::CopyAddress if self.listening.is_none() => "unavailable-not-listening",
+            _ => return None,
+        };
+        Some(Message::new(reason))
+    }
+
+    /// Does what `action` does, whether its key was pressed or it was
+    /// picked from the command palette, returning whether to quit. The
+    /// editing actions are left to the keys, as they need a text field.
+    async fn perform(&mut self, action: Action) -> Result<bool, Error> {
+        match action {
+            Action::Palette => self.palette = Some(Palette::default()),
+            Action::ContentView => self.content_view = self.content_view.next(),
+            Action::Search => self.open_search(),
+            Action::GoToTurn => self.open_go_to(),
+            Action::Away => self.set_away(!self.away).await?,
+            Action::Export => self.prompt = Some(Prompt::Export),
+            Action::NewStory => self.app_handle.end_story().await?,
+            Action::ClearStory => self.app_handle.propose_clear().await?,
+            Action::VerifyStory => self.app_handle.verify_story().await?,
+            Action::Spell => self.respell(),
+            Action::BlockPeer => self.prompt = Some(Prompt::BlockPeer),
+            Action::Disconnect => self.app_handle.disconnect().await?,
+            Action::Reroll => {
+                self.next_constraint = self.constraints.as_ref().map(ConstraintPool::roll);
+            }
+            Action::Import => self.import_path = Some(EditBuffer::default()),
+            Action::Archive => {
+                if self.archive.is_some() {
+                    self.close_archive();
+                } else {
+                    self.app_handle.list_saved().await?;
+                }
+            }
+            action => return Ok(self.handle_independent_action(action).unwrap_or(false)),
+        }
+        Ok(false)
+    }
+
+    fn open_search(&mut self) {
+        self.go_to = None;
+        self.search = Some(Search {
+            query: EditBuffer::default(),
+            editing: true,
+            current: None,
+        });
+    }
+
+    fn open_go_to(&mut self) {
+        self.search = None;
+        self.go_to = Some(GoTo::default());
+    }
+
+    /// Lets the import box take every key while it's open, returning whether
+    /// it was.
+    async fn handle_import_event(&mut self, key: KeyEvent) -> Result<bool, Error> {
+        let action = self.keymap.action(&key);
+        let path = match &mut self.import_path {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        match key.code {
+            _ if action == Some(Action::Quit) => self.import_path = None,
+            KeyCode::Enter => {
+                let path = path.text();
+                self.import_path = None;
+                match import::load(Path::new(path.trim()), self.import_first) {
+                    Ok(session) => {
+                        self.log_buffer.info(
+                            self.catalog.render(
+                                &Message::new("imported").arg("count", session.turns().len()),
+                            ),
+                        );
+                        self.close_archive();
+                        self.app_state = Replay(session.clone());
+                        self.app_handle.resume(session).await?;
+                    }
+                    Err(err) => self.log_buffer.error(
+                        self.catalog
+                            .render(&Message::new("import-failed").arg("error", err)),
+                    ),
+                }
+            }
+            KeyCode::Backspace => {
+                path.backspace();
+            }
+            KeyCode::Left => path.left(),
+            KeyCode::Right => path.right(),
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => path.insert(c),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// Whether the story has sentences that were never exported.
+    fn has_unexported_story(&self) -> bool {
+        match &self.app_state {
+            InSession { session, .. } => session.turns().len() > self.exported_turns,
+            Replay(_) | Waiting | Connecting(_) => false,
+        }
+    }
+
+    /// Exports the story being written, returning whether that worked.
+    fn export_story(&mut self) -> bool {
+        if let InSession { session, .. } = &self.app_state {
+            let path = export::file_name(session, Format::Markdown);
+            let mut session = self.appendices.apply(session);
+            if self.appendices.notes {
+                session.set_notes(self.scratchpad.text());
+            }
+            match std::fs::write(&path, export::to_markdown(&session)) {
+                Ok(()) => return true,
+                Err(err) => {
+                    warn!(%err, %path, "couldn't export story");
+                    self.log_buffer.error(
+                        self.catalog.render(
+                            &Message::new("export-failed")
+                                .arg("path", &path)
+                                .arg("error", err),
+                        ),
+                    )
+                }
+            }
+        }
+        false
+    }
+
+    /// Quits without asking, for when the user or the system wants the
+    /// process gone now. The story is exported first so it isn't lost.
+    fn interrupt(&mut self) {
+        if self.has_unexported_story() {
+            self.export_story();
+        }
+    }
+
+    /// Answers the open prompt, returning whether to quit.
+    async fn handle_prompt_event(&mut self, prompt: Prompt, key: KeyEvent) -> Result<bool, Error> {
+        match (prompt, key.code) {
+            (Prompt::Quit, KeyCode::Char('y')) => return Ok(true),
+            (Prompt::Quit, KeyCode::Char('s')) => {
+                if self.export_story() {
+                    return Ok(true);
+                }
+            }
+            (Prompt::Quit, KeyCode::Char('n')) => {}
+            (Prompt::Rematch, KeyCode::Char(c @ ('y' | 'n'))) => {
+                self.prompt = None;
+                if c == 'y' {
+                    self.log_buffer.info(self.catalog.tr("waiting-for-rematch"));
+                }
+                self.app_handle.rematch(c == 'y').await?;
+                return Ok(false);
+            }
+            // Needs an answer, and counts as a no if none comes in time
+            (Prompt::Rematch, _) => return Ok(false),
+            (Prompt::Clear, KeyCode::Char(c @ ('y' | 'n'))) => {
+                self.prompt = None;
+                if c == 'n' {
+                    self.log_buffer.info(self.catalog.tr("declined-clear"));
+                }
+                self.app_handle.answer_clear(c == 'y').await?;
+                return Ok(false);
+            }
+            // The peer is waiting on an answer
+            (Prompt::Clear, _) => return Ok(false),
+            (Prompt::Resync, KeyCode::Char(c @ ('y' | 'n'))) => {
+                self.prompt = None;
+                if c == 'n' {
+                    self.log_buffer.warn(self.catalog.tr("declined-resync"));
+                }
+                self.app_handle.answer_resync(c == 'y').await?;
+                return Ok(false);
+            }
+            (Prompt::Resync, _) => return Ok(false),
+            (Prompt::BlockPeer, KeyCode::Char('y')) => {
+                self.prompt = None;
+                self.app_handle.block_peer().await?;
+                return Ok(false);
+            }
+            (Prompt::BlockPeer, KeyCode::Char('n')) => {}
+            (Prompt::Export, KeyCode::Char(c @ ('m' | 'h' | 'e'))) => {
+                self.prompt = None;
+                if let InSession { session, .. } = &self.app_state {
+                    self.exported_turns = session.turns().len();
+                    let format = match c {
+                        'm' => Format::Markdown,
+                        'h' => Format::Html,
+                        _ => Format::Epub,
+                    };
+                    self.app_handle.export(format).await?;
+                }
+                return Ok(false);
+            }
+            _ if self.keymap.action(&key) == Some(Action::Quit) => {}
+            _ => return Ok(false),
+        }
+        self.prompt = None;
+        Ok(false)
+    }
+
+    // Check for input that is independent of state
+    fn handle_independent_action(&mut self, action: Action) -> Option<bool> {
+        match action {
+            Action::Quit if self.has_unexported_story() => {
+                self.prompt = Some(Prompt::Quit);
+                Some(false)
+            }
+            Action::Quit => Some(true),
+            Action::NextTheme => {
+                self.theme = self.theme.next();
+                self.log_buffer.info(
+                    self.catalog
+                        .render(&Message::new("theme").arg("name", self.theme.name)),
+                );
+                Some(false)
+            }
+            Action::LogLevel => {
+                let level = self.log_buffer.cycle_min_level();
+                self.log_buffer.info(
+                    self.catalog
+                        .render(&Message::new("log-level").arg("level", level)),
+                );
+                Some(false)
+            }
+            Action::Notify => {
+                self.alerts.notify = self.alerts.notify.next();
+                self.log_buffer.info(
+                    self.catalog
+                        .render(&Message::new("notify").arg("setting", self.alerts.notify)),
+                );
+                Some(false)
+            }
+            action @ (Action::CopyStory | Action::CopySentence) => {
+                let text = match (action, self.story()) {
+                    (Action::CopyStory, Some(session)) => Some(session.content()),
+                    (_, Some(session)) => session.turns().last().map(|turn| turn.text.clone()),
+                    (_, None) => None,
+                };
+                if let Some(text) = text {
+                    clipboard::copy(&text);
+                    self.log_buffer.info(
+                        self.catalog
+                            .render(&Message::new("copied").arg("count", Counts::of(&text).chars)),
+                    );
+                }
+                Some(false)
+            }
+            Action::GrowContent if !self.zen => {
+                self.content_percent = (self.content_percent + 5).min(MAX_CONTENT_PERCENT);
+                Some(false)
+            }
+            Action::ShrinkContent if !self.zen => {
+                self.content_percent = (self.content_percent - 5).max(MIN_CONTENT_PERCENT);
+                Some(false)
+            }
+            Action::Preview => {
+                self.show_preview = !self.show_preview;
+                Some(false)
+            }
+            Action::Metrics => {
+                let now = Instant::now();
+                self.metrics_overlay = match self.metrics_overlay {
+                    Some(_) => None,
+                    None => Some(MetricsOverlay {
+                        ui: Rate::new(self.metrics.handled_count(), now),
+                        app: Rate::new(self.app_handle.metrics().handled_count(), now),
+                        draws: Rate::new(self.draws, now),
+                    }),
+                };
+                Some(false)
+            }
+            Action::Stats => {
+                self.stats = self.story().map(|session| {
+                    Stats::new(session.turns().iter().map(|turn| turn.text.as_str()))
+                });
+                Some(false)
+            }
+            Action::Zen => {
+                self.zen = !self.zen;
+                self.refocus();
+                Some(false)
+            }
+            Action::CollapseLog if !self.zen => {
+                self.log_collapsed = !self.log_collapsed;
+                self.log_buffer.scroll_to_newest();
+                self.refocus();
+                Some(false)
+            }
+            Action::Scratchpad if !self.zen => {
+                self.show_scratchpad = !self.show_scratchpad;
+                if self.show_scratchpad {
+                    self.selected_element = Element::Scratchpad;
+                } else {
+                    self.refocus();
+                }
+                Some(false)
+            }
+            Action::Layout if !self.zen => {
+                self.layout = self.layout.next();
+                self.content_percent = self.layout.content_percent();
+                self.log_buffer.info(self.catalog.render(
+                    &Message::new("layout").arg_message("name", Message::new(self.layout.name())),
+                ));
+                Some(false)
+            }
+            Action::NextAddress if self.endpoints.len() > 1 => {
+                let index = self
+                    .listening
+                    .and_then(|address| self.endpoints.iter().position(|a| *a == address))
+                    .map_or(0, |index| (index + 1) % self.endpoints.len());
+                self.highlight_endpoint(self.endpoints[index]);
+                Some(false)
+            }
+            Action::CopyAddress => {
+                if let Some(address) = self.listening {
+                    clipboard::copy(&address.to_string());
+                    self.log_buffer.info(
+                        self.catalog
+                            .render(&Message::new("copied-address").arg("address", address)),
+                    );
+                }
+                Some(false)
+            }
+            Action::ToggleTimestamps => {
+                self.show_timestamps = !self.show_timestamps;
+                Some(false)
+            }
+            Action::ScrollUp if self.selected_element == Element::Log => {
+                self.log_buffer.scroll_up();
+                Some(false)
+            }
+            Action::ScrollDown if self.selected_element == Element::Log => {
+                self.log_buffer.scroll_down();
+                Some(false)
+            }
+            Action::NextFocus => {
+                self.cycle_focus(true);
+                Some(false)
+            }
+            Action::PreviousFocus => {
+                self.cycle_focus(false);
+                Some(false)
+            }
+            action @ (Action::Backspace
+            | Action::Delete
+            | Action::Left
+            | Action::Right
+            | Action::Home
+            | Action::End
+            | Action::WordLeft
+            | Action::WordRight
+            | Action::DeleteWord
+            | Action::DeleteToStart
+            | Action::DeleteToEnd) => {
+                match self.selected_element {
+                    Element::Input => self.input_edited(),
+                    Element::Scratchpad => self.notes_edited = true,
+                    _ => {}
+                }
+                if let Some(buffer) = self.focused_buffer() {
+                    match action {
+                        Action::Backspace => {
+                            buffer.backspace();
+                        }
+                        Action::Delete => {
+                            buffer.delete();
+                        }
+                        Action::Left => buffer.left(),
+                        Action::Right => buffer.right(),
+                        Action::Home => buffer.home(),
+                        Action::End => buffer.end(),
+                        Action::WordLeft => buffer.word_left(),
+                        Action::WordRight => buffer.word_right(),
+                        Action::DeleteWord => buffer.delete_word(),
+                        Action::DeleteToStart => buffer.delete_to_start(),
+                        _ => buffer.delete_to_end(),
+                    }
+                }
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+
+    /// Takes back a queued sentence, and stops recalling the history, once
+    /// the Input is changed by hand.
+    fn input_edited(&mut self) {
+        self.queued = false;
+        self.sentence_index = None;
+        self.sentence_query = None;
+    }
+
+    /// Shows the sentence at `index` in the history in the Input, if there
+    /// is one.
+    fn recall_sentence(&mut self, index: usize) {
+        if let Some(sentence) = self.sentences.get(index) {
+            // Without the full stop that submitted it, so it can submit again
+            self.input_buffer.set(sentence.trim_end_matches('.'));
+            self.sentence_index = Some(index);
+            self.queued = false;
+        }
+    }
+
+    /// Sends the sentence in the Input on our turn, unless it breaks the
+    /// constraint it was given. Returns whether it was sent.
+    async fn send_input(&mut self) -> Result<bool, Error> {
+        let sentence = self.input_buffer.text();
+        // Kept even if it doesn't make it, so it needn't be typed again
+        if let Err(err) = self.sentences.record(&sentence) {
+            self.log_buffer.warn(
+                self.catalog
+                    .render(&Message::new("save-sentence-history-failed").arg("error", err)),
+            );
+        }
+        let (is_our_turn, session) = match &mut self.app_state {
+            InSession {
+                is_our_turn: is_our_turn @ true,
+                session,
+            } => (is_our_turn, session),
+            _ => return Ok(false),
+        };
+        if let Some(range) = self
+            .word_filter
+            .as_ref()
+            .and_then(|filter| filter.find(&sentence).into_iter().next())
+        {
+            self.log_buffer.warn(
+                self.catalog
+                    .render(&Message::new("on-word-list").arg("word", &sentence[range])),
+            );
+            return Ok(false);
+        }
+        if let Some(Err(reason)) = session
+            .current_constraint()
+            .map(|constraint| constraint.check(&sentence))
+        {
+            self.log_buffer.warn(self.catalog.render(&reason));
+            return Ok(false);
+        }
+        self.app_handle
+            .send_sentence(sentence, self.next_constraint.take())
+            .await?;
+        *is_our_turn = false;
+        self.input_buffer.clear();
+        self.input_edited();
+        self.next_constraint = self.constraints.as_ref().map(ConstraintPool::roll);
+        Ok(true)
+    }
+
+    /// Sends the queued sentence once it's our turn. One that breaks the
+    /// constraint stays in the Input to be fixed.
+    async fn send_queued(&mut self) -> Result<(), Error> {
+        let our_turn = matches!(
+            self.app_state,
+            InSession {
+                is_our_turn: true,
+                ..
+            }
+        );
+        if our_turn && std::mem::take(&mut self.queued) {
+            self.send_input().await?;
+        }
         Ok(())
     }
+
+    /// Handles `first` along with whatever other terminal events are already
+    /// waiting, up to [`INPUT_BATCH`], so that the screen is drawn once for
+    /// the lot rather than falling behind a held key. Repeats of a key that
+    /// ends up where one would, such as Home, count once, as does a run of
+    /// resizes. crossterm only reports key presses, on Windows too, so there
+    /// are no releases or repeats to tell apart here. Returns whether to
+    /// quit, leaving anything after a quit or Ctrl+Z for later.
+    async fn handle_input_events<S>(&mut self, first: Event, events: &mut S) -> bool
+    where
+        S: Stream<Item = crossterm::Result<Event>> + Unpin,
+    {
+        let mut next = Some(Ok(first));
+        let mut previous = None;
+        for _ in 0..INPUT_BATCH {
+            match next
+                .take()
+                .or_else(|| events.next().now_or_never().flatten())
+            {
+                Some(Ok(event)) => {
+                    if previous.is_none_or(|previous| !self.repeats(previous, event)) {
+                        if self.handle_input_event(event).await.unwrap_or(false) {
+                            return true;
+                        }
+                        if self.suspending {
+                            break;
+                        }
+                    }
+                    previous = Some(event);
+                }
+                Some(Err(_)) => {}
+                // Nothing more yet, or the terminal has gone away
+                None => break,
+            }
+        }
+        false
+    }
+
+    /// Whether `event` straight after `previous` would leave things as they
+    /// are.
+    fn repeats(&self, previous: Event, event: Event) -> bool {
+        match (previous, event) {
+            (Event::Resize(..), Event::Resize(..)) => true,
+            (Event::Key(previous), Event::Key(key)) => {
+                previous == key
+                    && matches!(self.keymap.action(&key), Some(Action::Home | Action::End))
+            }
+            _ => false,
+        }
+    }
+
+    async fn handle_input_event(&mut self, event: Event) -> Result<bool, Error> {
+        trace!(?event, "terminal event");
+        let key = match event {
+            Event::Key(key) => {
+                self.dirty = true;
+                self.last_key = Instant::now();
+                // Any key dismisses toasts, and still does what it normally does
+                self.toasts.clear();
+                // Raw mode delivers Ctrl+C as a key rather than a signal
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    self.interrupt();
+                    return Ok(true);
+                }
+                if cfg!(unix)
+                    && key.code == KeyCode::Char('z')
+                    && key.modifiers.contains(KeyModifiers::CONTROL)
+                {
+                    self.suspending = true;
+                    return Ok(false);
+                }
+                key
+            }
+            Event::Resize(..) => {
+                self.resized = true;
+                self.dirty = true;
+                return Ok(false);
+            }
+            Event::Mouse(_) => return Ok(false),
+        };
+        // Any key brings us back, and the away key only does that
+        let away_key = self.keymap.action(&key) == Some(Action::Away);
+        if self.away || away_key {
+            self.set_away(!self.away).await?;
+            if away_key {
+                return Ok(false);
+            }
+        }
+        if let Some(prompt) = self.prompt {
+            return self.handle_prompt_event(prompt, key).await;
+        }
+        // Any key closes the statistics
+        if self.stats.take().is_some() {
+            return Ok(false);
+        }
+        if let Some(quit) = self.handle_palette_event(key).await? {
+            return Ok(quit);
+        }
+        if self.handle_search_event(key) || self.handle_go_to_event(key) {
+            return Ok(false);
+        }
+        if self.handle_import_event(key).await? {
+            return Ok(false);
+        }
+        let action = self.keymap.action(&key);
+        // Plain keys only work where they can't be typed into a text field
+        let plain = |c| key.code == KeyCode::Char(c) && self.selected_element == Element::Log;
+        if action == Some(Action::ContentView) || plain('v') {
+            return self.perform(Action::ContentView).await;
+        }
+        if action == Some(Action::Palette) || plain(':') {
+            return self.perform(Action::Palette).await;
+        }
+        let opens_search = action == Some(Action::Search) || plain('/');
+        if opens_search && self.story().is_some() {
+            return self.perform(Action::Search).await;
+        }
+        if action == Some(Action::GoToTurn) && self.story().is_some() {
+            return self.perform(Action::GoToTurn).await;
+        }
+        if self.handle_suggestion_event(action) {
+            return Ok(false);
+        }
+
+        // Quitting backs out of the archive browser, replays and queued
+        // sentences first
+        if action == Some(Action::Quit) {
+            if std::mem::take(&mut self.queued) {
+                return Ok(false);
+            }
+            if self.archive.is_some() {
+                self.close_archive();
+                return Ok(false);
+            }
+            if let Replay(_) = self.app_state {
+                self.app_state = Waiting;
+                return Ok(false);
+            }
+        }
+        if let Some(quit) = action.and_then(|action| self.handle_independent_action(action)) {
+            return Ok(quit);
+        }
+        let typed = match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => Some(c),
+            _ => None,
+        };
+        // Notes take every character, the full stop that submits included
+        if self.selected_element == Element::Scratchpad
+            && !key.modifiers.contains(KeyModifiers::ALT)
+        {
+            if let Some(c) = typed {
+                self.scratchpad.insert(c);
+                self.notes_edited = true;
+                return Ok(false);
+            }
+        }
+
+        match &mut self.app_state {
+            InSession { is_our_turn, .. } => match action {
+                Some(
+                    action @ (Action::Export
+                    | Action::NewStory
+                    | Action::ClearStory
+                    | Action::VerifyStory
+                    | Action::BlockPeer
+                    | Action::Disconnect
+                    | Action::Reroll),
+                ) => {
+                    self.perform(action).await?;
+                }
+                Some(Action::Spell) if self.selected_element == Element::Input => self.respell(),
+                // Like a shell, only from an empty Input or while already recalling
+                Some(Action::HistoryPrevious)
+                    if self.selected_element == Element::Input
+                        && (self.input_buffer.len() == 0 || self.sentence_index.is_some()) =>
+                {
+                    let index = self.sentence_index.map_or(0, |index| index + 1);
+                    self.recall_sentence(index);
+                }
+                Some(Action::HistoryNext)
+                    if self.selected_element == Element::Input && self.sentence_index.is_some() =>
+                {
+                    match self.sentence_index {
+                        Some(0) | None => {
+                            self.input_edited();
+                            self.input_buffer.clear();
+                        }
+                        Some(index) => self.recall_sentence(index - 1),
+                    }
+                }
+                Some(Action::HistorySearch) if self.selected_element == Element::Input => {
+                    let query = match &self.sentence_query {
+                        Some(query) => query.clone(),
+                        None => self.input_buffer.text(),
+                    };
+                    let from = self.sentence_index.map_or(0, |index| index + 1);
+                    match self.sentences.find(&query, from) {
+                        Some(index) => {
+                            self.recall_sentence(index);
+                            self.sentence_query = Some(query);
+                        }
+                        None => self.log_buffer.info(
+                            self.catalog.render(
+                                &Message::new(if from > 0 {
+                                    "no-older-sentence-contains"
+                                } else {
+                                    "no-sentence-contains"
+                                })
+                                .arg("query", query),
+                            ),
+                        ),
+                    }
+                }
+                Some(Action::Submit) if self.selected_element == Element::Input => {
+                    let our_turn = *is_our_turn;
+                    // A submit key that types a character, like the default full stop,
+                    // only submits when typed at the end of the sentence
+                    let at_end = self.input_buffer.cursor() == self.input_buffer.len();
+                    if let Some(c) = typed {
+                        self.input_buffer.insert(c);
+                        self.input_edited();
+                        if !at_end {
+                            return Ok(false);
+                        }
+                    }
+                    if self.input_buffer.text().trim().is_empty() {
+                        return Ok(false);
+                    }
+                    if !our_turn {
+                        self.queued = true;
+                        return Ok(false);
+                    }
+                    if !self.send_input().await? && typed.is_some() {
+                        self.input_buffer.backspace();
+                    }
+                }
+                _ => {
+                    if let (Some(c), Element::Input) = (typed, self.selected_element) {
+                        self.input_buffer.insert(c);
+                        self.input_edited();
+                        self.suggestion = 0;
+                    }
+                }
+            },
+            // Nothing to do but wait, and not connect twice
+            Connecting(_) => {}
+            Replay(_) | Waiting => match action {
+                Some(action @ (Action::Import | Action::Archive)) => {
+                    self.perform(action).await?;
+                }
+                _ if self.selected_element == Element::Archive => {
+                    self.handle_archive_event(key.code).await?;
+                }
+                _ if self.selected_element == Element::RecentPeers => {
+                    self.handle_recent_peers_event(key.code, action).await?;
+                }
+                Some(Action::Connect) if self.selected_element == Element::Connect => {
+                    let text = self.address_buffer.text();
+                    match validate_address(&text) {
+                        Ok(address) => {
+                            if let Ok(Invite {
+                                hint: Some(hint), ..
+                            }) = Invite::decode(&text)
+                            {
+                                self.log_buffer.info(
+                                    self.catalog
+                                        .render(&Message::new("invite-hint").arg("hint", hint)),
+                                );
+                            }
+                            self.pending_address = Some(address);
+                            self.app_handle.connect(address).await?
+                        }
+                        Err(reason) => {
+                            self.log_buffer.error(self.catalog.render(
+                                &Message::new("cant-connect").arg_message("reason", reason),
+                            ))
+                        }
+                    }
+                }
+                Some(Action::HistoryPrevious) if self.selected_element == Element::Connect => {
+                    let index = self.history_index.map_or(0, |index| index + 1);
+                    if let Some(address) = self.history.get(index) {
+                        self.history_index = Some(index);
+                        self.address_buffer.set(&address.to_string());
+                    }
+                }
+                Some(Action::HistoryNext) if self.selected_element == Element::Connect => {
+                    match self.history_index {
+                        Some(0) | None => {
+                            self.history_index = None;
+                            self.address_buffer.clear();
+                        }
+                        Some(index) => {
+                            self.history_index = Some(index - 1);
+                            if let Some(address) = self.history.get(index - 1) {
+                                self.address_buffer.set(&address.to_string());
+                            }
+                        }
+                    }
+                }
+                Some(Action::ForgetAddress) if self.selected_element == Element::Connect => {
+                    if let Some(index) = self.history_index.take() {
+                        if let Err(err) = self.history.remove(index) {
+                            self.log_buffer.warn(self.catalog.render(
+                                &Message::new("save-address-history-failed").arg("error", err),
+                            ));
+                        }
+                        self.address_buffer.clear();
+                    }
+                }
+                _ => {
+                    if let Some(c) = typed.filter(|_| self.selected_element == Element::Connect) {
+                        self.history_index = None;
+                        self.connect_error = None;
+                        self.address_buffer.insert(c)
+                    }
+                }
+            },
+        }
+
+        Ok(false)
+    }
+
+    /// Draws the screen if anything on it changed since it was last drawn.
+    /// Draws if anything changed, at most once a tick so that a burst of
+    /// key presses is drawn once. What changes after that is drawn on the
+    /// next tick.
+    fn redraw<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), Error> {
+        if self.dirty && !self.drawn_this_tick {
+            self.drawn_this_tick = true;
+            self.draw(terminal)?;
+        }
+        Ok(())
+    }
+
+    fn draw<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), Error> {
+        self.dirty = false;
+        self.check_spelling();
+        self.draws += 1;
+        self.drawn_status = status_text(&self.status_bar());
+        if self.resized {
+            self.resized = false;
+            terminal.autoresize()?;
+            terminal.clear()?;
+        }
+        let flashing = self.flash_until.is_some();
+        terminal.draw(|frame| {
+            self.draw_view(frame);
+            self.draw_toasts(frame);
+            if let Some(prompt) = self.prompt {
+                self.draw_prompt(frame, prompt);
+            }
+            if let Some(path) = &self.import_path {
+                self.draw_import(frame, path);
+            }
+            if let Some(palette) = &self.palette {
+                self.draw_palette(frame, palette);
+            }
+            if let Some(stats) = &self.stats {
+                self.draw_stats(frame, stats);
+            }
+            if let Some(overlay) = &self.metrics_overlay {
+                self.draw_metrics(frame, overlay);
+            }
+            if flashing {
+                frame.render_widget(Invert, frame.size());
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Draws the archive browser, the story, or what to do first.
+    fn draw_content<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        match &self.archive {
+            Some(archive) => {
+                let list = List::new(archive.items(&self.catalog))
+                    .block(self.focus_block(
+                        Element::Archive,
+                        BorderType::Rounded,
+                        self.catalog.tr("archive-title"),
+                    ))
+                    .highlight_style(self.theme.selection);
+                let mut state = ListState::default();
+                state.select(Some(archive.selected).filter(|_| !archive.sessions.is_empty()));
+                frame.render_stateful_widget(list, area, &mut state);
+            }
+            None if self.showing_recent_peers() => {
+                let now = Utc::now();
+                let items: Vec<ListItem> = (0..self.recent_peers.len().min(RECENT_PEERS))
+                    .filter_map(|index| self.recent_peers.get(index).map(|peer| (index, peer)))
+                    .map(|(index, peer)| {
+                        let name = match &peer.nickname {
+                            Some(nickname) => format!("{} — {}", nickname, peer.address),
+                            None => peer.address.to_string(),
+                        };
+                        ListItem::new(
+                            self.catalog.render(
+                                &Message::new(if peer.stories == 1 {
+                                    "recent-peer-one-story"
+                                } else {
+                                    "recent-peer"
+                                })
+                                .arg("number", index + 1)
+                                .arg("name", name)
+                                .arg("stories", peer.stories)
+                                .arg("when", format_relative(peer.last_connected, now)),
+                            ),
+                        )
+                    })
+                    .collect();
+                let list =
+                    List::new(items)
+                        .block(self.focus_block(
+                            Element::RecentPeers,
+                            BorderType::Rounded,
+                            self.catalog.render(&Message::new("recent-peers-title").arg(
+                                "key",
+                                self.keymap.key(Action::ForgetAddress).map_or_else(
+                                    || self.catalog.tr("unbound"),
+                                    |key| key.to_string(),
+                                ),
+                            )),
+                        ))
+                        .highlight_style(self.theme.selection);
+                let mut state = ListState::default();
+                state.select(
+                    Some(self.recent_selected)
+                        .filter(|_| self.selected_element == Element::RecentPeers),
+                );
+                frame.render_stateful_widget(list, area, &mut state);
+            }
+            None if matches!(self.app_state, Waiting) && self.address_buffer.len() == 0 => {
+                frame.render_widget(self.onboarding(), area)
+            }
+            None => frame.render_widget(self.content_view(area), area),
+        }
+    }
+
+    /// Only the Content pane, with the text field being typed into as a
+    /// single line under it.
+    fn draw_zen<B: Backend>(&self, frame: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.size());
+        self.draw_content(frame, chunks[0]);
+
+        let buffer = match self.app_state {
+            InSession { .. } => &self.input_buffer,
+            Replay(_) | Waiting | Connecting(_) => &self.address_buffer,
+        };
+        let prompt = "> ";
+        let (text, cursor_x) = buffer.window(chunks[1].width.saturating_sub(3) as usize);
+        frame.render_widget(
+            Paragraph::new(Spans::from(vec![
+                Span::styled(prompt, self.theme.focused),
+                Span::raw(text),
+            ])),
+            chunks[1],
+        );
+        frame.set_cursor(
+            chunks[1].x + (prompt.width() + cursor_x) as u16,
+            chunks[1].y,
+        );
+    }
+
+    /// What to do first, shown while there's nothing else to look at.
+    fn onboarding(&self) -> Paragraph<'static> {
+        let key = |action| {
+            self.keymap
+                .key(action)
+                .map_or_else(|| self.catalog.tr("unbound"), |key| key.to_string())
+        };
+        let (listening, next) = match (self.listening, &self.invite) {
+            (Some(address), Some((invite, _))) => (
+                Message::new("onboarding-listening-invite")
+                    .arg("address", address)
+                    .arg("invite", invite),
+                Message::new("onboarding-share-either"),
+            ),
+            (Some(address), None) => (
+                Message::new("onboarding-listening").arg("address", address),
+                Message::new("onboarding-share-address"),
+            ),
+            (None, _) => (
+                Message::new("onboarding-not-listening"),
+                Message::new("onboarding-type-address"),
+            ),
+        };
+        let mut text = vec![Spans::from(self.catalog.render(&listening))];
+        if self.endpoints.len() > 1 {
+            let others: Vec<_> = self
+                .endpoints
+                .iter()
+                .filter(|address| Some(**address) != self.listening)
+                .map(ToString::to_string)
+                .collect();
+            text.push(Spans::from(
+                self.catalog.render(
+                    &Message::new("onboarding-other-addresses")
+                        .arg("addresses", others.join(", "))
+                        .arg("next", key(Action::NextAddress))
+                        .arg("copy", key(Action::CopyAddress)),
+                ),
+            ));
+        }
+        text.extend(vec![
+            Spans::from(""),
+            Spans::from(
+                self.catalog.render(
+                    &Message::new("onboarding-connect")
+                        .arg_message("next", next)
+                        .arg("focus", key(Action::NextFocus))
+                        .arg("connect", key(Action::Connect)),
+                ),
+            ),
+            Spans::from(""),
+            Spans::from(
+                self.catalog.render(
+                    &Message::new("onboarding-keys")
+                        .arg("archive", key(Action::Archive))
+                        .arg("quit", key(Action::Quit)),
+                ),
+            ),
+        ]);
+        Paragraph::new(text)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(self.theme.border)
+                    .title(self.catalog.tr("onboarding-title")),
+            )
+            .wrap(Wrap { trim: true })
+    }
+
+    /// Where the sentence of `turn` is in the Content pane's `lines`, as a
+    /// line index and byte range.
+    fn turn_range(&self, lines: &[Spans<'static>], turn: usize) -> Option<(usize, Range<usize>)> {
+        let turns = self.story()?.turns();
+        let text = &turns.get(turn)?.text;
+        let (line, on_line) = self
+            .app_state
+            .content_layout(self.content_view, self.show_timestamps)
+            .into_iter()
+            .enumerate()
+            .find_map(|(line, content)| match content {
+                ContentLine::Turns(on_line) if on_line.contains(&turn) => Some((line, on_line)),
+                _ => None,
+            })?;
+        if self.content_view == ContentView::Prose && !self.show_timestamps {
+            // With a space after each sentence
+            let start: usize = turns[on_line.start..turn]
+                .iter()
+                .map(|turn| turn.text.len() + 1)
+                .sum();
+            return Some((line, start..start + text.len()));
+        }
+        let spans = lines.get(line)?;
+        Some((line, 0..spans.0.iter().map(|span| span.content.len()).sum()))
+    }
+
+    /// The story wrapped to fit `area`, with search matches highlighted and
+    /// scrolled to the current one, or to the turn gone to, or else to the
+    /// end.
+    fn content_view(&self, area: Rect) -> Paragraph<'static> {
+        let lines = self.content_lines();
+        let matches = self.search_matches(&lines);
+        let current = self
+            .search
+            .as_ref()
+            .and_then(|search| search.current)
+            .filter(|current| *current < matches.len());
+        let gone_to = self.go_to.as_ref().and_then(|go_to| {
+            let range = self.turn_range(&lines, go_to.turn?)?;
+            Some((range, go_to.highlight_until.is_some()))
+        });
+        // Everything is laid out and scrolled to as if the sentence being
+        // typed out were all there, so that only its untyped end is blank
+        let untyped = self.typed().and_then(|(turn, typed)| {
+            let (line, range) = self.turn_range(&lines, turn)?;
+            let length = self.story()?.turns()[turn].text.len();
+            Some((line, range.end - length + typed..range.end))
+        });
+        let lines: Vec<Spans> = lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let mut ranges: Vec<(Range<usize>, Style)> = matches
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (line, _))| *line == index)
+                    .map(|(number, (_, range))| {
+                        let style = if Some(number) == current {
+                            self.theme
+                                .selection
+                                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+                        } else {
+                            self.theme.selection
+                        };
+                        (range.clone(), style)
+                    })
+                    .collect();
+                if let Some(((line, range), true)) = &gone_to {
+                    if *line == index {
+                        ranges.push((range.clone(), self.theme.selection));
+                    }
+                }
+                search::highlight(line, &ranges)
+            })
+            .collect();
+
+        let height = area.height.saturating_sub(2) as usize;
+        let rows = wrap::wrap(&lines, area.width.saturating_sub(2) as usize);
+        let last_page = rows.len().saturating_sub(height);
+        let target = match current.map(|current| &matches[current]) {
+            Some((line, range)) => Some((*line, range.start)),
+            None => gone_to
+                .as_ref()
+                .map(|((line, range), _)| (*line, range.start)),
+        };
+        let scroll = match target {
+            Some((line, start)) => rows
+                .iter()
+                .rposition(|row| row.source == line && row.start <= start)
+                .unwrap_or(0)
+                .saturating_sub(height / 3)
+                .min(last_page),
+            None => last_page,
+        };
+
+        let mut title = self.catalog.tr("content-title");
+        if self.content_view != ContentView::Prose {
+            title.push_str(&format!(
+                " ({})",
+                self.catalog.render(&self.content_view.message())
+            ));
+        }
+        let search = match &self.search {
+            Some(search) if search.editing => Some(
+                Message::new("search-editing")
+                    .arg("query", search.query.text())
+                    .arg("count", matches.len()),
+            ),
+            Some(search) => Some(match current {
+                Some(current) => Message::new("search-found")
+                    .arg("query", search.query.text())
+                    .arg("current", current + 1)
+                    .arg("count", matches.len()),
+                None => Message::new("search-not-found").arg("query", search.query.text()),
+            }),
+            None => self.go_to.as_ref().map(|go_to| {
+                let count = self.story().map_or(0, |story| story.turns().len());
+                match go_to.turn {
+                    Some(turn) => Message::new("go-to-at")
+                        .arg("turn", turn + 1)
+                        .arg("count", count),
+                    None if go_to.out_of_range => Message::new("go-to-out-of-range")
+                        .arg("number", go_to.number.text())
+                        .arg("count", count),
+                    None => Message::new("go-to-editing")
+                        .arg("number", go_to.number.text())
+                        .arg("count", count),
+                }
+            }),
+        };
+        if let Some(search) = search {
+            title.push_str(&self.catalog.render(&search));
+        }
+        Paragraph::new(
+            rows.into_iter()
+                .skip(scroll)
+                .take(height)
+                .map(|mut row| {
+                    match &untyped {
+                        Some((line, range)) if row.source == *line => wrap::blank(&mut row, range),
+                        _ => {}
+                    }
+                    row.spans
+                })
+                .collect::<Vec<_>>(),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(self.theme.border)
+                .title(title),
+        )
+    }
+
+    /// Stacks the toasts down from the top-right corner, newest at the top.
+    /// Banners take the whole width.
+    fn draw_toasts<B: Backend>(&self, frame: &mut Frame<B>) {
+        let size = frame.size();
+        let mut y = size.y;
+        for toast in self.toasts.iter().rev() {
+            let width = if toast.banner {
+                size.width
+            } else {
+                size.width.min(TOAST_WIDTH)
+            };
+            let inner_width = width.saturating_sub(2).max(1) as usize;
+            let rows = wrap::wrap(&[Spans::from(toast.text.clone())], inner_width).len() as u16;
+            let area = Rect {
+                x: size.right() - width,
+                y,
+                width,
+                height: rows + 2,
+            }
+            .intersection(size);
+            let border = match toast.level {
+                Level::Info => self.theme.focused,
+                level => self.theme.log_level(level),
+            };
+            let popup = Paragraph::new(toast.text.clone())
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .border_style(border),
+                )
+                .alignment(if toast.banner {
+                    Alignment::Center
+                } else {
+                    Alignment::Left
+                })
+                .wrap(Wrap { trim: true });
+            frame.render_widget(Clear, area);
+            frame.render_widget(popup, area);
+            y = area.bottom();
+        }
+    }
+
+    /// Draws the open prompt over the middle of the screen.
+    fn draw_prompt<B: Backend>(&self, frame: &mut Frame<B>, prompt: Prompt) {
+        let text = match (prompt, &self.app_state) {
+            (Prompt::Quit, InSession { session, .. }) => self.catalog.render(
+                &Message::new("prompt-quit-unexported")
+                    .arg("path", export::file_name(session, Format::Markdown)),
+            ),
+            (Prompt::Quit, _) => self.catalog.tr("prompt-quit"),
+            (Prompt::Export, _) => self.catalog.tr("prompt-export"),
+            (Prompt::Rematch, _) => self.catalog.tr("prompt-rematch"),
+            (Prompt::Clear, _) => self.catalog.tr("prompt-clear"),
+            (Prompt::Resync, _) => self.catalog.tr("prompt-resync"),
+            (Prompt::BlockPeer, _) => self.catalog.render(
+                &Message::new("prompt-block").arg(
+                    "address",
+                    self.peer
+                        .map_or_else(String::new, |peer| peer.ip().to_string()),
+                ),
+            ),
+        };
+        self.draw_popup(frame, text);
+    }
+
+    /// Draws the open import box over the middle of the screen.
+    fn draw_import<B: Backend>(&self, frame: &mut Frame<B>, path: &EditBuffer) {
+        self.draw_popup(
+            frame,
+            self.catalog
+                .render(&Message::new("prompt-import").arg("path", path.text())),
+        );
+    }
+
+    /// Draws the open command palette over the middle of the screen, with
+    /// the commands that can't be used now greyed out.
+    fn draw_palette<B: Backend>(&self, frame: &mut Frame<B>, palette: &Palette) {
+        let query = palette.query.text();
+        let items: Vec<ListItem<'static>> = match (query.trim().parse::<usize>(), self.story()) {
+            (Ok(number), Some(_)) => vec![ListItem::new(
+                self.catalog
+                    .render(&Message::new("palette-go-to").arg("number", number)),
+            )],
+            _ => palette_matches(&query)
+                .into_iter()
+                .map(|action| {
+                    let key = self
+                        .keymap
+                        .key(action)
+                        .map_or_else(String::new, |key| key.to_string());
+                    let style = match self.unavailable(action) {
+                        Some(_) => Style::default().add_modifier(Modifier::DIM),
+                        None => Style::default(),
+                    };
+                    ListItem::new(Span::styled(
+                        format!(" {:<22}{:>12} ", action.name(), key),
+                        style,
+                    ))
+                })
+                .collect(),
+        };
+        let size = frame.size();
+        let width = size.width.min(40);
+        let height = size
+            .height
+            .min(items.len().clamp(1, PALETTE_ROWS) as u16 + 3);
+        let area = Rect {
+            x: size.x + (size.width - width) / 2,
+            y: size.y + (size.height - height) / 2,
+            width,
+            height,
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .border_style(self.theme.focused)
+            .title(self.catalog.tr("palette-title"));
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        if inner.height == 0 {
+            return;
+        }
+        frame.render_widget(
+            Paragraph::new(format!(": {}", query)),
+            Rect { height: 1, ..inner },
+        );
+        let rest = Rect {
+            y: inner.y + 1,
+            height: inner.height - 1,
+            ..inner
+        };
+        if items.is_empty() {
+            frame.render_widget(
+                Paragraph::new(self.catalog.tr("palette-no-match"))
+                    .style(Style::default().add_modifier(Modifier::DIM)),
+                rest,
+            );
+            return;
+        }
+        let mut state = ListState::default();
+        state.select(Some(palette.selected.min(items.len() - 1)));
+        let list = List::new(items).highlight_style(self.theme.selection);
+        frame.render_stateful_widget(list, rest, &mut state);
+    }
+
+    /// Draws the story's statistics over the middle of the screen.
+    fn draw_stats<B: Backend>(&self, frame: &mut Frame<B>, stats: &Stats) {
+        let mut lines = vec![
+            self.catalog.tr("stats-title"),
+            self.app_state.stats(&self.catalog).unwrap_or_default(),
+            self.catalog.render(
+                &Message::new("stats-words")
+                    .arg("count", stats.words)
+                    .arg("per_sentence", format!("{:.1}", stats.words_per_sentence)),
+            ),
+            self.catalog.render(
+                &Message::new("stats-unique")
+                    .arg("percent", format!("{:.0}", stats.unique_ratio * 100.0)),
+            ),
+        ];
+        if let Some(session) = self.story() {
+            if let Some(times) = session.writing_times() {
+                let (ours, theirs) = if session.we_started() {
+                    (times[0], times[1])
+                } else {
+                    (times[1], times[0])
+                };
+                lines.push(
+                    self.catalog.render(
+                        &Message::new("stats-writing")
+                            .arg("ours", format_duration(ours))
+                            .arg("peer", self.peer_name())
+                            .arg("theirs", format_duration(theirs)),
+                    ),
+                );
+            }
+        }
+        lines.push(match stats.reading_ease {
+            Some(ease) => self.catalog.render(
+                &Message::new("stats-reading-ease")
+                    .arg("ease", format!("{:.0}", ease))
+                    .arg_message("level", stats::reading_level(ease)),
+            ),
+            // Syllables are only guessed for English
+            None => self.catalog.tr("stats-reading-ease-unknown"),
+        });
+        lines.push(if stats.repeated.is_empty() {
+            self.catalog.tr("stats-no-repeats")
+        } else {
+            self.catalog.render(
+                &Message::new("stats-repeated").arg(
+                    "words",
+                    stats
+                        .repeated
+                        .iter()
+                        .map(|(word, count)| format!("{} ×{}", word, count))
+                        .join(", "),
+                ),
+            )
+        });
+        self.draw_popup(frame, lines.join("\n"));
+    }
+
+    /// Draws how the actors are keeping up in the top-left corner, out of
+    /// the way of the story.
+    fn draw_metrics<B: Backend>(&self, frame: &mut Frame<B>, overlay: &MetricsOverlay) {
+        let actor = |key, metrics: &ActorMetrics, rate: &Rate| {
+            self.catalog.render(
+                &Message::new(key)
+                    .arg("queued", metrics.queued())
+                    .arg("rate", format!("{:.1}", rate.per_second()))
+                    .arg(
+                        "slowest",
+                        format!("{:.1}", metrics.slowest().as_secs_f64() * 1000.0),
+                    ),
+            )
+        };
+        let lines = [
+            actor("metrics-ui", &self.metrics, &overlay.ui),
+            actor("metrics-app", self.app_handle.metrics(), &overlay.app),
+            self.catalog.render(
+                &Message::new("metrics-draws")
+                    .arg("rate", format!("{:.1}", overlay.draws.per_second())),
+            ),
+        ];
+        let size = frame.size();
+        let width = lines
+            .iter()
+            .map(|line| line.width() as u16 + 2)
+            .max()
+            .unwrap_or_default()
+            .min(size.width);
+        let area = Rect {
+            x: size.x,
+            y: size.y,
+            width,
+            height: (lines.len() as u16 + 2).min(size.height),
+        };
+        let overlay = Paragraph::new(lines.join("\n")).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double)
+                .border_style(self.theme.border)
+                .title(self.catalog.tr("metrics-title")),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(overlay, area);
+    }
+
+    fn draw_popup<B: Backend>(&self, frame: &mut Frame<B>, text: String) {
+        let size = frame.size();
+        let width = size.width.min(70);
+        let height = size.height.min(text.lines().count() as u16 + 2);
+        let area = Rect {
+            x: size.x + (size.width - width) / 2,
+            y: size.y + (size.height - height) / 2,
+            width,
+            height,
+        };
+        let popup = Paragraph::new(text)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Double)
+                    .border_style(self.theme.focused),
+            )
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        frame.render_widget(Clear, area);
+        frame.render_widget(popup, area);
+    }
+
+    /// Our notes beside the Content pane, scrolled to keep the cursor in
+    /// view.
+    fn draw_scratchpad<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let width = area.width.saturating_sub(2).max(1) as usize;
+        let height = area.height.saturating_sub(2).max(1) as usize;
+        let (lines, (cursor_x, cursor_y)) = self.scratchpad.wrap(width);
+        let scroll = (cursor_y + 1).saturating_sub(height);
+        let lines: Vec<_> = lines.into_iter().skip(scroll).map(Spans::from).collect();
+        let block = self.focus_block(
+            Element::Scratchpad,
+            BorderType::Rounded,
+            self.catalog.tr("scratchpad"),
+        );
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+        if self.selected_element == Element::Scratchpad {
+            frame.set_cursor(
+                area.x + 1 + cursor_x as u16,
+                area.y + 1 + (cursor_y - scroll) as u16,
+            );
+        }
+    }
+
+    /// The width and height of the invite beside the Content pane, while
+    /// waiting for a peer and if it fits in `area` with room to spare.
+    fn invite_panel(&self, area: Rect) -> Option<(u16, u16)> {
+        let (invite, code) = self.invite.as_ref()?;
+        if !matches!(self.app_state, Waiting) || self.archive.is_some() {
+            return None;
+        }
+        let modules = (code.size() + 2 * QUIET_ZONE) as u16;
+        let width = modules.max(invite.len() as u16) + 2;
+        // Two modules to a row, then the invite itself
+        let height = modules.div_ceil(2) + 3;
+        (area.width >= width + MIN_CONTENT_WIDTH && area.height >= height)
+            .then_some((width, height))
+    }
+
+    /// The invite as a QR code drawn in half blocks, each cell the upper
+    /// module in the foreground over the lower one in the background, with
+    /// the invite written under it. Without color, the dark modules are
+    /// drawn as blocks of the terminal's own foreground instead.
+    fn draw_invite<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let (invite, code) = match &self.invite {
+            Some(invite) => invite,
+            None => return,
+        };
+        let colour = |x: usize, y: usize| {
+            // The quiet zone is outside the code, where it's light
+            let dark =
+                x >= QUIET_ZONE && y >= QUIET_ZONE && code.is_dark(x - QUIET_ZONE, y - QUIET_ZONE);
+            if dark {
+                Color::Black
+            } else {
+                Color::White
+            }
+        };
+        let modules = code.size() + 2 * QUIET_ZONE;
+        let mut lines: Vec<Spans> = (0..modules)
+            .step_by(2)
+            .map(|y| {
+                Spans::from(
+                    (0..modules)
+                        .map(|x| {
+                            if !self.theme.color {
+                                let dark = |y| colour(x, y) == Color::Black;
+                                return Span::raw(match (dark(y), dark(y + 1)) {
+                                    (true, true) => "█",
+                                    (true, false) => "▀",
+                                    (false, true) => "▄",
+                                    (false, false) => " ",
+                                });
+                            }
+                            Span::styled(
+                                "▀",
+                                Style::default().fg(colour(x, y)).bg(colour(x, y + 1)),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        lines.push(Spans::from(Span::styled(
+            invite.clone(),
+            self.theme.focused,
+        )));
+        let panel = Paragraph::new(lines).alignment(Alignment::Center).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(self.theme.border)
+                .title(self.catalog.tr("invite-title")),
+        );
+        frame.render_widget(panel, area);
+    }
+
+    /// Shows completions in a faint popup starting at `x`, `y`.
+    fn draw_suggestions<B: Backend>(&self, frame: &mut Frame<B>, x: u16, y: u16) {
+        let suggestions = self.suggestions();
+        if suggestions.is_empty() {
+            return;
+        }
+        let size = frame.size();
+        let width = suggestions
+            .iter()
+            .map(|word| word.width())
+            .max()
+            .unwrap_or(0) as u16
+            + 2;
+        let area = Rect {
+            x: x.min(size.right().saturating_sub(width)),
+            y,
+            width,
+            height: suggestions.len() as u16,
+        }
+        .intersection(size);
+        let items = suggestions
+            .into_iter()
+            .map(|word| ListItem::new(format!(" {} ", word)))
+            .collect::<Vec<_>>();
+        let mut state = ListState::default();
+        state.select(Some(self.suggestion.min(items.len() - 1)));
+        let list = List::new(items)
+            .style(Style::default().add_modifier(Modifier::DIM))
+            .highlight_style(self.theme.selection);
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn draw_view<B: Backend>(&self, frame: &mut Frame<B>) {
+        let size = frame.size();
+        if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+            draw_too_small(frame, &self.catalog);
+            return;
+        }
+        if self.zen {
+            self.draw_zen(frame);
+            return;
+        }
+
+        let address = self.address_buffer.text();
+        // Only validated while it can be used, and once something is typed
+        let validation = match self.app_state {
+            Replay(_) | Waiting if !address.is_empty() => Some(validate_address(&address)),
+            _ => None,
+        };
+        let reason = match &validation {
+            Some(Err(reason)) => Some(self.catalog.render(reason)),
+            _ => self.connect_error.clone(),
+        };
+        let panes = self.layout.split(
+            size,
+            self.content_percent,
+            self.log_collapsed,
+            reason.is_some(),
+        );
+        frame.render_widget(
+            Paragraph::new(self.status_bar()).style(self.theme.status_bar),
+            panes.status_bar,
+        );
+
+        let content_area = if self.show_scratchpad {
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(100 - SCRATCHPAD_PERCENT),
+                    Constraint::Percentage(SCRATCHPAD_PERCENT),
+                ])
+                .split(panes.content);
+            self.draw_scratchpad(frame, chunks[1]);
+            chunks[0]
+        } else {
+            panes.content
+        };
+        match self.invite_panel(content_area) {
+            Some((width, _)) => {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Min(1), Constraint::Length(width)])
+                    .split(content_area);
+                self.draw_content(frame, chunks[0]);
+                self.draw_invite(frame, chunks[1]);
+            }
+            None => self.draw_content(frame, content_area),
+        }
+
+        let stats = Paragraph::new(self.app_state.stats(&self.catalog).unwrap_or_default())
+            .alignment(Alignment::Right);
+        frame.render_widget(stats, panes.stats);
+
+        let input_area = match self.constraint_line() {
+            Some(line) => {
+                let input_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(1), Constraint::Min(1)])
+                    .split(panes.input);
+                let constraint = Paragraph::new(line)
+                    .style(self.theme.constraint)
+                    .alignment(Alignment::Center);
+                frame.render_widget(constraint, input_chunks[0]);
+                input_chunks[1]
+            }
+            None => panes.input,
+        };
+        let input_area = match self.preview_line(input_area.width as usize) {
+            Some(preview) => {
+                let input_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(1), Constraint::Min(1)])
+                    .split(input_area);
+                frame.render_widget(Paragraph::new(preview), input_chunks[0]);
+                input_chunks[1]
+            }
+            None => input_area,
+        };
+        let input_area = match self.counter_line() {
+            Some(counter) => {
+                let input_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(1)])
+                    .split(input_area);
+                frame.render_widget(
+                    Paragraph::new(Spans::from(counter)).alignment(Alignment::Right),
+                    input_chunks[1],
+                );
+                input_chunks[0]
+            }
+            None => input_area,
+        };
+
+        // Wrapped by character rather than by word so the cursor position
+        // is known
+        let input_width = input_area.width.saturating_sub(2).max(1) as usize;
+        let (input_lines, (cursor_x, cursor_y)) = self.input_buffer.wrap(input_width);
+        let input_block = match &self.app_state {
+            InSession {
+                is_our_turn: true, ..
+            } => self
+                .focus_block(
+                    Element::Input,
+                    BorderType::Double,
+                    self.catalog.tr("input-your-turn"),
+                )
+                .border_style(self.theme.focused.add_modifier(Modifier::BOLD)),
+            InSession { .. } if self.queued => self.focus_block(
+                Element::Input,
+                BorderType::Rounded,
+                self.catalog.tr(if self.confirm_queued {
+                    "input-queued-confirm"
+                } else {
+                    "input-queued"
+                }),
+            ),
+            InSession { .. } => self.focus_block(
+                Element::Input,
+                BorderType::Rounded,
+                self.catalog
+                    .render(&Message::new("input-waiting").arg("peer", self.peer_name())),
+            ),
+            Replay(_) | Waiting | Connecting(_) => self.focus_block(
+                Element::Input,
+                BorderType::Rounded,
+                self.catalog.tr("input"),
+            ),
+        };
+        // Listed words are highlighted as they are typed, and misspelled ones
+        // underlined once typed
+        let text = self.input_buffer.text();
+        let listed = match &self.word_filter {
+            Some(filter) => filter.find(&text),
+            None => vec![],
+        };
+        let misspelled = self.misspelled(&text);
+        let marked: Vec<_> = listed
+            .iter()
+            .map(|range| {
+                let style = self.theme.warning.add_modifier(Modifier::UNDERLINED);
+                (range, style)
+            })
+            .chain(
+                misspelled
+                    .iter()
+                    .filter(|range| !listed.contains(range))
+                    .map(|range| (range, Style::default().add_modifier(Modifier::UNDERLINED))),
+            )
+            .collect();
+        let mut offset = 0;
+        let input_lines: Vec<_> = input_lines
+            .into_iter()
+            .map(|line| {
+                let ranges: Vec<_> = marked
+                    .iter()
+                    .filter(|(range, _)| range.start < offset + line.len() && range.end > offset)
+                    .map(|(range, style)| {
+                        let start = range.start.max(offset) - offset;
+                        let end = range.end.min(offset + line.len()) - offset;
+                        (start..end, *style)
+                    })
+                    .collect();
+                offset += line.len();
+                search::highlight(Spans::from(line), &ranges)
+            })
+            .collect();
+        let mut input_para = Paragraph::new(input_lines).block(input_block);
+        if self.queued {
+            input_para = input_para.style(Style::default().add_modifier(Modifier::DIM));
+        }
+        frame.render_widget(input_para, input_area);
+        if self.selected_element == Element::Input {
+            let (x, y) = (
+                input_area.x + 1 + cursor_x as u16,
+                input_area.y + 1 + cursor_y as u16,
+            );
+            frame.set_cursor(x, y);
+            self.draw_suggestions(frame, x, y + 1);
+        }
+
+        let mut address_block = self.focus_block(
+            Element::Connect,
+            BorderType::Plain,
+            self.catalog.tr("connect"),
+        );
+        match validation {
+            Some(Ok(_)) => address_block = address_block.border_style(self.theme.valid),
+            Some(Err(_)) => address_block = address_block.border_style(self.theme.warning),
+            None => {}
+        }
+        let connecting = match self.app_state {
+            Connecting(address) => Some(address),
+            _ => None,
+        };
+        let address_input = Paragraph::new(match connecting {
+            Some(address) => format!(
+                "{} {}",
+                SPINNER[self.spinner % SPINNER.len()],
+                self.catalog
+                    .render(&Message::new("status-connecting").arg("address", address))
+            ),
+            None => address,
+        })
+        .block(address_block)
+        .alignment(Alignment::Center);
+
+        frame.render_widget(address_input, panes.connect);
+        if let Some(reason) = reason {
+            frame.render_widget(
+                Paragraph::new(reason)
+                    .style(self.theme.warning)
+                    .alignment(Alignment::Center),
+                panes.reason,
+            );
+        }
+        if self.selected_element == Element::Connect && connecting.is_none() {
+            // Mirrors how the centered paragraph positions its text
+            let inner_width = panes.connect.width.saturating_sub(2);
+            let start = inner_width.saturating_sub(self.address_buffer.width() as u16) / 2;
+            frame.set_cursor(
+                panes.connect.x + 1 + start + self.address_buffer.cursor_width() as u16,
+                panes.connect.y + 1,
+            );
+        }
+        if self.log_collapsed {
+            let latest = Paragraph::new(self.log_buffer.visible(1, &self.theme));
+            frame.render_widget(latest, panes.log);
+        } else {
+            let log_height = panes.log.height.saturating_sub(2) as usize;
+            let log_block = Paragraph::new(self.log_buffer.visible(log_height, &self.theme)).block(
+                self.focus_block(Element::Log, BorderType::Rounded, self.log_buffer.title()),
+            );
+            frame.render_widget(log_block, panes.log)
+        }
+    }
+}
+
+const MIN_CONTENT_PERCENT: u16 = 20;
+const MAX_CONTENT_PERCENT: u16 = 85;
+/// How much of the Content pane's width the Scratchpad takes while shown.
+const SCRATCHPAD_PERCENT: u16 = 35;
+
+const TOAST: Duration = Duration::from_secs(4);
+const MAX_TOASTS: usize = 3;
+const TOAST_WIDTH: u16 = 40;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// How many recent peers are listed, each picked by its digit.
+const RECENT_PEERS: usize = 5;
+const COMPLETE_AFTER: usize = 3;
+const SUGGESTIONS: usize = 3;
+/// How many spellings the spelling key goes through for a word.
+const SPELLINGS: usize = 5;
+/// How many words are remembered as spelled right or wrong before starting
+/// over.
+const SPELLED: usize = 4096;
+
+/// The commands whose names match `query`, best first.
+fn palette_matches(query: &str) -> Vec<Action> {
+    Action::commands()
+        .filter_map(|action| Some((search::fuzzy(action.name(), query)?, action)))
+        .sorted_by_key(|(score, _)| *score)
+        .map(|(_, action)| action)
+        .collect()
+}
+
+/// Parses an address or invite typed into the Connect box, describing
+/// what's wrong with it when it doesn't parse.
+fn validate_address(address: &str) -> Result<SocketAddr, Message> {
+    if Invite::is_invite(address) {
+        return Invite::decode(address).map(|invite| invite.address);
+    }
+    if let Ok(address) = SocketAddr::from_str(address) {
+        return Ok(address);
+    }
+    let (host, port) = match address.rsplit_once(':') {
+        // A bare IPv6 address is all colons, so check it has a port at all
+        Some((host, _)) if !address.starts_with('[') && host.contains(':') => (address, None),
+        Some((host, port)) => (host, Some(port)),
+        None => (address, None),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.parse::<IpAddr>().is_err() {
+        return Err(Message::new("invalid-ip"));
+    }
+    match port {
+        None | Some("") => Err(Message::new("missing-port")),
+        Some(_) => Err(Message::new("invalid-port")),
+    }
+}
+
+const MIN_WIDTH: u16 = 60;
+/// How much of the Content pane has to be left beside the invite.
+const MIN_CONTENT_WIDTH: u16 = 40;
+/// Light modules around a QR code, fewer than the standard's four as
+/// scanners manage and the terminal is short of room.
+const QUIET_ZONE: usize = 2;
+const MIN_HEIGHT: u16 = 20;
+
+/// Replaces the whole layout with a note when it would not fit.
+fn draw_too_small<B: Backend>(frame: &mut Frame<B>, catalog: &Catalog) {
+    let size = frame.size();
+    let message = Paragraph::new(
+        catalog.render(
+            &Message::new("too-small")
+                .arg("width", MIN_WIDTH)
+                .arg("height", MIN_HEIGHT),
+        ),
+    )
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true });
+    let area = Rect {
+        y: size.y + size.height / 2,
+        height: size.height - size.height / 2,
+        ..size
+    };
+    frame.render_widget(message, area);
+}
+
+const FLASH: Duration = Duration::from_millis(200);
+/// How long the turn gone to stands out.
+const JUMP_HIGHLIGHT: Duration = Duration::from_secs(2);
+/// How many commands the palette shows at once.
+const PALETTE_ROWS: usize = 10;
+/// How long without key presses before the user is assumed to be elsewhere.
+const IDLE: Duration = Duration::from_secs(30);
+
+/// Inverts everything already drawn beneath it.
+struct Invert;
+
+impl Widget for Invert {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        buf.set_style(area, Style::default().add_modifier(Modifier::REVERSED));
+    }
+}
+
+/// Rings the bell, or sets when the screen stops flashing, as `notify` asks.
+fn alert(notify: Notify, flash_until: &mut Option<Instant>) {
+    match notify {
+        Notify::None => {}
+        Notify::Bell => ring_bell(),
+        Notify::Flash => *flash_until = Some(Instant::now() + FLASH),
+    }
+}
+
+fn ring_bell() {
+    let mut stdout = io::stdout();
+    // Nothing useful can be done if the bell can't be written
+    let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+}
+
+/// How a block is drawn depending on whether it has focus.
+struct FocusLook {
+    style: Style,
+    /// Replaces the block's own border, when set.
+    border_type: Option<BorderType>,
+    /// Put in front of the title.
+    marker: &'static str,
+}
+
+/// Focus is shown by the border and a title marker as well as by color, so
+/// it can be seen without telling the theme's colors apart.
+fn focus_look(theme: &Theme, this_element: Element, selected_element: Element) -> FocusLook {
+    if selected_element == this_element {
+        FocusLook {
+            style: theme.focused,
+            border_type: Some(BorderType::Thick),
+            marker: "▶ ",
+        }
+    } else {
+        FocusLook {
+            style: theme.border,
+            border_type: None,
+            marker: "",
+        }
+    }
+}
+
+fn status_text(status: &Spans) -> String {
+    status.0.iter().map(|span| span.content.as_ref()).collect()
+}
+
+async fn run_ui_actor<B: Backend>(
+    mut actor: UIActor,
+    event_stream: EventStream,
+    terminal: &mut Terminal<B>,
+) -> Result<(), Error> {
+    let result = run_ui_loop(&mut actor, event_stream, terminal).await;
+    if let Err(err) = actor.save_notes(true).await {
+        debug!(%err, "couldn't save the notes");
+    }
+    // Stop the app even when the UI failed, and report why it failed if it did
+    let stopped = actor.app_handle.shutdown().await;
+    result.and(stopped)
+}
+
+/// The most terminal events handled between two draws.
+const INPUT_BATCH: usize = 256;
+
+async fn run_ui_loop<B: Backend>(
+    actor: &mut UIActor,
+    mut event_stream: EventStream,
+    terminal: &mut Terminal<B>,
+) -> Result<(), Error> {
+    let mut ticks = interval(actor.tick_rate);
+    let mut signals = Signals::new()?;
+    loop {
+        actor.redraw(terminal)?;
+        tokio::select! {
+            Some(msg) = actor.receiver.recv() => {
+                actor.metrics.received();
+                let started = Instant::now();
+                actor.handle_message(msg);
+                actor.metrics.handled(started.elapsed());
+                // The app is gone if this fails, and says so itself
+                if let Err(err) = actor.send_queued().await {
+                    debug!(%err, "couldn't send the queued sentence");
+                }
+                if let Err(err) = actor.save_notes(false).await {
+                    debug!(%err, "couldn't save the notes");
+                }
+            }
+            event = event_stream.next() => match event {
+                Some(Ok(event)) => {
+                    let started = Instant::now();
+                    let quit = actor.handle_input_events(event, &mut event_stream).await;
+                    actor.metrics.handled(started.elapsed());
+                    if quit {
+                        break;
+                    }
+                    if let Err(err) = actor.save_notes(false).await {
+                        debug!(%err, "couldn't save the notes");
+                    }
+                    #[cfg(unix)]
+                    if std::mem::take(&mut actor.suspending) {
+                        crate::screen::suspend()?;
+                        info!("resumed");
+                        // Whatever was drawn is gone, and the size may have changed
+                        actor.resized = true;
+                    }
+                }
+                Some(Err(_)) => {}
+                // The terminal has gone away
+                None => break,
+            },
+            Some(()) = OptionFuture::from(actor.flash_until.map(sleep_until)) => {
+                actor.flash_until = None;
+                actor.dirty = true;
+            }
+            _ = ticks.tick() => {
+                actor.tick();
+                if actor.idle() {
+                    if let Err(err) = actor.set_away(true).await {
+                        debug!(%err, "couldn't tell the app we're away");
+                    }
+                }
+                if let Err(err) = actor.remind().await {
+                    debug!(%err, "couldn't tell the peer we're still thinking");
+                }
+            }
+            signal = signals.recv() => {
+                info!(signal, "shutting down");
+                actor.interrupt();
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// How many messages can wait for the UI before log messages are dropped
+/// and anything else waits for room.
+const QUEUE: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct UIHandle {
+    sender: Sender<UIMessage>,
+    /// Log messages dropped because the queue was full, for the UI to mention.
+    dropped: Arc<AtomicUsize>,
+    metrics: Arc<ActorMetrics>,
+}
+
+type UIStarter<'a, B> = Box<
+    dyn FnOnce(
+        EventStream,
+        AppHandle,
+        &'a mut Terminal<B>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + 'a>>,
+>;
+
+impl UIHandle {
+    pub fn new<'a, B: Backend>(config: UIConfig) -> (Self, UIStarter<'a, B>) {
+        let (handle, receiver) = Self::channel();
+        let dropped = handle.dropped.clone();
+        let metrics = handle.metrics.clone();
+
+        (
+            handle,
+            Box::new(move |event_stream, app_handle, terminal| {
+                let actor = UIActor::new(receiver, dropped, metrics, app_handle, config);
+                Box::pin(run_ui_actor(actor, event_stream, terminal))
+            }),
+        )
+    }
+
+    /// A handle for running the app without a terminal, e.g. in tests. The
+    /// messages it is sent are only traced.
+    pub fn headless() -> Self {
+        let (handle, mut receiver) = Self::channel();
+        tokio::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                debug!(message = %msg, "headless ui message");
+            }
+        });
+        handle
+    }
+
+    /// A handle whose messages arrive on the returned receiver rather than
+    /// at a UI, for watching what the app does.
+    pub fn channel() -> (Self, mpsc::Receiver<UIMessage>) {
+        let (sender, receiver) = mpsc::channel(QUEUE);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let handle = Self {
+            sender,
+            dropped,
+            metrics: Arc::default(),
+        };
+        (handle, receiver)
+    }
+}
+
+impl UISink for UIHandle {
+    async fn send(&self, message: UIMessage) -> Result<(), Error> {
+        self.metrics.sent();
+        if let Err(err) = self.sender.send(message).await {
+            self.metrics.received();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    fn send_or_drop(&self, message: UIMessage) -> Result<(), Error> {
+        self.metrics.sent();
+        let sent = self.sender.try_send(message);
+        if sent.is_err() {
+            self.metrics.received();
+        }
+        match sent {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(message)) => {
+                trace!(%message, "ui busy, dropped message");
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Closed(message)) => Err(mpsc::error::SendError(message).into()),
+        }
+    }
+}
+
+/// Where the app sends what there is to show, such as the terminal UI's
+/// [`UIHandle`].
+///
+/// Messages that change what the UI knows of the session wait for room, so
+/// none are lost. Log messages and toasts are dropped when the UI is behind,
+/// so that a stalled UI can never hold up the app.
+pub trait UISink: Clone + std::fmt::Debug + Send + Sync + 'static {
+    fn send(&self, message: UIMessage) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Sends `message` if there is room for it straight away, and otherwise
+    /// drops it. Only fails if the UI is gone.
+    fn send_or_drop(&self, message: UIMessage) -> Result<(), Error>;
+
+    fn log(&self, message: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        future::ready(self.send_or_drop(UIMessage::Log(Level::Info, message)))
+    }
+
+    /// Logs `message` and shows it in a toast, for events worth noticing
+    /// while typing.
+    fn toast(&self, message: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        future::ready(self.send_or_drop(UIMessage::Toast(message)))
+    }
+
+    fn warn(&self, message: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        future::ready(self.send_or_drop(UIMessage::Log(Level::Warn, message)))
+    }
+
+    fn error(&self, message: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        future::ready(self.send_or_drop(UIMessage::Log(Level::Error, message)))
+    }
+
+    fn sentence_received(&self, turn: Turn) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::SentenceReceived(turn))
+    }
+
+    fn sentence_accepted(&self, turn: Turn) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::SentenceAccepted(turn))
+    }
+
+    fn sentence_bounced(
+        &self,
+        index: usize,
+        kept: Turn,
+        bounced: Vec<Turn>,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::SentenceBounced {
+            index,
+            kept,
+            bounced,
+        })
+    }
+
+    fn peer_away(&self, away: bool) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::PeerAway(away))
+    }
+
+    fn cooldown(&self, wait: Duration) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Cooldown(wait))
+    }
+
+    /// Celebrates `milestone` with a banner, unless the UI is behind.
+    fn milestone(&self, milestone: Milestone) -> impl Future<Output = Result<(), Error>> + Send {
+        future::ready(self.send_or_drop(UIMessage::Milestone(milestone)))
+    }
+
+    fn sentence_refused(
+        &self,
+        index: usize,
+        turn: Turn,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::SentenceRefused { index, turn })
+    }
+
+    fn listening(&self, address: SocketAddr) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Listening(address))
+    }
+
+    fn connected(
+        &self,
+        session: SessionInstance,
+        peer: SocketAddr,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Connected { session, peer })
+    }
+
+    fn story_ended(&self) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::StoryEnded)
+    }
+
+    fn new_story(
+        &self,
+        session: SessionInstance,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::NewStory(session))
+    }
+
+    fn clear_proposed(&self) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::ClearProposed)
+    }
+
+    fn diverged(&self, kept: bool) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Diverged(kept))
+    }
+
+    fn resynced(&self, session: SessionInstance) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Resynced(session))
+    }
+
+    fn saved_sessions(
+        &self,
+        sessions: Vec<SavedSession>,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::SavedSessions(sessions))
+    }
+
+    fn connecting(&self, address: SocketAddr) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Connecting(address))
+    }
+
+    fn connect_failed(
+        &self,
+        address: SocketAddr,
+        reason: Message,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::ConnectFailed { address, reason })
+    }
+
+    fn disconnected(&self) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::Disconnected)
+    }
+
+    fn app_stopped(&self, reason: String) -> impl Future<Output = Result<(), Error>> + Send {
+        self.send(UIMessage::AppStopped(reason))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        status_text, validate_address,
+        AppState::{Connecting, InSession, Replay, Waiting},
+        Catalog, ContentView, EditBuffer, Element, LayoutMode, Message, Prompt, Search, UIActor,
+        UIConfig, UIHandle, UIMessage, MIN_HEIGHT, MIN_WIDTH, QUEUE,
+    };
+    use crate::{
+        app::{AppConfig, AppHandle, AppInput},
+        completion::Completer,
+        export::{Appendices, Format},
+        filter::{Incoming, WordFilter},
+        history::{AddressHistory, RecentPeers, SentenceHistory},
+        keymap::KeyMap,
+        log_buffer::Level,
+        notify::{Alerts, Notify},
+        sessions::{self, Milestone, SessionInstance, Turn},
+        spelling::Speller,
+        theme::{Theme, ThemeName},
+    };
+    use chrono::Utc;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+    use futures::{stream, FutureExt};
+    use std::{io, path::Path, sync::atomic::Ordering};
+    use tokio::{
+        sync::mpsc,
+        time::{timeout, Duration, Instant},
+    };
+    use tokio_stream::StreamExt;
+    use tui::{
+        backend::{Backend, TestBackend},
+        buffer::Cell,
+        layout::Rect,
+        style::Color,
+        Terminal,
+    };
+    use unicode_width::UnicodeWidthStr;
+
+    fn actor() -> UIActor {
+        let (_, receiver) = mpsc::channel(1);
+        UIActor::new(
+            receiver,
+            Default::default(),
+            Default::default(),
+            AppHandle::detached(),
+            UIConfig {
+                constraints: None,
+                theme: Theme::default(),
+                keymap: KeyMap::default(),
+                alerts: Alerts {
+                    notify: Notify::None,
+                    desktop: false,
+                },
+                history: AddressHistory::default(),
+                recent_peers: RecentPeers::default(),
+                sentences: SentenceHistory::default(),
+                completer: Some(Completer::default()),
+                speller: None,
+                warnings: vec![],
+                tick_rate: Duration::from_millis(100),
+                confirm_queued: false,
+                import_first: false,
+                imported: None,
+                word_filter: None,
+                away_after: None,
+                remind_after: None,
+                remind_peer: false,
+                typewriter: None,
+                invite_hint: None,
+                appendices: Appendices::default(),
+                catalog: Catalog::english(),
+            },
+        )
+    }
+
+    fn render(width: u16, height: u16) -> String {
+        render_actor(&mut actor(), width, height)
+    }
+
+    fn render_actor(actor: &mut UIActor, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        actor.draw(&mut terminal).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect()
+    }
+
+    /// The screen line by line, with clock times masked so that it is the
+    /// same on every run.
+    fn render_lines(actor: &mut UIActor, width: u16, height: u16) -> String {
+        let screen: Vec<char> = render_actor(actor, width, height).chars().collect();
+        let mut masked = screen.clone();
+        for (i, window) in screen.windows(5).enumerate() {
+            if let [h, m, ':', s, t] = window {
+                if [h, m, s, t].iter().all(|c| c.is_ascii_digit()) {
+                    for j in [i, i + 1, i + 3, i + 4] {
+                        masked[j] = '#';
+                    }
+                }
+            }
+        }
+        masked
+            .chunks(width as usize)
+            .map(|line| line.iter().collect::<String>().trim_end().to_string() + "\n")
+            .collect()
+    }
+
+    /// Compares what `actor` draws with `tests/snapshots/<name>.txt`. Run
+    /// with `UPDATE_SNAPSHOTS=1` to write the snapshots afresh after meaning
+    /// to change the layout, then review their diff.
+    fn assert_snapshot(name: &str, actor: &mut UIActor, width: u16, height: u16) {
+        let screen = render_lines(actor, width, height);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("snapshots")
+            .join(format!("{}.txt", name));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &screen).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "no snapshot at {}, run with UPDATE_SNAPSHOTS=1",
+                path.display()
+            )
+        });
+        assert!(
+            screen == expected,
+            "{} no longer matches its snapshot, it is now:\n{}",
+            name,
+            screen
+        );
+    }
+
+    /// An actor in a story that has been going for an hour, with `texts`
+    /// written alternately by us and the peer.
+    fn actor_in_story(texts: &[&str]) -> UIActor {
+        let mut actor = actor();
+        actor.listening = Some("127.0.0.1:4000".parse().unwrap());
+        let mut session = SessionInstance::new(true);
+        let now = Utc::now();
+        session.started_at = now - chrono::Duration::hours(1);
+        for text in texts {
+            session.push(Turn {
+                text: String::from(*text),
+                timestamp: now - chrono::Duration::minutes(10),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        actor.handle_message(UIMessage::Connected {
+            session,
+            peer: "127.0.0.1:5000".parse().unwrap(),
+        });
+        actor
+    }
+
+    #[test]
+    fn snapshot_waiting_with_instructions() {
+        let mut actor = actor();
+        actor.listening = Some("127.0.0.1:4000".parse().unwrap());
+        assert_snapshot("waiting", &mut actor, 100, 30);
+        actor.listening = None;
+        assert_snapshot("waiting_not_listening", &mut actor, 100, 30);
+    }
+
+    #[test]
+    fn snapshot_mid_story() {
+        let mut actor = actor_in_story(&[
+            "The lighthouse was dark.",
+            "Nobody had kept it for years.",
+            "Still, someone had left the door open.",
+        ]);
+        assert_snapshot("mid_story", &mut actor, 100, 30);
+    }
+
+    #[tokio::test]
+    async fn snapshot_each_layout_and_a_collapsed_log() {
+        let mut actor = actor_in_story(&[
+            "The lighthouse was dark.",
+            "Nobody had kept it for years.",
+            "Still, someone had left the door open.",
+        ]);
+        let alt = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT));
+        for (name, layout) in [
+            ("layout_writing", LayoutMode::Writing),
+            ("layout_debugging", LayoutMode::Debugging),
+        ] {
+            actor.handle_input_event(alt('w')).await.unwrap();
+            assert_eq!(actor.layout, layout);
+            assert_snapshot(name, &mut actor, 100, 30);
+        }
+        actor.handle_input_event(alt('w')).await.unwrap();
+        assert_eq!(actor.layout, LayoutMode::Balanced);
+        assert_eq!(actor.content_percent, 60);
+
+        actor.handle_input_event(alt('l')).await.unwrap();
+        assert!(!actor.focus_order().contains(&Element::Log));
+        assert_snapshot("log_collapsed", &mut actor, 100, 30);
+    }
+
+    #[tokio::test]
+    async fn snapshot_typing_on_our_turn() {
+        let mut actor = actor_in_story(&["The lighthouse was dark.", "Nobody kept it."]);
+        for c in "A gull landed".chars() {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+                .await
+                .unwrap();
+        }
+        assert!(actor.selected_element == Element::Input);
+        assert_snapshot("typing", &mut actor, 100, 30);
+    }
+
+    #[tokio::test]
+    async fn a_sentence_written_early_is_sent_when_our_turn_comes() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        for c in "A gull landed.".chars() {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+                .await
+                .unwrap();
+        }
+        assert!(actor.queued);
+        assert!(
+            render_actor(&mut actor, 100, 30).contains("Input (queued — will send on your turn)")
+        );
+
+        // Editing takes it back, as does Esc, without losing the text
+        for (code, queued) in [
+            (KeyCode::Backspace, false),
+            (KeyCode::Char('!'), false),
+            (KeyCode::Char('.'), true),
+            (KeyCode::Esc, false),
+            (KeyCode::Backspace, false),
+            (KeyCode::Char('.'), true),
+        ] {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(code)))
+                .await
+                .unwrap();
+            assert_eq!(actor.queued, queued, "after {:?}", code);
+        }
+        assert_eq!(actor.input_buffer.text(), "A gull landed!.");
+        actor.send_queued().await.unwrap();
+        assert!(requests.recv().now_or_never().is_none());
+
+        actor.handle_message(UIMessage::SentenceReceived(Turn {
+            text: String::from("Nobody kept it."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        }));
+        actor.send_queued().await.unwrap();
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Input(sentence, None))) if sentence == "A gull landed!."
+        ));
+        assert!(!actor.queued);
+        assert!(actor.input_buffer.text().is_empty());
+    }
+
+    #[tokio::test]
+    async fn up_and_alt_r_recall_sentences_written_before() {
+        let mut actor = actor_in_story(&["The lighthouse was dark.", "Nobody kept it."]);
+        let (app_handle, _requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        for key in "The gull flew off.".chars().map(KeyCode::Char) {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(key)))
+                .await
+                .unwrap();
+        }
+        for sentence in ["It rained.", "A gull landed."] {
+            actor.sentences.record(sentence).unwrap();
+        }
+
+        let mut recalled = vec![];
+        for key in [
+            KeyEvent::from(KeyCode::Up),
+            KeyEvent::from(KeyCode::Up),
+            KeyEvent::from(KeyCode::Down),
+            KeyEvent::from(KeyCode::Down),
+            // Only from an empty Input
+            KeyEvent::from(KeyCode::Char('g')),
+            KeyEvent::from(KeyCode::Up),
+            KeyEvent::new(KeyCode::Char('r'), KeyModifiers::ALT),
+            KeyEvent::new(KeyCode::Char('r'), KeyModifiers::ALT),
+            KeyEvent::new(KeyCode::Char('r'), KeyModifiers::ALT),
+        ] {
+            actor.handle_input_event(Event::Key(key)).await.unwrap();
+            recalled.push(actor.input_buffer.text());
+        }
+        assert_eq!(
+            recalled,
+            vec![
+                "A gull landed",
+                "It rained",
+                "A gull landed",
+                "",
+                "g",
+                "g",
+                "A gull landed",
+                "The gull flew off",
+                "The gull flew off",
+            ]
+        );
+        assert!(render_actor(&mut actor, 200, 30)
+            .contains("No older sentence you wrote contains \"g\""));
+    }
+
+    #[tokio::test]
+    async fn exporting_asks_for_a_format() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        for key in [
+            KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL),
+            KeyEvent::from(KeyCode::Char('h')),
+        ] {
+            actor.handle_input_event(Event::Key(key)).await.unwrap();
+        }
+        assert!(actor.prompt.is_none());
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Export(Format::Html)))
+        ));
+        assert!(!actor.has_unexported_story());
+    }
+
+    #[tokio::test]
+    async fn blocking_the_peer_asks_first() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        let alt_k = KeyEvent::new(KeyCode::Char('k'), KeyModifiers::ALT);
+        actor.handle_input_event(Event::Key(alt_k)).await.unwrap();
+        assert!(matches!(actor.prompt, Some(Prompt::BlockPeer)));
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(
+            screen.contains("Disconnect from 127.0.0.1 and never let it connect again?"),
+            "{}",
+            screen
+        );
+        actor
+            .handle_input_event(Event::Key(KeyCode::Char('n').into()))
+            .await
+            .unwrap();
+        assert!(actor.prompt.is_none());
+        assert!(requests.recv().now_or_never().is_none());
+
+        actor.handle_input_event(Event::Key(alt_k)).await.unwrap();
+        actor
+            .handle_input_event(Event::Key(KeyCode::Char('y').into()))
+            .await
+            .unwrap();
+        assert!(actor.prompt.is_none());
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::BlockPeer))
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_diverged_story_is_replaced_only_when_agreed() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        let alt_h = KeyEvent::new(KeyCode::Char('h'), KeyModifiers::ALT);
+        actor.handle_input_event(Event::Key(alt_h)).await.unwrap();
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Verify))
+        ));
+
+        actor.handle_message(UIMessage::Diverged(false));
+        assert!(matches!(actor.prompt, Some(Prompt::Resync)));
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(
+            screen.contains("Your story and the peer's differ!"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("Take theirs in place of yours?"));
+        // Nothing else answers it
+        actor
+            .handle_input_event(Event::Key(KeyCode::Esc.into()))
+            .await
+            .unwrap();
+        assert!(matches!(actor.prompt, Some(Prompt::Resync)));
+        actor
+            .handle_input_event(Event::Key(KeyCode::Char('y').into()))
+            .await
+            .unwrap();
+        assert!(actor.prompt.is_none());
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::AnswerResync(true)))
+        ));
+
+        let mut theirs = SessionInstance::new(true);
+        for text in ["The lighthouse was lit.", "Someone kept it."] {
+            theirs.push(Turn {
+                text: String::from(text),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        actor.handle_message(UIMessage::Resynced(theirs));
+        assert!(matches!(
+            &actor.app_state,
+            InSession { session, is_our_turn: true } if session.turns().len() == 2
+        ));
+        assert!(render_actor(&mut actor, 100, 30).contains("Someone kept it."));
+
+        // Keeping ours only warns
+        actor.handle_message(UIMessage::Diverged(true));
+        assert!(actor.prompt.is_none());
+    }
+
+    #[tokio::test]
+    async fn imports_a_text_file_while_waiting() {
+        let path = std::env::temp_dir().join("write_together-import-ui.txt");
+        std::fs::write(&path, "It began elsewhere. And went on.").unwrap();
+        let mut actor = actor();
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        let keys = std::iter::once(KeyEvent::new(KeyCode::Char('i'), KeyModifiers::ALT))
+            .chain(
+                path.to_str()
+                    .unwrap()
+                    .chars()
+                    .map(|c| KeyCode::Char(c).into()),
+            )
+            .chain(Some(KeyCode::Enter.into()));
+        for key in keys {
+            actor.handle_input_event(Event::Key(key)).await.unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(actor.import_path.is_none());
+        assert!(matches!(&actor.app_state, Replay(session) if session.turns().len() == 2));
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Resume(session))) if !session.is_our_turn()
+        ));
+    }
+
+    #[tokio::test]
+    async fn misspelled_words_are_underlined_and_respelled() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        actor.speller = Some(Speller::parse("the\nlight\nlift\nwent\nout\n"));
+        let key = |code| Event::Key(KeyEvent::from(code));
+        for c in "The lihgt wnet".chars() {
+            actor
+                .handle_input_event(key(KeyCode::Char(c)))
+                .await
+                .unwrap();
+        }
+        render_actor(&mut actor, 100, 30);
+        let text = actor.input_buffer.text();
+        // Not the word still being typed
+        assert_eq!(actor.misspelled(&text), vec![4..9]);
+        let looked_up = actor.spelled.len();
+        actor
+            .handle_input_event(key(KeyCode::Char(' ')))
+            .await
+            .unwrap();
+        render_actor(&mut actor, 100, 30);
+        let text = actor.input_buffer.text();
+        assert_eq!(actor.misspelled(&text), vec![4..9, 10..14]);
+        // Each word was looked up as it was typed, and none since
+        assert_eq!(actor.spelled.len(), looked_up);
+        actor
+            .handle_input_event(key(KeyCode::Char('o')))
+            .await
+            .unwrap();
+        render_actor(&mut actor, 100, 30);
+        assert_eq!(actor.spelled.len(), looked_up + 1);
+
+        // The spelling key goes through the closest words, then back
+        actor.input_buffer.set("The lihgt");
+        let mut spellings = vec![];
+        for _ in 0..4 {
+            actor.handle_input_event(key(KeyCode::F(7))).await.unwrap();
+            spellings.push(actor.input_buffer.text());
+        }
+        assert_eq!(
+            spellings,
+            ["The lift", "The light", "The lihgt", "The lift"]
+        );
+
+        actor.input_buffer.set("The xylophone");
+        actor.handle_input_event(key(KeyCode::F(7))).await.unwrap();
+        assert_eq!(actor.input_buffer.text(), "The xylophone");
+        assert_eq!(
+            actor.toasts[0].text,
+            "Nothing in the dictionary is close to \"xylophone\""
+        );
+    }
+
+    #[tokio::test]
+    async fn a_sentence_with_a_listed_word_is_not_sent() {
+        let mut actor = actor_in_story(&["The lighthouse was dark.", "Nobody kept it."]);
+        actor.word_filter = Some(WordFilter::parse("darn", Incoming::Mask));
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        for c in "Darn it.".chars() {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+                .await
+                .unwrap();
+        }
+        assert!(requests.recv().now_or_never().is_none());
+        assert_eq!(actor.input_buffer.text(), "Darn it");
+        assert!(render_actor(&mut actor, 100, 30).contains("\"Darn\" is on your word list"));
+    }
+
+    #[tokio::test]
+    async fn away_until_the_next_key() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        actor
+            .handle_input_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('a'),
+                KeyModifiers::ALT,
+            )))
+            .await
+            .unwrap();
+        assert!(matches!(requests.recv().await, Some(AppInput::Away(true))));
+        assert!(status_text(&actor.status_bar()).contains("AWAY"));
+
+        // The key that brings us back still does what it normally does
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char('x'))))
+            .await
+            .unwrap();
+        assert!(matches!(requests.recv().await, Some(AppInput::Away(false))));
+        assert!(!actor.away);
+        assert_eq!(actor.input_buffer.text(), "x");
+
+        actor.away_after = Some(Duration::from_secs(60));
+        assert!(!actor.idle());
+        actor.last_key = Instant::now() - Duration::from_secs(61);
+        assert!(actor.idle());
+    }
+
+    #[tokio::test]
+    async fn a_finished_story_asks_whether_to_write_another() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        actor.handle_message(UIMessage::StoryEnded);
+        assert!(render_actor(&mut actor, 100, 30).contains("Write another?"));
+        // Only an answer closes it
+        for code in [KeyCode::Esc, KeyCode::Char('x'), KeyCode::Char('y')] {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(code)))
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            requests.recv().await,
+            Some(AppInput::Rematch(true))
+        ));
+        assert_eq!(actor.prompt, None);
+        assert_eq!(actor.input_buffer.text(), "");
+    }
+
+    #[tokio::test]
+    async fn reminds_once_an_interval_while_our_turn_lasts() {
+        let mut actor = actor_in_story(&[]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        actor.remind_after = Some(Duration::from_secs(300));
+        actor.remind_peer = true;
+        let reminders = |actor: &UIActor| {
+            actor
+                .toasts
+                .iter()
+                .filter(|toast| toast.text.starts_with("Still your turn"))
+                .count()
+        };
+
+        // The turn became ours six minutes ago
+        actor.remind().await.unwrap();
+        let (turns, since) = actor.turn_since.unwrap();
+        actor.turn_since = Some((turns, since - Duration::from_secs(360)));
+        actor.next_reminder = Some(Instant::now() - Duration::from_secs(60));
+        actor.remind().await.unwrap();
+        assert_eq!(reminders(&actor), 1);
+        assert!(actor.toasts[0].text.contains("6 minutes"));
+        // Not again until another interval has gone by
+        actor.remind().await.unwrap();
+        assert_eq!(reminders(&actor), 1);
+
+        // Nor while we're away, or once the turn has changed
+        actor.away = true;
+        actor.next_reminder = Some(Instant::now());
+        actor.remind().await.unwrap();
+        assert_eq!(reminders(&actor), 1);
+        actor.away = false;
+        let turn = |text: &str| Turn {
+            text: String::from(text),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        };
+        if let InSession { is_our_turn, .. } = &mut actor.app_state {
+            *is_our_turn = false;
+        }
+        actor.handle_message(UIMessage::SentenceAccepted(turn(
+            "The lighthouse was dark.",
+        )));
+        actor.remind().await.unwrap();
+        assert_eq!(actor.next_reminder, None);
+        // A new turn of ours starts the wait over
+        actor.handle_message(UIMessage::SentenceReceived(turn("Nobody had kept it.")));
+        actor.remind().await.unwrap();
+        assert!(actor.next_reminder.unwrap() > Instant::now() + Duration::from_secs(290));
+        assert_eq!(reminders(&actor), 1);
+
+        drop(actor);
+        assert!(matches!(
+            requests.recv().await,
+            Some(AppInput::StillThinking(6))
+        ));
+        assert!(requests.recv().await.is_none());
+    }
+
+    #[test]
+    fn shows_when_the_peer_is_away() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        actor.handle_message(UIMessage::PeerAway(true));
+        assert!(status_text(&actor.status_bar()).contains("PEER AWAY"));
+        assert!(render_actor(&mut actor, 200, 30).contains("The peer has stepped away"));
+        actor.handle_message(UIMessage::PeerAway(false));
+        assert!(!status_text(&actor.status_bar()).contains("PEER AWAY"));
+        assert!(render_actor(&mut actor, 200, 30).contains("The peer is back"));
+    }
+
+    #[tokio::test]
+    async fn alt_s_shows_statistics_until_any_key() {
+        let mut actor = actor_in_story(&[
+            "Suddenly the lights went out.",
+            "Suddenly they came back on.",
+        ]);
+        actor
+            .handle_input_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('s'),
+                KeyModifiers::ALT,
+            )))
+            .await
+            .unwrap();
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains("10 words, 5.0 per sentence"));
+        assert!(screen.contains("Most repeated: suddenly ×2"));
+        assert!(!screen.contains("Writing time"));
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char('x'))))
+            .await
+            .unwrap();
+        assert!(actor.stats.is_none());
+        // The key only closed the statistics
+        assert_eq!(actor.input_buffer.text(), "");
+    }
+
+    #[test]
+    fn snapshot_long_story_wraps() {
+        let mut actor = actor_in_story(&[
+            "The lighthouse keeper's daughter had never once been allowed up the spiral stairs to the lamp room.",
+            "On the night of the storm she climbed them anyway, counting every one of the two hundred steps.",
+            "At the top she found the lamp already burning, though nobody had lit it in twenty years.",
+        ]);
+        assert_snapshot("long_story", &mut actor, 60, 20);
+    }
+
+    /// Counts how many frames are drawn to the terminal.
+    struct CountingBackend {
+        inner: TestBackend,
+        frames: usize,
+    }
+
+    impl Backend for CountingBackend {
+        fn draw<'a, I>(&mut self, content: I) -> Result<(), io::Error>
+        where
+            I: Iterator<Item = (u16, u16, &'a Cell)>,
+        {
+            self.inner.draw(content)
+        }
+        fn hide_cursor(&mut self) -> Result<(), io::Error> {
+            self.inner.hide_cursor()
+        }
+        fn show_cursor(&mut self) -> Result<(), io::Error> {
+            self.inner.show_cursor()
+        }
+        fn get_cursor(&mut self) -> Result<(u16, u16), io::Error> {
+            self.inner.get_cursor()
+        }
+        fn set_cursor(&mut self, x: u16, y: u16) -> Result<(), io::Error> {
+            self.inner.set_cursor(x, y)
+        }
+        fn clear(&mut self) -> Result<(), io::Error> {
+            self.inner.clear()
+        }
+        fn size(&self) -> Result<Rect, io::Error> {
+            self.inner.size()
+        }
+        // Every `Terminal::draw` ends with exactly one flush
+        fn flush(&mut self) -> Result<(), io::Error> {
+            self.frames += 1;
+            self.inner.flush()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_stalled_ui_never_holds_up_the_app() {
+        let (ui_handle, mut receiver) = UIHandle::channel();
+        let dropped = ui_handle.dropped.clone();
+        let save_dir = std::env::temp_dir().join("write_together-stalled-ui");
+        let app = AppHandle::new(
+            AppConfig {
+                save_dir,
+                ..AppConfig::default()
+            },
+            ui_handle,
+        );
+        // Every resume is logged, and nothing reads the log until the end
+        let flood = async {
+            for _ in 0..QUEUE * 4 {
+                app.resume(SessionInstance::new(true)).await.unwrap();
+            }
+            app.shutdown().await.unwrap();
+        };
+        timeout(Duration::from_secs(2), flood)
+            .await
+            .expect("the app waited for the UI");
+        // Along with the log that it isn't listening
+        let missed = QUEUE * 3 + 1;
+        assert_eq!(dropped.load(Ordering::Relaxed), missed);
+
+        let mut actor = actor();
+        actor.dropped = dropped;
+        actor.handle_message(receiver.recv().await.unwrap());
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains(&format!("Missed {} log messages", missed)));
+    }
+
+    #[tokio::test]
+    async fn only_redraws_when_something_changed() {
+        let mut actor = actor();
+        let mut terminal = Terminal::new(CountingBackend {
+            inner: TestBackend::new(80, 24),
+            frames: 0,
+        })
+        .unwrap();
+        let frames = |terminal: &Terminal<CountingBackend>| terminal.backend().frames;
+
+        actor.redraw(&mut terminal).unwrap();
+        assert_eq!(frames(&terminal), 1);
+        actor.redraw(&mut terminal).unwrap();
+        assert_eq!(frames(&terminal), 1);
+
+        let mouse = MouseEvent::ScrollDown(0, 0, KeyModifiers::NONE);
+        actor.handle_input_event(Event::Mouse(mouse)).await.unwrap();
+        actor.tick();
+        actor.redraw(&mut terminal).unwrap();
+        assert_eq!(frames(&terminal), 1);
+
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char('a'))))
+            .await
+            .unwrap();
+        actor.redraw(&mut terminal).unwrap();
+        assert_eq!(frames(&terminal), 2);
+
+        actor.tick();
+        actor.handle_message(UIMessage::Log(Level::Info, Message::new("story-finished")));
+        actor.redraw(&mut terminal).unwrap();
+        assert_eq!(frames(&terminal), 3);
+    }
+
+    #[tokio::test]
+    async fn draws_at_most_once_a_tick_and_never_when_idle() {
+        let mut actor = actor();
+        let mut terminal = Terminal::new(CountingBackend {
+            inner: TestBackend::new(80, 24),
+            frames: 0,
+        })
+        .unwrap();
+        let frames = |terminal: &Terminal<CountingBackend>| terminal.backend().frames;
+        actor.redraw(&mut terminal).unwrap();
+
+        // Nothing moves, so ticking away draws nothing
+        for _ in 0..50 {
+            actor.tick();
+            actor.redraw(&mut terminal).unwrap();
+        }
+        assert_eq!(frames(&terminal), 1);
+
+        // A burst of keys within one tick is drawn as it starts, then once
+        // more on the next tick
+        actor.tick();
+        for c in "hello".chars() {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+                .await
+                .unwrap();
+            actor.redraw(&mut terminal).unwrap();
+        }
+        assert_eq!(frames(&terminal), 2);
+        actor.tick();
+        actor.redraw(&mut terminal).unwrap();
+        assert_eq!(frames(&terminal), 3);
+
+        // While connecting, the spinner is drawn on every tick
+        actor.app_state = Connecting("127.0.0.1:4000".parse().unwrap());
+        for _ in 0..5 {
+            actor.tick();
+            actor.redraw(&mut terminal).unwrap();
+        }
+        assert_eq!(frames(&terminal), 8);
+    }
+
+    #[tokio::test]
+    async fn a_burst_of_input_is_handled_in_order_and_drawn_in_a_few_frames() {
+        let mut actor = actor();
+        let mut terminal = Terminal::new(CountingBackend {
+            inner: TestBackend::new(80, 24),
+            frames: 0,
+        })
+        .unwrap();
+        let key = |code| Event::Key(KeyEvent::from(code));
+        let burst: Vec<_> = std::iter::repeat_n(key(KeyCode::Char('x')), 100)
+            .chain(std::iter::repeat_n(key(KeyCode::Home), 300))
+            .chain(std::iter::once(key(KeyCode::Char('y'))))
+            .chain(std::iter::repeat_n(key(KeyCode::End), 99))
+            .collect();
+        assert_eq!(burst.len(), 500);
+        let mut events = stream::iter(burst.into_iter().map(Ok));
+
+        // However slow the terminal, so that a tick passes between reads,
+        // everything waiting is handled before drawing again
+        while let Some(Ok(event)) = events.next().await {
+            actor.tick();
+            assert!(!actor.handle_input_events(event, &mut events).await);
+            actor.redraw(&mut terminal).unwrap();
+        }
+        // Typed into the address box, as nobody is connected
+        assert_eq!(actor.address_buffer.text(), format!("y{}", "x".repeat(100)));
+        assert_eq!(actor.address_buffer.cursor(), 101);
+        assert_eq!(terminal.backend().frames, 2);
+    }
+
+    #[test]
+    fn renders_at_pathological_sizes() {
+        for (width, height) in [
+            (0, 0),
+            (1, 1),
+            (19, 40),
+            (200, 3),
+            (MIN_WIDTH - 1, MIN_HEIGHT),
+        ] {
+            render(width, height);
+        }
+    }
+
+    #[test]
+    fn too_small_terminal_shows_message() {
+        assert!(render(59, 20).contains("Terminal too small"));
+        assert!(!render(MIN_WIDTH, MIN_HEIGHT).contains("Terminal too small"));
+        assert!(render(MIN_WIDTH, MIN_HEIGHT).contains("Content"));
+    }
+
+    #[test]
+    fn list_views_put_each_sentence_on_its_own_line() {
+        let mut session = SessionInstance::new(true);
+        for text in ["The lighthouse was dark.", "Nobody kept it."] {
+            session.push(Turn {
+                text: String::from(text),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        let state = InSession {
+            is_our_turn: true,
+            session,
+        };
+        let lines = |view| {
+            state
+                .content_log(view, false, &Theme::default(), &Catalog::english())
+                .unwrap()
+                .lines
+                .iter()
+                .map(|line| line.0.iter().map(|span| span.content.as_ref()).collect())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            lines(ContentView::Prose),
+            vec!["The lighthouse was dark. Nobody kept it."]
+        );
+        assert_eq!(
+            lines(ContentView::List),
+            vec!["The lighthouse was dark.", "Nobody kept it."]
+        );
+        assert_eq!(
+            lines(ContentView::Numbered),
+            vec!["1. The lighthouse was dark.", "2. Nobody kept it."]
+        );
+    }
+
+    #[test]
+    fn replaying_shows_the_timeline_between_the_sentences() {
+        let mut session = SessionInstance::new(true);
+        let push = |session: &mut SessionInstance, text: &str| {
+            session.push(Turn {
+                text: String::from(text),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            })
+        };
+        session.record(Some(1), sessions::Event::Connected);
+        push(&mut session, "The lighthouse was dark.");
+        push(&mut session, "Nobody kept it.");
+        session.record(Some(2), sessions::Event::Disconnected);
+        session.record(Some(2), sessions::Event::Resumed);
+        push(&mut session, "Still.");
+        let mut actor = actor();
+        actor.app_state = Replay(session);
+        let lines = |actor: &UIActor| {
+            actor
+                .content_lines()
+                .iter()
+                .map(|line| line.0.iter().map(|span| span.content.as_ref()).collect())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            lines(&actor),
+            vec![
+                "· Player 1 connected (0s ago)",
+                "The lighthouse was dark. Nobody kept it.",
+                "· Player 2 disconnected (0s ago)",
+                "· Player 2 connected again (0s ago)",
+                "Still.",
+            ]
+        );
+        assert_eq!(
+            actor.turn_range(&actor.content_lines(), 1),
+            Some((1, 25..40))
+        );
+        assert_eq!(actor.turn_range(&actor.content_lines(), 2), Some((4, 0..6)));
+
+        actor.content_view = ContentView::List;
+        assert_eq!(
+            lines(&actor)[1..3],
+            ["The lighthouse was dark.", "Nobody kept it."]
+        );
+        assert_eq!(
+            actor.turn_range(&actor.content_lines(), 1),
+            Some((2, 0..15))
+        );
+
+        // Only the replay has a timeline
+        let session = match &actor.app_state {
+            Replay(session) => session.clone(),
+            _ => unreachable!(),
+        };
+        actor.app_state = InSession {
+            is_our_turn: true,
+            session,
+        };
+        assert_eq!(lines(&actor).len(), 3);
+    }
+
+    #[test]
+    fn focus_is_shown_without_color() {
+        let mut actor = actor();
+        actor.theme = Theme::named(ThemeName::HighContrast);
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains("▶ Connect"));
+        assert!(screen.contains("┏"));
+        assert!(!screen.contains("▶ Log"));
+
+        actor.cycle_focus(true);
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains("▶ Log"));
+        assert!(!screen.contains("▶ Connect"));
+    }
+
+    #[test]
+    fn focus_skips_elements_unusable_in_the_current_state() {
+        let mut actor = actor();
+        assert!(actor.selected_element == Element::Connect);
+        actor.cycle_focus(true);
+        assert!(actor.selected_element == Element::Log);
+        actor.cycle_focus(true);
+        assert!(actor.selected_element == Element::Connect);
+
+        actor.handle_message(UIMessage::Connected {
+            session: SessionInstance::new(true),
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        assert!(actor.selected_element == Element::Input);
+        actor.cycle_focus(false);
+        assert!(actor.selected_element == Element::Log);
+        actor.cycle_focus(false);
+        assert!(actor.selected_element == Element::Input);
+
+        actor.handle_message(UIMessage::Disconnected);
+        assert!(actor.selected_element == Element::Connect);
+    }
+
+    #[test]
+    fn flashes_only_when_the_peer_passes_the_turn() {
+        let turn = |text: &str| Turn {
+            text: String::from(text),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        };
+        let mut actor = actor();
+        actor.alerts.notify = Notify::Flash;
+        actor.handle_message(UIMessage::Connected {
+            session: SessionInstance::new(true),
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        // As submitting does before the app accepts the sentence
+        if let InSession { is_our_turn, .. } = &mut actor.app_state {
+            *is_our_turn = false;
+        }
+        actor.handle_message(UIMessage::SentenceAccepted(turn("Ours.")));
+        actor.handle_message(UIMessage::Log(Level::Info, Message::new("story-finished")));
+        assert!(actor.flash_until.is_none());
+
+        actor.handle_message(UIMessage::SentenceReceived(turn("Theirs.")));
+        assert!(actor.flash_until.is_some());
+    }
+
+    #[tokio::test]
+    async fn escape_dismisses_completions_and_tab_accepts_them() {
+        let key = |code| Event::Key(KeyEvent::from(code));
+        let mut actor = actor();
+        actor.handle_message(UIMessage::Connected {
+            session: SessionInstance::new(true),
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        actor
+            .completer
+            .as_mut()
+            .unwrap()
+            .add_text("The lantern flickered.");
+        for c in "the lan".chars() {
+            actor
+                .handle_input_event(key(KeyCode::Char(c)))
+                .await
+                .unwrap();
+        }
+        assert_eq!(actor.suggestions(), vec!["lantern"]);
+
+        assert!(!actor.handle_input_event(key(KeyCode::Esc)).await.unwrap());
+        assert!(actor.suggestions().is_empty());
+
+        actor
+            .handle_input_event(key(KeyCode::Char('t')))
+            .await
+            .unwrap();
+        actor.handle_input_event(key(KeyCode::Tab)).await.unwrap();
+        assert_eq!(actor.input_buffer.text(), "the lantern");
+        assert!(actor.selected_element == Element::Input);
+    }
+
+    #[tokio::test]
+    async fn quitting_an_unexported_story_asks_first() {
+        let key = |code| Event::Key(KeyEvent::from(code));
+        let mut actor = actor();
+        assert!(actor.handle_input_event(key(KeyCode::Esc)).await.unwrap());
+
+        let mut session = SessionInstance::new(true);
+        session.push(Turn {
+            text: String::from("Once upon a time."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        });
+        actor.handle_message(UIMessage::Connected {
+            session,
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        assert!(!actor.handle_input_event(key(KeyCode::Esc)).await.unwrap());
+        assert!(render_actor(&mut actor, 100, 30).contains("[y]es / [n]o"));
+        assert!(!actor
+            .handle_input_event(key(KeyCode::Char('n')))
+            .await
+            .unwrap());
+        assert!(actor.prompt.is_none());
+
+        actor.handle_input_event(key(KeyCode::Esc)).await.unwrap();
+        assert!(actor
+            .handle_input_event(key(KeyCode::Char('y')))
+            .await
+            .unwrap());
+    }
+
+    #[test]
+    fn failed_connect_from_the_command_line_fills_in_the_address() {
+        let mut actor = actor();
+        let address = "192.0.2.4:4001".parse().unwrap();
+        actor.handle_message(UIMessage::Connecting(address));
+        actor.handle_message(UIMessage::ConnectFailed {
+            address,
+            reason: Message::new("reason-refused"),
+        });
+        assert!(matches!(actor.app_state, Waiting));
+        assert_eq!(actor.address_buffer.text(), "192.0.2.4:4001");
+    }
+
+    #[tokio::test]
+    async fn ctrl_c_quits_even_from_a_prompt() {
+        let mut actor = actor();
+        actor.prompt = Some(Prompt::Quit);
+        assert!(actor
+            .handle_input_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('c'),
+                KeyModifiers::CONTROL,
+            )))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn ctrl_z_suspends_only_where_there_is_job_control() {
+        let mut actor = actor();
+        let ctrl_z = Event::Key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        let mut events = stream::iter(vec![Ok(ctrl_z), Ok(ctrl_z)]);
+        assert!(!actor.handle_input_events(ctrl_z, &mut events).await);
+        assert_eq!(actor.suspending, cfg!(unix));
+        // The rest waits until the process is back
+        let left = events.collect::<Vec<_>>().await.len();
+        assert_eq!(left, if cfg!(unix) { 2 } else { 0 });
+    }
+
+    #[tokio::test]
+    async fn search_takes_escape_before_quitting() {
+        let key = |code| Event::Key(KeyEvent::from(code));
+        let mut actor = actor();
+        let mut session = SessionInstance::new(true);
+        for text in ["The lighthouse was dark.", "Nobody kept the lighthouse."] {
+            session.push(Turn {
+                text: String::from(text),
+                timestamp: Utc::now(),
+                next_constraint: None,
+                writing_secs: None,
+            });
+        }
+        actor.handle_message(UIMessage::Connected {
+            session,
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        actor.exported_turns = 2;
+
+        actor
+            .handle_input_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('f'),
+                KeyModifiers::CONTROL,
+            )))
+            .await
+            .unwrap();
+        for c in "LIGHTHOUSE".chars() {
+            actor
+                .handle_input_event(key(KeyCode::Char(c)))
+                .await
+                .unwrap();
+        }
+        actor.handle_input_event(key(KeyCode::Enter)).await.unwrap();
+        actor
+            .handle_input_event(key(KeyCode::Char('N')))
+            .await
+            .unwrap();
+        assert!(render_actor(&mut actor, 100, 30).contains("/LIGHTHOUSE (2/2"));
+        assert!(actor.input_buffer.text().is_empty());
+
+        assert!(!actor.handle_input_event(key(KeyCode::Esc)).await.unwrap());
+        assert!(actor.search.is_none());
+        assert!(actor.handle_input_event(key(KeyCode::Esc)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn f12_shows_how_the_actors_keep_up() {
+        let mut actor = actor();
+        let f12 = Event::Key(KeyEvent::from(KeyCode::F(12)));
+        actor.metrics.sent();
+        actor.metrics.handled(Duration::from_micros(2500));
+        actor.handle_input_event(f12).await.unwrap();
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(
+            screen.contains("UI: 1 queued · 0.0/s · slowest 2.5ms"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("App: 0 queued"));
+        assert!(screen.contains("Draws: 0.0/s"));
+
+        actor.handle_input_event(f12).await.unwrap();
+        assert!(!render_actor(&mut actor, 100, 30).contains("Draws:"));
+    }
+
+    #[tokio::test]
+    async fn goes_to_a_turn_by_its_number() {
+        let key = |code| Event::Key(KeyEvent::from(code));
+        let texts: Vec<String> = (1..=40)
+            .map(|number| format!("Sentence number {}.", number))
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let mut actor = actor_in_story(&texts);
+        actor.exported_turns = texts.len();
+        actor.content_view = ContentView::Numbered;
+        assert!(!render_actor(&mut actor, 100, 30).contains("Sentence number 3."));
+
+        actor
+            .handle_input_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('g'),
+                KeyModifiers::ALT,
+            )))
+            .await
+            .unwrap();
+        for code in [KeyCode::Char('9'), KeyCode::Char('x'), KeyCode::Char('9')] {
+            actor.handle_input_event(key(code)).await.unwrap();
+        }
+        actor.handle_input_event(key(KeyCode::Enter)).await.unwrap();
+        assert!(render_actor(&mut actor, 100, 30).contains("there's no turn 99, only 1 to 40"));
+        assert!(actor.input_buffer.text().is_empty());
+
+        for code in [KeyCode::Backspace, KeyCode::Backspace, KeyCode::Char('3')] {
+            actor.handle_input_event(key(code)).await.unwrap();
+        }
+        actor.handle_input_event(key(KeyCode::Enter)).await.unwrap();
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains(" 3. Sentence number 3."), "{}", screen);
+        assert!(screen.contains("turn 3 of 40"));
+        assert!(actor.go_to.as_ref().unwrap().highlight_until.is_some());
+
+        // The prose view has all the sentences on one line
+        actor.content_view = ContentView::Prose;
+        let screen = render_actor(&mut actor, 60, 20);
+        assert!(screen.contains("Sentence number 2."), "{}", screen);
+        assert!(!screen.contains("Sentence number 40."));
+
+        assert!(!actor.handle_input_event(key(KeyCode::Esc)).await.unwrap());
+        assert!(actor.go_to.is_none());
+        assert!(render_actor(&mut actor, 100, 30).contains("Sentence number 40."));
+    }
+
+    #[tokio::test]
+    async fn the_palette_runs_commands_by_name_unless_they_cant_be_run_now() {
+        let key = |code| Event::Key(KeyEvent::from(code));
+        let ctrl_p = Event::Key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL));
+        let mut actor = actor();
+        actor.handle_input_event(ctrl_p).await.unwrap();
+        for c in "dsc".chars() {
+            actor
+                .handle_input_event(key(KeyCode::Char(c)))
+                .await
+                .unwrap();
+        }
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains(": dsc"), "{}", screen);
+        assert!(screen.contains("disconnect") && screen.contains("Ctrl+d"));
+        actor.handle_input_event(key(KeyCode::Enter)).await.unwrap();
+        assert!(actor.palette.is_some());
+        assert!(render_actor(&mut actor, 200, 30)
+            .contains("Can't disconnect now: you aren't writing with anyone"));
+        actor.handle_input_event(key(KeyCode::Esc)).await.unwrap();
+        assert!(actor.palette.is_none());
+
+        let mut actor = actor_in_story(&["The lighthouse was dark.", "Nobody kept it."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        actor.handle_input_event(ctrl_p).await.unwrap();
+        for code in [
+            KeyCode::Char('n'),
+            KeyCode::Char('s'),
+            KeyCode::Down,
+            KeyCode::Up,
+        ] {
+            actor.handle_input_event(key(code)).await.unwrap();
+        }
+        actor.handle_input_event(key(KeyCode::Enter)).await.unwrap();
+        assert!(actor.palette.is_none());
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::EndStory))
+        ));
+        // Nothing was typed into the Input on the way
+        assert!(actor.input_buffer.text().is_empty());
+
+        // A number goes to that turn, and `:` opens it from the Log
+        actor.selected_element = Element::Log;
+        for code in [KeyCode::Char(':'), KeyCode::Char('2'), KeyCode::Enter] {
+            actor.handle_input_event(key(code)).await.unwrap();
+        }
+        assert!(actor.palette.is_none());
+        assert_eq!(actor.go_to.as_ref().unwrap().turn, Some(1));
+    }
+
+    #[tokio::test]
+    async fn shows_an_invite_to_connect_with() {
+        let mut actor = actor();
+        actor.invite_hint = Some(String::from("the usual"));
+        actor.handle_message(UIMessage::Listening("192.168.1.20:4000".parse().unwrap()));
+        let (invite, _) = actor.invite.clone().unwrap();
+        let screen = render_actor(&mut actor, 140, 50);
+        assert!(screen.contains(&invite), "{}", screen);
+        assert!(screen.contains('▀'));
+        // Left out where it doesn't fit
+        assert!(!render_actor(&mut actor, 100, 30).contains('▀'));
+
+        actor.address_buffer.set(&invite.to_lowercase());
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Enter)))
+            .await
+            .unwrap();
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Connect(address))) if address == "192.168.1.20:4000".parse().unwrap()
+        ));
+        assert!(
+            render_actor(&mut actor, 200, 50).contains("The invite comes with a hint: the usual")
+        );
+    }
+
+    #[test]
+    fn the_peers_sentences_are_typed_out_but_whole_from_the_start() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        actor.typewriter = Some(10);
+        actor.handle_message(UIMessage::SentenceReceived(Turn {
+            text: String::from("Nobody had kept it since the storm."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        }));
+        let (turn, _) = actor.typing.unwrap();
+        actor.typing = Some((turn, Instant::now() - Duration::from_millis(1050)));
+
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(
+            screen.contains("The lighthouse was dark. Nobody had "),
+            "{}",
+            screen
+        );
+        assert!(!screen.contains("storm"), "{}", screen);
+        // The turn has passed, and searching finds what isn't shown yet
+        assert!(matches!(
+            actor.app_state,
+            InSession {
+                is_our_turn: true,
+                ..
+            }
+        ));
+        let mut query = EditBuffer::default();
+        query.set("storm");
+        actor.search = Some(Search {
+            query,
+            editing: false,
+            current: None,
+        });
+        assert_eq!(actor.search_matches(&actor.content_lines()).len(), 1);
+        actor.search = None;
+
+        actor.typing = Some((turn, Instant::now() - Duration::from_secs(10)));
+        actor.tick();
+        assert!(actor.typing.is_none());
+        assert!(render_actor(&mut actor, 100, 30).contains("since the storm."));
+    }
+
+    #[test]
+    fn without_color_focus_shows_in_borders_and_markers() {
+        for mut actor in [actor(), actor_in_story(&["The lighthouse was dark."])] {
+            actor.theme = Theme::new(ThemeName::Default, false);
+            actor.handle_message(UIMessage::Listening("192.168.1.20:4000".parse().unwrap()));
+            let mut terminal = Terminal::new(TestBackend::new(140, 50)).unwrap();
+            actor.draw(&mut terminal).unwrap();
+            let cells = terminal.backend().buffer().content();
+            assert!(cells
+                .iter()
+                .all(|cell| cell.fg == Color::Reset && cell.bg == Color::Reset));
+            let screen: String = cells.iter().map(|cell| cell.symbol.as_str()).collect();
+            assert!(screen.contains("┏"), "{}", screen);
+            assert!(screen.contains("▶ "));
+        }
+        // The invite's code is drawn in blocks instead
+        let mut actor = actor();
+        actor.theme = Theme::new(ThemeName::Default, false);
+        actor.handle_message(UIMessage::Listening("192.168.1.20:4000".parse().unwrap()));
+        assert!(render_actor(&mut actor, 140, 50).contains('█'));
+    }
+
+    #[tokio::test]
+    async fn the_scratchpad_keeps_notes_to_ourselves() {
+        let mut actor = actor_in_story(&["The lighthouse was dark."]);
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        let alt = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT));
+        actor.handle_input_event(alt('p')).await.unwrap();
+        assert!(actor.selected_element == Element::Scratchpad);
+        for c in "Keeper lied. Why".chars() {
+            actor
+                .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+                .await
+                .unwrap();
+        }
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Backspace)))
+            .await
+            .unwrap();
+        // Typed into the notes rather than submitted, and only saved once
+        // focus moves on
+        assert!(actor.input_buffer.text().is_empty());
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(
+            screen.contains("Scratchpad (only you see this)"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("┃Keeper lied. Wh"), "{}", screen);
+        actor.save_notes(false).await.unwrap();
+        assert!(requests.recv().now_or_never().is_none());
+
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Tab)))
+            .await
+            .unwrap();
+        assert!(actor.selected_element != Element::Scratchpad);
+        actor.save_notes(false).await.unwrap();
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Notes(notes))) if notes == "Keeper lied. Wh"
+        ));
+
+        // Hidden, it's out of the focus order, and the notes are still there
+        actor.handle_input_event(alt('p')).await.unwrap();
+        assert!(!actor.focus_order().contains(&Element::Scratchpad));
+        assert!(!render_actor(&mut actor, 100, 30).contains("Scratchpad"));
+        actor.handle_input_event(alt('p')).await.unwrap();
+        assert!(render_actor(&mut actor, 100, 30).contains("Keeper lied. Wh"));
+    }
+
+    #[tokio::test]
+    async fn lists_every_address_and_switches_the_invite_between_them() {
+        let mut actor = actor();
+        let alt = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT));
+        actor.handle_message(UIMessage::Listening("127.0.0.1:4000".parse().unwrap()));
+        assert_eq!(actor.endpoints.len(), 1);
+        assert!(!render_actor(&mut actor, 100, 30).contains("also be reached"));
+
+        actor.endpoints = vec![
+            "192.168.1.20:4000".parse().unwrap(),
+            "10.0.0.2:4000".parse().unwrap(),
+        ];
+        actor.highlight_endpoint(actor.endpoints[0]);
+        let screen = render_actor(&mut actor, 140, 50);
+        assert!(
+            screen.contains("Listening on 192.168.1.20:4000 (1/2)"),
+            "{}",
+            screen
+        );
+        assert!(
+            screen.contains(
+                "You can also be reached at 10.0.0.2:4000 (Alt+n to switch, Alt+e to copy)."
+            ),
+            "{}",
+            screen
+        );
+
+        actor.handle_input_event(alt('n')).await.unwrap();
+        let (invite, _) = actor.invite.clone().unwrap();
+        assert_eq!(
+            super::Invite::decode(&invite).unwrap().address,
+            "10.0.0.2:4000".parse().unwrap()
+        );
+        assert!(render_actor(&mut actor, 140, 50).contains("Listening on 10.0.0.2:4000 (2/2)"));
+        actor.handle_input_event(alt('n')).await.unwrap();
+        assert_eq!(actor.listening, Some(actor.endpoints[0]));
+
+        actor.handle_input_event(alt('e')).await.unwrap();
+        assert!(render_actor(&mut actor, 140, 50).contains("Copied 192.168.1.20:4000"));
+    }
+
+    #[tokio::test]
+    async fn recent_peers_connect_by_number_and_count_stories() {
+        let address = "192.0.2.4:4000".parse().unwrap();
+        let mut actor = actor();
+        actor
+            .recent_peers
+            .connected("192.0.2.9:4000".parse().unwrap())
+            .unwrap();
+        actor.recent_peers.connected(address).unwrap();
+        let (app_handle, mut requests) = AppHandle::recording();
+        actor.app_handle = app_handle;
+        assert!(
+            render_actor(&mut actor, 100, 30).contains("1. 192.0.2.4:4000 — 0 stories — 0s ago")
+        );
+
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Tab)))
+            .await
+            .unwrap();
+        assert!(actor.selected_element == Element::RecentPeers);
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char('1'))))
+            .await
+            .unwrap();
+        assert!(matches!(
+            requests.recv().now_or_never(),
+            Some(Some(AppInput::Connect(connected))) if connected == address
+        ));
+
+        let mut session = SessionInstance::new(true);
+        session.push(Turn {
+            text: String::from("It rained."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        });
+        actor.handle_message(UIMessage::Connected {
+            session,
+            peer: address,
+        });
+        actor.handle_message(UIMessage::Disconnected);
+        assert!(render_actor(&mut actor, 100, 30).contains("1. 192.0.2.4:4000 — 1 story"));
+
+        // Forgetting the last one leaves the Connect box focused
+        for _ in 0..2 {
+            actor.selected_element = Element::RecentPeers;
+            actor
+                .handle_input_event(Event::Key(KeyEvent::new(
+                    KeyCode::Char('x'),
+                    KeyModifiers::CONTROL,
+                )))
+                .await
+                .unwrap();
+        }
+        assert!(actor.recent_peers.is_empty());
+        assert!(actor.selected_element == Element::Connect);
+    }
+
+    #[tokio::test]
+    async fn connecting_ignores_enter_and_shows_failures() {
+        let address = "192.0.2.4:4000".parse().unwrap();
+        let mut actor = actor();
+        actor.handle_message(UIMessage::Connecting(address));
+        assert!(render_actor(&mut actor, 100, 30).contains("Connecting to 192.0.2.4:4000…"));
+        // The detached app would fail a second connect
+        assert!(!actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Enter)))
+            .await
+            .unwrap());
+
+        actor.handle_message(UIMessage::ConnectFailed {
+            address,
+            reason: Message::new("reason-refused"),
+        });
+        assert!(render_actor(&mut actor, 100, 30).contains("connection refused"));
+        assert!(matches!(actor.app_state, Waiting));
+    }
+
+    #[tokio::test]
+    async fn toasts_stack_and_any_key_dismisses_them() {
+        let mut actor = actor();
+        for n in 1..=4 {
+            actor.handle_message(UIMessage::Toast(Message::new("copied").arg("count", n)));
+        }
+        let texts: Vec<&str> = actor
+            .toasts
+            .iter()
+            .map(|toast| toast.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["Copied 2 chars", "Copied 3 chars", "Copied 4 chars"]
+        );
+        assert!(render_actor(&mut actor, 100, 30).contains("│Copied 4 chars"));
+
+        actor
+            .handle_input_event(Event::Key(KeyEvent::from(KeyCode::Char('1'))))
+            .await
+            .unwrap();
+        assert!(actor.toasts.is_empty());
+        assert_eq!(actor.address_buffer.text(), "1");
+    }
+
+    #[test]
+    fn milestones_get_a_banner_across_the_screen() {
+        let mut actor = actor();
+        actor.handle_message(UIMessage::Milestone(Milestone {
+            words: 1000,
+            reached_at: Utc::now(),
+            goal: false,
+        }));
+        let screen = render_actor(&mut actor, 60, 30);
+        assert!(screen.starts_with(&format!("╭{}╮", "─".repeat(58))));
+        // The cell the emoji's second column covers reads as a space
+        assert!(screen.contains("🎉  1,000 words!"));
+    }
+
+    #[test]
+    fn onboarding_shows_until_there_is_something_to_connect_to() {
+        let mut actor = actor();
+        actor.handle_message(UIMessage::Listening("127.0.0.1:4000".parse().unwrap()));
+        assert!(render_actor(&mut actor, 100, 30).contains("You are listening on 127.0.0.1:4000."));
+
+        actor.handle_message(UIMessage::Connected {
+            session: SessionInstance::new(true),
+            peer: "127.0.0.1:4001".parse().unwrap(),
+        });
+        assert!(!render_actor(&mut actor, 100, 30).contains("getting started"));
+    }
+
+    #[tokio::test]
+    async fn zen_mode_hides_everything_but_content_and_input() {
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let mut actor = actor();
+        actor.handle_message(UIMessage::Connected {
+            session: SessionInstance::new(true),
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        for _ in 0..10 {
+            actor
+                .handle_input_event(key(KeyCode::Down, KeyModifiers::CONTROL))
+                .await
+                .unwrap();
+        }
+        assert_eq!(actor.content_percent, 20);
+
+        actor
+            .handle_input_event(key(KeyCode::F(11), KeyModifiers::NONE))
+            .await
+            .unwrap();
+        actor
+            .handle_input_event(key(KeyCode::Char('a'), KeyModifiers::NONE))
+            .await
+            .unwrap();
+        let screen = render_actor(&mut actor, 100, 30);
+        assert!(screen.contains("> a"));
+        assert!(!screen.contains("Log"));
+        actor.cycle_focus(true);
+        assert!(actor.selected_element == Element::Input);
+    }
+
+    #[test]
+    fn preview_shows_the_draft_after_the_story_tail() {
+        let mut actor = actor();
+        let mut session = SessionInstance::new(true);
+        session.push(Turn {
+            text: String::from("The lighthouse was dark."),
+            timestamp: Utc::now(),
+            next_constraint: None,
+            writing_secs: None,
+        });
+        actor.handle_message(UIMessage::Connected {
+            session,
+            peer: "127.0.0.1:4000".parse().unwrap(),
+        });
+        assert!(actor.preview_line(20).is_none());
+
+        actor.input_buffer.set("Nobody came");
+        let preview = actor.preview_line(20).unwrap();
+        let pieces: Vec<&str> = preview.0.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(pieces, vec!["as dark.", " Nobody came"]);
+
+        // Stops short of a wide character with only one column left for it
+        actor.input_buffer.set("漢字漢");
+        let preview = actor.preview_line(5).unwrap();
+        let pieces: Vec<&str> = preview.0.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(pieces, vec![".", "字漢"]);
+    }
+
+    #[test]
+    fn wide_characters_are_drawn_whole_and_the_cursor_follows_them() {
+        let opening = "灯台守の娘は、嵐の夜に一度も許されなかった螺旋階段を数えながら上った。";
+        let mut actor = actor_in_story(&[opening, "Nobody kept 灯台 lit."]);
+        actor
+            .input_buffer
+            .set("The 灯台 was 暗い, and 誰も来なかった for years");
+        actor.selected_element = Element::Input;
+        let mut terminal = Terminal::new(TestBackend::new(MIN_WIDTH, MIN_HEIGHT)).unwrap();
+        let mut draw = |actor: &mut UIActor| {
+            actor.draw(&mut terminal).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            // Each row as it reads, without the cell a wide character covers
+            let rows: Vec<String> = (0..MIN_HEIGHT)
+                .map(|y| {
+                    let mut row = String::new();
+                    let mut x = 0;
+                    while x < MIN_WIDTH {
+                        let symbol = &buffer.get(x, y).symbol;
+                        row.push_str(symbol);
+                        x += symbol.width().max(1) as u16;
+                    }
+                    row
+                })
+                .collect();
+            (rows, buffer, terminal.backend_mut().get_cursor().unwrap())
+        };
+
+        let (rows, buffer, (x, y)) = draw(&mut actor);
+        // The Content pane breaks between characters, none of them lost
+        let content: String = rows
+            .iter()
+            .filter_map(|row| row.trim().strip_prefix('│'))
+            .map(|row| row.trim_end().trim_end_matches('│').trim_end())
+            .collect();
+        assert!(content.starts_with(opening), "{}", rows.join("\n"));
+        // The Input wraps without splitting one either
+        assert!(rows
+            .iter()
+            .any(|row| row.contains("┃The 灯台 was 暗い, and 誰も来な┃")));
+        assert!(rows.iter().any(|row| row.contains("┃かった for years ")));
+        assert_eq!(buffer.get(x - 1, y).symbol, "s");
+
+        // Two columns to the right of each wide character before it
+        for _ in 0.." for years".len() {
+            actor.input_buffer.left();
+        }
+        let (rows, buffer, (x, y)) = draw(&mut actor);
+        assert!(rows[y as usize].contains("┃かった"));
+        assert_eq!(buffer.get(x - 2, y).symbol, "た");
+        assert_eq!(buffer.get(x - 7, y).symbol, "┃");
+    }
+
+    #[test]
+    fn address_validation_reasons() {
+        assert!(validate_address("127.0.0.1:4000").is_ok());
+        assert!(validate_address("[::1]:4000").is_ok());
+        let reason = |address| validate_address(address).unwrap_err().to_string();
+        assert_eq!(reason("127.0.0.1"), "missing port");
+        assert_eq!(reason("127.0.0.1:"), "missing port");
+        assert_eq!(reason("::1"), "missing port");
+        assert_eq!(reason("127.0.0.1:http"), "invalid port");
+        assert_eq!(reason("127.0.0.1:70000"), "invalid port");
+        assert_eq!(reason("127.0.0:4000"), "invalid IP");
+        assert_eq!(reason("localhost:4000"), "invalid IP");
+    }
 }
@@ -0,0 +1,227 @@
+use std::ops::Range;
+use tui::{
+    style::Style,
+    text::{Span, Spans},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// One row of wrapped text, with where it starts in the line it came from.
+#[derive(Debug)]
+pub struct WrappedLine {
+    pub spans: Spans<'static>,
+    /// Index of the line it was wrapped from.
+    pub source: usize,
+    /// Byte offset into the source line the row starts at.
+    pub start: usize,
+}
+
+struct Cell<'a> {
+    offset: usize,
+    grapheme: &'a str,
+    style: Style,
+}
+
+fn is_whitespace(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+/// Whether a grapheme takes two columns, as CJK characters do. Text in
+/// those scripts has no spaces, and may break before or after any of them.
+fn is_wide(grapheme: &str) -> bool {
+    grapheme.width() > 1
+}
+
+/// Breaks styled lines into rows at most `width` columns wide, between
+/// words or wide characters where possible. Doing it here rather than in the
+/// `Paragraph` means the row anything ends up on is known, e.g. to scroll
+/// to it.
+pub fn wrap(lines: &[Spans<'static>], width: usize) -> Vec<WrappedLine> {
+    let width = width.max(1);
+    let mut wrapped = vec![];
+    for (source, line) in lines.iter().enumerate() {
+        let mut cells = vec![];
+        let mut offset = 0;
+        for span in &line.0 {
+            for (index, grapheme) in span.content.grapheme_indices(true) {
+                cells.push(Cell {
+                    offset: offset + index,
+                    grapheme,
+                    style: span.style,
+                });
+            }
+            offset += span.content.len();
+        }
+
+        let mut rows: Vec<Range<usize>> = vec![];
+        let mut row_start = 0;
+        let mut row_width = 0;
+        // Where the row could end, and where the next would then start
+        let mut last_break: Option<(usize, usize)> = None;
+        let mut index = 0;
+        while index < cells.len() {
+            let cell_width = cells[index].grapheme.width();
+            let grapheme = cells[index].grapheme;
+            if index > row_start && !is_whitespace(grapheme) {
+                let previous = cells[index - 1].grapheme;
+                if (is_wide(grapheme) || is_wide(previous)) && !is_whitespace(previous) {
+                    last_break = Some((index, index));
+                }
+            }
+            if row_width + cell_width > width && index > row_start {
+                if is_whitespace(cells[index].grapheme) {
+                    // Break at the space itself, dropping it
+                    rows.push(row_start..index);
+                    row_start = index + 1;
+                    row_width = 0;
+                    last_break = None;
+                    index += 1;
+                    continue;
+                }
+                let (end, start) = last_break.unwrap_or((index, index));
+                rows.push(row_start..end);
+                row_start = start;
+                row_width = cells[row_start..index]
+                    .iter()
+                    .map(|cell| cell.grapheme.width())
+                    .sum();
+                last_break = None;
+            }
+            if is_whitespace(grapheme) {
+                last_break = Some((index, index + 1));
+            }
+            row_width += cell_width;
+            index += 1;
+        }
+        rows.push(row_start..cells.len());
+
+        for row in rows {
+            let mut spans: Vec<Span<'static>> = vec![];
+            for cell in &cells[row.clone()] {
+                match spans.last_mut() {
+                    Some(span) if span.style == cell.style => {
+                        span.content.to_mut().push_str(cell.grapheme)
+                    }
+                    _ => spans.push(Span::styled(cell.grapheme.to_string(), cell.style)),
+                }
+            }
+            wrapped.push(WrappedLine {
+                spans: Spans::from(spans),
+                source,
+                start: cells.get(row.start).map_or(offset, |cell| cell.offset),
+            });
+        }
+    }
+    wrapped
+}
+
+/// Blanks out whatever of `row` is at `range` in the line it came from,
+/// leaving the rest where it was.
+pub fn blank(row: &mut WrappedLine, range: &Range<usize>) {
+    let mut offset = row.start;
+    for span in &mut row.spans.0 {
+        let start = offset;
+        offset += span.content.len();
+        let from = range.start.clamp(start, offset) - start;
+        let to = range.end.clamp(start, offset) - start;
+        if from < to {
+            let content = span.content.to_mut();
+            let spaces = " ".repeat(content[from..to].width());
+            content.replace_range(from..to, &spaces);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blank, wrap, WrappedLine};
+    use tui::text::{Span, Spans};
+
+    fn rows(text: &str, width: usize) -> Vec<(String, usize)> {
+        wrap(&[Spans::from(text.to_string())], width)
+            .into_iter()
+            .map(|row| {
+                let text = row
+                    .spans
+                    .0
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect();
+                (text, row.start)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn breaks_between_words() {
+        assert_eq!(
+            rows("the lighthouse keeper slept", 12),
+            vec![
+                (String::from("the"), 0),
+                (String::from("lighthouse"), 4),
+                (String::from("keeper slept"), 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_long_words_anywhere() {
+        assert_eq!(
+            rows("abcdefgh ij", 3),
+            vec![
+                (String::from("abc"), 0),
+                (String::from("def"), 3),
+                (String::from("gh"), 6),
+                (String::from("ij"), 9),
+            ]
+        );
+        assert_eq!(rows("", 3), vec![(String::new(), 0)]);
+    }
+
+    #[test]
+    fn breaks_between_wide_characters() {
+        // Each CJK character takes two columns and three bytes
+        assert_eq!(
+            rows("ab 漢字漢字", 6),
+            vec![(String::from("ab 漢"), 0), (String::from("字漢字"), 6)]
+        );
+        assert_eq!(
+            rows("漢字 and more", 7),
+            vec![
+                (String::from("漢字"), 0),
+                (String::from("and"), 7),
+                (String::from("more"), 11),
+            ]
+        );
+        // Never split, even where a row has room for only half of one
+        assert_eq!(
+            rows("a漢字", 2),
+            vec![
+                (String::from("a"), 0),
+                (String::from("漢"), 1),
+                (String::from("字"), 4),
+            ]
+        );
+        assert_eq!(rows("漢", 1), vec![(String::from("漢"), 0)]);
+    }
+
+    #[test]
+    fn blanking_keeps_everything_in_place() {
+        let line = Spans::from(vec![Span::raw("One. "), Span::raw("Two 漢字. Three.")]);
+        let mut rows = wrap(&[line], 13);
+        let text = |row: &WrappedLine| -> String {
+            row.spans
+                .0
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        };
+        assert_eq!(text(&rows[0]), "One. Two 漢字");
+        for row in &mut rows {
+            blank(row, &(7..21));
+        }
+        // Wide characters leave as many columns as they took
+        assert_eq!(text(&rows[0]), "One. Tw      ");
+        assert_eq!(text(&rows[1]), "      e.");
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+/// Writes a zip archive of uncompressed entries, which is all an EPUB needs
+/// and keeps its `mimetype` readable at a fixed offset as the format asks.
+pub struct ZipWriter {
+    out: Vec<u8>,
+    /// The central directory, written once every entry is in.
+    directory: Vec<u8>,
+    entries: u16,
+    /// When every entry was last modified, in MS-DOS time and date.
+    modified: (u16, u16),
+}
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+/// Version 2.0, the first that every reader understands.
+const VERSION: u16 = 20;
+/// Entry names are UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+
+impl ZipWriter {
+    /// An empty archive whose entries were all last modified at `modified`.
+    pub fn new(modified: DateTime<Utc>) -> Self {
+        Self {
+            out: vec![],
+            directory: vec![],
+            entries: 0,
+            modified: dos_time(modified),
+        }
+    }
+
+    /// Adds a file called `name` holding `data`.
+    pub fn add(&mut self, name: &str, data: &[u8]) {
+        let offset = self.out.len() as u32;
+        let crc = crc32(data);
+        let (time, date) = self.modified;
+
+        put32(&mut self.out, LOCAL_HEADER);
+        for field in [VERSION, UTF8_NAMES, 0, time, date] {
+            put16(&mut self.out, field);
+        }
+        for field in [crc, data.len() as u32, data.len() as u32] {
+            put32(&mut self.out, field);
+        }
+        put16(&mut self.out, name.len() as u16);
+        put16(&mut self.out, 0);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+
+        let directory = &mut self.directory;
+        put32(directory, CENTRAL_HEADER);
+        for field in [VERSION, VERSION, UTF8_NAMES, 0, time, date] {
+            put16(directory, field);
+        }
+        for field in [crc, data.len() as u32, data.len() as u32] {
+            put32(directory, field);
+        }
+        // Name length, then no extra field, comment, disk or attributes
+        for field in [name.len() as u16, 0, 0, 0, 0] {
+            put16(directory, field);
+        }
+        put32(directory, 0);
+        put32(directory, offset);
+        directory.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+    }
+
+    /// The archive with everything added so far.
+    pub fn finish(mut self) -> Vec<u8> {
+        let offset = self.out.len() as u32;
+        self.out.extend_from_slice(&self.directory);
+        put32(&mut self.out, END_OF_DIRECTORY);
+        for field in [0, 0, self.entries, self.entries] {
+            put16(&mut self.out, field);
+        }
+        put32(&mut self.out, self.directory.len() as u32);
+        put32(&mut self.out, offset);
+        put16(&mut self.out, 0);
+        self.out
+    }
+}
+
+fn put16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// `at` as MS-DOS time and date, which start in 1980 and count seconds in
+/// twos.
+fn dos_time(at: DateTime<Utc>) -> (u16, u16) {
+    let time = (at.hour() << 11 | at.minute() << 5 | (at.second() / 2)) as u16;
+    let year = (at.year() - 1980).clamp(0, 127) as u32;
+    let date = (year << 9 | at.month() << 5 | at.day()) as u16;
+    (time, date)
+}
+
+/// The CRC-32 zip files check each entry with.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// The entries of an archive from [`ZipWriter`], with their names, for
+/// checking what was written.
+#[cfg(test)]
+pub fn read(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]) as usize;
+    let u32_at = |at: usize| {
+        u32::from_le_bytes([
+            archive[at],
+            archive[at + 1],
+            archive[at + 2],
+            archive[at + 3],
+        ])
+    };
+    let mut entries = vec![];
+    let mut at = 0;
+    while u32_at(at) == LOCAL_HEADER {
+        assert_eq!(u16_at(at + 8), 0, "entries are stored");
+        let crc = u32_at(at + 14);
+        let size = u32_at(at + 22) as usize;
+        let name_len = u16_at(at + 26);
+        let data_at = at + 30 + name_len + u16_at(at + 28);
+        let name = String::from_utf8(archive[at + 30..at + 30 + name_len].to_vec()).unwrap();
+        let data = archive[data_at..data_at + size].to_vec();
+        assert_eq!(crc32(&data), crc, "{} is intact", name);
+        entries.push((name, data));
+        at = data_at + size;
+    }
+    assert_eq!(u32_at(at), CENTRAL_HEADER);
+    let end = archive.len() - 22;
+    assert_eq!(u32_at(end), END_OF_DIRECTORY);
+    assert_eq!(u16_at(end + 10), entries.len());
+    assert_eq!(u32_at(end + 16) as usize, at);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, dos_time, read, ZipWriter};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn checksums_match_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn entries_read_back_in_order() {
+        let modified = Utc.with_ymd_and_hms(2021, 5, 1, 20, 4, 7).unwrap();
+        assert_eq!(
+            dos_time(modified),
+            (20 << 11 | 4 << 5 | 3, 41 << 9 | 5 << 5 | 1)
+        );
+        let mut zip = ZipWriter::new(modified);
+        zip.add("mimetype", b"application/epub+zip");
+        zip.add("dir/ünïcode.txt", "héllo".as_bytes());
+        let archive = zip.finish();
+        // Readers find the mimetype at a fixed offset
+        assert_eq!(&archive[30..38], b"mimetype");
+        assert_eq!(
+            read(&archive),
+            vec![
+                (String::from("mimetype"), b"application/epub+zip".to_vec()),
+                (String::from("dir/ünïcode.txt"), "héllo".as_bytes().to_vec()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,864 @@
+use chrono::Utc;
+use futures::FutureExt;
+use std::{net::SocketAddr, path::PathBuf};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines},
+    net::TcpStream,
+    sync::{mpsc::Receiver, oneshot},
+    time::{sleep, timeout, Duration},
+};
+use write_together::{
+    app::{AppConfig, AppHandle},
+    bot::{self, Script},
+    error::Error,
+    filter::{Incoming, WordFilter},
+    hook::TurnHook,
+    i18n::Catalog,
+    log_buffer::Level,
+    plain,
+    protocol::{self, PeerMessage, TurnPreference},
+    sessions::{self, Milestones, SessionInstance, Turn},
+    ui_actor::{UIHandle, UIMessage},
+};
+
+/// A fresh directory to save sessions to.
+fn save_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("write_together-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// An app actor together with what it tells its UI.
+struct Peer {
+    app: AppHandle,
+    ui: Receiver<UIMessage>,
+    save_dir: PathBuf,
+}
+
+impl Peer {
+    /// Starts an app listening on a port of the system's choosing, returning
+    /// it with the address it listens on.
+    async fn listening(name: &str) -> (Self, SocketAddr) {
+        let mut peer = Self::start(name, Some(0), None);
+        let address = peer.listening_address().await;
+        (peer, address)
+    }
+
+    fn start(name: &str, port: Option<u16>, turn_preference: Option<TurnPreference>) -> Self {
+        Self::with_config(
+            name,
+            AppConfig {
+                listen_port: port,
+                turn_preference,
+                ..AppConfig::default()
+            },
+        )
+    }
+
+    /// Starts an app with `config`, saving to a directory of its own.
+    fn with_config(name: &str, config: AppConfig) -> Self {
+        let save_dir = save_dir(name);
+        let (ui_handle, ui) = UIHandle::channel();
+        Self {
+            app: AppHandle::new(
+                AppConfig {
+                    save_dir: save_dir.clone(),
+                    ..config
+                },
+                ui_handle,
+            ),
+            ui,
+            save_dir,
+        }
+    }
+
+    async fn listening_address(&mut self) -> SocketAddr {
+        self.expect(|msg| match msg {
+            UIMessage::Listening(address) => Some(*address),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Waits for the first message `matches` picks something out of,
+    /// skipping the others.
+    async fn expect<T>(&mut self, matches: impl Fn(&UIMessage) -> Option<T>) -> T {
+        expect(&mut self.ui, matches).await
+    }
+
+    async fn connected(&mut self) -> SessionInstance {
+        self.expect(|msg| match msg {
+            UIMessage::Connected { session, .. } => Some(session.clone()),
+            _ => None,
+        })
+        .await
+    }
+}
+
+/// Waits for the first message on `ui` that `matches` picks something out
+/// of, skipping the others.
+async fn expect<T>(ui: &mut Receiver<UIMessage>, matches: impl Fn(&UIMessage) -> Option<T>) -> T {
+    let wait = async {
+        loop {
+            let msg = ui.recv().await.expect("the app stopped");
+            if let Some(found) = matches(&msg) {
+                return found;
+            }
+        }
+    };
+    timeout(Duration::from_secs(5), wait)
+        .await
+        .expect("timed out waiting for the app")
+}
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.save_dir);
+    }
+}
+
+#[tokio::test]
+async fn peers_take_turns_and_notice_disconnects() {
+    let (mut listener, address) = Peer::listening("listener").await;
+    let mut connector = Peer::start("connector", None, Some(TurnPreference::First));
+
+    connector.app.connect(address).await.unwrap();
+    let connector_session = connector.connected().await;
+    let listener_session = listener.connected().await;
+    assert_eq!(listener_session.id, connector_session.id);
+    assert!(connector_session.is_our_turn());
+    assert!(!listener_session.is_our_turn());
+
+    connector
+        .app
+        .send_sentence(String::from("Once upon a time."), None)
+        .await
+        .unwrap();
+    let accepted = connector
+        .expect(|msg| match msg {
+            UIMessage::SentenceAccepted(turn) => Some(turn.text.clone()),
+            _ => None,
+        })
+        .await;
+    let received = listener
+        .expect(|msg| match msg {
+            UIMessage::SentenceReceived(turn) => Some(turn.text.clone()),
+            _ => None,
+        })
+        .await;
+    assert_eq!(accepted, "Once upon a time.");
+    assert_eq!(received, "Once upon a time.");
+
+    let (saved, _) = sessions::scan_saved(&listener.save_dir);
+    assert_eq!(saved[0].session.turns().len(), 1);
+
+    drop(connector);
+    listener
+        .expect(|msg| match msg {
+            UIMessage::Disconnected => Some(()),
+            _ => None,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn an_imported_opening_is_shared_and_continued_by_the_peer() {
+    let (mut listener, address) = Peer::listening("importer").await;
+    let opening = vec![
+        String::from("It began elsewhere."),
+        String::from("And went on."),
+    ];
+    listener
+        .app
+        .resume(SessionInstance::imported(opening, false))
+        .await
+        .unwrap();
+    let mut connector = Peer::start("import-connector", None, Some(TurnPreference::Second));
+
+    connector.app.connect(address).await.unwrap();
+    // The connector starts a story of its own before it learns of the resumed one
+    let connector_session = connector
+        .expect(|msg| match msg {
+            UIMessage::Connected { session, .. } if !session.turns().is_empty() => {
+                Some(session.clone())
+            }
+            _ => None,
+        })
+        .await;
+    let listener_session = listener.connected().await;
+    for session in [&connector_session, &listener_session] {
+        assert_eq!(session.content(), "It began elsewhere. And went on.");
+        assert!(session.is_imported(1) && !session.is_imported(2));
+    }
+    // The peer continues, whatever it asked for
+    assert!(connector_session.is_our_turn());
+    assert!(!listener_session.is_our_turn());
+}
+
+#[tokio::test]
+async fn a_sentence_with_a_listed_word_is_handed_back() {
+    let mut listener = Peer::with_config(
+        "filtered",
+        AppConfig {
+            listen_port: Some(0),
+            word_filter: Some(WordFilter::parse("darn", Incoming::Reject)),
+            ..AppConfig::default()
+        },
+    );
+    let address = listener.listening_address().await;
+    let mut connector = Peer::start("unfiltered", None, Some(TurnPreference::First));
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    listener.connected().await;
+
+    connector
+        .app
+        .send_sentence(String::from("Darn it."), None)
+        .await
+        .unwrap();
+    let refused = connector
+        .expect(|msg| match msg {
+            UIMessage::SentenceRefused { index, turn } => Some((*index, turn.text.clone())),
+            _ => None,
+        })
+        .await;
+    assert_eq!(refused, (0, String::from("Darn it.")));
+
+    // The turn is the connector's again, and the story goes on without it
+    connector
+        .app
+        .send_sentence(String::from("Drat."), None)
+        .await
+        .unwrap();
+    let received = listener
+        .expect(|msg| match msg {
+            UIMessage::SentenceReceived(turn) => Some(turn.text.clone()),
+            _ => None,
+        })
+        .await;
+    assert_eq!(received, "Drat.");
+}
+
+#[tokio::test]
+async fn the_peer_hears_when_we_step_away() {
+    let (mut listener, address) = Peer::listening("away-listener").await;
+    let mut connector = Peer::start("away-connector", None, None);
+    // Away before connecting, which the peer is told once it connects
+    connector.app.set_away(true).await.unwrap();
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    let away = |msg: &UIMessage| match msg {
+        UIMessage::PeerAway(away) => Some(*away),
+        _ => None,
+    };
+    assert!(listener.expect(away).await);
+    connector.app.set_away(false).await.unwrap();
+    assert!(!listener.expect(away).await);
+}
+
+#[tokio::test]
+async fn both_sides_celebrate_a_milestone_once() {
+    let milestones = Milestones {
+        every: Some(5),
+        goal: None,
+    };
+    let mut listener = Peer::with_config(
+        "milestone-listener",
+        AppConfig {
+            listen_port: Some(0),
+            milestones: Some(milestones),
+            ..AppConfig::default()
+        },
+    );
+    let address = listener.listening_address().await;
+    let mut connector = Peer::with_config(
+        "milestone-connector",
+        AppConfig {
+            turn_preference: Some(TurnPreference::First),
+            milestones: Some(milestones),
+            ..AppConfig::default()
+        },
+    );
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    listener.connected().await;
+
+    connector
+        .app
+        .send_sentence(String::from("One two three four five six."), None)
+        .await
+        .unwrap();
+    let milestone = |msg: &UIMessage| match msg {
+        UIMessage::Milestone(milestone) => Some(milestone.words),
+        _ => None,
+    };
+    assert_eq!(connector.expect(milestone).await, 5);
+    assert_eq!(listener.expect(milestone).await, 5);
+
+    // Recorded in the saved session, so resuming it doesn't celebrate again
+    let (saved, _) = sessions::scan_saved(&connector.save_dir);
+    let mut session = saved[0].session.clone();
+    assert_eq!(session.milestones().len(), 1);
+    assert_eq!(session.reach_milestones(&milestones), None);
+}
+
+/// Connects a fresh pair of peers, the connector writing first, and has
+/// the connector write a sentence and end the story.
+async fn ended_story(name: &str, rematch_timeout: Duration) -> (Peer, Peer) {
+    let config = || AppConfig {
+        rematch_timeout,
+        ..AppConfig::default()
+    };
+    let mut listener = Peer::with_config(
+        &format!("{}-listener", name),
+        AppConfig {
+            listen_port: Some(0),
+            ..config()
+        },
+    );
+    let address = listener.listening_address().await;
+    let mut connector = Peer::with_config(
+        &format!("{}-connector", name),
+        AppConfig {
+            turn_preference: Some(TurnPreference::First),
+            ..config()
+        },
+    );
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    listener.connected().await;
+    connector
+        .app
+        .send_sentence(String::from("And that was that."), None)
+        .await
+        .unwrap();
+    connector.app.end_story().await.unwrap();
+    let ended = |msg: &UIMessage| matches!(msg, UIMessage::StoryEnded).then_some(());
+    connector.expect(ended).await;
+    listener.expect(ended).await;
+    (connector, listener)
+}
+
+fn new_story(msg: &UIMessage) -> Option<SessionInstance> {
+    match msg {
+        UIMessage::NewStory(session) => Some(session.clone()),
+        _ => None,
+    }
+}
+
+fn disconnected(msg: &UIMessage) -> Option<()> {
+    matches!(msg, UIMessage::Disconnected).then_some(())
+}
+
+#[tokio::test]
+async fn both_wanting_another_story_starts_one_with_the_other_writer_first() {
+    let (mut connector, mut listener) = ended_story("rematch", Duration::from_secs(5)).await;
+    listener.app.rematch(true).await.unwrap();
+    connector.app.rematch(true).await.unwrap();
+    let connector_story = connector.expect(new_story).await;
+    let listener_story = listener.expect(new_story).await;
+    assert_eq!(connector_story.id, listener_story.id);
+    assert!(connector_story.turns().is_empty());
+    assert!(listener_story.is_our_turn());
+    assert!(!connector_story.is_our_turn());
+}
+
+#[tokio::test]
+async fn a_no_says_goodbye_to_both_sides() {
+    let (mut connector, mut listener) = ended_story("goodbye", Duration::from_secs(5)).await;
+    connector.app.rematch(true).await.unwrap();
+    listener.app.rematch(false).await.unwrap();
+    listener.expect(disconnected).await;
+    connector
+        .expect(|msg| match msg {
+            UIMessage::Log(_, message) if message.to_string().contains("said goodbye") => Some(()),
+            _ => None,
+        })
+        .await;
+    connector.expect(disconnected).await;
+}
+
+#[tokio::test]
+async fn no_answer_in_time_counts_as_a_no() {
+    let (mut connector, mut listener) =
+        ended_story("rematch-timeout", Duration::from_millis(200)).await;
+    connector.app.rematch(true).await.unwrap();
+    connector.expect(disconnected).await;
+    listener.expect(disconnected).await;
+}
+
+/// Writes an executable shell script running `body`.
+#[cfg(unix)]
+fn script(name: &str, body: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path =
+        std::env::temp_dir().join(format!("write_together-{}-{}.sh", name, std::process::id()));
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn clearing_the_story_waits_for_the_peer_to_agree() {
+    let (mut listener, address) = Peer::listening("clear-listener").await;
+    let mut connector = Peer::start("clear-connector", None, Some(TurnPreference::First));
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    listener.connected().await;
+    connector
+        .app
+        .send_sentence(String::from("It was a dark and stormy night."), None)
+        .await
+        .unwrap();
+    let received = |msg: &UIMessage| matches!(msg, UIMessage::SentenceReceived(_)).then_some(());
+    listener.expect(received).await;
+    let proposed = |msg: &UIMessage| matches!(msg, UIMessage::ClearProposed).then_some(());
+
+    // A no changes nothing, and the proposer hears about it
+    connector.app.propose_clear().await.unwrap();
+    listener.expect(proposed).await;
+    listener.app.answer_clear(false).await.unwrap();
+    connector
+        .expect(|msg| match msg {
+            UIMessage::Toast(message) if message.to_string().contains("keep the story") => Some(()),
+            _ => None,
+        })
+        .await;
+    let (saved, _) = sessions::scan_saved(&listener.save_dir);
+    assert_eq!(saved[0].session.turns().len(), 1);
+    let old_id = saved[0].session.id.clone();
+
+    // A yes starts both sides on the same empty story, the old one exported
+    connector.app.propose_clear().await.unwrap();
+    listener.expect(proposed).await;
+    listener.app.answer_clear(true).await.unwrap();
+    let connector_story = connector.expect(new_story).await;
+    let listener_story = listener.expect(new_story).await;
+    assert_eq!(connector_story.id, listener_story.id);
+    assert!(connector_story.turns().is_empty());
+    assert_ne!(connector_story.is_our_turn(), listener_story.is_our_turn());
+    let export = PathBuf::from(format!("story-{}.md", old_id));
+    let exported = std::fs::read_to_string(&export).unwrap();
+    let _ = std::fs::remove_file(&export);
+    assert!(exported.contains("It was a dark and stormy night."));
+}
+
+#[tokio::test]
+async fn the_turn_hook_gets_every_sentence() {
+    let out = save_dir("hook-out");
+    let hook = script(
+        "hook",
+        &format!(
+            "echo \"$WRITE_TOGETHER_AUTHOR $WRITE_TOGETHER_TURN: $(cat)\" >> {}",
+            out.display()
+        ),
+    );
+    let (mut listener, address) = Peer::listening("hook-listener").await;
+    let mut connector = Peer::with_config(
+        "hooked",
+        AppConfig {
+            turn_preference: Some(TurnPreference::First),
+            turn_hook: Some(TurnHook::new(hook.clone(), Duration::from_secs(5))),
+            ..AppConfig::default()
+        },
+    );
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    listener.connected().await;
+
+    connector
+        .app
+        .send_sentence(String::from("The ferry left late."), None)
+        .await
+        .unwrap();
+    listener
+        .expect(|msg| matches!(msg, UIMessage::SentenceReceived(_)).then_some(()))
+        .await;
+    listener
+        .app
+        .send_sentence(String::from("Nobody minded."), None)
+        .await
+        .unwrap();
+
+    // The hook runs on its own, so give it a moment to catch up
+    let expected = "me 1: The ferry left late.\npeer 2: Nobody minded.\n";
+    let mut written = String::new();
+    for _ in 0..50 {
+        written = std::fs::read_to_string(&out).unwrap_or_default();
+        if written == expected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(written, expected);
+    let _ = std::fs::remove_file(&out);
+    let _ = std::fs::remove_file(&hook);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn a_runaway_turn_hook_is_killed_and_reported_once() {
+    let hook = script("slow-hook", "sleep 30");
+    let (mut listener, address) = Peer::listening("slow-listener").await;
+    let mut connector = Peer::with_config(
+        "slow-hooked",
+        AppConfig {
+            turn_preference: Some(TurnPreference::First),
+            turn_hook: Some(TurnHook::new(hook.clone(), Duration::from_millis(200))),
+            ..AppConfig::default()
+        },
+    );
+    connector.app.connect(address).await.unwrap();
+    connector.connected().await;
+    listener.connected().await;
+
+    let started = std::time::Instant::now();
+    connector
+        .app
+        .send_sentence(String::from("The clock stopped."), None)
+        .await
+        .unwrap();
+    // Play goes on while the hook runs
+    listener
+        .expect(|msg| matches!(msg, UIMessage::SentenceReceived(_)).then_some(()))
+        .await;
+    listener
+        .app
+        .send_sentence(String::from("Then it started again."), None)
+        .await
+        .unwrap();
+    let warning = connector
+        .expect(|msg| match msg {
+            UIMessage::Log(Level::Warn, message) if message.to_string().contains("turn hook") => {
+                Some(message.clone())
+            }
+            _ => None,
+        })
+        .await;
+    assert!(
+        warning.to_string().contains("killed after 0.2s"),
+        "{}",
+        warning
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+    // The second run times out too, but only the first is reported
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    while let Some(Some(msg)) = connector.ui.recv().now_or_never() {
+        assert!(
+            !matches!(&msg, UIMessage::Log(_, message) if message.to_string().contains("turn hook")),
+            "{:?}",
+            msg
+        );
+    }
+    let _ = std::fs::remove_file(&hook);
+}
+
+/// Reads what plain mode says until a line containing `wanted`.
+async fn read_until(screen: &mut Lines<BufReader<DuplexStream>>, wanted: &str) -> String {
+    let wait = async {
+        loop {
+            let line = screen
+                .next_line()
+                .await
+                .unwrap()
+                .expect("plain mode stopped");
+            if line.contains(wanted) {
+                return line;
+            }
+        }
+    };
+    timeout(Duration::from_secs(5), wait)
+        .await
+        .unwrap_or_else(|_| panic!("never said \"{}\"", wanted))
+}
+
+#[tokio::test]
+async fn plain_mode_reads_sentences_and_commands_a_line_at_a_time() {
+    let (mut peer, address) = Peer::listening("sighted").await;
+    let plain_dir = save_dir("plain");
+    let (ui_handle, ui) = UIHandle::channel();
+    let plain_app = AppHandle::new(
+        AppConfig {
+            save_dir: plain_dir.clone(),
+            turn_preference: Some(TurnPreference::First),
+            ..AppConfig::default()
+        },
+        ui_handle,
+    );
+    let (mut keyboard, input) = tokio::io::duplex(1024);
+    let (output, screen) = tokio::io::duplex(4096);
+    let plain = tokio::spawn(plain::run(
+        plain_app,
+        ui,
+        Catalog::english(),
+        BufReader::new(input),
+        output,
+        None,
+    ));
+    let mut screen = BufReader::new(screen).lines();
+
+    keyboard.write_all(b"It was a dark night.\n").await.unwrap();
+    read_until(&mut screen, "Not connected").await;
+    keyboard
+        .write_all(format!("/connect {}\n", address).as_bytes())
+        .await
+        .unwrap();
+    read_until(&mut screen, "Connected to").await;
+    read_until(&mut screen, "Your turn.").await;
+    keyboard.write_all(b"It was a dark night.\n").await.unwrap();
+    assert_eq!(
+        read_until(&mut screen, "You:").await,
+        "You: It was a dark night."
+    );
+    keyboard.write_all(b"Too soon.\n").await.unwrap();
+    read_until(&mut screen, "It's the peer's turn").await;
+
+    let received = peer
+        .expect(|msg| match msg {
+            UIMessage::SentenceReceived(turn) => Some(turn.text.clone()),
+            _ => None,
+        })
+        .await;
+    assert_eq!(received, "It was a dark night.");
+    peer.app
+        .send_sentence(String::from("Nobody was awake."), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        read_until(&mut screen, "Peer:").await,
+        "Peer: Nobody was awake."
+    );
+    read_until(&mut screen, "Your turn.").await;
+
+    keyboard.write_all(b"/quit\n").await.unwrap();
+    timeout(Duration::from_secs(5), plain)
+        .await
+        .expect("plain mode didn't quit")
+        .unwrap()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(plain_dir);
+}
+
+#[tokio::test]
+async fn bot_answers_until_its_script_runs_out() {
+    let (mut human, address) = Peer::listening("human").await;
+    let script_path =
+        std::env::temp_dir().join(format!("write_together-script-{}.txt", std::process::id()));
+    std::fs::write(&script_path, "It was a dark night.\nThe end.\n").unwrap();
+    let script = Script::load(&script_path).unwrap();
+    let _ = std::fs::remove_file(&script_path);
+
+    let bot_dir = save_dir("bot");
+    let (ui_handle, ui) = UIHandle::channel();
+    let bot_app = AppHandle::new(
+        AppConfig {
+            save_dir: bot_dir.clone(),
+            turn_preference: Some(TurnPreference::First),
+            ..AppConfig::default()
+        },
+        ui_handle,
+    );
+    let bot = tokio::spawn(bot::run(
+        bot_app,
+        ui,
+        script,
+        Duration::from_millis(0),
+        Some(address),
+    ));
+
+    let mut received = vec![];
+    for reply in ["Nobody was awake.", "Or so it seemed."] {
+        received.push(
+            human
+                .expect(|msg| match msg {
+                    UIMessage::SentenceReceived(turn) => Some(turn.text.clone()),
+                    _ => None,
+                })
+                .await,
+        );
+        human
+            .app
+            .send_sentence(String::from(reply), None)
+            .await
+            .unwrap();
+    }
+    assert_eq!(received, vec!["It was a dark night.", "The end."]);
+    timeout(Duration::from_secs(5), bot)
+        .await
+        .expect("the bot didn't finish")
+        .unwrap()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(bot_dir);
+}
+
+#[tokio::test]
+async fn shutdown_reports_why_the_app_stopped() {
+    let (_first, address) = Peer::listening("first").await;
+    let (ui_handle, mut ui) = UIHandle::channel();
+    let second = AppHandle::new(
+        AppConfig {
+            listen_port: Some(address.port()),
+            save_dir: save_dir("second"),
+            ..AppConfig::default()
+        },
+        ui_handle,
+    );
+    let stopped = timeout(Duration::from_secs(5), ui.recv())
+        .await
+        .expect("timed out waiting for the app");
+    assert!(matches!(stopped, Some(UIMessage::AppStopped(_))));
+
+    let err = second.shutdown().await.unwrap_err();
+    assert!(matches!(err, Error::Bind { addr, .. } if addr == address));
+}
+
+#[tokio::test]
+async fn no_sentence_is_lost_while_the_ui_keeps_the_app_busy() {
+    const SENTENCES: usize = 100;
+    let (mut listener, address) = Peer::listening("busy").await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let writer = tokio::spawn(async move {
+        for index in 0..SENTENCES {
+            let frame = protocol::encode(&PeerMessage::Sentence {
+                index,
+                turn: Turn {
+                    text: format!("Sentence {}.", index),
+                    timestamp: Utc::now(),
+                    next_constraint: None,
+                    writing_secs: None,
+                },
+            });
+            // Split frames across reads, so some arrive in pieces
+            for chunk in frame.chunks(32) {
+                stream.write_all(chunk).await.unwrap();
+            }
+        }
+        // Closing it with the app's checks of the story unread would reset it
+        stream
+    });
+    let app = &listener.app;
+    let busy = async {
+        for _ in 0..SENTENCES {
+            app.list_saved().await.unwrap();
+        }
+    };
+    let ui = &mut listener.ui;
+    let receive = async {
+        let mut received = Vec::new();
+        while received.len() < SENTENCES {
+            match ui.recv().await.expect("the app stopped") {
+                UIMessage::SentenceReceived(turn) => received.push(turn.text),
+                UIMessage::Log(_, message) if message.to_string().contains("turn") => {
+                    panic!("unexpected {}", message)
+                }
+                _ => {}
+            }
+        }
+        received
+    };
+    // Generous, as this checks that nothing is lost or reordered rather
+    // than how fast the machine is
+    let (_, received) = timeout(Duration::from_secs(60), async {
+        tokio::join!(busy, receive)
+    })
+    .await
+    .expect("timed out waiting for the sentences");
+    let expected: Vec<_> = (0..SENTENCES)
+        .map(|index| format!("Sentence {}.", index))
+        .collect();
+    assert_eq!(received, expected);
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn the_last_sentence_reaches_the_peer_when_quitting_straight_after() {
+    let save_dir = save_dir("quitter");
+    let (ui_handle, mut ui) = UIHandle::channel();
+    let app = AppHandle::new(
+        AppConfig {
+            listen_port: Some(0),
+            turn_preference: Some(TurnPreference::First),
+            save_dir: save_dir.clone(),
+            ..AppConfig::default()
+        },
+        ui_handle,
+    );
+    let address = expect(&mut ui, |msg| match msg {
+        UIMessage::Listening(address) => Some(*address),
+        _ => None,
+    })
+    .await;
+
+    // The peer is played by hand, to keep sending while it doesn't read
+    let (mut read, mut write) = TcpStream::connect(address).await.unwrap().into_split();
+    let hello = PeerMessage::Hello {
+        nonce: 1,
+        preference: Some(TurnPreference::Second),
+        cooldown_secs: 0,
+        compression: false,
+    };
+    let session = PeerMessage::Session {
+        session: SessionInstance::new(false),
+        resumed: false,
+    };
+    for message in [hello, session] {
+        write.write_all(&protocol::encode(&message)).await.unwrap();
+    }
+    expect(&mut ui, |msg| match msg {
+        UIMessage::Connected { .. } => Some(()),
+        _ => None,
+    })
+    .await;
+    tokio::spawn(async move { while ui.recv().await.is_some() {} });
+
+    let (stop, mut stopped) = oneshot::channel();
+    let chatter = tokio::spawn(async move {
+        // Always something more arriving, for the app to leave unread
+        let checked = protocol::encode(&PeerMessage::Checked {
+            matched: true,
+            turns: 0,
+        });
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                _ = sleep(Duration::from_millis(1)) => {
+                    if write.write_all(&checked).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = write.shutdown().await;
+    });
+    let reader = tokio::spawn(async move {
+        // Late, so that the sentence is still on its way when the app stops
+        sleep(Duration::from_millis(300)).await;
+        let mut bytes = vec![];
+        let read = read.read_to_end(&mut bytes).await;
+        let _ = stop.send(());
+        read.map(|_| bytes)
+    });
+
+    let sentence = format!("{}The end.", "And then ".repeat(200_000));
+    app.send_sentence(sentence.clone(), None).await.unwrap();
+    app.shutdown().await.unwrap();
+
+    let bytes = timeout(Duration::from_secs(5), reader)
+        .await
+        .expect("the app never closed the connection")
+        .unwrap()
+        .expect("the connection was reset");
+    chatter.await.unwrap();
+    let mut decoder = protocol::Decoder::default();
+    decoder.extend(&bytes);
+    let mut last = None;
+    while let Some(message) = decoder.next_message().unwrap() {
+        if let PeerMessage::Sentence { turn, .. } = message {
+            last = Some(turn.text);
+        }
+    }
+    assert!(last == Some(sentence), "the sentence was lost");
+    let _ = std::fs::remove_dir_all(save_dir);
+}